@@ -2,33 +2,15 @@
 // This is a part of Kickmess. See README.md and COPYING for details.
 
 use kickmessvst;
-use kickmessvst::ui::protocol::*;
 use kickmessvst::helpers::*;
+use kickmessvst::engine::{EngineParams, NoHost};
+use kickmessvst::editor::KickmessEditorController;
 use std::sync::Arc;
 
-
-struct TestController {
-}
-
-impl UIController for TestController {
-    fn init(&self, ui: &mut dyn UI) {
-        use kickmessvst::MonoProcessor;
-//        self.is_open.store(true, std::sync::atomic::Ordering::Relaxed);
-        let mut ps        = kickmessvst::ParamSet::new();
-        let mut public_ps = kickmessvst::ParamSet::new();
-        kickmessvst::OpKickmess::init_params(&mut ps, &mut public_ps);
-        kickmessvst::editor::define_gui(&ps, ui);
-        ui.set_version(kickmessvst::VERSION);
-        ui.set_default_values();
-    }
-
-    fn window_closed(&self, _ui: &mut dyn UI) {
-//        self.is_open.store(false, std::sync::atomic::Ordering::Relaxed);
-    }
-}
-
 fn main() {
-    let ctrl = Arc::new(TestController { });
+    let ctrl =
+        Arc::new(KickmessEditorController::new(
+            Arc::new(EngineParams::new()), Arc::new(NoHost)));
 
     init_cos_tab();
 
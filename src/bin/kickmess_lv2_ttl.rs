@@ -0,0 +1,36 @@
+// Copyright (c) 2020-2021 Weird Constructor <weirdconstructor@gmail.com>
+// This is a part of Kickmess. See README.md and COPYING for details.
+
+//! Writes the TTL files of the LV2 bundle, generated from the parameter table.
+//!
+//!     kickmess-lv2-ttl [bundle directory]
+//!
+//! The default bundle directory is `lv2/kickmess.lv2`, or `lv2/megamess.lv2`
+//! with the `mega` feature.
+
+use kickmessvst::lv2;
+
+fn write_file(dir: &std::path::Path, name: &str, content: &str) {
+    let path = dir.join(name);
+    if let Err(e) = std::fs::write(&path, content) {
+        eprintln!("Error: Couldn't write {:?}: {}", path, e);
+        std::process::exit(1);
+    }
+    println!("Wrote {:?}", path);
+}
+
+fn main() {
+    let dir =
+        std::env::args().nth(1).unwrap_or_else(||
+            if cfg!(feature="mega") { "lv2/megamess.lv2".to_string() }
+            else                    { "lv2/kickmess.lv2".to_string() });
+    let dir = std::path::Path::new(&dir);
+
+    if let Err(e) = std::fs::create_dir_all(dir) {
+        eprintln!("Error: Couldn't create {:?}: {}", dir, e);
+        std::process::exit(1);
+    }
+
+    write_file(dir, "manifest.ttl", &lv2::manifest_ttl());
+    write_file(dir, "kickmess.ttl", &lv2::plugin_ttl());
+}
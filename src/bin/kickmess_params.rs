@@ -0,0 +1,37 @@
+// Copyright (c) 2020-2021 Weird Constructor <weirdconstructor@gmail.com>
+// This is a part of Kickmess. See README.md and COPYING for details.
+
+//! Prints the parameter metadata, generated from the parameter table.
+//!
+//!     kickmess-params [--json | --toml | --markdown]
+//!
+//! With the `mega` feature the Megamess parameters are printed.
+//! See also `kickmessvst::metadata`.
+
+use kickmessvst::metadata;
+
+const USAGE : &str =
+"kickmess-params [--json | --toml | --markdown]
+
+Prints the parameter metadata to stdout, default: --json
+";
+
+fn main() {
+    let args : Vec<String> = std::env::args().skip(1).collect();
+
+    let out =
+        match args.first().map(|s| s.as_str()) {
+            None | Some("--json") => metadata::params_json(),
+            Some("--toml")        => metadata::params_toml(),
+            Some("--markdown")    => metadata::params_markdown(),
+            Some(arg) => {
+                if arg != "-h" && arg != "--help" {
+                    eprintln!("Error: Unknown option: '{}'\n", arg);
+                }
+                eprint!("{}", USAGE);
+                std::process::exit(1);
+            }
+        };
+
+    print!("{}", out);
+}
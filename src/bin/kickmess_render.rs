@@ -0,0 +1,231 @@
+// Copyright (c) 2020-2021 Weird Constructor <weirdconstructor@gmail.com>
+// This is a part of Kickmess. See README.md and COPYING for details.
+
+//! Renders notes with a Kickmess preset into a WAV file, without a host.
+//!
+//! The notes are played one after another, each one is held for its length
+//! and followed by the gap. See also `kickmessvst::offline`.
+
+use kickmessvst::offline::{OfflineRenderer, RenderNote, RenderQuality};
+
+const USAGE : &str =
+"kickmess-render [options] <output.wav>
+
+Options:
+    -p, --preset <file>     Preset file, as saved by the plugin.
+                            Without a preset the default parameters are used.
+    -r, --rate <hz>         Sample rate, default: 44100
+    -f, --format <fmt>      Sample format: 16, 24 or 32f, default: 24
+    -n, --note <note[:vel[:len]]>
+                            MIDI note number, velocity (0-127) and the length
+                            in milliseconds. Can be given multiple times.
+                            Default: 36:127:500
+    -g, --gap <ms>          Time between the end of a note and the
+                            start of the next one, default: 0
+    -t, --tail <ms>         Time to render after the last note, default: 500
+    -q, --quality <q>       Oversampling filter quality: offline, realtime or
+                            preset (the \"OS Quality\" parameter of the preset),
+                            default: offline
+    -h, --help              Show this help
+";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum SampleFormat {
+    Int16,
+    Int24,
+    Float32,
+}
+
+struct RenderOptions {
+    preset:     Option<String>,
+    srate:      u32,
+    format:     SampleFormat,
+    notes:      Vec<RenderNote>,
+    gap_ms:     f32,
+    tail_ms:    f32,
+    quality:    RenderQuality,
+    output:     String,
+}
+
+fn parse_num<T: std::str::FromStr>(arg: &str, s: &str) -> Result<T, String> {
+    s.parse::<T>().map_err(|_| format!("Bad value for {}: '{}'", arg, s))
+}
+
+fn parse_note(s: &str) -> Result<RenderNote, String> {
+    let parts : Vec<&str> = s.split(':').collect();
+    if parts.is_empty() || parts.len() > 3 {
+        return Err(format!("Bad note: '{}'", s));
+    }
+
+    let note : u8 = parse_num("note", parts[0])?;
+    let vel  : u8 =
+        if let Some(v) = parts.get(1) { parse_num("velocity", v)? }
+        else { 127 };
+    let len_ms : f32 =
+        if let Some(l) = parts.get(2) { parse_num("note length", l)? }
+        else { 500.0 };
+
+    if note > 127 || vel > 127 {
+        return Err(format!("Note and velocity must be in the range 0-127: '{}'", s));
+    }
+
+    Ok(RenderNote { note, vel, len_ms })
+}
+
+fn parse_args(args: &[String]) -> Result<RenderOptions, String> {
+    let mut opts = RenderOptions {
+        preset:  None,
+        srate:   44100,
+        format:  SampleFormat::Int24,
+        notes:   vec![],
+        gap_ms:  0.0,
+        tail_ms: 500.0,
+        quality: RenderQuality::Offline,
+        output:  String::new(),
+    };
+
+    let mut i = 0;
+    while i < args.len() {
+        let arg = args[i].as_str();
+
+        if arg == "-h" || arg == "--help" {
+            return Err(String::new());
+        }
+
+        if !arg.starts_with('-') {
+            if !opts.output.is_empty() {
+                return Err(format!("Only one output file allowed: '{}'", arg));
+            }
+            opts.output = arg.to_string();
+            i += 1;
+            continue;
+        }
+
+        let val =
+            args.get(i + 1)
+                .ok_or_else(|| format!("Missing value for {}", arg))?;
+
+        match arg {
+            "-p" | "--preset" => { opts.preset = Some(val.to_string()); },
+            "-r" | "--rate"   => { opts.srate = parse_num(arg, val)?; },
+            "-f" | "--format" => {
+                opts.format =
+                    match val.as_str() {
+                        "16"  => SampleFormat::Int16,
+                        "24"  => SampleFormat::Int24,
+                        "32f" => SampleFormat::Float32,
+                        _ => return Err(format!("Unknown sample format: '{}'", val)),
+                    };
+            },
+            "-n" | "--note"   => { opts.notes.push(parse_note(val)?); },
+            "-g" | "--gap"    => { opts.gap_ms  = parse_num(arg, val)?; },
+            "-t" | "--tail"   => { opts.tail_ms = parse_num(arg, val)?; },
+            "-q" | "--quality" => {
+                opts.quality =
+                    match val.as_str() {
+                        "offline"  => RenderQuality::Offline,
+                        "realtime" => RenderQuality::Realtime,
+                        "preset"   => RenderQuality::Preset,
+                        _ => return Err(format!("Unknown quality: '{}'", val)),
+                    };
+            },
+            _ => return Err(format!("Unknown option: '{}'", arg)),
+        }
+
+        i += 2;
+    }
+
+    if opts.output.is_empty() {
+        return Err("No output file given".to_string());
+    }
+
+    if opts.srate == 0 {
+        return Err("The sample rate must not be 0".to_string());
+    }
+
+    if opts.notes.is_empty() {
+        opts.notes.push(RenderNote { note: 36, vel: 127, len_ms: 500.0 });
+    }
+
+    Ok(opts)
+}
+
+fn write_wav(samples: &[f32], opts: &RenderOptions) -> Result<(), hound::Error> {
+    let (bits_per_sample, sample_format) =
+        match opts.format {
+            SampleFormat::Int16   => (16, hound::SampleFormat::Int),
+            SampleFormat::Int24   => (24, hound::SampleFormat::Int),
+            SampleFormat::Float32 => (32, hound::SampleFormat::Float),
+        };
+
+    let spec = hound::WavSpec {
+        channels:    1,
+        sample_rate: opts.srate,
+        bits_per_sample,
+        sample_format,
+    };
+
+    let mut writer = hound::WavWriter::create(&opts.output, spec)?;
+
+    for s in samples.iter() {
+        match opts.format {
+            SampleFormat::Int16 => {
+                let s = s.clamp(-1.0, 1.0);
+                writer.write_sample((s * i16::MAX as f32) as i16)?;
+            },
+            SampleFormat::Int24 => {
+                let s = s.clamp(-1.0, 1.0);
+                writer.write_sample((s * 8388607.0) as i32)?;
+            },
+            SampleFormat::Float32 => {
+                writer.write_sample(*s)?;
+            },
+        }
+    }
+
+    writer.finalize()
+}
+
+fn main() {
+    let args : Vec<String> = std::env::args().skip(1).collect();
+
+    let opts =
+        match parse_args(&args) {
+            Ok(opts) => opts,
+            Err(msg) => {
+                if !msg.is_empty() {
+                    eprintln!("Error: {}\n", msg);
+                }
+                eprint!("{}", USAGE);
+                std::process::exit(1);
+            }
+        };
+
+    let mut renderer = OfflineRenderer::new();
+    renderer.set_quality(opts.quality);
+
+    if let Some(preset) = &opts.preset {
+        match std::fs::read(preset) {
+            Ok(data) => renderer.load_preset(&data[..]),
+            Err(e) => {
+                eprintln!("Error: Couldn't read preset '{}': {}", preset, e);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    let samples =
+        renderer.render(opts.srate, &opts.notes[..], opts.gap_ms, opts.tail_ms);
+
+    if let Err(e) = write_wav(&samples, &opts) {
+        eprintln!("Error: Couldn't write '{}': {}", opts.output, e);
+        std::process::exit(1);
+    }
+
+    let peak = samples.iter().fold(0.0_f32, |p, s| p.max(s.abs()));
+    println!("Wrote {} frames ({:.2} s, peak {:.2} dBFS) to {}",
+             samples.len(),
+             samples.len() as f32 / opts.srate as f32,
+             20.0 * peak.max(0.000_001).log10(),
+             opts.output);
+}
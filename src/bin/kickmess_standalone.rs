@@ -0,0 +1,326 @@
+// Copyright (c) 2020-2021 Weird Constructor <weirdconstructor@gmail.com>
+// This is a part of Kickmess. See README.md and COPYING for details.
+
+//! Runs Kickmess without a host: The engine plays on JACK or ALSA,
+//! the editor is opened in its own window and the computer keyboard
+//! plays notes. See also `kickmessvst::standalone`.
+
+use kickmessvst::engine::{Engine, EngineParams};
+use kickmessvst::standalone::{MidiQueue, StandaloneController};
+use std::sync::Arc;
+
+const USAGE : &str =
+"kickmess-standalone [options]
+
+Options:
+    -b, --backend <name>    Audio backend: jack or alsa. By default JACK is
+                            used if a JACK server is running, ALSA otherwise.
+    -d, --device <name>     ALSA PCM device, default: default
+    -p, --preset <file>     Preset file, as saved by the plugin.
+    -h, --help              Show this help
+
+Computer keyboard:
+    Z S X D C ... /         Notes of the lower octave, starting at C2
+    Q 2 W 3 E ... P         Notes of the octave above
+    Arrow up / down         Change the octave
+";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Backend {
+    Auto,
+    Jack,
+    Alsa,
+}
+
+struct Options {
+    backend:    Backend,
+    device:     String,
+    preset:     Option<String>,
+}
+
+fn parse_args(args: &[String]) -> Result<Options, String> {
+    let mut opts = Options {
+        backend: Backend::Auto,
+        device:  "default".to_string(),
+        preset:  None,
+    };
+
+    let mut i = 0;
+    while i < args.len() {
+        let arg = args[i].as_str();
+
+        if arg == "-h" || arg == "--help" {
+            return Err(String::new());
+        }
+
+        let val =
+            args.get(i + 1)
+                .ok_or_else(|| format!("Missing value for {}", arg))?;
+
+        match arg {
+            "-b" | "--backend" => {
+                opts.backend =
+                    match val.as_str() {
+                        "jack" => Backend::Jack,
+                        "alsa" => Backend::Alsa,
+                        _ => return Err(format!("Unknown backend: '{}'", val)),
+                    };
+            },
+            "-d" | "--device" => { opts.device = val.to_string(); },
+            "-p" | "--preset" => { opts.preset = Some(val.to_string()); },
+            _ => return Err(format!("Unknown option: '{}'", arg)),
+        }
+
+        i += 2;
+    }
+
+    Ok(opts)
+}
+
+/// Feeds the notes of the computer keyboard into the engine.
+fn handle_keyboard_notes(engine: &mut Engine, keys: &MidiQueue) {
+    while let Some(msg) = keys.pop() {
+        engine.handle_midi(&msg[..], 0);
+    }
+}
+
+mod jack_backend {
+    use super::*;
+    use jack::{AudioOut, Client, ClientOptions, ClosureProcessHandler,
+               Control, MidiIn, PortFlags, ProcessScope};
+
+    type ProcessFn = Box<dyn FnMut(&Client, &ProcessScope) -> Control + Send>;
+
+    pub struct JackAudio {
+        _client: jack::AsyncClient<(), ClosureProcessHandler<ProcessFn>>,
+    }
+
+    /// Connects to a running JACK server. The output is connected
+    /// to the physical playback ports.
+    pub fn start(mut engine: Engine, keys: MidiQueue) -> Result<JackAudio, jack::Error> {
+        let (client, _status) =
+            Client::new("Kickmess", ClientOptions::NO_START_SERVER)?;
+
+        let mut out = client.register_port("out", AudioOut)?;
+        let midi_in = client.register_port("midi_in", MidiIn)?;
+        let out_name = out.name()?;
+
+        engine.set_sample_rate(client.sample_rate() as f32);
+
+        let process : ProcessFn =
+            Box::new(move |_client: &Client, ps: &ProcessScope| {
+                for ev in midi_in.iter(ps) {
+                    engine.handle_midi(ev.bytes, ev.time as usize);
+                }
+                handle_keyboard_notes(&mut engine, &keys);
+
+                engine.process(out.as_mut_slice(ps));
+
+                Control::Continue
+            });
+
+        let active = client.activate_async((), ClosureProcessHandler::new(process))?;
+
+        let playback =
+            active.as_client().ports(
+                None, Some("32 bit float mono audio"),
+                PortFlags::IS_INPUT | PortFlags::IS_PHYSICAL);
+        for port in playback.iter().take(2) {
+            if let Err(e) = active.as_client().connect_ports_by_name(&out_name, port) {
+                eprintln!("Couldn't connect to '{}': {}", port, e);
+            }
+        }
+
+        Ok(JackAudio { _client: active })
+    }
+}
+
+mod alsa_backend {
+    use super::*;
+    use alsa::{Direction, ValueOr};
+    use alsa::pcm::{Access, Format, HwParams, PCM};
+    use alsa::seq::{EventType, EvNote, PortCap, PortInfo, PortType, Seq};
+    use std::ffi::CString;
+
+    const PERIOD_FRAMES : usize = 256;
+    const CHANNELS      : usize = 2;
+
+    fn open_pcm(device: &str) -> Result<(PCM, u32), alsa::Error> {
+        let pcm = PCM::new(device, Direction::Playback, false)?;
+        let rate = {
+            let hwp = HwParams::any(&pcm)?;
+            hwp.set_channels(CHANNELS as u32)?;
+            hwp.set_rate_near(44100, ValueOr::Nearest)?;
+            hwp.set_format(Format::s16())?;
+            hwp.set_access(Access::RWInterleaved)?;
+            hwp.set_period_size_near(PERIOD_FRAMES as alsa::pcm::Frames, ValueOr::Nearest)?;
+            hwp.set_buffer_size_near(4 * PERIOD_FRAMES as alsa::pcm::Frames)?;
+            pcm.hw_params(&hwp)?;
+            hwp.get_rate()?
+        };
+        pcm.prepare()?;
+
+        Ok((pcm, rate))
+    }
+
+    /// Creates a sequencer port, other clients can connect
+    /// their MIDI outputs to it.
+    fn open_seq() -> Result<Seq, alsa::Error> {
+        let seq = Seq::open(None, Some(Direction::Capture), true)?;
+        seq.set_client_name(&CString::new("Kickmess").unwrap())?;
+
+        let mut port = PortInfo::empty()?;
+        port.set_capability(PortCap::WRITE | PortCap::SUBS_WRITE);
+        port.set_type(PortType::MIDI_GENERIC | PortType::APPLICATION);
+        port.set_name(&CString::new("midi_in").unwrap());
+        seq.create_port(&port)?;
+
+        Ok(seq)
+    }
+
+    fn handle_seq_events(engine: &mut Engine, seq: &Seq) {
+        let mut input = seq.input();
+
+        while let Ok(n) = input.event_input_pending(true) {
+            if n == 0 {
+                break;
+            }
+
+            let ev =
+                match input.event_input() {
+                    Ok(ev) => ev,
+                    Err(_) => break,
+                };
+
+            let status =
+                match ev.get_type() {
+                    EventType::Noteon  => 0x90,
+                    EventType::Noteoff => 0x80,
+                    _ => continue,
+                };
+
+            if let Some(note) = ev.get_data::<EvNote>() {
+                engine.handle_midi(
+                    &[status | (note.channel & 0x0F), note.note, note.velocity],
+                    0);
+            }
+        }
+    }
+
+    /// Plays the engine on the ALSA PCM `device` in a new thread.
+    /// MIDI notes are received with an ALSA sequencer port.
+    pub fn start(device: &str, mut engine: Engine, keys: MidiQueue)
+        -> Result<std::thread::JoinHandle<()>, alsa::Error>
+    {
+        let (pcm, rate) = open_pcm(device)?;
+        engine.set_sample_rate(rate as f32);
+
+        let seq =
+            match open_seq() {
+                Ok(seq) => Some(seq),
+                Err(e) => {
+                    eprintln!("No ALSA MIDI input: {}", e);
+                    None
+                },
+            };
+
+        Ok(std::thread::spawn(move || {
+            let io = pcm.io_i16().expect("an i16 interleaved PCM");
+
+            let mut out = vec![0.0; PERIOD_FRAMES];
+            let mut buf = vec![0_i16; PERIOD_FRAMES * CHANNELS];
+
+            loop {
+                if let Some(seq) = &seq {
+                    handle_seq_events(&mut engine, seq);
+                }
+                handle_keyboard_notes(&mut engine, &keys);
+
+                engine.process(&mut out[..]);
+
+                for (frame, s) in buf.chunks_mut(CHANNELS).zip(out.iter()) {
+                    let s = (s.clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
+                    for ch in frame.iter_mut() { *ch = s; }
+                }
+
+                if let Err(e) = io.writei(&buf[..]) {
+                    if let Err(e) = pcm.try_recover(e, true) {
+                        eprintln!("ALSA playback failed: {}", e);
+                        return;
+                    }
+                }
+            }
+        }))
+    }
+}
+
+fn main() {
+    kickmessvst::log::init_from_env();
+
+    let args : Vec<String> = std::env::args().skip(1).collect();
+
+    let opts =
+        match parse_args(&args) {
+            Ok(opts) => opts,
+            Err(msg) => {
+                if !msg.is_empty() {
+                    eprintln!("Error: {}\n", msg);
+                }
+                eprint!("{}", USAGE);
+                std::process::exit(1);
+            }
+        };
+
+    let params = Arc::new(EngineParams::new());
+
+    if let Some(preset) = &opts.preset {
+        match std::fs::read(preset) {
+            Ok(data) => params.load_preset(&data[..]),
+            Err(e) => {
+                eprintln!("Error: Couldn't read preset '{}': {}", preset, e);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    let keys   = MidiQueue::new();
+    let engine = Engine::new(params.clone());
+
+    let start_alsa = |engine: Engine| {
+        match alsa_backend::start(&opts.device, engine, keys.clone()) {
+            Ok(thread) => thread,
+            Err(e) => {
+                eprintln!("Error: Couldn't open ALSA device '{}': {}", opts.device, e);
+                std::process::exit(1);
+            }
+        }
+    };
+
+    let mut _jack = None;
+    let mut _alsa = None;
+
+    if opts.backend == Backend::Alsa {
+        _alsa = Some(start_alsa(engine));
+
+    } else {
+        match jack_backend::start(engine, keys.clone()) {
+            Ok(audio) => { _jack = Some(audio); },
+            Err(e) if opts.backend == Backend::Auto => {
+                eprintln!("No JACK server ({}), using ALSA", e);
+                _alsa = Some(start_alsa(Engine::new(params.clone())));
+            },
+            Err(e) => {
+                eprintln!("Error: Couldn't start JACK: {}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    let ctrl = Arc::new(StandaloneController::new(params, keys));
+
+    kickmessvst::window::open_window(
+        if cfg!(feature="mega") { "Megamess" } else { "Kickmess" },
+        kickmessvst::editor::WINDOW_WIDTH,
+        kickmessvst::editor::WINDOW_HEIGHT,
+        None, ctrl);
+}
@@ -0,0 +1,993 @@
+// Copyright (c) 2020-2021 Weird Constructor <weirdconstructor@gmail.com>
+// This is a part of Kickmess. See README.md and COPYING for details.
+
+//! CLAP plugin around the same DSP core as the VST plugin.
+//!
+//! The host sees the public parameters of the `param_model!` table with
+//! their index as parameter ID and their plain values (e.g. Hz or ms).
+//! Note and parameter events are applied at their sample offset in the
+//! block. The state is saved in the format of `serialize_preset`.
+//! The delay of the output limiter is reported with the latency extension.
+//! Switching the limiter changes the latency, so the plugin asks the host
+//! for a restart and tells it the new latency in `activate`.
+//!
+//! The `clap_entry` symbol is only exported with the `clap` feature.
+
+use std::os::raw::{c_char, c_void};
+use std::ffi::CStr;
+use std::cell::UnsafeCell;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+use clap_sys::audio_buffer::clap_audio_buffer;
+use clap_sys::entry::clap_plugin_entry;
+use clap_sys::events::*;
+use clap_sys::ext::audio_ports::*;
+use clap_sys::ext::latency::*;
+use clap_sys::ext::note_ports::*;
+use clap_sys::ext::params::*;
+use clap_sys::ext::state::*;
+use clap_sys::factory::plugin_factory::*;
+use clap_sys::host::clap_host;
+use clap_sys::id::{clap_id, CLAP_INVALID_ID};
+use clap_sys::plugin::*;
+use clap_sys::process::*;
+use clap_sys::stream::*;
+use clap_sys::version::CLAP_VERSION;
+
+use crate::proc::ParamProvider;
+use crate::param_model::{pid, ParamInfo, PUB_PARAM_COUNT};
+use crate::ui::protocol::UIValueSpec;
+use crate::engine::{Engine, EngineParams};
+
+#[cfg(not(feature="mega"))]
+macro_rules! plugin_id { () => { "com.weirdconstructor.kickmess" } }
+#[cfg(feature="mega")]
+macro_rules! plugin_id { () => { "com.weirdconstructor.megamess" } }
+
+pub const PLUGIN_ID   : &str = plugin_id!();
+pub const PLUGIN_NAME : &str = if cfg!(feature="mega") { "Megamess" } else { "Kickmess" };
+
+macro_rules! c_str { ($s: expr) => { concat!($s, "\0").as_ptr() as *const c_char } }
+
+/// Null terminated list of the CLAP plugin features.
+struct Features([*const c_char; 5]);
+unsafe impl Sync for Features {}
+
+static FEATURES : Features = Features([
+    c_str!("instrument"),
+    c_str!("synthesizer"),
+    c_str!("drum"),
+    c_str!("mono"),
+    std::ptr::null(),
+]);
+
+static DESCRIPTOR : clap_plugin_descriptor = clap_plugin_descriptor {
+    clap_version: CLAP_VERSION,
+    id:           c_str!(plugin_id!()),
+    name:         if cfg!(feature="mega") { c_str!("Megamess") } else { c_str!("Kickmess") },
+    vendor:       c_str!("Weird Constructor"),
+    url:          c_str!("https://github.com/WeirdConstructor/kickmess"),
+    manual_url:   c_str!("https://github.com/WeirdConstructor/kickmess"),
+    support_url:  c_str!("https://github.com/WeirdConstructor/kickmess/issues"),
+    version:      c_str!(env!("CARGO_PKG_VERSION")),
+    description:  c_str!("Kickmess - Port of LMMS Kicker Plugin"),
+    features:     &FEATURES as *const Features as *const *const c_char,
+};
+
+static FACTORY : clap_plugin_factory = clap_plugin_factory {
+    get_plugin_count:      Some(factory_get_plugin_count),
+    get_plugin_descriptor: Some(factory_get_plugin_descriptor),
+    create_plugin:         Some(factory_create_plugin),
+};
+
+static PARAMS_EXT : clap_plugin_params = clap_plugin_params {
+    count:         Some(params_count),
+    get_info:      Some(params_get_info),
+    get_value:     Some(params_get_value),
+    value_to_text: Some(params_value_to_text),
+    text_to_value: Some(params_text_to_value),
+    flush:         Some(params_flush),
+};
+
+static NOTE_PORTS_EXT : clap_plugin_note_ports = clap_plugin_note_ports {
+    count: Some(note_ports_count),
+    get:   Some(note_ports_get),
+};
+
+static AUDIO_PORTS_EXT : clap_plugin_audio_ports = clap_plugin_audio_ports {
+    count: Some(audio_ports_count),
+    get:   Some(audio_ports_get),
+};
+
+static STATE_EXT : clap_plugin_state = clap_plugin_state {
+    save: Some(state_save),
+    load: Some(state_load),
+};
+
+static LATENCY_EXT : clap_plugin_latency = clap_plugin_latency {
+    get: Some(latency_get),
+};
+
+const ENTRY : clap_plugin_entry = clap_plugin_entry {
+    clap_version: CLAP_VERSION,
+    init:         Some(entry_init),
+    deinit:       Some(entry_deinit),
+    get_factory:  Some(entry_get_factory),
+};
+
+/// Returns the entry, that is exported as `clap_entry`.
+pub fn entry() -> &'static clap_plugin_entry { &ENTRY }
+
+#[cfg(feature="clap")]
+#[no_mangle]
+#[allow(non_upper_case_globals)]
+pub static clap_entry : clap_plugin_entry = ENTRY;
+
+/// The part of the plugin, that the main and the audio
+/// thread may both read at the same time.
+struct Shared {
+    host:    *const clap_host,
+    params:  Arc<EngineParams>,
+    /// The latency of the engine at the sample rate of `activate`.
+    latency: AtomicUsize,
+    /// Set by `process`, when the latency changed until the next `activate`.
+    restart_requested: AtomicBool,
+    /// The names, modules and units of the parameters, by their id.
+    infos:   Vec<ParamInfo>,
+    /// Formats the parameter values like the editor does.
+    value_specs: Vec<UIValueSpec>,
+}
+
+impl Shared {
+    /// Converts the plain `value` of the parameter to the normalized
+    /// value, returns `None` for ids the host doesn't know about.
+    fn unmap(&self, id: clap_id, value: f64) -> Option<f32> {
+        if id as usize >= PUB_PARAM_COUNT {
+            return None;
+        }
+        let pd = self.params.ps().definition(id as usize)?;
+        Some(pd.unmap(value as f32))
+    }
+}
+
+struct ClapKickmess {
+    plugin: clap_plugin,
+    shared: Shared,
+    /// Only `activate` and `process` touch the engine, the host
+    /// never calls them at the same time.
+    engine: UnsafeCell<Engine>,
+}
+
+impl ClapKickmess {
+    fn new(host: *const clap_host) -> Box<Self> {
+        let params = Arc::new(EngineParams::new());
+        let engine = Engine::new(params.clone());
+
+        let mut this = Box::new(Self {
+            plugin: clap_plugin {
+                desc:             &DESCRIPTOR,
+                plugin_data:      std::ptr::null_mut(),
+                init:             Some(plugin_init),
+                destroy:          Some(plugin_destroy),
+                activate:         Some(plugin_activate),
+                deactivate:       Some(plugin_deactivate),
+                start_processing: Some(plugin_start_processing),
+                stop_processing:  Some(plugin_stop_processing),
+                reset:            Some(plugin_reset),
+                process:          Some(plugin_process),
+                get_extension:    Some(plugin_get_extension),
+                on_main_thread:   Some(plugin_on_main_thread),
+            },
+            shared: Shared {
+                host,
+                latency:     AtomicUsize::new(engine.latency()),
+                restart_requested: AtomicBool::new(false),
+                infos:       crate::param_model::param_infos(),
+                value_specs: crate::editor::param_value_specs(params.ps()),
+                params,
+            },
+            engine: UnsafeCell::new(engine),
+        });
+
+        this.plugin.plugin_data = &mut *this as *mut Self as *mut c_void;
+        this
+    }
+
+    /// Handles a note, MIDI or parameter event at its offset in the block.
+    unsafe fn handle_event(&self, engine: &mut Engine, hdr: *const clap_event_header) {
+        if (*hdr).space_id != CLAP_CORE_EVENT_SPACE_ID {
+            return;
+        }
+
+        let time = (*hdr).time as usize;
+
+        match (*hdr).type_ {
+            CLAP_EVENT_NOTE_ON | CLAP_EVENT_NOTE_OFF => {
+                let ev = &*(hdr as *const clap_event_note);
+                if ev.key < 0 || ev.key > 127 {
+                    return;
+                }
+
+                // Channel -1 is a wildcard for all channels:
+                let note_chan =
+                    if ev.channel < 0 {
+                        self.shared.params.ps().get(
+                            pid::midi_chan, &*self.shared.params).floor() as u8
+                    } else {
+                        (ev.channel & 0x0F) as u8
+                    };
+                let data =
+                    if (*hdr).type_ == CLAP_EVENT_NOTE_ON {
+                        let vel = (ev.velocity * 127.0).round().max(1.0).min(127.0);
+                        [0x90 | note_chan, ev.key as u8, vel as u8]
+                    } else {
+                        [0x80 | note_chan, ev.key as u8, 0]
+                    };
+                engine.handle_midi(&data, time);
+            },
+            CLAP_EVENT_MIDI => {
+                let ev = &*(hdr as *const clap_event_midi);
+                engine.handle_midi(&ev.data, time);
+            },
+            CLAP_EVENT_PARAM_VALUE => {
+                let ev = &*(hdr as *const clap_event_param_value);
+                if let Some(p) = self.shared.unmap(ev.param_id, ev.value) {
+                    engine.set_param_at(ev.param_id as usize, p, time);
+                }
+            },
+            _ => (),
+        }
+    }
+
+    /// Runs on the audio thread, the only place besides
+    /// `activate` that borrows the engine mutably.
+    unsafe fn process(&self, process: &clap_process) -> clap_process_status {
+        if process.audio_outputs_count < 1 || process.audio_outputs.is_null() {
+            return CLAP_PROCESS_ERROR;
+        }
+
+        let out_bus = &mut *process.audio_outputs;
+        if out_bus.channel_count < 1 || out_bus.data32.is_null() {
+            return CLAP_PROCESS_ERROR;
+        }
+        out_bus.constant_mask = 0;
+
+        let engine = &mut *self.engine.get();
+
+        let in_events = process.in_events;
+        if !in_events.is_null() {
+            if let (Some(size), Some(get)) = ((*in_events).size, (*in_events).get) {
+                for i in 0..size(in_events) {
+                    self.handle_event(engine, get(in_events, i));
+                }
+            }
+        }
+
+        let nframes = process.frames_count as usize;
+        let out     = std::slice::from_raw_parts_mut(*out_bus.data32, nframes);
+        engine.process(out);
+
+        // The host reactivates the plugin to take over the new latency:
+        if engine.latency() != self.shared.latency.load(Ordering::Relaxed)
+           && !self.shared.restart_requested.swap(true, Ordering::Relaxed)
+        {
+            if let Some(request_restart) = (*self.shared.host).request_restart {
+                request_restart(self.shared.host);
+            }
+        }
+
+        // Mono output, other channels of the port get a copy:
+        for ch in 1..(out_bus.channel_count as usize) {
+            let dst = *out_bus.data32.add(ch);
+            if !dst.is_null() {
+                std::ptr::copy_nonoverlapping(out.as_ptr(), dst, nframes);
+            }
+        }
+
+        CLAP_PROCESS_CONTINUE
+    }
+}
+
+/// The plugin is shared between the main and the audio thread,
+/// so it is only handed out by shared reference.
+unsafe fn instance<'a>(plugin: *const clap_plugin) -> &'a ClapKickmess {
+    &*((*plugin).plugin_data as *const ClapKickmess)
+}
+
+/// Copies `s` into the C string buffer `buf` with the
+/// capacity `cap`, truncating it if necessary.
+unsafe fn write_c_str(s: &str, buf: *mut c_char, cap: usize) {
+    if cap == 0 {
+        return;
+    }
+
+    let len = s.len().min(cap - 1);
+    std::ptr::copy_nonoverlapping(s.as_ptr() as *const c_char, buf, len);
+    *buf.add(len) = 0;
+}
+
+unsafe extern "C" fn entry_init(_plugin_path: *const c_char) -> bool { true }
+
+unsafe extern "C" fn entry_deinit() { }
+
+unsafe extern "C" fn entry_get_factory(factory_id: *const c_char) -> *const c_void {
+    if CStr::from_ptr(factory_id) == CLAP_PLUGIN_FACTORY_ID {
+        &FACTORY as *const clap_plugin_factory as *const c_void
+    } else {
+        std::ptr::null()
+    }
+}
+
+unsafe extern "C" fn factory_get_plugin_count(_factory: *const clap_plugin_factory) -> u32 {
+    1
+}
+
+unsafe extern "C" fn factory_get_plugin_descriptor(
+    _factory: *const clap_plugin_factory, index: u32) -> *const clap_plugin_descriptor
+{
+    if index == 0 { &DESCRIPTOR } else { std::ptr::null() }
+}
+
+unsafe extern "C" fn factory_create_plugin(
+    _factory: *const clap_plugin_factory, host: *const clap_host,
+    plugin_id: *const c_char) -> *const clap_plugin
+{
+    if host.is_null() || plugin_id.is_null()
+       || CStr::from_ptr(plugin_id).to_bytes() != PLUGIN_ID.as_bytes()
+    {
+        return std::ptr::null();
+    }
+
+    let plugin = Box::into_raw(ClapKickmess::new(host));
+    &(*plugin).plugin
+}
+
+unsafe extern "C" fn plugin_init(_plugin: *const clap_plugin) -> bool {
+    crate::log::init_from_env();
+    klog!(Host, Info, "clap init, version {}", crate::VERSION);
+    true
+}
+
+unsafe extern "C" fn plugin_destroy(plugin: *const clap_plugin) {
+    drop(Box::from_raw((*plugin).plugin_data as *mut ClapKickmess));
+}
+
+unsafe extern "C" fn plugin_activate(
+    plugin: *const clap_plugin, sample_rate: f64,
+    _min_frames_count: u32, _max_frames_count: u32) -> bool
+{
+    // The host doesn't call `process` while the plugin is deactivated:
+    let plugin = instance(plugin);
+    let engine = &mut *plugin.engine.get();
+    engine.set_sample_rate(sample_rate as f32);
+
+    let latency = engine.latency();
+    let changed = plugin.shared.latency.swap(latency, Ordering::Relaxed) != latency;
+    plugin.shared.restart_requested.store(false, Ordering::Relaxed);
+
+    let host = plugin.shared.host;
+    if changed {
+        if let Some(get_extension) = (*host).get_extension {
+            let ext = get_extension(host, CLAP_EXT_LATENCY.as_ptr()) as *const clap_host_latency;
+            if let Some(latency_changed) = ext.as_ref().and_then(|ext| ext.changed) {
+                latency_changed(host);
+            }
+        }
+    }
+
+    true
+}
+
+unsafe extern "C" fn plugin_deactivate(_plugin: *const clap_plugin) { }
+
+unsafe extern "C" fn plugin_start_processing(_plugin: *const clap_plugin) -> bool { true }
+
+unsafe extern "C" fn plugin_stop_processing(_plugin: *const clap_plugin) { }
+
+unsafe extern "C" fn plugin_reset(_plugin: *const clap_plugin) { }
+
+unsafe extern "C" fn plugin_process(
+    plugin: *const clap_plugin, process: *const clap_process) -> clap_process_status
+{
+    instance(plugin).process(&*process)
+}
+
+unsafe extern "C" fn plugin_get_extension(
+    _plugin: *const clap_plugin, id: *const c_char) -> *const c_void
+{
+    let id = CStr::from_ptr(id);
+
+    if id == CLAP_EXT_PARAMS {
+        &PARAMS_EXT as *const clap_plugin_params as *const c_void
+    } else if id == CLAP_EXT_NOTE_PORTS {
+        &NOTE_PORTS_EXT as *const clap_plugin_note_ports as *const c_void
+    } else if id == CLAP_EXT_AUDIO_PORTS {
+        &AUDIO_PORTS_EXT as *const clap_plugin_audio_ports as *const c_void
+    } else if id == CLAP_EXT_STATE {
+        &STATE_EXT as *const clap_plugin_state as *const c_void
+    } else if id == CLAP_EXT_LATENCY {
+        &LATENCY_EXT as *const clap_plugin_latency as *const c_void
+    } else {
+        std::ptr::null()
+    }
+}
+
+unsafe extern "C" fn plugin_on_main_thread(_plugin: *const clap_plugin) { }
+
+/// The latency of the output limiter, at the time of `activate`.
+unsafe extern "C" fn latency_get(plugin: *const clap_plugin) -> u32 {
+    instance(plugin).shared.latency.load(Ordering::Relaxed) as u32
+}
+
+unsafe extern "C" fn params_count(plugin: *const clap_plugin) -> u32 {
+    instance(plugin).shared.params.public_ps().param_count() as u32
+}
+
+unsafe extern "C" fn params_get_info(
+    plugin: *const clap_plugin, param_index: u32, param_info: *mut clap_param_info) -> bool
+{
+    let plugin = &instance(plugin).shared;
+    let pd =
+        if let Some(pd) = plugin.params.public_ps().definition(param_index as usize) { pd }
+        else { return false; };
+
+    let pi   = &plugin.infos[pd.idx()];
+    let name = if pi.title.is_empty() { pi.label } else { pi.title };
+
+    let info = &mut *param_info;
+    info.id            = pd.idx() as clap_id;
+    info.flags         = CLAP_PARAM_IS_AUTOMATABLE;
+    info.cookie        = std::ptr::null_mut();
+    info.min_value     = pi.min.min(pi.max) as f64;
+    info.max_value     = pi.min.max(pi.max) as f64;
+    info.default_value = pd.map(pd.default_p()) as f64;
+    write_c_str(name,      info.name.as_mut_ptr(),   info.name.len());
+    write_c_str(pi.module, info.module.as_mut_ptr(), info.module.len());
+    true
+}
+
+unsafe extern "C" fn params_get_value(
+    plugin: *const clap_plugin, param_id: clap_id, out_value: *mut f64) -> bool
+{
+    let plugin = &instance(plugin).shared;
+    if param_id as usize >= PUB_PARAM_COUNT {
+        return false;
+    }
+
+    if let Some(pd) = plugin.params.ps().definition(param_id as usize) {
+        *out_value = pd.map(plugin.params.param(pd.idx())) as f64;
+        true
+    } else {
+        false
+    }
+}
+
+unsafe extern "C" fn params_value_to_text(
+    plugin: *const clap_plugin, param_id: clap_id, value: f64,
+    out_buffer: *mut c_char, out_buffer_capacity: u32) -> bool
+{
+    let plugin = &instance(plugin).shared;
+    let p =
+        if let Some(p) = plugin.unmap(param_id, value) { p }
+        else { return false; };
+
+    let text = plugin.value_specs[param_id as usize].fmt_with_unit(p as f64);
+    write_c_str(&text, out_buffer, out_buffer_capacity as usize);
+    true
+}
+
+unsafe extern "C" fn params_text_to_value(
+    plugin: *const clap_plugin, param_id: clap_id,
+    param_value_text: *const c_char, out_value: *mut f64) -> bool
+{
+    if instance(plugin).shared.unmap(param_id, 0.0).is_none() {
+        return false;
+    }
+
+    match CStr::from_ptr(param_value_text).to_string_lossy().trim().parse::<f64>() {
+        Ok(v)  => { *out_value = v; true },
+        Err(_) => false,
+    }
+}
+
+/// Takes over parameter changes while the plugin is not processing.
+unsafe extern "C" fn params_flush(
+    plugin: *const clap_plugin, in_: *const clap_input_events,
+    _out: *const clap_output_events)
+{
+    let plugin = &instance(plugin).shared;
+    if in_.is_null() {
+        return;
+    }
+
+    if let (Some(size), Some(get)) = ((*in_).size, (*in_).get) {
+        for i in 0..size(in_) {
+            let hdr = get(in_, i);
+            if (*hdr).space_id == CLAP_CORE_EVENT_SPACE_ID
+               && (*hdr).type_ == CLAP_EVENT_PARAM_VALUE
+            {
+                let ev = &*(hdr as *const clap_event_param_value);
+                if let Some(p) = plugin.unmap(ev.param_id, ev.value) {
+                    plugin.params.set(ev.param_id as usize, p);
+                }
+            }
+        }
+    }
+}
+
+unsafe extern "C" fn note_ports_count(_plugin: *const clap_plugin, is_input: bool) -> u32 {
+    if is_input { 1 } else { 0 }
+}
+
+unsafe extern "C" fn note_ports_get(
+    _plugin: *const clap_plugin, index: u32, is_input: bool,
+    info: *mut clap_note_port_info) -> bool
+{
+    if !is_input || index != 0 {
+        return false;
+    }
+
+    let info = &mut *info;
+    info.id                 = 0;
+    info.supported_dialects = CLAP_NOTE_DIALECT_CLAP | CLAP_NOTE_DIALECT_MIDI;
+    info.preferred_dialect  = CLAP_NOTE_DIALECT_CLAP;
+    write_c_str("MIDI In", info.name.as_mut_ptr(), info.name.len());
+    true
+}
+
+unsafe extern "C" fn audio_ports_count(_plugin: *const clap_plugin, is_input: bool) -> u32 {
+    if is_input { 0 } else { 1 }
+}
+
+unsafe extern "C" fn audio_ports_get(
+    _plugin: *const clap_plugin, index: u32, is_input: bool,
+    info: *mut clap_audio_port_info) -> bool
+{
+    if is_input || index != 0 {
+        return false;
+    }
+
+    let info = &mut *info;
+    info.id            = 0;
+    info.flags         = CLAP_AUDIO_PORT_IS_MAIN;
+    info.channel_count = 1;
+    info.port_type     = CLAP_PORT_MONO.as_ptr();
+    info.in_place_pair = CLAP_INVALID_ID;
+    write_c_str("Out", info.name.as_mut_ptr(), info.name.len());
+    true
+}
+
+unsafe extern "C" fn state_save(plugin: *const clap_plugin, stream: *const clap_ostream) -> bool {
+    let plugin = &instance(plugin).shared;
+    let write  =
+        if let Some(write) = (*stream).write { write }
+        else { return false; };
+
+    let data = plugin.params.serialize_preset();
+
+    // The host may take less than the whole buffer at once:
+    let mut written = 0;
+    while written < data.len() {
+        let len =
+            write(stream,
+                  data[written..].as_ptr() as *const c_void,
+                  (data.len() - written) as u64);
+        if len <= 0 {
+            return false;
+        }
+        written += len as usize;
+    }
+
+    true
+}
+
+unsafe extern "C" fn state_load(plugin: *const clap_plugin, stream: *const clap_istream) -> bool {
+    let plugin = &instance(plugin).shared;
+    let read   =
+        if let Some(read) = (*stream).read { read }
+        else { return false; };
+
+    let mut data = vec![];
+    let mut buf  = [0_u8; 4096];
+    loop {
+        let len = read(stream, buf.as_mut_ptr() as *mut c_void, buf.len() as u64);
+        if len < 0 {
+            return false;
+        } else if len == 0 {
+            break;
+        }
+        data.extend_from_slice(&buf[0..(len as usize)]);
+    }
+
+    plugin.params.load_preset(&data[..]);
+
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct TestEvents(Vec<*const clap_event_header>);
+
+    /// Counts the requests of the plugin to the host.
+    #[derive(Default)]
+    struct TestHostData {
+        restarts:        AtomicUsize,
+        latency_changes: AtomicUsize,
+    }
+
+    static TEST_HOST_LATENCY : clap_host_latency = clap_host_latency {
+        changed: Some(test_latency_changed),
+    };
+
+    unsafe fn host_data<'a>(host: *const clap_host) -> &'a TestHostData {
+        &*((*host).host_data as *const TestHostData)
+    }
+
+    unsafe extern "C" fn test_get_extension(
+        _host: *const clap_host, id: *const c_char) -> *const c_void
+    {
+        if CStr::from_ptr(id) == CLAP_EXT_LATENCY {
+            &TEST_HOST_LATENCY as *const clap_host_latency as *const c_void
+        } else {
+            std::ptr::null()
+        }
+    }
+
+    unsafe extern "C" fn test_latency_changed(host: *const clap_host) {
+        host_data(host).latency_changes.fetch_add(1, Ordering::Relaxed);
+    }
+
+    unsafe extern "C" fn test_request_restart(host: *const clap_host) {
+        host_data(host).restarts.fetch_add(1, Ordering::Relaxed);
+    }
+
+    unsafe extern "C" fn test_request(_host: *const clap_host) { }
+
+    /// A host, that lives as long as the test.
+    fn test_host() -> *const clap_host {
+        let data = Box::leak(Box::new(TestHostData::default()));
+        Box::leak(Box::new(clap_host {
+            clap_version:     CLAP_VERSION,
+            host_data:        data as *mut TestHostData as *mut c_void,
+            name:             c_str!("Test Host"),
+            vendor:           c_str!(""),
+            url:              c_str!(""),
+            version:          c_str!("1.0"),
+            get_extension:    Some(test_get_extension),
+            request_restart:  Some(test_request_restart),
+            request_process:  Some(test_request),
+            request_callback: Some(test_request),
+        }))
+    }
+
+    unsafe extern "C" fn test_events_size(list: *const clap_input_events) -> u32 {
+        let events = &*((*list).ctx as *const TestEvents);
+        events.0.len() as u32
+    }
+
+    unsafe extern "C" fn test_events_get(
+        list: *const clap_input_events, index: u32) -> *const clap_event_header
+    {
+        let events = &*((*list).ctx as *const TestEvents);
+        events.0[index as usize]
+    }
+
+    unsafe extern "C" fn test_write(
+        stream: *const clap_ostream, buffer: *const c_void, size: u64) -> i64
+    {
+        // Take at most 7 bytes at once, to check that the plugin writes everything:
+        let len = size.min(7) as usize;
+        let out = &mut *((*stream).ctx as *mut Vec<u8>);
+        out.extend_from_slice(std::slice::from_raw_parts(buffer as *const u8, len));
+        len as i64
+    }
+
+    unsafe extern "C" fn test_read(
+        stream: *const clap_istream, buffer: *mut c_void, size: u64) -> i64
+    {
+        let data = &mut *((*stream).ctx as *mut Vec<u8>);
+        let len  = data.len().min(size as usize);
+        std::ptr::copy_nonoverlapping(data.as_ptr(), buffer as *mut u8, len);
+        data.drain(0..len);
+        len as i64
+    }
+
+    fn header<T>(type_: clap_event_type, time: u32) -> clap_event_header {
+        clap_event_header {
+            size:     std::mem::size_of::<T>() as u32,
+            time,
+            space_id: CLAP_CORE_EVENT_SPACE_ID,
+            type_,
+            flags:    0,
+        }
+    }
+
+    fn note_on(time: u32, key: i16) -> clap_event_note {
+        clap_event_note {
+            header:     header::<clap_event_note>(CLAP_EVENT_NOTE_ON, time),
+            note_id:    -1,
+            port_index: 0,
+            channel:    -1,
+            key,
+            velocity:   1.0,
+        }
+    }
+
+    fn param_value(time: u32, param_id: usize, value: f64) -> clap_event_param_value {
+        clap_event_param_value {
+            header:     header::<clap_event_param_value>(CLAP_EVENT_PARAM_VALUE, time),
+            param_id:   param_id as clap_id,
+            cookie:     std::ptr::null_mut(),
+            note_id:    -1,
+            port_index: -1,
+            channel:    -1,
+            key:        -1,
+            value,
+        }
+    }
+
+    fn factory() -> &'static clap_plugin_factory {
+        unsafe {
+            assert!((entry().init.unwrap())(c_str!("")));
+            let factory =
+                (entry().get_factory.unwrap())(CLAP_PLUGIN_FACTORY_ID.as_ptr());
+            assert!(!factory.is_null());
+            &*(factory as *const clap_plugin_factory)
+        }
+    }
+
+    fn create_plugin() -> *const clap_plugin {
+        let factory = factory();
+        unsafe {
+            let id     = concat!(plugin_id!(), "\0").as_ptr() as *const c_char;
+            let plugin = (factory.create_plugin.unwrap())(factory, test_host(), id);
+            assert!(!plugin.is_null());
+            assert!(((*plugin).init.unwrap())(plugin));
+            assert!(((*plugin).activate.unwrap())(plugin, 44100.0, 1, 512));
+            plugin
+        }
+    }
+
+    unsafe fn extension<T>(plugin: *const clap_plugin, id: &CStr) -> &'static T {
+        let ext = ((*plugin).get_extension.unwrap())(plugin, id.as_ptr());
+        assert!(!ext.is_null(), "extension {:?} missing", id);
+        &*(ext as *const T)
+    }
+
+    /// Runs one block with the `events` and returns the output.
+    unsafe fn process(
+        plugin: *const clap_plugin, nframes: usize,
+        events: Vec<*const clap_event_header>) -> Vec<f32>
+    {
+        let mut out     = vec![0.0_f32; nframes];
+        let mut out_ptr = out.as_mut_ptr();
+        let mut bus = clap_audio_buffer {
+            data32:        &mut out_ptr,
+            data64:        std::ptr::null_mut(),
+            channel_count: 1,
+            latency:       0,
+            constant_mask: 0,
+        };
+
+        let events = TestEvents(events);
+        let in_events = clap_input_events {
+            ctx:  &events as *const TestEvents as *mut c_void,
+            size: Some(test_events_size),
+            get:  Some(test_events_get),
+        };
+
+        let process = clap_process {
+            steady_time:         -1,
+            frames_count:        nframes as u32,
+            transport:           std::ptr::null(),
+            audio_inputs:        std::ptr::null(),
+            audio_outputs:       &mut bus,
+            audio_inputs_count:  0,
+            audio_outputs_count: 1,
+            in_events:           &in_events,
+            out_events:          std::ptr::null(),
+        };
+
+        let status = ((*plugin).process.unwrap())(plugin, &process);
+        assert_eq!(status, CLAP_PROCESS_CONTINUE);
+        out
+    }
+
+    #[test]
+    fn check_clap_factory() {
+        let factory = factory();
+        unsafe {
+            assert_eq!((factory.get_plugin_count.unwrap())(factory), 1);
+            let desc = (factory.get_plugin_descriptor.unwrap())(factory, 0);
+            assert_eq!(CStr::from_ptr((*desc).id).to_str().unwrap(), PLUGIN_ID);
+            assert_eq!(CStr::from_ptr((*desc).name).to_str().unwrap(), PLUGIN_NAME);
+            assert_eq!(CStr::from_ptr(*(*desc).features).to_str().unwrap(), "instrument");
+
+            let plugin =
+                (factory.create_plugin.unwrap())(
+                    factory, std::ptr::null(), c_str!("com.example.other"));
+            assert!(plugin.is_null());
+        }
+    }
+
+    #[test]
+    fn check_clap_params_info() {
+        let plugin = create_plugin();
+        let params : &clap_plugin_params = unsafe { extension(plugin, CLAP_EXT_PARAMS) };
+
+        let engine_params = EngineParams::new();
+        let ps            = engine_params.ps();
+        let infos         = crate::param_model::param_infos();
+
+        unsafe {
+            let count = (params.count.unwrap())(plugin);
+            assert_eq!(count as usize, engine_params.public_ps().param_count());
+
+            let mut ids = vec![];
+            for i in 0..count {
+                let mut info : clap_param_info = std::mem::zeroed();
+                assert!((params.get_info.unwrap())(plugin, i, &mut info));
+
+                let pd = ps.definition(info.id as usize).unwrap();
+                let pi = &infos[pd.idx()];
+                let name   = CStr::from_ptr(info.name.as_ptr()).to_str().unwrap();
+                let module = CStr::from_ptr(info.module.as_ptr()).to_str().unwrap();
+                assert_eq!(name, pi.title);
+                assert_eq!(module, pi.module);
+                assert!(!module.is_empty(), "no module for {}", pi.symbol);
+                assert!(info.min_value <= info.default_value);
+                assert!(info.default_value <= info.max_value);
+
+                let mut value = 0.0;
+                assert!((params.get_value.unwrap())(plugin, info.id, &mut value));
+                assert_eq!(value, info.default_value);
+
+                assert!(!ids.contains(&info.id), "duplicate id {}", info.id);
+                ids.push(info.id);
+            }
+
+            let mut info : clap_param_info = std::mem::zeroed();
+            assert!(!(params.get_info.unwrap())(plugin, count, &mut info));
+
+            let mut text = [0 as c_char; 64];
+            assert!((params.value_to_text.unwrap())(
+                plugin, pid::freq_start as clap_id, 150.0, text.as_mut_ptr(), 64));
+            assert_eq!(CStr::from_ptr(text.as_ptr()).to_str().unwrap(), "150.00 Hz");
+            assert!((params.value_to_text.unwrap())(
+                plugin, pid::dist_on as clap_id, 1.0, text.as_mut_ptr(), 64));
+            assert_eq!(CStr::from_ptr(text.as_ptr()).to_str().unwrap(), "On");
+
+            let mut value = 0.0;
+            assert!((params.text_to_value.unwrap())(
+                plugin, pid::main_gain as clap_id, c_str!(" 0.5 "), &mut value));
+            assert_eq!(value, 0.5);
+
+            // The private parameters are not listed, so the host can't use them:
+            let private = PUB_PARAM_COUNT as clap_id;
+            assert!(!(params.get_value.unwrap())(plugin, private, &mut value));
+            assert!(!(params.value_to_text.unwrap())(
+                plugin, private, 1.0, text.as_mut_ptr(), 64));
+            assert!(!(params.text_to_value.unwrap())(
+                plugin, private, c_str!("1.0"), &mut value));
+
+            ((*plugin).destroy.unwrap())(plugin);
+        }
+    }
+
+    #[test]
+    fn check_clap_process_and_state() {
+        let plugin = create_plugin();
+
+        let dist_on = param_value(0, pid::dist_on, 1.0);
+        let note    = note_on(10, 36);
+
+        let out = unsafe {
+            process(plugin, 512, vec![
+                &dist_on.header as *const clap_event_header,
+                &note.header    as *const clap_event_header,
+            ])
+        };
+
+        assert!(out[0..10].iter().all(|s| *s == 0.0));
+        assert!(out[10..].iter().any(|s| s.abs() > 0.01));
+
+        let params : &clap_plugin_params = unsafe { extension(plugin, CLAP_EXT_PARAMS) };
+        let mut value = 0.0;
+        unsafe {
+            assert!((params.get_value.unwrap())(plugin, pid::dist_on as clap_id, &mut value));
+        }
+        assert_eq!(value, 1.0);
+
+        let state : &clap_plugin_state = unsafe { extension(plugin, CLAP_EXT_STATE) };
+
+        let mut saved : Vec<u8> = vec![];
+        let ostream = clap_ostream {
+            ctx:   &mut saved as *mut Vec<u8> as *mut c_void,
+            write: Some(test_write),
+        };
+        assert!(unsafe { (state.save.unwrap())(plugin, &ostream) });
+        assert!(saved.len() > 7);
+
+        let plugin2 = create_plugin();
+        let istream = clap_istream {
+            ctx:  &mut saved.clone() as *mut Vec<u8> as *mut c_void,
+            read: Some(test_read),
+        };
+        assert!(unsafe { (state.load.unwrap())(plugin2, &istream) });
+
+        unsafe {
+            let p1 = &instance(plugin).shared.params;
+            let p2 = &instance(plugin2).shared.params;
+            assert_eq!(p2.param(pid::dist_on), 1.0);
+            assert_eq!(p1.serialize_preset(), p2.serialize_preset());
+
+            ((*plugin).destroy.unwrap())(plugin);
+            ((*plugin2).destroy.unwrap())(plugin2);
+        }
+    }
+
+    #[test]
+    fn check_clap_param_event_is_sample_accurate() {
+        let plugin     = create_plugin();
+        let reference  = create_plugin();
+
+        let note     = note_on(0, 36);
+        let gain_off = param_value(100, pid::main_gain, 0.0);
+
+        let (out, ref_out) = unsafe {
+            (process(plugin, 512, vec![
+                &note.header     as *const clap_event_header,
+                &gain_off.header as *const clap_event_header,
+             ]),
+             process(reference, 512, vec![
+                &note.header     as *const clap_event_header,
+             ]))
+        };
+
+        let latency : &clap_plugin_latency = unsafe { extension(plugin, CLAP_EXT_LATENCY) };
+        let lat = unsafe { (latency.get.unwrap())(plugin) } as usize;
+
+        // Up to the change the output is the same, after it the
+        // smoothed gain goes down. Both are delayed by the latency:
+        assert_eq!(out[0..(100 + lat)], ref_out[0..(100 + lat)]);
+        assert_ne!(out[100 + lat], ref_out[100 + lat]);
+        let peak = |s: &[f32]| s.iter().fold(0.0_f32, |p, s| p.max(s.abs()));
+        assert!(peak(&out[400..]) < peak(&ref_out[400..]) * 0.1);
+
+        unsafe {
+            ((*plugin).destroy.unwrap())(plugin);
+            ((*reference).destroy.unwrap())(reference);
+        }
+    }
+
+    #[test]
+    fn check_clap_latency_follows_limiter() {
+        let plugin  = create_plugin();
+        let latency : &clap_plugin_latency = unsafe { extension(plugin, CLAP_EXT_LATENCY) };
+
+        unsafe {
+            let host = instance(plugin).shared.host;
+            assert_eq!((latency.get.unwrap())(plugin), 0);
+
+            // The limiter is switched on in the editor:
+            instance(plugin).shared.params.set(pid::limiter_on, 1.0);
+            process(plugin, 64, vec![]);
+            process(plugin, 64, vec![]);
+            assert_eq!(host_data(host).restarts.load(Ordering::Relaxed), 1);
+            assert_eq!((latency.get.unwrap())(plugin), 0);
+
+            ((*plugin).deactivate.unwrap())(plugin);
+            assert!(((*plugin).activate.unwrap())(plugin, 44100.0, 1, 512));
+            assert_eq!(host_data(host).latency_changes.load(Ordering::Relaxed), 1);
+            assert!((latency.get.unwrap())(plugin) >= 66);
+
+            process(plugin, 64, vec![]);
+            assert_eq!(host_data(host).restarts.load(Ordering::Relaxed), 1);
+
+            ((*plugin).destroy.unwrap())(plugin);
+        }
+    }
+}
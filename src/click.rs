@@ -0,0 +1,128 @@
+// Copyright (c) 2020-2021 Weird Constructor <weirdconstructor@gmail.com>
+// This is a part of Kickmess. See README.md and COPYING for details.
+
+use crate::helpers::*;
+
+const PI2 : f64 = std::f64::consts::PI * 2.0;
+
+/// The click has decayed to -80dB, no need to compute it anymore.
+const CLICK_SILENCE : f64 = 0.0001;
+
+pub trait ClickInputParams {
+    fn level(&self)         -> f32;
+    /// Decay time in milliseconds until -60dB.
+    fn decay(&self)         -> f32;
+    /// Cutoff frequency of the tone lowpass.
+    fn tone(&self)          -> f32;
+    fn waveform(&self)      -> f32;
+}
+
+impl ClickInputParams for (f32, f32, f32, f32) {
+    fn level(&self)         -> f32 { self.0 }
+    fn decay(&self)         -> f32 { self.1 }
+    fn tone(&self)          -> f32 { self.2 }
+    fn waveform(&self)      -> f32 { self.3 }
+}
+
+/// A short transient, that is layered on top of the kick.
+///
+/// The source is either a noise burst or one of the built-in click
+/// waveforms. It runs through an exponentially decaying envelope and
+/// a 12dB lowpass for the tone.
+pub struct ClickLayer {
+    srate:      f64,
+    rng:        RandGen,
+    noise:      f64,
+    noise_hold: usize,
+    noise_left: usize,
+    env:        f64,
+    phase:      f64,
+    time:       f64,
+    lp1:        f64,
+    lp2:        f64,
+}
+
+impl ClickLayer {
+    pub fn new() -> Self {
+        Self {
+            srate:      44100.0,
+            rng:        RandGen::new(),
+            noise:      0.0,
+            noise_hold: 1,
+            noise_left: 0,
+            env:        0.0,
+            phase:      0.0,
+            time:       0.0,
+            lp1:        0.0,
+            lp2:        0.0,
+        }
+    }
+
+    pub fn set_sample_rate(&mut self, srate: f32) {
+        self.srate = srate as f64;
+    }
+
+    /// Holds each noise sample for `samples` samples. With oversampling
+    /// the noise is drawn at the host rate, so that its level does not
+    /// depend on the oversampling factor.
+    pub fn set_noise_hold(&mut self, samples: usize) {
+        self.noise_hold = samples.max(1);
+    }
+
+    /// Restarts the click, called at the start of a note.
+    pub fn trigger(&mut self) {
+        self.noise_left = 0;
+        self.env   = 1.0;
+        self.phase = 0.0;
+        self.time  = 0.0;
+        self.lp1   = 0.0;
+        self.lp2   = 0.0;
+    }
+
+    fn next_source(&mut self, wave: f32) -> f64 {
+        let (s, freq) =
+            if wave < 0.25 {
+                // Noise burst
+                if self.noise_left == 0 {
+                    self.noise      = (self.rng.next_open01() * 2.0) - 1.0;
+                    self.noise_left = self.noise_hold;
+                }
+                self.noise_left -= 1;
+                (self.noise, 0.0)
+            } else if wave < 0.5 {
+                // Sine burst
+                (fast_sin(self.phase * PI2), 3000.0)
+            } else if wave < 0.75 {
+                // Square burst
+                (if self.phase < 0.5 { 1.0 } else { -1.0 }, 1500.0)
+            } else {
+                // Sine sweeping down from 8kHz within 5ms, like a beater
+                let f = 400.0 + 7600.0 * (-self.time / 0.005).exp();
+                (fast_sin(self.phase * PI2), f)
+            };
+
+        self.phase = (self.phase + freq / self.srate).fract();
+        self.time += 1.0 / self.srate;
+
+        s
+    }
+
+    pub fn next<P: ClickInputParams>(&mut self, params: &P) -> f32 {
+        if self.env < CLICK_SILENCE {
+            return 0.0;
+        }
+
+        let s = self.next_source(params.waveform());
+
+        // 0.001 is -60dB, reached after the decay time:
+        let decay_samples = (params.decay() as f64 * self.srate / 1000.0).max(1.0);
+        self.env *= (0.001_f64.ln() / decay_samples).exp();
+
+        let cutoff = (params.tone() as f64).min(self.srate * 0.45);
+        let coef   = 1.0 - (-PI2 * cutoff / self.srate).exp();
+        self.lp1 += coef * (s - self.lp1);
+        self.lp2 += coef * (self.lp1 - self.lp2);
+
+        (self.lp2 * self.env * params.level() as f64) as f32
+    }
+}
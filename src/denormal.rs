@@ -0,0 +1,145 @@
+// Copyright (c) 2020-2021 Weird Constructor <weirdconstructor@gmail.com>
+// This is a part of Kickmess. See README.md and COPYING for details.
+
+//! Protection against denormal floats, which make the feedback paths
+//! of the filters and oscillators very slow when they decay to zero.
+//!
+//! `FtzGuard` turns on the flush-to-zero mode of the CPU, where the
+//! architecture has one (x86 with SSE and AArch64). Because that is not
+//! available everywhere, the DSP code also flushes its state variables
+//! explicitly with `flush_denormal`.
+
+/// Values below this are flushed to zero by `flush_denormal`.
+/// That is about -300dB, far below anything audible, but well
+/// above the denormal range of `f32` (1.2e-38).
+const FLUSH_THRESHOLD : f32 = 1e-15;
+
+/// Returns 0.0 for values too small to be audible, before they become denormal.
+#[inline]
+pub fn flush_denormal(v: f32) -> f32 {
+    if v.abs() < FLUSH_THRESHOLD { 0.0 } else { v }
+}
+
+/// The `f64` version of `flush_denormal`.
+#[inline]
+pub fn flush_denormal_f64(v: f64) -> f64 {
+    if v.abs() < FLUSH_THRESHOLD as f64 { 0.0 } else { v }
+}
+
+#[cfg(any(target_arch="x86", target_arch="x86_64"))]
+mod fpu {
+    #[cfg(target_arch="x86")]
+    use core::arch::x86::{_mm_getcsr, _mm_setcsr};
+    #[cfg(target_arch="x86_64")]
+    use core::arch::x86_64::{_mm_getcsr, _mm_setcsr};
+
+    /// Flush-To-Zero and Denormals-Are-Zero bits of the MXCSR register.
+    const FTZ_DAZ : u32 = 0x8000 | 0x0040;
+
+    pub const HAS_FTZ : bool = true;
+
+    #[allow(deprecated)]
+    pub fn enable_ftz() -> u32 {
+        unsafe {
+            let prev = _mm_getcsr();
+            _mm_setcsr(prev | FTZ_DAZ);
+            prev
+        }
+    }
+
+    #[allow(deprecated)]
+    pub fn restore(prev: u32) {
+        unsafe { _mm_setcsr(prev); }
+    }
+}
+
+#[cfg(target_arch="aarch64")]
+mod fpu {
+    use core::arch::asm;
+
+    /// The FZ bit of the FPCR register.
+    const FZ : u64 = 1 << 24;
+
+    pub const HAS_FTZ : bool = true;
+
+    pub fn enable_ftz() -> u64 {
+        let prev : u64;
+        unsafe {
+            asm!("mrs {}, fpcr", out(reg) prev);
+            asm!("msr fpcr, {}", in(reg) prev | FZ);
+        }
+        prev
+    }
+
+    pub fn restore(prev: u64) {
+        unsafe { asm!("msr fpcr, {}", in(reg) prev); }
+    }
+}
+
+#[cfg(not(any(target_arch="x86", target_arch="x86_64", target_arch="aarch64")))]
+mod fpu {
+    pub const HAS_FTZ : bool = false;
+
+    pub fn enable_ftz() -> u32 { 0 }
+    pub fn restore(_prev: u32) { }
+}
+
+/// Turns on the flush-to-zero mode of the CPU while it lives,
+/// the previous mode is restored when it is dropped.
+/// On architectures without such a mode it does nothing.
+pub struct FtzGuard {
+    prev: Option<u64>,
+}
+
+impl FtzGuard {
+    pub fn new() -> Self {
+        if fpu::HAS_FTZ {
+            Self { prev: Some(fpu::enable_ftz() as u64) }
+        } else {
+            Self { prev: None }
+        }
+    }
+
+    /// Whether the CPU flushes denormals while the guard lives.
+    pub fn is_hardware_ftz(&self) -> bool { self.prev.is_some() }
+}
+
+impl Drop for FtzGuard {
+    fn drop(&mut self) {
+        if let Some(prev) = self.prev {
+            #[allow(clippy::unnecessary_cast)]
+            fpu::restore(prev as _);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_flush_denormal() {
+        assert_eq!(flush_denormal(1e-20), 0.0);
+        assert_eq!(flush_denormal(-1e-20), 0.0);
+        assert_eq!(flush_denormal(f32::MIN_POSITIVE * 0.5), 0.0);
+        assert_eq!(flush_denormal(1e-6), 1e-6);
+        assert_eq!(flush_denormal_f64(1e-300), 0.0);
+        assert_eq!(flush_denormal_f64(-0.25), -0.25);
+    }
+
+    #[test]
+    fn check_ftz_guard() {
+        // black_box keeps the compiler from folding the multiplication:
+        let tiny = std::hint::black_box(f32::MIN_POSITIVE);
+
+        {
+            let guard = FtzGuard::new();
+            if guard.is_hardware_ftz() {
+                assert_eq!(std::hint::black_box(tiny * 0.5), 0.0);
+            }
+        }
+
+        // The previous mode is restored:
+        assert!(std::hint::black_box(tiny * 0.5) > 0.0);
+    }
+}
@@ -1,17 +1,18 @@
 // Copyright (c) 2020-2021 Weird Constructor <weirdconstructor@gmail.com>
 // This is a part of Kickmess. See README.md and COPYING for details.
 
-use crate::KickmessVSTParams;
+use crate::engine::{EngineParams, HostAutomation};
 use vst::editor::{Editor, KeyCode};
 use std::sync::Arc;
 use std::rc::Rc;
-use vst::plugin::{HostCallback};
-use vst::host::Host;
 use crate::ringbuf_shared::RingBuf;
+use crate::spectrum::SpectrumAnalyzer;
+use crate::meter::MeterDisplay;
 use keyboard_types::KeyboardEvent;
 
 use crate::param_model::pid::{self};
-use crate::param_model::PARAM_COUNT;
+use crate::param_model::{PARAM_COUNT, FREQ_ENV_POINTS, AMP_ENV_POINTS};
+use crate::env::{EnvShape, shape_point_count};
 use crate::ui::protocol::*;
 use crate::ui::constants::*;
 use crate::ui;
@@ -38,20 +39,53 @@ macro_rules! define_window_size {
 
 define_window_size!{}
 
+/// The zoom levels of the editor, selected with the `ID_ZOOM` switch.
+pub const ZOOM_LEVELS : [f64; 5] = [0.75, 1.0, 1.25, 1.5, 2.0];
+
+/// Maps the value of the `ID_ZOOM` switch to the zoom factor of the editor.
+pub fn zoom_factor(p: f32) -> f64 {
+    let idx = (p * ZOOM_LEVELS.len() as f32).floor().max(0.0) as usize;
+    ZOOM_LEVELS[idx.min(ZOOM_LEVELS.len() - 1)]
+}
+
+/// The value of the `ID_ZOOM` switch for the nearest zoom level to `zoom`.
+pub fn zoom_switch_value(zoom: f64) -> f32 {
+    let idx =
+        ZOOM_LEVELS.iter()
+            .enumerate()
+            .min_by(|a, b| (a.1 - zoom).abs().partial_cmp(&(b.1 - zoom).abs()).unwrap())
+            .map(|(idx, _)| idx)
+            .unwrap_or(1);
+    (idx as f32 + 0.5) / ZOOM_LEVELS.len() as f32
+}
+
+/// The size of the editor window at the zoom factor `zoom`.
+pub fn editor_size(zoom: f64) -> (i32, i32) {
+    ((WINDOW_WIDTH  as f64 * zoom).round() as i32,
+     (WINDOW_HEIGHT as f64 * zoom).round() as i32)
+}
+
 #[derive(Debug, Clone)]
 enum VSTKeyEvent {
     Pressed(KeyboardEvent),
     Released(KeyboardEvent),
 }
 
-pub(crate) struct KickmessEditorController {
-    host:           HostCallback,
-    params:         Arc<KickmessVSTParams>,
+/// Connects the GUI with the `EngineParams`. Changes made in
+/// the GUI are reported to the host with `HostAutomation`.
+pub struct KickmessEditorController {
+    host:           Arc<dyn HostAutomation>,
+    params:         Arc<EngineParams>,
     is_open:        std::sync::atomic::AtomicBool,
     close_request:  std::sync::atomic::AtomicBool,
     heart_beat:     std::sync::atomic::AtomicU64,
     key_events:     RingBuf<VSTKeyEvent>,
-    log:            crate::log::LogHandle,
+    scope_gen:      std::sync::atomic::AtomicU64,
+    /// The zoom factor (as `f32` bits), for which the host was
+    /// last asked to resize the editor window.
+    sized_zoom:     std::sync::atomic::AtomicU32,
+    spectrum:       std::sync::Mutex<SpectrumAnalyzer>,
+    meter:          std::sync::Mutex<MeterDisplay>,
 }
 
 pub(crate) struct KickmessEditor {
@@ -59,11 +93,31 @@ pub(crate) struct KickmessEditor {
 }
 
 impl KickmessEditorController {
+    pub fn new(params: Arc<EngineParams>, host: Arc<dyn HostAutomation>) -> Self {
+        Self {
+            host,
+            params,
+            is_open:        std::sync::atomic::AtomicBool::new(true),
+            close_request:  std::sync::atomic::AtomicBool::new(false),
+            heart_beat:     std::sync::atomic::AtomicU64::new(0),
+            key_events:     RingBuf::new(MAX_KEY_EVENTS_PER_FRAME),
+            scope_gen:      std::sync::atomic::AtomicU64::new(0),
+            sized_zoom:     std::sync::atomic::AtomicU32::new(0),
+            spectrum:       std::sync::Mutex::new(SpectrumAnalyzer::new()),
+            meter:          std::sync::Mutex::new(MeterDisplay::new()),
+        }
+    }
+
     pub fn request_close(&self) {
-        if crate::DEBUG_LOGGING { self.log.log_str("request_close"); }
+        klog!(Gui, Debug, "request_close");
         self.close_request.store(true, std::sync::atomic::Ordering::Relaxed)
     }
 
+    /// The zoom level of the editor, see `ZOOM_LEVELS`.
+    pub fn zoom(&self) -> f64 {
+        zoom_factor(zoom_switch_value(self.params.editor_zoom() as f64))
+    }
+
     pub fn is_still_open(&self) -> bool {
         self.is_open.load(std::sync::atomic::Ordering::Relaxed)
     }
@@ -78,28 +132,69 @@ impl KickmessEditorController {
                 .as_millis() as u64,
             std::sync::atomic::Ordering::Relaxed);
     }
+
+    /// Randomizes the parameters and tells the host about the
+    /// new values, see `EngineParams::randomize`.
+    fn randomize(&self) {
+        use crate::proc::ParamProvider;
+
+        let seed =
+            std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map(|d| d.as_nanos() as u64)
+                .unwrap_or(0);
+
+        for id in self.params.randomize(seed) {
+            let value = self.params.param(id);
+            self.host.begin_edit(id);
+            self.host.automate(id, value);
+            self.host.end_edit(id);
+        }
+    }
 }
 
 impl UIController for KickmessEditorController {
     fn init(&self, ui: &mut dyn UI) {
         self.is_open.store(true, std::sync::atomic::Ordering::Relaxed);
-        define_gui(&self.params.ps, ui);
+        define_gui(self.params.ps(), ui);
 
         ui.set_version(crate::VERSION);
 
-        for (i, p) in self.params.params.iter().enumerate() {
+        // Makes `pre_frame` send the last hit to the new window:
+        self.scope_gen.store(0, std::sync::atomic::Ordering::Relaxed);
+        // The window was opened with the size of the current zoom:
+        self.sized_zoom.store(
+            (self.zoom() as f32).to_bits(), std::sync::atomic::Ordering::Relaxed);
+
+        use crate::proc::ParamProvider;
+
+        for i in 0..self.params.ps().param_count() {
             ui.set_values(
                 &[UIInputValue {
                     id: i,
-                    value: p.get(),
+                    value: self.params.param(i),
                 }]);
         }
+
+        ui.set_values(
+            &[UIInputValue {
+                id:    ID_ZOOM,
+                value: zoom_switch_value(self.zoom()),
+            }]);
     }
 
+    fn window_zoom(&self) -> f64 { self.zoom() }
+
     fn is_active(&self) -> bool {
         let last_heart_beat =
             self.heart_beat.load(std::sync::atomic::Ordering::Relaxed);
 
+        // Without a host driving the heart beat, like in
+        // the standalone GUI, the window is always active:
+        if last_heart_beat == 0 {
+            return true;
+        }
+
         use std::time::{SystemTime, UNIX_EPOCH};
         let now = SystemTime::now();
         let now_as_ms =
@@ -111,13 +206,11 @@ impl UIController for KickmessEditorController {
     }
 
     fn window_closed(&self, _ui: &mut dyn UI) {
-        if crate::DEBUG_LOGGING { self.log.log_str("window_closed"); }
+        klog!(Gui, Debug, "window_closed");
         self.is_open.store(false, std::sync::atomic::Ordering::Relaxed);
     }
 
     fn pre_frame(&self, ui: &mut dyn UI) {
-        if crate::DEBUG_LOGGING { self.log.log_str("pre_frame1"); }
-
         if !self.is_open.load(std::sync::atomic::Ordering::Relaxed) {
             return;
         }
@@ -126,11 +219,11 @@ impl UIController for KickmessEditorController {
             return;
         }
 
-        if crate::DEBUG_LOGGING { self.log.log_str("pre_frame2"); }
+        klog!(Gui, Trace, "pre_frame");
 
         use crate::proc::ParamProvider;
 
-        while let Some(id) = self.params.dirty_params.pop() {
+        while let Some(id) = self.params.pop_dirty() {
             ui.set_values(
                 &[UIInputValue {
                     id: id,
@@ -138,46 +231,170 @@ impl UIController for KickmessEditorController {
                 }]);
         }
 
+        // The zoom changes in the GUI and by loading a state:
+        let zoom = self.zoom();
+        let zoom_bits = (zoom as f32).to_bits();
+        if self.sized_zoom.swap(zoom_bits, std::sync::atomic::Ordering::Relaxed) != zoom_bits {
+            ui.set_values(
+                &[UIInputValue {
+                    id:    ID_ZOOM,
+                    value: zoom_switch_value(zoom),
+                }]);
+
+            let (w, h) = editor_size(zoom);
+            klog!(Gui, Debug, "resize editor to {}x{}", w, h);
+            if !self.host.resize_editor(w, h) {
+                klog!(Gui, Debug, "host did not resize the editor");
+            }
+        }
+
         while let Some(vst_ev) = self.key_events.pop() {
             match vst_ev {
                 VSTKeyEvent::Pressed(kev)  => ui.key_pressed(kev),
                 VSTKeyEvent::Released(kev) => ui.key_released(kev),
             }
         }
+
+        let scope = self.params.scope();
+        if scope.generation() != self.scope_gen.load(std::sync::atomic::Ordering::Relaxed) {
+            let mut points = vec![];
+            if let Some(gen) = scope.read(&mut points) {
+                self.scope_gen.store(gen, std::sync::atomic::Ordering::Relaxed);
+                ui.set_scope_data(ID_SCOPE_OUT, &points[..]);
+            }
+        }
+
+        if let Ok(mut an) = self.spectrum.lock() {
+            if an.analyze(self.params.spectrum()) {
+                use crate::spectrum::{db_to_y, freq_to_x, note_name};
+
+                ui.set_spectrum_data(ID_SPECTRUM_OUT, &UISpectrum {
+                    levels: an.levels().iter().map(|db| db_to_y(*db)).collect(),
+                    peaks:  an.peaks().iter().map(|db| db_to_y(*db)).collect(),
+                    grid:
+                        [100.0, 1000.0, 10000.0].iter()
+                            .map(|f| freq_to_x(*f)).collect(),
+                    fundamental:
+                        an.fundamental().map(|f| (freq_to_x(f), note_name(f))),
+                });
+            }
+        }
+
+        if let Ok(mut meter) = self.meter.lock() {
+            use crate::meter::db_to_meter;
+
+            meter.update(self.params.meter(), std::time::Instant::now());
+            ui.set_meter_data(ID_METER_OUT, &UIMeter {
+                peak: db_to_meter(meter.peak_db()),
+                rms:  db_to_meter(meter.rms_db()),
+                hold: db_to_meter(meter.hold_db()),
+                clip: meter.clip(),
+                grid:
+                    [0.0, -6.0, -12.0, -24.0, -48.0].iter()
+                        .map(|db| db_to_meter(*db)).collect(),
+            });
+        }
     }
 
     fn value_change_start(&self, ui: &mut dyn UI, id: usize, value: f32) {
-        if let Some(af) = self.params.params.get(id) {
-            af.set(value);
+        if self.params.change(id, value) {
 
             if crate::param_model::ParamModel::is_public(id) {
-                self.host.begin_edit(id as i32);
+                self.host.begin_edit(id);
                 //d// println!("START AUTOM {}: {}", id, value);
-                self.host.automate(id as i32, value);
+                self.host.automate(id, value);
             }
         }
     }
 
     fn value_change(&self, ui: &mut dyn UI, id: usize, value: f32, single_change: bool) {
-        if let Some(af) = self.params.params.get(id) {
-            af.set(value);
+        if id == ID_METER_OUT {
+            if let Ok(mut meter) = self.meter.lock() {
+                meter.reset_clip();
+            }
+            return;
+        }
+
+        if id == ID_ZOOM {
+            self.params.set_editor_zoom(zoom_factor(value) as f32);
+            return;
+        }
+
+        if self.params.change(id, value) {
 
             if crate::param_model::ParamModel::is_public(id) {
-                if single_change { self.host.begin_edit(id as i32); }
-                self.host.automate(id as i32, value);
-                if single_change { self.host.end_edit(id as i32); }
+                if single_change { self.host.begin_edit(id); }
+                self.host.automate(id, value);
+                if single_change { self.host.end_edit(id); }
             }
         }
     }
 
+    fn menu_entry_state(&self, id: usize, action: UIMenuAction) -> UIMenuState {
+        let checked = |on| if on { UIMenuState::Checked } else { UIMenuState::Enabled };
+
+        match action {
+            UIMenuAction::MidiLearn if id < PARAM_COUNT => {
+                let ml = self.params.midi_learn();
+                checked(ml.learning() == Some(id) || ml.cc_of(id).is_some())
+            },
+            UIMenuAction::ShowAutomation
+                if crate::param_model::ParamModel::is_public(id) => {
+                UIMenuState::Enabled
+            },
+            UIMenuAction::LockRandomize if self.params.is_randomizable(id) => {
+                checked(self.params.is_locked(id))
+            },
+            UIMenuAction::MidiLearn
+            | UIMenuAction::ShowAutomation
+            | UIMenuAction::LockRandomize => UIMenuState::Disabled,
+            _ => UIMenuState::Enabled,
+        }
+    }
+
+    fn menu_action(&self, ui: &mut dyn UI, id: usize, action: UIMenuAction) -> bool {
+        match action {
+            UIMenuAction::MidiLearn => {
+                let ml = self.params.midi_learn();
+                if ml.learning() == Some(id) {
+                    ml.cancel();
+                } else if ml.cc_of(id).is_some() {
+                    ml.forget(id);
+                } else {
+                    klog!(Gui, Debug, "midi learn for {}", id);
+                    ml.start(id);
+                }
+                true
+            },
+            UIMenuAction::ShowAutomation => {
+                // There is no host API for this, but most hosts show
+                // the automation of the parameter, that was touched last:
+                use crate::proc::ParamProvider;
+                let value = self.params.param(id);
+                self.host.begin_edit(id);
+                self.host.automate(id, value);
+                self.host.end_edit(id);
+                true
+            },
+            UIMenuAction::LockRandomize => {
+                self.params.set_locked(id, !self.params.is_locked(id));
+                true
+            },
+            UIMenuAction::Randomize => {
+                self.randomize();
+                true
+            },
+            _ => false,
+        }
+    }
+
     fn value_change_stop(&self, ui: &mut dyn UI, id: usize, value: f32) {
-        if let Some(af) = self.params.params.get(id) {
-            af.set(value);
+        if self.params.change(id, value) {
 
             if crate::param_model::ParamModel::is_public(id) {
                 //d// println!("STOP AUTOM {}: {}", id, value);
-                self.host.automate(id as i32, value);
-                self.host.end_edit(id as i32);
+                self.host.automate(id, value);
+                self.host.end_edit(id);
             }
         }
     }
@@ -186,28 +403,74 @@ impl UIController for KickmessEditorController {
 
 const ID_MAIN_TAB : usize = PARAM_COUNT;
 const ID_LIC_TAB  : usize = PARAM_COUNT + 1;
+const ID_OUT_TAB  : usize = PARAM_COUNT + 2;
+/// The output meter, clicking it resets the clip indicator.
+pub const ID_METER_OUT : usize = PARAM_COUNT + 3;
+/// The zoom switch of the editor, see `EngineParams::editor_zoom`.
+pub const ID_ZOOM : usize = PARAM_COUNT + 4;
+
+/// The oscilloscopes have their own ids, see `UI::set_scope_data`.
+pub const ID_SCOPE_OUT : usize = 0;
+/// The spectrum analyzers have their own ids, see `UI::set_spectrum_data`.
+pub const ID_SPECTRUM_OUT : usize = 0;
 
 fn prepare_values(values: &mut [UIValueSpec]) {
 
-    let ht = crate::param_model::help_texts[pid::freq_note_start];
-    values[pid::freq_note_start] = UIValueSpec::new_toggle(&[ "Off", "On" ]).help(ht.0, ht.1);
-    let ht = crate::param_model::help_texts[pid::freq_note_end];
-    values[pid::freq_note_end] = UIValueSpec::new_toggle(&[ "Off", "On" ]).help(ht.0, ht.1);
-    let ht = crate::param_model::help_texts[pid::dist_on];
+    let ht = crate::param_model::help_text(pid::dist_on);
     values[pid::dist_on]  = UIValueSpec::new_toggle(&[ "Off", "On" ]).help(ht.0, ht.1);
-    let ht = crate::param_model::help_texts[pid::f1_on];
+    let ht = crate::param_model::help_text(pid::f1_on);
     values[pid::f1_on]    = UIValueSpec::new_toggle(&[ "Off", "On" ]).help(ht.0, ht.1);
 
-    let ht = crate::param_model::help_texts[pid::midi_chan];
+    let ht = crate::param_model::help_text(pid::midi_chan);
     values[pid::midi_chan]= UIValueSpec::new_toggle(&[
         "1", "2", "3", "4", "5", "6", "7", "8", "9",
         "10", "11", "12", "13", "14", "15", "16"
     ]).help(ht.0, ht.1);
 
+    let ht = crate::param_model::help_text(pid::os_factor);
+    values[pid::os_factor] =
+        UIValueSpec::new_toggle(&[ "1x", "2x", "4x", "8x" ])
+        .help(ht.0, ht.1);
+    let ht = crate::param_model::help_text(pid::os_quality);
+    values[pid::os_quality] =
+        UIValueSpec::new_toggle(&[ "Realtime", "Offline" ])
+        .help(ht.0, ht.1);
+
+    let ht = crate::param_model::help_text(pid::click_type);
+    values[pid::click_type] =
+        UIValueSpec::new_toggle(&[ "Noise", "Sine", "Square", "Beater" ])
+        .help(ht.0, ht.1);
+    let ht = crate::param_model::help_text(pid::fe_points);
+    values[pid::fe_points] =
+        UIValueSpec::new_toggle(&[ "0", "1", "2", "3" ]).help(ht.0, ht.1);
+    let ht = crate::param_model::help_text(pid::ae_points);
+    values[pid::ae_points] =
+        UIValueSpec::new_toggle(&[ "0", "1", "2", "3" ]).help(ht.0, ht.1);
+    let ht = crate::param_model::help_text(pid::limiter_on);
+    values[pid::limiter_on] =
+        UIValueSpec::new_toggle(&[ "Off", "On" ]).help(ht.0, ht.1);
+    values[ID_ZOOM] =
+        UIValueSpec::new_toggle(&[ "75%", "100%", "125%", "150%", "200%" ])
+        .default(zoom_switch_value(1.0) as f64)
+        .help("Editor Zoom",
+            "The size of the editor window, from 75% to 200%.\n\
+             It is stored with the plugin state, so each instance\n\
+             remembers its own size.");
+    values[ID_METER_OUT] =
+        UIValueSpec::new_id().help("Output Meter",
+            "Shows the peak (dark) and RMS (bright) level of the output\n\
+             from -60dB to +6dB, with marks at 0, -6, -12, -24 and -48dB.\n\
+             The light on top turns red when the output clipped.\n\
+             Click the meter to turn it off again.");
+
+    values[pid::click_decay].set_active_when_gt0(pid::click_level);
+    values[pid::click_tone] .set_active_when_gt0(pid::click_level);
+    values[pid::click_type] .set_active_when_gt0(pid::click_level);
+
     values[pid::dist_start] .set_active_when_gt05(pid::dist_on);
     values[pid::dist_end]   .set_active_when_gt05(pid::dist_on);
 
-    let ht = crate::param_model::help_texts[pid::f1_type];
+    let ht = crate::param_model::help_text(pid::f1_type);
     values[pid::f1_type] =
         UIValueSpec::new_toggle(&[ "LP", "HP", "BP" ])
         .help(ht.0, ht.1);
@@ -218,7 +481,7 @@ fn prepare_values(values: &mut [UIValueSpec]) {
 
     #[cfg(feature="mega")]
     {
-        let ht = crate::param_model::help_texts[pid::o2fm_mode];
+        let ht = crate::param_model::help_text(pid::o2fm_mode);
         values[pid::o2fm_mode] =
             UIValueSpec::new_toggle(&[ "Env", "Fixed" ]).help(ht.0, ht.1);
 
@@ -235,7 +498,7 @@ fn prepare_values(values: &mut [UIValueSpec]) {
 
         values[pid::o2fm_freq]  .set_active_when_gt05(pid::o2fm_mode);
 
-        let ht = crate::param_model::help_texts[pid::m1_fun];
+        let ht = crate::param_model::help_text(pid::m1_fun);
         values[pid::m1_fun] =
             UIValueSpec::new_toggle(&[
                 "a * x",
@@ -244,18 +507,18 @@ fn prepare_values(values: &mut [UIValueSpec]) {
                 "1 - a * (1 - x)",
             ]).help(ht.0, ht.1);
 
-        let ht = crate::param_model::help_texts[pid::m1_dest_id];
+        let ht = crate::param_model::help_text(pid::m1_dest_id);
         let mod_params = crate::param_model::create_mod_params();
         values[pid::m1_dest_id] =
             UIValueSpec::new_mod_target_list(
                 &mod_params,
                 "-").help(ht.0, ht.1);
 
-        let ht = crate::param_model::help_texts[pid::m1_src_id];
+        let ht = crate::param_model::help_text(pid::m1_src_id);
         values[pid::m1_src_id] =
             UIValueSpec::new_toggle(&[ "-", "LFO 1", "Env 1" ]).help(ht.0, ht.1);
 
-        let ht = crate::param_model::help_texts[pid::lfo1_wave];
+        let ht = crate::param_model::help_text(pid::lfo1_wave);
         values[pid::lfo1_wave] =
             UIValueSpec::new_toggle(&[ "Sine", "Tri", "Saw", "Pulse" ]).help(ht.0, ht.1);
     }
@@ -268,11 +531,17 @@ About the knobs and adjustment areas:
 
     Coarse adjustment: Center of the knob (value label) dragging.
     Fine adjustment:   Label/Name of the knob dragging.
+    Envelope graphs:   Dragging the small squares (breakpoints).
 
 Mouse controls:
 
     Middle Mouse Button - Set Default value
-    Right Mouse Button  - Enter value input mode
+    Right Mouse Button  - Open the context menu of a knob: type the
+                          value, reset, copy/paste the value between
+                          the knobs (not via the system clipboard),
+                          MIDI learn, show the host automation lane,
+                          lock the knob and randomize the knobs, that
+                          are not locked
     Mouse Wheel Up/Down - Adjust knob value according to coarse/fine area
 
 Keyboard controls:
@@ -283,46 +552,164 @@ Keyboard controls:
     Enter               - Accept entered value in value input mode
     Escape              - Exit help or value input mode
     Shift + Drag        - fine adjustment
+    Tab / Shift + Tab   - Move the keyboard focus to the next/previous
+                          element, Escape removes the focus
+    Arrow keys          - Adjust the focused element, fine with Shift
+    Enter               - Enter value input mode for the focused element
+    Delete              - Set Default value of the focused element
 "#,
     14.0,
     pos)
 }
 
+/// Maps a frequency onto the logarithmic Y axis of the frequency
+/// envelope graph.
+fn freq_to_graph_y(freq: f64) -> f64 {
+    let (min_f, max_f) = (5.0_f64, 6000.0_f64);
+    ((freq.max(min_f) / min_f).ln() / (max_f / min_f).ln()).min(1.0)
+}
+
+/// The end of the envelopes in the envelope graphs.
+fn env_graph_max_x(src: &mut dyn UIValueSource) -> f64 {
+    let min_x = 0.2;
+    min_x + (1.0 - min_x) * src.param_value(pid::f_env_release).sqrt()
+}
+
+/// The shape of the frequency envelope, like `OpKickmess` plays it.
+fn freq_env_shape(src: &mut dyn UIValueSource) -> EnvShape {
+    let count     = shape_point_count(src.param_value(pid::fe_points) as f32);
+    let end_curve = src.param_value(pid::freq_slope).max(0.01);
+    EnvShape::from_params(
+        &FREQ_ENV_POINTS[..], count, end_curve,
+        |id| src.param_value_denorm(id))
+}
+
+/// The shape of the amplitude envelope, like `OpKickmess` plays it.
+fn amp_env_shape(src: &mut dyn UIValueSource) -> EnvShape {
+    let count     = shape_point_count(src.param_value(pid::ae_points) as f32);
+    let end_curve = src.param_value(pid::env_slope).max(0.01);
+    EnvShape::from_params(
+        &AMP_ENV_POINTS[..], count, end_curve,
+        |id| src.param_value_denorm(id))
+}
+
+/// Maps a level of the frequency envelope shape onto the graph.
+fn freq_level_to_graph_y(src: &mut dyn UIValueSource, level: f64) -> f64 {
+    let freq_start = src.param_value_denorm(pid::freq_start);
+    let freq_end   = src.param_value_denorm(pid::freq_end);
+    freq_to_graph_y(freq_end + (freq_start - freq_end) * level)
+}
+
+/// The handles for the breakpoints of an envelope shape and for
+/// the curvature of its segments. The curvature handles sit in
+/// the middle of their segment, the last segment is curved by
+/// the parameter `end_curve_id`.
+fn env_shape_handles(
+    shape_fun:    fn(&mut dyn UIValueSource) -> EnvShape,
+    level_to_y:   fn(&mut dyn UIValueSource, f64) -> f64,
+    points:       &[(usize, usize, usize)],
+    end_curve_id: usize) -> Vec<UIGraphHandle> {
+
+    let segment_mid = move |src: &mut dyn UIValueSource, idx: usize| {
+        let shape = shape_fun(src);
+        if idx > shape.len() {
+            return None;
+        }
+
+        let (start, end) = shape.segment(idx);
+        let x            = (start + end) * 0.5;
+        Some((x * env_graph_max_x(src), level_to_y(src, shape.value(x))))
+    };
+
+    let mut handles = vec![];
+
+    for (i, (time_id, level_id, curve_id)) in points.iter().enumerate() {
+        handles.push(UIGraphHandle::new(
+            Some(*time_id), Some(*level_id),
+            Arc::new(move |src: &mut dyn UIValueSource| {
+                let shape = shape_fun(src);
+                if i >= shape.len() {
+                    return None;
+                }
+
+                let (time, level) = shape.point(i);
+                Some((time * env_graph_max_x(src), level_to_y(src, level)))
+            })));
+
+        handles.push(UIGraphHandle::new(
+            None, Some(*curve_id),
+            Arc::new(move |src: &mut dyn UIValueSource| {
+                if i < shape_fun(src).len() { segment_mid(src, i) }
+                else                        { None }
+            })));
+    }
+
+    handles.push(UIGraphHandle::new(
+        None, Some(end_curve_id),
+        Arc::new(move |src: &mut dyn UIValueSource| {
+            let last = shape_fun(src).len();
+            segment_mid(src, last)
+        })));
+
+    handles
+}
+
 fn new_freq_env(pos: UIPos) -> UIInput {
 
     let f_env_fun =
         Arc::new(move |_id: usize, src: &mut dyn UIValueSource, out: &mut Vec<(f64, f64)>| {
-            let min_x = 0.2;
-            let max_x =
-                min_x + (1.0 - min_x) * src.param_value(pid::f_env_release).sqrt();
-            let slope = src.param_value(pid::freq_slope).max(0.01);
-
-            let (sign, y_offs) =
-                if (src.param_value_denorm(pid::freq_start)
-                    - src.param_value_denorm(pid::freq_end))
-                   < 0.0 {
+            let max_x = env_graph_max_x(src);
+            let shape = freq_env_shape(src);
 
-                    (-1.0, -1.0)
-                } else {
-                    (1.0, 0.0)
-                };
+            let freq_start = src.param_value_denorm(pid::freq_start);
+            let freq_end   = src.param_value_denorm(pid::freq_end);
+            let trk_start  = src.param_value_denorm(pid::freq_note_start);
+            let trk_end    = src.param_value_denorm(pid::freq_note_end);
 
             let samples = 80;
 
-            for x in 0..(samples + 1) {
-                let x = max_x * (x as f64 / (samples as f64));
-                out.push(
-                    (x,
-                     y_offs
-                     + (1.0 - sign * (x / max_x).powf(slope))));
+            // The sweep for the root note and the sweep one octave above
+            // it, so that the key tracking amounts become visible:
+            for octave in 0..2 {
+                if octave > 0 {
+                    out.push((std::f64::NAN, std::f64::NAN));
+                }
+
+                let f_start = freq_start * 2.0_f64.powf(octave as f64 * trk_start);
+                let f_end   = freq_end   * 2.0_f64.powf(octave as f64 * trk_end);
+
+                for x in 0..(samples + 1) {
+                    let x = max_x * (x as f64 / (samples as f64));
+                    let freq =
+                        f_end + (f_start - f_end) * shape.value(x / max_x);
+                    out.push((x, freq_to_graph_y(freq)));
+                }
             }
         });
 
+    let mut handles = vec![
+        UIGraphHandle::new(
+            None, Some(pid::freq_start),
+            Arc::new(|src: &mut dyn UIValueSource| {
+                Some((0.0, freq_to_graph_y(src.param_value_denorm(pid::freq_start))))
+            })),
+        UIGraphHandle::new(
+            Some(pid::f_env_release), Some(pid::freq_end),
+            Arc::new(|src: &mut dyn UIValueSource| {
+                Some((env_graph_max_x(src),
+                      freq_to_graph_y(src.param_value_denorm(pid::freq_end))))
+            })),
+    ];
+    handles.append(&mut env_shape_handles(
+        freq_env_shape, freq_level_to_graph_y,
+        &FREQ_ENV_POINTS[..], pid::freq_slope));
+
     UIInput::graph_huge(
         0,
         String::from("Freq. Env"),
         pos,
         f_env_fun.clone())
+    .with_handles(handles)
 }
 
 fn new_amp_env(pos: UIPos) -> UIInput {
@@ -331,25 +718,34 @@ fn new_amp_env(pos: UIPos) -> UIInput {
                        src: &mut dyn UIValueSource,
                        out: &mut Vec<(f64, f64)>| {
 
-            let slope = src.param_value(pid::env_slope).max(0.01);
-            let min_x = 0.2;
-            let max_x =
-                min_x + (1.0 - min_x) * src.param_value(pid::f_env_release).sqrt();
+            let shape = amp_env_shape(src);
+            let max_x = env_graph_max_x(src);
 
             let samples = 80;
 
             for x in 0..(samples + 1) {
                 let x = max_x * (x as f64 / (samples as f64));
-                out.push(
-                    (x, (1.0 - (x / max_x).powf(slope))));
+                out.push((x, shape.value(x / max_x)));
             }
         });
 
+    let mut handles = vec![
+        UIGraphHandle::new(
+            Some(pid::f_env_release), None,
+            Arc::new(|src: &mut dyn UIValueSource| {
+                Some((env_graph_max_x(src), 0.0))
+            })),
+    ];
+    handles.append(&mut env_shape_handles(
+        amp_env_shape, |_src, level| level,
+        &AMP_ENV_POINTS[..], pid::env_slope));
+
     UIInput::graph_huge(
         0,
         String::from("Amp Env"),
         pos,
         amp_env_fun.clone())
+    .with_handles(handles)
 }
 
 fn new_click_section(pos: UIPos) -> UIInput {
@@ -369,17 +765,35 @@ fn new_click_section(pos: UIPos) -> UIInput {
             }
         });
 
-    UIInput::container_border(pos, 0.98, "", vec![
+    UIInput::container_border(pos, 0.98, "Click", vec![
         vec![
             UIInput::knob_small(
                 pid::phase_offs,
-                String::from("Click"),
-                UIPos::left(6, 12).middle()),
+                String::from("Phase"),
+                UIPos::left(4, 6).middle()),
             UIInput::graph_small(
                 0,
                 String::from("Click"),
-                UIPos::right(6, 12).middle(),
+                UIPos::left(4, 6).middle(),
                 phase_fun.clone()),
+            UIInput::btn_toggle_small(
+                pid::click_type,
+                String::from("Type"),
+                UIPos::center(4, 6).middle()),
+        ],
+        vec![
+            UIInput::knob_small(
+                pid::click_level,
+                String::from("Level"),
+                UIPos::center(4, 6).middle()),
+            UIInput::knob_small(
+                pid::click_decay,
+                String::from("Decay"),
+                UIPos::center(4, 6).middle()),
+            UIInput::knob_small(
+                pid::click_tone,
+                String::from("Tone"),
+                UIPos::center(4, 6).middle()),
         ],
     ])
 }
@@ -412,6 +826,27 @@ fn new_filter_section(pos: UIPos) -> UIInput {
 }
 
 
+fn new_output_tabs(pos: UIPos) -> UIInput {
+    UIInput::Tabs(UITabData {
+        pos: pos,
+        id: ID_OUT_TAB,
+        labels: vec![
+            String::from("Scope"),
+            String::from("Spectrum"),
+        ],
+        childs: vec![
+            vec![vec![UIInput::scope(
+                ID_SCOPE_OUT,
+                String::from("Last Hit"),
+                UIPos::center(12, 12).middle())]],
+            vec![vec![UIInput::spectrum(
+                ID_SPECTRUM_OUT,
+                String::from("Output"),
+                UIPos::center(12, 12).middle())]],
+        ],
+    })
+}
+
 fn new_main_osc(pos: UIPos) -> UIInput {
     UIInput::container_border(pos, 1.0, "Main Oscillator", vec![
         vec![
@@ -420,15 +855,19 @@ fn new_main_osc(pos: UIPos) -> UIInput {
                 UIInput::knob(
                     pid::f_env_release,
                     String::from("Length (ms)"),
-                    UIPos::center(4, 12).bottom()),
+                    UIPos::center(3, 12).bottom()),
                 UIInput::knob(
                     pid::env_slope,
                     String::from("Amp Slope"),
-                    UIPos::center(4, 12).bottom()),
+                    UIPos::center(3, 12).bottom()),
                 UIInput::knob(
                     pid::env_release,
                     String::from("Rel (ms)"),
-                    UIPos::center(4, 12).bottom()),
+                    UIPos::center(3, 12).bottom()),
+                UIInput::btn_toggle_small(
+                    pid::ae_points,
+                    String::from("Points"),
+                    UIPos::center(3, 12).bottom()),
             ]]),
         ],
         vec![
@@ -437,27 +876,35 @@ fn new_main_osc(pos: UIPos) -> UIInput {
                 UIInput::knob(
                     pid::freq_start,
                     String::from("Start Hz"),
-                    UIPos::center(4, 12).bottom()),
+                    UIPos::center(3, 12).bottom()),
                 UIInput::knob(
                     pid::freq_end,
                     String::from("End Hz"),
-                    UIPos::center(4, 12).bottom()),
+                    UIPos::center(3, 12).bottom()),
                 UIInput::knob(
                     pid::freq_slope,
                     String::from("Freq Slope"),
-                    UIPos::center(4, 12).bottom()),
+                    UIPos::center(3, 12).bottom()),
+                UIInput::btn_toggle_small(
+                    pid::fe_points,
+                    String::from("Points"),
+                    UIPos::center(3, 12).bottom()),
             ]]),
         ],
         vec![
-            new_click_section(UIPos::center(4, 4).middle()),
-            UIInput::container(UIPos::center(8, 4), 1.0, "", vec![vec![
-                UIInput::btn_toggle(
+            new_click_section(UIPos::center(7, 4).middle()),
+            UIInput::container(UIPos::center(5, 4), 1.0, "Key Tracking", vec![vec![
+                UIInput::knob_small(
                     pid::freq_note_start,
-                    String::from("Note>St. F"),
+                    String::from("Start Trk"),
                     UIPos::center(4, 12).middle()),
-                UIInput::btn_toggle(
+                UIInput::knob_small(
                     pid::freq_note_end,
-                    String::from("Note>End F"),
+                    String::from("End Trk"),
+                    UIPos::center(4, 12).middle()),
+                UIInput::knob_small(
+                    pid::key_root,
+                    String::from("Key Root"),
                     UIPos::center(4, 12).middle()),
             ]]),
         ],
@@ -874,28 +1321,50 @@ Glyphs imported from Arev fonts are (c) Tavmjong Bah
 }
 
 fn new_mixer_section(pos: UIPos) -> UIInput {
-    UIInput::container_border(pos, 1.0, "Mixer", vec![
-        vec![
-            UIInput::knob(
-                pid::gain,
-                String::from("MOsc Gain"),
-                UIPos::center(6, 6).middle()),
-            UIInput::knob(
-                pid::noise,
-                String::from("Tone/Noise"),
-                UIPos::center(6, 6).middle()),
-        ],
-        vec![
-            UIInput::btn_toggle_small(
-                pid::midi_chan,
-                String::from("Chan"),
-                UIPos::center(6, 6).middle()),
-            UIInput::knob(
-                pid::main_gain,
-                String::from("Main Gain"),
-                UIPos::center(6, 6).middle()),
-        ],
-    ])
+    UIInput::container_border(pos, 1.0, "Mixer", vec![vec![
+        UIInput::container(UIPos::center(9, 12), 1.0, "", vec![
+            vec![
+                UIInput::knob(
+                    pid::gain,
+                    String::from("MOsc Gain"),
+                    UIPos::center(6, 6).middle()),
+                UIInput::knob(
+                    pid::noise,
+                    String::from("Tone/Noise"),
+                    UIPos::center(6, 6).middle()),
+            ],
+            vec![
+                UIInput::btn_toggle_small(
+                    pid::midi_chan,
+                    String::from("Chan"),
+                    UIPos::center(6, 6).middle()),
+                UIInput::knob(
+                    pid::main_gain,
+                    String::from("Main Gain"),
+                    UIPos::center(6, 6).middle()),
+            ],
+        ]),
+        UIInput::container(UIPos::center(3, 12), 1.0, "", vec![
+            vec![
+                UIInput::meter(
+                    ID_METER_OUT,
+                    String::from("Out"),
+                    UIPos::center(12, 6).middle()),
+            ],
+            vec![
+                UIInput::btn_toggle_small(
+                    pid::limiter_on,
+                    String::from("Limit"),
+                    UIPos::center(12, 3).middle()),
+            ],
+            vec![
+                UIInput::btn_toggle_small(
+                    ID_ZOOM,
+                    String::from("Zoom"),
+                    UIPos::center(12, 3).middle()),
+            ],
+        ]),
+    ]])
 }
 
 fn new_distortion_section(pos: UIPos) -> UIInput {
@@ -904,7 +1373,15 @@ fn new_distortion_section(pos: UIPos) -> UIInput {
             UIInput::btn_toggle(
                 pid::dist_on,
                 String::from("Distortion"),
-                UIPos::center(12, 4).top()),
+                UIPos::center(4, 4).top()),
+            UIInput::btn_toggle(
+                pid::os_factor,
+                String::from("Oversample"),
+                UIPos::center(4, 4).top()),
+            UIInput::btn_toggle(
+                pid::os_quality,
+                String::from("OS Quality"),
+                UIPos::center(4, 4).top()),
         ],
         vec![
             UIInput::knob(
@@ -943,7 +1420,10 @@ fn new_kickmess_layout() -> UILayout {
                                     vec![ new_distortion_section(UIPos::center(12, 5)) ],
                                 ]),
                             ],
-                            vec![ new_filter_section(UIPos::center(12, 3)), ],
+                            vec![
+                                new_filter_section(UIPos::center(8, 3)),
+                                new_output_tabs(UIPos::center(4, 3)),
+                            ],
                         ])]],
                         vec![ vec![ new_help_tabs(UIPos::center(12, 12)) ] ],
                     ]
@@ -975,6 +1455,7 @@ fn new_megamess_layout() -> UILayout {
                                 UIInput::container(UIPos::center(2, 12), 1.0, "", vec![
                                     vec![ new_mixer_section(UIPos::center(12, 4)) ],
                                     vec![ new_distortion_section(UIPos::center(12, 4)) ],
+                                    vec![ new_output_tabs(UIPos::center(12, 4)) ],
                                 ]),
                                 UIInput::container(UIPos::center(4, 12), 1.0, "", vec![
                                     vec![ new_fm1_section(UIPos::center(12, 4)) ],
@@ -994,24 +1475,28 @@ fn new_megamess_layout() -> UILayout {
     }
 }
 
-pub fn define_gui(ps: &crate::ParamSet, gui: &mut dyn ui::protocol::UI) {
+/// The value specs of all parameters and display values of the editor,
+/// indexed by their id. They format the values with their unit.
+pub fn param_value_specs(ps: &crate::ParamSet) -> Vec<UIValueSpec> {
     let mut values = vec![];
-    values.resize(PARAM_COUNT + 2, UIValueSpec::new_id());
+    values.resize(PARAM_COUNT + 5, UIValueSpec::new_id());
 
     for i in 0..ps.param_count() {
-        let help_text =
-            if i < crate::param_model::help_texts.len() {
-                crate::param_model::help_texts[i]
-            } else { ("", "") };
+        let help_text = crate::param_model::help_text(i);
         values[i] =
             ps.definition(i).unwrap()
               .to_ui_value_spec()
-              .help(help_text.0, help_text.1);
+              .help(help_text.0, help_text.1)
+              .unit(crate::param_model::param_unit(i));
     }
 
     prepare_values(&mut values[..]);
 
-    gui.define_value_spec(values);
+    values
+}
+
+pub fn define_gui(ps: &crate::ParamSet, gui: &mut dyn ui::protocol::UI) {
+    gui.define_value_spec(param_value_specs(ps));
 
     gui.define_layout(
         #[cfg(feature="mega")]
@@ -1021,18 +1506,8 @@ pub fn define_gui(ps: &crate::ParamSet, gui: &mut dyn ui::protocol::UI) {
 }
 
 impl KickmessEditor {
-    pub(crate) fn new(host: HostCallback, params: Arc<KickmessVSTParams>, log: crate::log::LogHandle) -> Self {
-        Self {
-            controller: Arc::new(KickmessEditorController {
-                host,
-                params,
-                is_open:        std::sync::atomic::AtomicBool::new(true),
-                close_request:  std::sync::atomic::AtomicBool::new(false),
-                heart_beat:     std::sync::atomic::AtomicU64::new(0),
-                key_events:     RingBuf::new(MAX_KEY_EVENTS_PER_FRAME),
-                log,
-            }),
-        }
+    pub(crate) fn new(host: Arc<dyn HostAutomation>, params: Arc<EngineParams>) -> Self {
+        Self { controller: Arc::new(KickmessEditorController::new(params, host)) }
     }
 }
 
@@ -1096,7 +1571,7 @@ fn keycode_to_keyevent(is_down: bool, kc: KeyCode) -> KeyboardEvent {
 
 impl Editor for KickmessEditor {
     fn size(&self) -> (i32, i32) {
-        (WINDOW_WIDTH as i32, WINDOW_HEIGHT as i32)
+        editor_size(self.controller.zoom())
     }
 
     fn position(&self) -> (i32, i32) {
@@ -1104,14 +1579,15 @@ impl Editor for KickmessEditor {
     }
 
     fn open(&mut self, parent: *mut std::ffi::c_void) -> bool {
-        if crate::DEBUG_LOGGING { self.controller.log.log_str("open"); }
+        klog!(Host, Debug, "editor open");
+        let (w, h) = editor_size(self.controller.zoom());
         crate::window::open_window(
             if cfg!(feature="mega") {
                 "Megamess"
             } else {
                 "Kickmess"
             },
-            WINDOW_WIDTH, WINDOW_HEIGHT,
+            w, h,
             Some(parent), self.controller.clone());
 
         true
@@ -1122,12 +1598,12 @@ impl Editor for KickmessEditor {
     }
 
     fn idle(&mut self) {
-        if crate::DEBUG_LOGGING { self.controller.log.log_str("idle"); }
+        klog!(Host, Trace, "editor idle");
         self.controller.drive_heart_beat();
     }
 
     fn close(&mut self) {
-        if crate::DEBUG_LOGGING { self.controller.log.log_str("close"); }
+        klog!(Host, Debug, "editor close");
         self.controller.request_close();
     }
 
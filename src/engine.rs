@@ -0,0 +1,873 @@
+// Copyright (c) 2020-2021 Weird Constructor <weirdconstructor@gmail.com>
+// This is a part of Kickmess. See README.md and COPYING for details.
+
+//! The plugin independent of a plugin format.
+//!
+//! `EngineParams` holds the parameters and is shared between the
+//! audio thread, the GUI and the host. `Engine` runs on the audio
+//! thread and renders the voices. The VST, LV2 and CLAP plugins and
+//! the offline renderer are adapters around these two types.
+
+use crate::proc::{ParamProvider, ParamSet, SmoothParameters, VoiceManager, MonoProcessor};
+use crate::proc::{ParamChange, ParamChangeQueue};
+use crate::ringbuf_shared::RingBuf;
+use crate::param_model::{pid, param_symbols, serialize_preset, deserialize_preset};
+use crate::param_model::{preset_section, has_preset_section};
+use crate::{OpKickmess, MAX_BLOCKSIZE, MAX_POLY};
+use crate::denormal::FtzGuard;
+use crate::scope::{ScopeBuffer, ScopeRecorder};
+use crate::spectrum::SpectrumTap;
+use crate::limiter::TruePeakLimiter;
+use crate::meter::{LevelMeter, LevelRecorder};
+use crate::midi_learn::MidiLearn;
+use crate::helpers::RandGen;
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+
+static INIT_TABLES : std::sync::Once = std::sync::Once::new();
+
+/// A `f32` that can be shared between threads.
+pub struct AtomicFloat {
+    atom: AtomicU32,
+}
+
+impl AtomicFloat {
+    pub fn new(v: f32) -> Self {
+        Self { atom: AtomicU32::new(v.to_bits()) }
+    }
+
+    #[inline]
+    pub fn get(&self) -> f32 { f32::from_bits(self.atom.load(Ordering::Relaxed)) }
+
+    #[inline]
+    pub fn set(&self, v: f32) { self.atom.store(v.to_bits(), Ordering::Relaxed) }
+}
+
+/// Receives the parameter changes made in the GUI, so that
+/// the host can record them as automation.
+pub trait HostAutomation : Send + Sync {
+    fn begin_edit(&self, _idx: usize) { }
+    fn automate(&self, _idx: usize, _value: f32) { }
+    fn end_edit(&self, _idx: usize) { }
+    /// Asks the host to resize the editor window. Returns false
+    /// if the host can't do that.
+    fn resize_editor(&self, _width: i32, _height: i32) -> bool { false }
+}
+
+/// For running without a host, like the standalone GUI.
+pub struct NoHost;
+
+impl HostAutomation for NoHost { }
+
+/// The normalized parameter values (0.0 to 1.0), shared
+/// by the audio thread, the GUI and the host.
+pub struct EngineParams {
+    ps:             ParamSet,
+    public_ps:      ParamSet,
+    params:         Vec<AtomicFloat>,
+    dirty_params:   RingBuf<usize>,
+    changes:        RingBuf<ParamChange>,
+    changes_lost:   AtomicBool,
+    scope:          ScopeBuffer,
+    spectrum:       SpectrumTap,
+    meter:          LevelMeter,
+    midi_learn:     MidiLearn,
+    /// The zoom factor of the editor. It is saved with the
+    /// state, but is no parameter, so the host can't automate it.
+    editor_zoom:    AtomicFloat,
+    /// The parameters, that `randomize` leaves alone.
+    locked:         Vec<AtomicBool>,
+}
+
+impl EngineParams {
+    pub fn new() -> Self {
+        let mut ps        = ParamSet::new();
+        let mut public_ps = ParamSet::new();
+        OpKickmess::init_params(&mut ps, &mut public_ps);
+
+        let params =
+            (0..ps.param_count())
+            .map(|idx| AtomicFloat::new(ps.definition(idx).unwrap().default_p()))
+            .collect();
+
+        // 10 times the parameter count, just to make sure it fits if the
+        // DAW is sending too many updates per frame. Or the GUI thread is too
+        // slow.
+        let dirty_params = RingBuf::new(public_ps.param_count() * 10);
+
+        // Same reasoning as above, but the GUI may change private
+        // parameters too. Loading a preset touches all of them.
+        let changes = RingBuf::new(ps.param_count() * 10);
+
+        let locked =
+            (0..ps.param_count()).map(|_| AtomicBool::new(false)).collect();
+
+        Self {
+            ps,
+            public_ps,
+            params,
+            dirty_params,
+            changes,
+            changes_lost: AtomicBool::new(false),
+            scope:        ScopeBuffer::new(),
+            spectrum:     SpectrumTap::new(),
+            meter:        LevelMeter::new(),
+            midi_learn:   MidiLearn::new(),
+            editor_zoom:  AtomicFloat::new(1.0),
+            locked,
+        }
+    }
+
+    /// All parameters.
+    pub fn ps(&self) -> &ParamSet { &self.ps }
+
+    /// The parameters that are visible to the host.
+    pub fn public_ps(&self) -> &ParamSet { &self.public_ps }
+
+    /// The last hit, as recorded by the audio thread.
+    pub fn scope(&self) -> &ScopeBuffer { &self.scope }
+
+    /// The last output samples, for the spectrum analyzer.
+    pub fn spectrum(&self) -> &SpectrumTap { &self.spectrum }
+
+    /// The levels of the output, as measured by the audio thread.
+    pub fn meter(&self) -> &LevelMeter { &self.meter }
+
+    /// The MIDI CC assignments of the parameters.
+    pub fn midi_learn(&self) -> &MidiLearn { &self.midi_learn }
+
+    /// The zoom factor of the editor, see `crate::editor::ZOOM_LEVELS`.
+    pub fn editor_zoom(&self) -> f32 { self.editor_zoom.get() }
+
+    pub fn set_editor_zoom(&self, zoom: f32) {
+        self.editor_zoom.set(zoom);
+    }
+
+    /// True if the parameter is locked from `randomize`.
+    pub fn is_locked(&self, idx: usize) -> bool {
+        self.locked.get(idx).map(|l| l.load(Ordering::Relaxed)).unwrap_or(false)
+    }
+
+    pub fn set_locked(&self, idx: usize, locked: bool) {
+        if let Some(l) = self.locked.get(idx) {
+            l.store(locked, Ordering::Relaxed);
+        }
+    }
+
+    /// True for the parameters, that `randomize` changes: All public
+    /// parameters, except the main gain to not blow up the speakers.
+    pub fn is_randomizable(&self, idx: usize) -> bool {
+           crate::param_model::ParamModel::is_public(idx)
+        && idx != pid::main_gain
+    }
+
+    /// Sets the randomizable parameters, that are not locked, to
+    /// random values like `set` does. Returns the changed parameters.
+    pub fn randomize(&self, seed: u64) -> Vec<usize> {
+        let mut rng = RandGen::new_seeded(seed);
+
+        (0..self.ps.param_count())
+            .filter(|idx| self.is_randomizable(*idx) && !self.is_locked(*idx))
+            .map(|idx| {
+                self.set(idx, rng.next_open01() as f32);
+                idx
+            })
+            .collect()
+    }
+
+    fn changes_capacity(&self) -> usize {
+        self.ps.param_count() * 10
+    }
+
+    /// Sets the parameter and queues a change for the audio thread,
+    /// which applies it at the start of the next block.
+    /// Returns false for unknown parameters.
+    pub fn change(&self, idx: usize, val: f32) -> bool {
+        if let Some(af) = self.params.get(idx) {
+            af.set(val);
+            let queued =
+                self.changes.push(ParamChange {
+                    idx,
+                    value: val,
+                    offs:  0,
+                });
+            if !queued {
+                self.changes_lost.store(true, Ordering::Relaxed);
+            }
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Like `change`, but for changes from the host, which
+    /// the GUI has to pick up with `pop_dirty`.
+    pub fn set(&self, idx: usize, val: f32) {
+        if self.change(idx, val) {
+            self.dirty_params.push(idx);
+        }
+    }
+
+    /// Returns the next parameter, that was changed by the host.
+    pub fn pop_dirty(&self) -> Option<usize> {
+        self.dirty_params.pop()
+    }
+
+    /// Serializes the parameters like `serialize_preset` and
+    /// appends the `!EDITOR` section with the editor settings and
+    /// the `!MIDI` section with the MIDI learn assignments.
+    pub fn serialize_preset(&self) -> Vec<u8> {
+        let mut data = serialize_preset(self);
+        data.extend_from_slice(
+            format!("!EDITOR;\nzoom={};\n", self.editor_zoom()).as_bytes());
+
+        data.extend_from_slice(b"!MIDI;\n");
+        let symbols = param_symbols();
+        for (cc, id) in self.midi_learn.assignments() {
+            if let Some(symbol) = symbols.get(id) {
+                data.extend_from_slice(format!("{}={};\n", symbol, cc).as_bytes());
+            }
+        }
+
+        data
+    }
+
+    /// Loads a preset in the format of `serialize_preset`.
+    /// Parameters missing in the preset are reset to their default value.
+    pub fn load_preset(&self, data: &[u8]) {
+        deserialize_preset(data, |idx, v| self.set(idx, v));
+
+        for (name, value) in preset_section(data, "!EDITOR") {
+            if name == "zoom" {
+                if let Ok(zoom) = value.parse::<f32>() {
+                    self.set_editor_zoom(zoom);
+                }
+            }
+        }
+
+        // Presets from before MIDI learn keep the assignments:
+        if has_preset_section(data, "!MIDI") {
+            let symbols = param_symbols();
+            let assignments : Vec<(u8, usize)> =
+                preset_section(data, "!MIDI")
+                    .into_iter()
+                    .filter_map(|(symbol, cc)| {
+                        let id = symbols.iter().position(|s| *s == symbol)?;
+                        Some((cc.parse::<u8>().ok()?, id))
+                    })
+                    .collect();
+            self.midi_learn.set_assignments(&assignments[..]);
+        }
+    }
+}
+
+impl Default for EngineParams {
+    fn default() -> Self { Self::new() }
+}
+
+impl ParamProvider for EngineParams {
+    fn param(&self, idx: usize) -> f32 {
+        if let Some(af) = self.params.get(idx) {
+            af.get()
+        } else {
+            0.0
+        }
+    }
+}
+
+/// Renders the voices on the audio thread.
+pub struct Engine {
+    params:         Arc<EngineParams>,
+    voices:         VoiceManager<OpKickmess>,
+    smooth_param:   SmoothParameters,
+    changes:        ParamChangeQueue,
+    targets:        Vec<f32>,
+    scope:          ScopeRecorder,
+    /// The frame of the last note on in the next block.
+    hit_offs:       Option<usize>,
+    limiter:        TruePeakLimiter,
+    levels:         LevelRecorder,
+}
+
+impl Engine {
+    pub fn new(params: Arc<EngineParams>) -> Self {
+        INIT_TABLES.call_once(|| crate::helpers::init_cos_tab());
+
+        // The engine starts with the current values, so the changes
+        // that were queued before are already applied:
+        while params.changes.pop().is_some() { }
+        params.changes_lost.store(false, Ordering::Relaxed);
+
+        let smooth_param = SmoothParameters::new(MAX_BLOCKSIZE, params.ps.param_count());
+        let changes      = ParamChangeQueue::new(params.changes_capacity());
+        let targets      =
+            (0..params.ps.param_count()).map(|i| params.param(i)).collect();
+
+        let mut limiter = TruePeakLimiter::new();
+        limiter.set_active(params.ps.get(pid::limiter_on, &*params) > 0.5);
+        limiter.reset();
+
+        Self {
+            voices: VoiceManager::new(MAX_POLY),
+            params,
+            smooth_param,
+            changes,
+            targets,
+            scope:          ScopeRecorder::new(),
+            hit_offs:       None,
+            limiter,
+            levels:         LevelRecorder::new(),
+        }
+    }
+
+    pub fn params(&self) -> &Arc<EngineParams> { &self.params }
+
+    /// The delay of the output in samples, for the latency
+    /// compensation of the host. It depends on the sample rate and
+    /// is 0 while the limiter is off. Switching the limiter changes
+    /// it in `process`, so the plugins check it after every block.
+    pub fn latency(&self) -> usize {
+        if self.limiter.is_active() { self.limiter.latency() } else { 0 }
+    }
+
+    pub fn set_sample_rate(&mut self, rate: f32) {
+        self.voices.set_sample_rate(rate);
+        self.smooth_param.set_sample_rate(rate);
+        self.scope.set_sample_rate(rate);
+        self.params.spectrum.set_sample_rate(rate);
+        self.limiter.set_sample_rate(rate);
+        self.levels.set_sample_rate(rate);
+    }
+
+    /// Handles a MIDI message at the frame `delta_frames`
+    /// of the next call to `process`.
+    pub fn handle_midi(&mut self, data: &[u8], delta_frames: usize) {
+        if data.len() < 3 {
+            return;
+        }
+
+        let my_channel = self.params.ps.get(pid::midi_chan, &*self.params).floor() as u8;
+        self.voices.handle_midi(data, delta_frames, my_channel);
+
+        if data[0] == (0xB0 | my_channel) {
+            if let Some(idx) = self.params.midi_learn.handle_cc(data[1]) {
+                self.set_param_at(idx, (data[2] as f32 / 127.0).min(1.0), delta_frames);
+            }
+        }
+
+        if data[0] == (0x90 | my_channel) && data[2] > 0 {
+            // The hit leaves the limiter delay line later:
+            self.hit_offs = Some(delta_frames + self.latency());
+        }
+    }
+
+    /// Sets a parameter from a host event, that applies at the
+    /// frame `offs` of the next call to `process`.
+    pub fn set_param_at(&mut self, idx: usize, val: f32, offs: usize) {
+        if let Some(af) = self.params.params.get(idx) {
+            af.set(val);
+            self.params.dirty_params.push(idx);
+            let queued =
+                self.changes.push(ParamChange {
+                    idx,
+                    value: val,
+                    offs,
+                });
+            if !queued {
+                self.params.changes_lost.store(true, Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// Renders the next block into `out`.
+    pub fn process(&mut self, out: &mut [f32]) {
+        let nframes  = out.len();
+        let mut offs = 0;
+
+        let _ftz = FtzGuard::new();
+
+        for os in out.iter_mut() { *os = 0.0; }
+
+        // Changes that did not fit into the queue are not lost,
+        // they just are not sample accurate:
+        if self.params.changes_lost.swap(false, Ordering::Relaxed) {
+            for (idx, t) in self.targets.iter_mut().enumerate() {
+                *t = self.params.param(idx);
+            }
+        }
+
+        // The changes from the GUI, that arrived while the last
+        // block was played, apply at the start of this block:
+        while let Some(change) = self.params.changes.pop() {
+            if !self.changes.push(change) {
+                self.params.changes_lost.store(true, Ordering::Relaxed);
+            }
+        }
+        self.changes.prepare(nframes);
+
+        while offs < nframes {
+            let seg_end =
+                self.changes.next_segment(offs, nframes, &mut self.targets[..]);
+
+            while offs < seg_end {
+                let advance_frames = (seg_end - offs).min(MAX_BLOCKSIZE);
+
+                self.smooth_param.advance_params(
+                    advance_frames, &self.params.ps, &self.targets);
+
+                self.voices.process(
+                    offs,
+                    &mut out[offs..(offs + advance_frames)],
+                    &self.smooth_param);
+
+                offs += advance_frames;
+            }
+        }
+
+        self.changes.clear();
+
+        // The limiter only delays the output while it is on:
+        let limiter_on = self.params.ps.get(pid::limiter_on, &*self.params) > 0.5;
+        self.limiter.set_active(limiter_on);
+        self.limiter.process(out);
+
+        self.levels.feed(out, &self.params.meter);
+        self.record_scope(out);
+        self.params.spectrum.write(out);
+    }
+
+    fn record_scope(&mut self, out: &[f32]) {
+        if let Some(offs) = self.hit_offs.take() {
+            if offs >= out.len() {
+                self.scope.feed(out, &self.params.scope);
+                self.hit_offs = Some(offs - out.len());
+                return;
+            }
+
+            self.scope.feed(&out[..offs], &self.params.scope);
+
+            let len_ms =
+                self.params.ps.get(pid::f_env_release, &*self.params)
+                + self.params.ps.get(pid::env_release, &*self.params);
+            self.scope.start(len_ms, &self.params.scope);
+            self.scope.feed(&out[offs..], &self.params.scope);
+
+        } else {
+            self.scope.feed(out, &self.params.scope);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn peak(s: &[f32]) -> f32 {
+        s.iter().fold(0.0_f32, |p, s| p.max(s.abs()))
+    }
+
+    fn new_engine() -> Engine {
+        let mut engine = Engine::new(Arc::new(EngineParams::new()));
+        engine.set_sample_rate(44100.0);
+        engine
+    }
+
+    #[test]
+    fn check_engine_note_at_delta_frames() {
+        let mut engine = new_engine();
+
+        let mut out = vec![0.0; 512];
+        engine.process(&mut out[..]);
+        assert_eq!(peak(&out[..]), 0.0);
+
+        engine.handle_midi(&[0x90, 36, 127], 100);
+        // Too short for a note on:
+        engine.handle_midi(&[0x90, 36], 10);
+        engine.process(&mut out[..]);
+
+        assert_eq!(peak(&out[0..100]), 0.0);
+        assert!(peak(&out[100..]) > 0.1);
+    }
+
+    #[test]
+    fn check_engine_midi_channel() {
+        let mut engine = new_engine();
+        let chan = engine.params().ps().definition(pid::midi_chan).unwrap().unmap(2.5);
+        engine.params().set(pid::midi_chan, chan);
+
+        let mut out = vec![0.0; 512];
+        engine.handle_midi(&[0x90, 36, 127], 0);
+        engine.process(&mut out[..]);
+        assert_eq!(peak(&out[..]), 0.0);
+
+        engine.handle_midi(&[0x92, 36, 127], 0);
+        engine.process(&mut out[..]);
+        assert!(peak(&out[..]) > 0.1);
+    }
+
+    #[test]
+    fn check_engine_param_at_offset() {
+        let mut engine    = new_engine();
+        let mut reference = new_engine();
+
+        let mut out     = vec![0.0; 512];
+        let mut ref_out = vec![0.0; 512];
+
+        engine.handle_midi(&[0x90, 36, 127], 0);
+        engine.set_param_at(pid::main_gain, 0.0, 100);
+        engine.process(&mut out[..]);
+
+        reference.handle_midi(&[0x90, 36, 127], 0);
+        reference.process(&mut ref_out[..]);
+
+        // The output is delayed by the limiter:
+        let lat = engine.latency();
+        assert_eq!(out[0..(100 + lat)], ref_out[0..(100 + lat)]);
+        assert_ne!(out[100 + lat], ref_out[100 + lat]);
+        assert!(peak(&out[400..]) < peak(&ref_out[400..]) * 0.1);
+
+        assert_eq!(engine.params().param(pid::main_gain), 0.0);
+        assert_eq!(engine.params().pop_dirty(), Some(pid::main_gain));
+        assert_eq!(engine.params().pop_dirty(), None);
+    }
+
+    #[test]
+    fn check_engine_gui_change_at_block_start() {
+        let mut engine    = new_engine();
+        let mut reference = new_engine();
+
+        let mut out     = vec![0.0; 512];
+        let mut ref_out = vec![0.0; 512];
+
+        engine.handle_midi(&[0x90, 36, 127], 0);
+        engine.process(&mut out[..]);
+        reference.handle_midi(&[0x90, 36, 127], 0);
+        reference.process(&mut ref_out[..]);
+        assert_eq!(out, ref_out);
+
+        // The change has no position, it applies from the
+        // first frame of the next block on:
+        engine.params().change(pid::main_gain, 0.0);
+        engine.process(&mut out[..]);
+        reference.process(&mut ref_out[..]);
+
+        let lat = engine.latency();
+        assert_eq!(out[0..lat], ref_out[0..lat]);
+        assert_ne!(out[lat], ref_out[lat]);
+        assert!(peak(&out[400..]) < peak(&ref_out[400..]) * 0.1);
+    }
+
+    #[test]
+    fn check_engine_lost_changes_are_applied() {
+        let mut engine = new_engine();
+        let params     = engine.params().clone();
+
+        // More changes than fit into the queue:
+        for i in 0..(params.changes_capacity() + 10) {
+            params.change(pid::main_gain, (i % 10) as f32 * 0.1);
+        }
+        params.change(pid::dist_on, 1.0);
+
+        let mut out = vec![0.0; 64];
+        engine.process(&mut out[..]);
+
+        assert_eq!(engine.targets[pid::dist_on],   1.0);
+        assert_eq!(engine.targets[pid::main_gain], params.param(pid::main_gain));
+    }
+
+    #[test]
+    fn check_engine_lost_host_events_are_applied() {
+        let mut engine = new_engine();
+        let capacity   = engine.params().changes_capacity();
+
+        // More host events than fit into the queue of the engine:
+        for i in 0..(capacity + 10) {
+            engine.set_param_at(pid::main_gain, (i % 10) as f32 * 0.1, 0);
+        }
+        engine.set_param_at(pid::dist_on, 1.0, 0);
+
+        let mut out = vec![0.0; 64];
+        engine.process(&mut out[..]);
+
+        assert_eq!(engine.targets[pid::dist_on], 1.0);
+    }
+
+    #[test]
+    fn check_engine_noise_level_with_oversampling() {
+        let rms = |noise: f32, click: f32, os_factor: f32| {
+            let mut engine = new_engine();
+            let params = engine.params().clone();
+            params.set(pid::noise,       noise);
+            params.set(pid::gain,        1.0 - click);
+            params.set(pid::click_level, click);
+            params.set(pid::click_decay, 1.0);
+            params.set(pid::click_type,  0.0);
+            params.set(pid::os_factor,   os_factor);
+
+            let mut out = vec![0.0; 512 * 8];
+            engine.handle_midi(&[0x90, 36, 127], 0);
+            for chunk in out.chunks_mut(512) {
+                engine.process(chunk);
+            }
+
+            let sum : f32 = out.iter().map(|s| s * s).sum();
+            (sum / out.len() as f32).sqrt()
+        };
+
+        for &(noise, click) in [(1.0, 0.0), (0.0, 1.0)].iter() {
+            let rms_1x = rms(noise, click, 0.0);
+            assert!(rms_1x > 0.01);
+
+            // 2x, 4x and 8x oversampling:
+            for &os_factor in [0.3, 0.6, 0.9].iter() {
+                let diff_db =
+                    20.0 * (rms(noise, click, os_factor) / rms_1x).log10();
+                assert!(diff_db.abs() < 1.5,
+                    "noise={} click={} os_factor={}: {}dB",
+                    noise, click, os_factor, diff_db);
+            }
+        }
+    }
+
+    #[test]
+    fn check_engine_amp_env_points() {
+        let render = |engine: &mut Engine| {
+            let mut out = vec![0.0; 512 * 20];
+            engine.handle_midi(&[0x90, 36, 127], 0);
+            for chunk in out.chunks_mut(512) {
+                engine.process(chunk);
+            }
+            out
+        };
+
+        let mut engine = new_engine();
+        let plain      = render(&mut engine);
+
+        // One breakpoint, that holds the full level
+        // for the first half of the 440ms:
+        let mut engine = new_engine();
+        engine.params().set(pid::ae_points,   0.3);
+        engine.params().set(pid::ae_p1_time,  0.5);
+        engine.params().set(pid::ae_p1_level, 1.0);
+        let held       = render(&mut engine);
+
+        // Both start at the same frame, the held level stays above the plain one:
+        assert_eq!(plain[0], held[0]);
+        assert!(plain[1..100].iter().zip(held[1..100].iter())
+                    .all(|(p, h)| h.abs() >= p.abs()));
+        // Around 160ms the plain envelope has dropped to 0.15:
+        assert!(peak(&plain[7000..7500]) < 0.3);
+        assert!(peak(&held[7000..7500])  > 0.8);
+    }
+
+    #[test]
+    fn check_engine_records_scope() {
+        let mut engine = new_engine();
+        let params     = engine.params().clone();
+
+        let mut out = vec![0.0; 512];
+        engine.handle_midi(&[0x90, 36, 127], 100);
+        engine.process(&mut out[..]);
+        assert_eq!(params.scope().generation(), 0);
+
+        // The default hit is 445ms long:
+        for _ in 0..40 {
+            engine.process(&mut out[..]);
+        }
+        assert_eq!(params.scope().generation(), 1);
+
+        let mut points = vec![];
+        assert_eq!(params.scope().read(&mut points), Some(1));
+        assert_eq!(points.len(), crate::scope::SCOPE_POINTS);
+        // The first cycles of the sine at 150Hz:
+        assert!(points[..10].iter().any(|p| p.1 >  0.1));
+        assert!(points[..10].iter().any(|p| p.0 < -0.1));
+        assert!(points.iter().all(|p| p.0 <= p.1));
+
+        // Other MIDI channels don't start a hit:
+        engine.handle_midi(&[0x91, 36, 127], 0);
+        for _ in 0..40 {
+            engine.process(&mut out[..]);
+        }
+        assert_eq!(params.scope().generation(), 1);
+    }
+
+    #[test]
+    fn check_engine_latency_follows_limiter() {
+        let mut engine = new_engine();
+        let params     = engine.params().clone();
+        assert_eq!(engine.latency(), 0);
+
+        let mut out = vec![0.0; 512];
+        params.set(pid::limiter_on, 1.0);
+        engine.process(&mut out[..]);
+        let lat = engine.latency();
+        assert!(lat >= 66, "latency {}", lat);
+
+        // The note leaves the limiter delayed:
+        engine.handle_midi(&[0x90, 36, 127], 0);
+        engine.process(&mut out[..]);
+        assert_eq!(peak(&out[0..lat]), 0.0);
+        assert!(peak(&out[lat..]) > 0.1);
+
+        params.set(pid::limiter_on, 0.0);
+        engine.process(&mut out[..]);
+        assert_eq!(engine.latency(), 0);
+    }
+
+    #[test]
+    fn check_engine_meter_and_limiter() {
+        let params = Arc::new(EngineParams::new());
+        let ps     = params.ps();
+        params.set(pid::gain,      ps.definition(pid::gain).unwrap().unmap(2.0));
+        params.set(pid::main_gain, ps.definition(pid::main_gain).unwrap().unmap(2.0));
+
+        let mut engine = Engine::new(params.clone());
+        engine.set_sample_rate(44100.0);
+
+        let mut out = vec![0.0; 512];
+        engine.handle_midi(&[0x90, 36, 127], 0);
+        for _ in 0..4 {
+            engine.process(&mut out[..]);
+        }
+        assert!(params.meter().take_peak() > 2.0);
+        assert!(params.meter().take_clips() > 0);
+
+        // The limiter fades in, before the next note:
+        params.set(pid::limiter_on, 1.0);
+        engine.process(&mut out[..]);
+        params.meter().take_peak();
+        params.meter().take_clips();
+
+        engine.handle_midi(&[0x90, 36, 127], 0);
+        for _ in 0..4 {
+            engine.process(&mut out[..]);
+        }
+
+        let ceiling = 10.0_f32.powf(crate::limiter::LIMITER_CEILING_DB / 20.0);
+        let peak    = params.meter().take_peak();
+        assert!(peak > 0.5 && peak <= ceiling, "peak {}", peak);
+        assert_eq!(params.meter().take_clips(), 0);
+    }
+
+    #[test]
+    fn check_engine_preset_roundtrip() {
+        let params = EngineParams::new();
+        params.set(pid::dist_on, 1.0);
+        params.change(pid::f1_on, 1.0);
+        params.set_editor_zoom(1.5);
+
+        let params2 = EngineParams::new();
+        params2.load_preset(&params.serialize_preset()[..]);
+
+        assert_eq!(params2.param(pid::dist_on), 1.0);
+        assert_eq!(params2.param(pid::f1_on),   1.0);
+        assert_eq!(params2.editor_zoom(),       1.5);
+        assert_eq!(params.serialize_preset(), params2.serialize_preset());
+
+        // Presets without the editor section keep the zoom:
+        params2.load_preset(&serialize_preset(&EngineParams::new())[..]);
+        assert_eq!(params2.param(pid::dist_on), 0.0);
+        assert_eq!(params2.editor_zoom(),       1.5);
+
+        // Only `set` tells the GUI about the change:
+        assert_eq!(params.pop_dirty(), Some(pid::dist_on));
+        assert_eq!(params.pop_dirty(), None);
+    }
+
+    #[test]
+    fn check_engine_old_preset_resets_new_params() {
+        let params = EngineParams::new();
+        let def    = |idx| params.ps().definition(idx).unwrap().default_p();
+
+        params.set(pid::click_level, 0.8);
+        params.set(pid::click_type,  0.9);
+        params.set(pid::fe_points,   1.0);
+        params.set(pid::fe_p1_level, 0.2);
+        params.set(pid::ae_p2_time,  0.7);
+        params.set(pid::limiter_on,  1.0);
+
+        // A preset, that was saved before the click layer,
+        // the breakpoints and the limiter existed:
+        params.load_preset(
+            b"!PARAMS;pub : freq_start=0.3;pub : freq_end=0.2;\
+              pub : freq_note_start=0;pub : freq_note_end=0;pub : dist_on=1;");
+
+        assert_eq!(params.param(pid::freq_start), 0.3);
+        assert_eq!(params.param(pid::dist_on),    1.0);
+        for idx in [
+            pid::click_level, pid::click_type, pid::fe_points,
+            pid::fe_p1_level, pid::ae_p2_time, pid::limiter_on,
+        ].iter() {
+            assert_eq!(params.param(*idx), def(*idx), "param {}", idx);
+        }
+    }
+
+    #[test]
+    fn check_engine_midi_learn() {
+        let mut engine = new_engine();
+        let mut out = vec![0.0; 64];
+
+        // Not assigned yet:
+        engine.handle_midi(&[0xB0, 7, 0], 0);
+        assert_eq!(engine.params().pop_dirty(), None);
+
+        engine.params().midi_learn().start(pid::gain);
+        engine.handle_midi(&[0xB0, 7, 127], 0);
+        engine.process(&mut out[..]);
+        assert_eq!(engine.params().param(pid::gain), 1.0);
+        assert_eq!(engine.params().midi_learn().cc_of(pid::gain), Some(7));
+
+        engine.handle_midi(&[0xB0, 7, 0], 10);
+        assert_eq!(engine.params().param(pid::gain), 0.0);
+
+        // CCs on other channels are ignored:
+        engine.handle_midi(&[0xB1, 7, 127], 0);
+        assert_eq!(engine.params().param(pid::gain), 0.0);
+    }
+
+    #[test]
+    fn check_engine_midi_learn_in_state() {
+        let params = EngineParams::new();
+        params.midi_learn().start(pid::gain);
+        params.midi_learn().handle_cc(7);
+        params.midi_learn().start(pid::freq_start);
+        params.midi_learn().handle_cc(21);
+
+        let params2 = EngineParams::new();
+        params2.midi_learn().start(pid::dist_on);
+        params2.midi_learn().handle_cc(1);
+        params2.load_preset(&params.serialize_preset()[..]);
+        assert_eq!(params2.midi_learn().assignments(),
+                   vec![(7, pid::gain), (21, pid::freq_start)]);
+        assert_eq!(params.serialize_preset(), params2.serialize_preset());
+
+        // A state without assignments removes them, a
+        // preset from before MIDI learn keeps them:
+        params2.load_preset(&serialize_preset(&EngineParams::new())[..]);
+        assert_eq!(params2.midi_learn().cc_of(pid::gain), Some(7));
+        params2.load_preset(&EngineParams::new().serialize_preset()[..]);
+        assert_eq!(params2.midi_learn().assignments(), vec![]);
+    }
+
+    #[test]
+    fn check_engine_randomize_skips_locked() {
+        let params = EngineParams::new();
+        params.set_locked(pid::freq_start, true);
+
+        let changed = params.randomize(42);
+        assert!(changed.contains(&pid::freq_end));
+        assert!(!changed.contains(&pid::freq_start));
+        assert!(!changed.contains(&pid::main_gain));
+        assert!(!changed.contains(&pid::limiter_on));
+
+        let def = |idx| params.ps().definition(idx).unwrap().default_p();
+        assert_eq!(params.param(pid::freq_start), def(pid::freq_start));
+        assert_eq!(params.param(pid::main_gain),  def(pid::main_gain));
+        assert!(changed.iter().any(|idx| params.param(*idx) != def(*idx)));
+
+        // The GUI picks up the changes:
+        assert_eq!(params.pop_dirty(), Some(changed[0]));
+    }
+}
@@ -398,6 +398,116 @@ pub mod generic {
     }
 }
 
+/// The number of breakpoints between the start and the end of the
+/// multi segment frequency and amplitude envelopes.
+pub const SHAPE_POINTS : usize = 3;
+
+/// Maps the normalized "Points" parameter to the number of active
+/// breakpoints of an `EnvShape`.
+pub fn shape_point_count(v: f32) -> usize {
+    ((v * (SHAPE_POINTS + 1) as f32).floor().max(0.0) as usize).min(SHAPE_POINTS)
+}
+
+/// The shape of a multi segment envelope, which falls from 1.0 at
+/// the start to 0.0 at the end, over up to `SHAPE_POINTS` breakpoints
+/// in between. Each segment has its own curvature: it moves from the
+/// level of one breakpoint to the next with `x^curve`.
+///
+/// Without breakpoints the shape is the plain `1 - x^end_curve`.
+#[derive(Debug, Clone, Copy)]
+pub struct EnvShape {
+    /// The (time, level, curve) of the breakpoints,
+    /// time and level from 0.0 to 1.0.
+    points:     [(f64, f64, f64); SHAPE_POINTS],
+    len:        usize,
+    end_curve:  f64,
+}
+
+impl EnvShape {
+    pub fn new(end_curve: f64) -> Self {
+        Self {
+            points: [(0.0, 0.0, 1.0); SHAPE_POINTS],
+            len:    0,
+            end_curve,
+        }
+    }
+
+    /// Builds the shape from the (time, level, curve) parameter ids in
+    /// `points`, of which the first `count` are active. `get` returns
+    /// the value of a parameter.
+    pub fn from_params<F: FnMut(usize) -> f64>(
+        points: &[(usize, usize, usize)], count: usize, end_curve: f64,
+        mut get: F) -> Self {
+
+        let mut shape = Self::new(end_curve);
+        for (time, level, curve) in points.iter().take(count) {
+            shape.push(get(*time), get(*level), get(*curve));
+        }
+        shape
+    }
+
+    /// Adds the next breakpoint. A breakpoint can't be placed
+    /// before the previous one.
+    pub fn push(&mut self, time: f64, level: f64, curve: f64) {
+        if self.len >= SHAPE_POINTS {
+            return;
+        }
+
+        let prev_time = if self.len > 0 { self.points[self.len - 1].0 } else { 0.0 };
+        self.points[self.len] = (
+            time.max(prev_time).min(1.0),
+            level.max(0.0).min(1.0),
+            curve,
+        );
+        self.len += 1;
+    }
+
+    /// The number of breakpoints.
+    pub fn len(&self) -> usize { self.len }
+
+    pub fn is_empty(&self) -> bool { self.len == 0 }
+
+    /// The time and level of the breakpoint `idx`.
+    pub fn point(&self, idx: usize) -> (f64, f64) {
+        (self.points[idx].0, self.points[idx].1)
+    }
+
+    /// The start and end time of the segment, that ends in the
+    /// breakpoint `idx`. The segment `len()` ends in the end of
+    /// the envelope.
+    pub fn segment(&self, idx: usize) -> (f64, f64) {
+        let start = if idx > 0 { self.points[idx - 1].0 } else { 0.0 };
+        let end   = if idx < self.len { self.points[idx].0 } else { 1.0 };
+        (start, end)
+    }
+
+    /// The value of the shape at `x` (0.0 to 1.0).
+    #[inline]
+    pub fn value(&self, x: f64) -> f64 {
+        let mut prev = (0.0, 1.0);
+
+        for i in 0..self.len {
+            let (time, level, curve) = self.points[i];
+            if x < time {
+                return Self::segment_value(prev, (time, level), curve, x);
+            }
+            prev = (time, level);
+        }
+
+        Self::segment_value(prev, (1.0, 0.0), self.end_curve, x)
+    }
+
+    #[inline]
+    fn segment_value(from: (f64, f64), to: (f64, f64), curve: f64, x: f64) -> f64 {
+        let w = to.0 - from.0;
+        if w <= 0.0 {
+            return to.1;
+        }
+
+        let t = ((x - from.0) / w).max(0.0).min(1.0);
+        from.1 + (to.1 - from.1) * t.powf(curve)
+    }
+}
 
 #[cfg(test)]
 mod tests {
@@ -861,4 +971,43 @@ mod tests {
         assert_float_tpl_eq!(out[17], (0.085, 0.9444));
         assert_float_tpl_eq!(out[18], (0.090, 1.0000));
     }
+
+    #[test]
+    fn check_env_shape_without_points() {
+        let shape = EnvShape::new(0.3);
+        for i in 0..10 {
+            let x = i as f64 / 10.0;
+            assert_float_eq!(shape.value(x), 1.0 - x.powf(0.3));
+        }
+    }
+
+    #[test]
+    fn check_env_shape_points() {
+        // Hold the start level, drop and a slow tail:
+        let mut shape = EnvShape::new(1.0);
+        shape.push(0.2, 1.0, 1.0);
+        shape.push(0.4, 0.2, 0.5);
+        assert_eq!(shape.len(), 2);
+
+        assert_float_eq!(shape.value(0.0),  1.0);
+        assert_float_eq!(shape.value(0.1),  1.0);
+        assert_float_eq!(shape.value(0.2),  1.0);
+        assert_float_eq!(shape.value(0.3),  1.0 - 0.8 * 0.5_f64.sqrt());
+        assert_float_eq!(shape.value(0.4),  0.2);
+        assert_float_eq!(shape.value(0.7),  0.1);
+        assert_float_eq!(shape.value(1.0),  0.0);
+
+        assert_float_tpl_eq!(shape.segment(1), (0.2, 0.4));
+        assert_float_tpl_eq!(shape.segment(2), (0.4, 1.0));
+
+        // A breakpoint can't go before the previous one:
+        shape.push(0.1, 0.5, 1.0);
+        assert_float_tpl_eq!(shape.point(2), (0.4, 0.5));
+        assert_float_eq!(shape.value(0.5), 0.5 * (1.0 - 0.1 / 0.6));
+
+        assert_eq!(shape_point_count(0.0),  0);
+        assert_eq!(shape_point_count(0.3),  1);
+        assert_eq!(shape_point_count(0.99), 3);
+        assert_eq!(shape_point_count(1.0),  3);
+    }
 }
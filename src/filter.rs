@@ -1,4 +1,5 @@
 use crate::helpers::*;
+use crate::denormal::{flush_denormal, flush_denormal_f64};
 
 // Digital approx. of Chamberlin two-pole low pass.
 // From: Author or source: Effect Deisgn Part 1, Jon Dattorro, J. Audio Eng. Soc.,
@@ -62,9 +63,9 @@ impl SvfFilterOversampled {
 
     fn run<P: FilterInputParams>(&mut self, input: f32, params: &P) -> f32 {
         let q = 1.0 - params.q();
-        self.low += self.f * self.band;
-        let high = q * (input - self.band) - self.low;
-        self.band += self.f * high;
+        self.low  = flush_denormal(self.low + self.f * self.band);
+        let high  = q * (input - self.band) - self.low;
+        self.band = flush_denormal(self.band + self.f * high);
 
         let ftype = params.typ();
 
@@ -136,7 +137,11 @@ impl MoogFilter {
         self.b4 = self.b4 - self.b4 * self.b4 * self.b4 * 0.166667;
         // clamp to keep feedback run-aways in check!
         self.b4 = self.b4.max(-1.5).min(1.5);
-        self.b0 = input;
+        self.b0 = flush_denormal(input);
+        self.b1 = flush_denormal(self.b1);
+        self.b2 = flush_denormal(self.b2);
+        self.b3 = flush_denormal(self.b3);
+        self.b4 = flush_denormal(self.b4);
 
 //        if input.abs() > 0.99 {
 //            log.log(|bw: &mut std::io::BufWriter<&mut [u8]>| {
@@ -194,8 +199,8 @@ impl SvfSimperFilter {
         let v1 = (a1 * self.ic1eq) + (a2 * v3);
         let v2 = self.ic2eq + (a2 * self.ic1eq) + (a3 * v3);
 
-        self.ic1eq = (2.0 * v1) - self.ic1eq;
-        self.ic2eq = (2.0 * v2) - self.ic2eq;
+        self.ic1eq = flush_denormal((2.0 * v1) - self.ic1eq);
+        self.ic2eq = flush_denormal((2.0 * v2) - self.ic2eq);
 
         // low   = v2
         // band  = v1
@@ -243,7 +248,64 @@ impl DCBlockFilter {
     pub fn next(&mut self, input: f32) -> f32 {
         let y = input as f64 - self.xm1 + self.R * self.ym1;
         self.xm1 = input as f64;
-        self.ym1 = y;
+        self.ym1 = flush_denormal_f64(y);
         y as f32
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct TestParams {
+        freq: f32,
+        q:    f32,
+        typ:  f32,
+    }
+
+    impl FilterInputParams for TestParams {
+        fn freq(&self)  -> f32 { self.freq }
+        fn q(&self)     -> f32 { self.q }
+        fn typ(&self)   -> f32 { self.typ }
+        fn drive(&self) -> f32 { 1.0 }
+    }
+
+    fn no_denormal(v: f32) -> bool { v == 0.0 || v.is_normal() }
+
+    /// An impulse followed by a signal that is far below the audible
+    /// range, without flush-to-zero mode of the CPU.
+    fn tiny_signal(i: usize) -> f32 {
+        if i == 0 { 1.0 } else { 1e-30 * ((i % 7) as f32 - 3.0) }
+    }
+
+    #[test]
+    fn check_filters_flush_denormals() {
+        for &(freq, q) in [(20.0, 0.0), (500.0, 0.5), (15000.0, 0.99)].iter() {
+            let params = TestParams { freq, q, typ: 0.0 };
+
+            let mut moog = MoogFilter::new();
+            let mut svf  = SvfSimperFilter::new();
+            let mut svfo = SvfFilterOversampled::new();
+            let mut dcb  = DCBlockFilter::new();
+
+            for i in 0..200000 {
+                let input = tiny_signal(i);
+                moog.next(input, &params);
+                svf.next(input, &params);
+                svfo.next(input, &params);
+                dcb.next(input);
+
+                for v in [moog.b0, moog.b1, moog.b2, moog.b3, moog.b4].iter() {
+                    assert!(no_denormal(*v), "moog state {} at {}", v, i);
+                }
+                assert!(no_denormal(svf.ic1eq) && no_denormal(svf.ic2eq), "svf at {}", i);
+                assert!(no_denormal(svfo.low) && no_denormal(svfo.band), "svfo at {}", i);
+                assert!(dcb.ym1 == 0.0 || dcb.ym1.is_normal(), "dc block at {}", i);
+            }
+
+            // The impulse has decayed completely:
+            assert_eq!(svf.ic1eq, 0.0);
+            assert_eq!(dcb.ym1,   0.0);
+        }
+    }
+}
@@ -104,6 +104,20 @@ impl RandGen {
         }
     }
 
+    /// A generator with the state derived from `seed`,
+    /// any seed gives a usable state.
+    pub fn new_seeded(seed: u64) -> Self {
+        let mut sm = seed;
+        let mut splitmix = || {
+            sm = sm.wrapping_add(0x9e3779b97f4a7c15);
+            let mut z = sm;
+            z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+            z ^ (z >> 31)
+        };
+        RandGen { r: [splitmix(), splitmix()] }
+    }
+
     pub fn next(&mut self) -> u64 {
         next_xoroshiro128(&mut self.r)
     }
@@ -139,6 +153,11 @@ pub fn note_to_freq(note: f32) -> f32 {
     440.0 * (2.0_f32).powf((note - 69.0) / 12.0)
 }
 
+/// The inverse of `note_to_freq`, returns a fractional MIDI note.
+pub fn freq_to_note(freq: f32) -> f32 {
+    69.0 + 12.0 * (freq / 440.0).log2()
+}
+
 // Ported from LMMS under GPLv2
 // * DspEffectLibrary.h - library with template-based inline-effects
 // * Copyright (c) 2006-2014 Tobias Doerffel <tobydox/at/users.sourceforge.net>
@@ -2,40 +2,47 @@
 // This is a part of Kickmess. See README.md and COPYING for details.
 
 #![allow(warnings)]
+#[macro_use]
+pub mod log;
 pub mod proc;
+pub mod engine;
+pub mod scope;
+pub mod spectrum;
+pub mod limiter;
+pub mod meter;
+pub mod midi_learn;
 pub mod helpers;
+mod denormal;
 mod op_kickmess;
 mod env;
 mod ringbuf_shared;
-mod param_model;
+pub mod param_model;
+pub mod metadata;
 mod filter;
 mod oscillator;
 mod lfo;
-mod log;
+mod oversampling;
+mod click;
+pub mod offline;
+pub mod lv2;
+pub mod clap;
+pub mod standalone;
 pub mod editor;
 pub mod ui;
 pub mod window;
 
 pub use op_kickmess::OpKickmess;
 
-use proc::{ParamProvider, VoiceManager, ParamDefinition, MonoVoice, SmoothParameters};
+use proc::{ParamProvider, ParamDefinition, MonoVoice};
 pub use proc::MonoProcessor;
 pub use proc::ParamSet;
+pub use engine::{Engine, EngineParams, HostAutomation};
 use op_kickmess::*;
 use helpers::note_to_freq;
-use log::Log;
-use core::arch::x86_64::{
-    _MM_FLUSH_ZERO_ON,
-    _MM_SET_FLUSH_ZERO_MODE,
-    _MM_GET_FLUSH_ZERO_MODE
-};
 
 #[macro_use]
 extern crate vst;
 
-pub const DEBUG_LOGGING : bool = true;
-
-use vst::util::AtomicFloat;
 use vst::api::Events;
 use vst::event::{Event, MidiEvent};
 use vst::buffer::AudioBuffer;
@@ -44,51 +51,67 @@ use vst::host::Host;
 
 use std::sync::Arc;
 
-const MAX_BLOCKSIZE: usize = 64;
-const MAX_POLY:      usize = 16;
+pub const MAX_BLOCKSIZE: usize = 64;
+pub const MAX_POLY:      usize = 16;
 
 pub const VERSION: &str = env!("CARGO_PKG_VERSION");
 
+/// The `audioMasterIOChanged` opcode, the host reads the `initialDelay` again.
+const AUDIO_MASTER_IO_CHANGED: i32 = 13;
+
 struct Kickmess {
     host:           HostCallback,
-    params:         Arc<KickmessVSTParams>,
-    voices:         VoiceManager<OpKickmess>,
-    smooth_param:   SmoothParameters,
-    log:            Log,
+    engine:         Engine,
+    /// The latency in the `initialDelay` field of the `AEffect`.
+    latency:        usize,
 }
 
 impl Default for Kickmess {
     fn default() -> Kickmess {
+        let engine = Engine::new(Arc::new(EngineParams::new()));
         Kickmess {
-            host:   HostCallback::default(),
-            params: Arc::new(KickmessVSTParams::default()),
-            voices: VoiceManager::new(MAX_POLY),
-            smooth_param: SmoothParameters::new(MAX_BLOCKSIZE, 0),
-            log:    Log::new(),
+            host:    HostCallback::default(),
+            latency: engine.latency(),
+            engine,
+        }
+    }
+}
+
+impl Kickmess {
+    /// Tells the host about a new latency of the engine, after a
+    /// sample rate change or when the limiter was switched.
+    fn update_latency(&mut self) {
+        let latency = self.engine.latency();
+        if latency == self.latency {
+            return;
+        }
+        self.latency = latency;
+
+        let effect = self.host.raw_effect();
+        if effect.is_null() {
+            return;
+        }
+
+        unsafe { (*effect).initialDelay = latency as i32; }
+        if let Some(callback) = self.host.raw_callback() {
+            callback(effect, AUDIO_MASTER_IO_CHANGED, 0, 0, std::ptr::null_mut(), 0.0);
         }
     }
 }
 
 impl Plugin for Kickmess {
     fn new(host: HostCallback) -> Self {
-        let params       = Arc::new(KickmessVSTParams::default());
-        let smooth_param = SmoothParameters::new(MAX_BLOCKSIZE, params.ps.param_count());
-
+        let engine = Engine::new(Arc::new(EngineParams::new()));
         Self {
             host,
-            voices: VoiceManager::new(MAX_POLY),
-            params,
-            smooth_param,
-            log: Log::new(),
+            latency: engine.latency(),
+            engine,
         }
     }
 
     fn init(&mut self) {
-        helpers::init_cos_tab();
-        if DEBUG_LOGGING {
-            use std::io::Write;
-            self.log.start_writer_thread();
-        }
+        log::init_from_env();
+        klog!(Host, Info, "init, version {}", VERSION);
     }
 
     fn get_info(&self) -> Info {
@@ -106,34 +129,24 @@ impl Plugin for Kickmess {
             outputs:       1,
             midi_inputs:   1,
             midi_outputs:  0,
-            parameters:    self.params.public_ps.param_count() as i32,
+            parameters:    self.engine.params().public_ps().param_count() as i32,
             version:       0221,
             category:      Category::Synth,
             preset_chunks: true,
+            initial_delay: self.latency as i32,
             ..Default::default()
         }
     }
 
     fn set_sample_rate(&mut self, rate: f32) {
-        self.voices.set_sample_rate(rate);
+        klog!(Host, Info, "set_sample_rate {}", rate);
+        self.engine.set_sample_rate(rate);
+        self.update_latency();
     }
 
     fn process(&mut self, buffer: &mut AudioBuffer<f32>) {
         let (_, mut outputbuf) = buffer.split();
-        let out_buf       = outputbuf.get_mut(0);
-        let mut remaining = out_buf.len();
-        let mut offs      = 0;
-
-        if DEBUG_LOGGING {
-            crate::log::global_set_log(&mut self.log);
-        }
-
-        let prev_ftz = unsafe { _MM_GET_FLUSH_ZERO_MODE() };
-        unsafe {
-            _MM_SET_FLUSH_ZERO_MODE(_MM_FLUSH_ZERO_ON);
-        }
-
-        for os in out_buf.iter_mut() { *os = 0.0; }
+        let out_buf = outputbuf.get_mut(0);
 
 //        let tiflag = {
 //            use vst::api::*;
@@ -180,48 +193,15 @@ impl Plugin for Kickmess {
 //            });
 //        }
 
-        loop {
-            let advance_frames =
-                if remaining > MAX_BLOCKSIZE { MAX_BLOCKSIZE } else { remaining };
-
-            let (lc, li) = (self.smooth_param.last_frame_cnt, self.smooth_param.last_frame_idx);
-
-//            self.log.log(|bw: &mut std::io::BufWriter<&mut [u8]>| {
-//                use std::io::Write;
-//                write!(bw, "adv: [{:4}] {:4} => {:4}, 3 in: {}",
-//                       lc, advance_frames, out_buf.len(),
-//                       self.params.param(3)).unwrap();
-//            });
-
-            self.smooth_param.advance_params(
-                advance_frames, out_buf.len(), &self.params.ps, &*self.params);
-
-            self.voices.process(
-                offs,
-                &mut out_buf[offs..(offs + advance_frames)],
-                &self.smooth_param);
-
-            offs      += advance_frames;
-            remaining -= advance_frames;
-            if remaining == 0 {
-                break;
-            }
-        }
-
-        unsafe {
-            _MM_SET_FLUSH_ZERO_MODE(prev_ftz);
-        }
+        self.engine.process(out_buf);
+        self.update_latency();
     }
 
     fn process_events(&mut self, events: &Events) {
         for e in events.events() {
             match e {
                 Event::Midi(MidiEvent { data, delta_frames, .. }) => {
-                    let my_channel =
-                        self.params.ps.get(
-                            crate::param_model::pid::midi_chan,
-                            &*self.params);
-                    self.voices.handle_midi(&data, delta_frames as usize, my_channel.floor() as u8);
+                    self.engine.handle_midi(&data, delta_frames as usize);
                 },
                 _ => (),
             }
@@ -239,112 +219,78 @@ impl Plugin for Kickmess {
     }
 
     fn get_parameter_object(&mut self) -> Arc<dyn PluginParameters> {
-        Arc::clone(&self.params) as Arc<dyn PluginParameters>
+        Arc::clone(self.engine.params()) as Arc<dyn PluginParameters>
     }
 
     fn get_editor(&mut self) -> Option<Box<dyn vst::editor::Editor>> {
         Some(Box::new(
             editor::KickmessEditor::new(
-                self.host,
-                self.params.clone(),
-                self.log.new_handle())))
+                Arc::new(self.host),
+                self.engine.params().clone())))
     }
 }
 
-pub(crate) struct KickmessVSTParams {
-    ps:             ParamSet,
-    public_ps:      ParamSet,
-    params:         Vec<AtomicFloat>,
-    dirty_params:   ringbuf_shared::RingBuf<usize>,
-}
-
-impl KickmessVSTParams {
-    fn set(&self, idx: usize, val: f32) {
-        if let Some(af) = self.params.get(idx) {
-            af.set(val);
-            self.dirty_params.push(idx);
-        }
+impl HostAutomation for HostCallback {
+    fn begin_edit(&self, idx: usize) {
+        Host::begin_edit(self, idx as i32);
     }
-}
 
-impl ParamProvider for KickmessVSTParams {
-    fn param(&self, idx: usize) -> f32 {
-        if let Some(af) = self.params.get(idx) {
-            af.get()
-        } else {
-            0.0
-        }
+    fn automate(&self, idx: usize, value: f32) {
+        Host::automate(self, idx as i32, value);
     }
-}
-
-fn new_default_atom(ps: &mut ParamSet, p: &ParamDefinition) -> AtomicFloat {
-    AtomicFloat::new(ps.definition(p.idx()).unwrap().default_p())
-}
-
-impl Default for KickmessVSTParams {
-    fn default() -> KickmessVSTParams {
-        let mut ps        = ParamSet::new();
-        let mut public_ps = ParamSet::new();
-        OpKickmess::init_params(&mut ps, &mut public_ps);
 
-        let mut params = vec![];
-
-        // 10 times the parameter count, just to make sure it fits if the
-        // DAW is sending too many updates per frame. Or the GUI thread is too
-        // slow.
-        let buf =
-            crate::ringbuf_shared::RingBuf::<usize>::new(
-                public_ps.param_count() * 10);
-
-        for idx in 0..ps.param_count() {
-            params.push(AtomicFloat::new(ps.definition(idx).unwrap().default_p()));
-        }
+    fn end_edit(&self, idx: usize) {
+        Host::end_edit(self, idx as i32);
+    }
 
-        KickmessVSTParams {
-            ps,
-            public_ps,
-            params,
-            dirty_params: buf,
+    fn resize_editor(&self, width: i32, height: i32) -> bool {
+        // The `Host` trait has no call for audioMasterSizeWindow,
+        // so it's sent with the raw host callback:
+        if let Some(callback) = self.raw_callback() {
+            let ret =
+                callback(
+                    self.raw_effect(),
+                    vst::host::OpCode::SizeWindow as i32,
+                    width, height as isize,
+                    std::ptr::null_mut(), 0.0);
+            ret != 0
+        } else {
+            false
         }
     }
 }
 
-impl PluginParameters for KickmessVSTParams {
+impl PluginParameters for EngineParams {
     fn get_parameter(&self, index: i32) -> f32 {
-        self.public_ps.get_raw(index as usize, self)
+        self.public_ps().get_raw(index as usize, self)
     }
 
     fn set_parameter(&self, index: i32, val: f32) {
-        if let Some(pd) = self.ps.definition(index as usize) {
+        if let Some(pd) = self.ps().definition(index as usize) {
             self.set(pd.idx(), val);
         }
     }
 
     fn get_parameter_text(&self, index: i32) -> String {
-        if index > self.public_ps.param_count() as i32 {
+        if index > self.public_ps().param_count() as i32 {
             return "".to_string();
         }
 
         let v = self.get_parameter(index);
-        let pd = self.public_ps.definition(index as usize).unwrap();
+        let pd = self.public_ps().definition(index as usize).unwrap();
         format!("{} <= {:.2} <= {}", pd.min(), pd.map(v), pd.max())
     }
 
     fn get_bank_data(&self) -> Vec<u8> {
-        crate::param_model::serialize_preset(self)
+        self.serialize_preset()
     }
 
     fn load_bank_data(&self, data: &[u8]) {
-        crate::param_model::deserialize_preset(
-            data, |idx, v| {
-                //d// println!("SET PARA {} = {}", idx, v);
-                dbg!("load data:", idx, v);
-                self.set_parameter(idx as i32, v)
-            });
+        self.load_preset(data);
     }
 
     fn get_parameter_name(&self, index: i32) -> String {
-        if let Some(pd) = self.public_ps.definition(index as usize) {
+        if let Some(pd) = self.public_ps().definition(index as usize) {
             pd.name().to_string()
         } else {
             "".to_string()
@@ -0,0 +1,338 @@
+// Copyright (c) 2020-2021 Weird Constructor <weirdconstructor@gmail.com>
+// This is a part of Kickmess. See README.md and COPYING for details.
+
+//! The true peak safety limiter on the output.
+//!
+//! The peaks between the samples are estimated by interpolating the
+//! output at 4 times the sample rate, like a true peak meter does.
+//! The gain reduction for a peak is held over the lookahead and faded
+//! in with a moving average, so that it is fully applied when the peak
+//! leaves the delay line. The limiter delays the output by the lookahead.
+//! While it is switched off, the input passes without any delay and the
+//! limiter only keeps its history up to date. Switching it crossfades
+//! between the direct and the delayed output.
+
+const PI : f64 = std::f64::consts::PI;
+
+/// The highest true peak level at the output.
+pub const LIMITER_CEILING_DB : f32 = -1.0;
+/// The lookahead and latency of the active limiter in milliseconds.
+pub const LIMITER_LOOKAHEAD_MS : f32 = 1.5;
+const LIMITER_RELEASE_MS : f32 = 80.0;
+/// The crossfade time for switching the limiter on or off.
+const LIMITER_FADE_MS : f32 = 10.0;
+
+/// The factor of the true peak interpolation.
+const TP_FACTOR : usize = 4;
+/// The taps of one phase of the interpolation filter.
+const TP_TAPS   : usize = 12;
+
+/// Estimates the true peak of a signal by interpolating
+/// it with a windowed sinc polyphase filter.
+#[derive(Debug, Clone)]
+struct TruePeakDetector {
+    /// The coefficients of the phases between the samples.
+    phases: Vec<[f32; TP_TAPS]>,
+    hist:   [f32; 2 * TP_TAPS],
+    pos:    usize,
+}
+
+impl TruePeakDetector {
+    fn new() -> Self {
+        let len    = TP_TAPS * TP_FACTOR;
+        let center = (len / 2) as f64;
+
+        let phases =
+            (1..TP_FACTOR).map(|phase| {
+                let mut coefs = [0.0; TP_TAPS];
+                for (k, c) in coefs.iter_mut().enumerate() {
+                    let n = (k * TP_FACTOR + phase) as f64;
+                    let x = (n - center) / TP_FACTOR as f64;
+                    let sinc = if x.abs() < 1e-9 { 1.0 } else { (PI * x).sin() / (PI * x) };
+
+                    // Blackman window:
+                    let wx = n / len as f64;
+                    let w =
+                        0.42
+                        - 0.5  * (2.0 * PI * wx).cos()
+                        + 0.08 * (4.0 * PI * wx).cos();
+
+                    *c = (sinc * w) as f32;
+                }
+                coefs
+            }).collect();
+
+        Self {
+            phases,
+            hist: [0.0; 2 * TP_TAPS],
+            pos:  0,
+        }
+    }
+
+    fn reset(&mut self) {
+        self.hist = [0.0; 2 * TP_TAPS];
+        self.pos  = 0;
+    }
+
+    /// The delay of the detector in samples.
+    fn delay() -> usize { TP_TAPS / 2 }
+
+    /// Only adds `x` to the history, without detecting the peak.
+    #[inline]
+    fn push(&mut self, x: f32) {
+        // The history is stored twice, see `HalfbandDecimator::push`:
+        self.pos = if self.pos == 0 { TP_TAPS - 1 } else { self.pos - 1 };
+        self.hist[self.pos]           = x;
+        self.hist[self.pos + TP_TAPS] = x;
+    }
+
+    /// Returns the absolute true peak around the sample
+    /// `TruePeakDetector::delay()` samples ago.
+    #[inline]
+    fn next(&mut self, x: f32) -> f32 {
+        self.push(x);
+
+        let hist = &self.hist[self.pos..(self.pos + TP_TAPS)];
+
+        let mut peak = hist[Self::delay()].abs();
+        for coefs in self.phases.iter() {
+            let y : f32 =
+                coefs.iter().rev().zip(hist.iter()).map(|(c, h)| c * h).sum();
+            peak = peak.max(y.abs());
+        }
+
+        peak
+    }
+}
+
+/// A lookahead limiter, that keeps the true peak of
+/// the output below `LIMITER_CEILING_DB`.
+#[derive(Debug, Clone)]
+pub struct TruePeakLimiter {
+    detector:   TruePeakDetector,
+    ceiling:    f32,
+    release:    f32,
+    /// The lookahead in samples.
+    lookahead:  usize,
+    /// The delayed input.
+    delay:      Vec<f32>,
+    /// The gain needed for the last peaks, held over the lookahead.
+    hold:       Vec<f32>,
+    /// The last gains, averaged to fade in the gain reduction.
+    ramp:       Vec<f32>,
+    ramp_sum:   f64,
+    env:        f32,
+    /// How much of the delayed and limited signal is in the
+    /// output, 0.0 is only the direct input.
+    mix:        f32,
+    mix_target: f32,
+    mix_step:   f32,
+    delay_pos:  usize,
+    hold_pos:   usize,
+    ramp_pos:   usize,
+}
+
+impl TruePeakLimiter {
+    pub fn new() -> Self {
+        let mut this = Self {
+            detector:   TruePeakDetector::new(),
+            ceiling:    10.0_f32.powf(LIMITER_CEILING_DB / 20.0),
+            release:    0.0,
+            lookahead:  1,
+            delay:      vec![],
+            hold:       vec![],
+            ramp:       vec![],
+            ramp_sum:   0.0,
+            env:        1.0,
+            mix:        1.0,
+            mix_target: 1.0,
+            mix_step:   0.0,
+            delay_pos:  0,
+            hold_pos:   0,
+            ramp_pos:   0,
+        };
+        this.set_sample_rate(44100.0);
+        this
+    }
+
+    /// Allocates the delay lines, must not be called on the audio thread.
+    pub fn set_sample_rate(&mut self, srate: f32) {
+        self.lookahead = ((srate * LIMITER_LOOKAHEAD_MS / 1000.0).ceil() as usize).max(1);
+        self.release   = 1.0 - (-1.0 / (srate * LIMITER_RELEASE_MS / 1000.0)).exp();
+        self.mix_step  = 1.0 / (srate * LIMITER_FADE_MS / 1000.0);
+
+        self.delay = vec![0.0; self.lookahead + TruePeakDetector::delay()];
+        // One more than the lookahead, for the peaks between the
+        // detected sample and the next one:
+        self.hold  = vec![1.0; self.lookahead + 2];
+        self.ramp  = vec![1.0; self.lookahead];
+        self.reset();
+    }
+
+    /// The delay of the output in samples, while the limiter is on.
+    pub fn latency(&self) -> usize { self.delay.len() }
+
+    /// Whether the limiter is switched on, also while it fades in or out.
+    pub fn is_active(&self) -> bool { self.mix_target > 0.0 }
+
+    /// Switches the limiter on or off, with a short crossfade.
+    pub fn set_active(&mut self, active: bool) {
+        if active && !self.is_active() && self.mix == 0.0 {
+            // The gain reduction from before the bypass is over:
+            self.reset_gain();
+        }
+
+        self.mix_target = if active { 1.0 } else { 0.0 };
+    }
+
+    pub fn reset(&mut self) {
+        self.detector.reset();
+        for d in self.delay.iter_mut() { *d = 0.0; }
+        self.reset_gain();
+        self.mix       = self.mix_target;
+        self.delay_pos = 0;
+    }
+
+    fn reset_gain(&mut self) {
+        for h in self.hold.iter_mut()  { *h = 1.0; }
+        for r in self.ramp.iter_mut()  { *r = 1.0; }
+        self.ramp_sum = self.ramp.len() as f64;
+        self.env       = 1.0;
+        self.hold_pos  = 0;
+        self.ramp_pos  = 0;
+    }
+
+    /// Limits the samples in place.
+    pub fn process(&mut self, buf: &mut [f32]) {
+        if self.mix == 0.0 && !self.is_active() {
+            // The input passes, the history is ready for switching it on:
+            for x in buf.iter() {
+                self.detector.push(*x);
+                self.delay[self.delay_pos] = *x;
+                self.delay_pos = (self.delay_pos + 1) % self.delay.len();
+            }
+            return;
+        }
+
+        for s in buf.iter_mut() {
+            let x = *s;
+
+            let peak = self.detector.next(x);
+            let gain = if peak > self.ceiling { self.ceiling / peak } else { 1.0 };
+
+            self.hold[self.hold_pos] = gain;
+            self.hold_pos = (self.hold_pos + 1) % self.hold.len();
+            let target = self.hold.iter().fold(1.0_f32, |m, g| m.min(*g));
+
+            if target < self.env {
+                self.env = target;
+            } else {
+                self.env += (target - self.env) * self.release;
+            }
+
+            self.ramp_sum += (self.env - self.ramp[self.ramp_pos]) as f64;
+            self.ramp[self.ramp_pos] = self.env;
+            self.ramp_pos = (self.ramp_pos + 1) % self.ramp.len();
+            if self.ramp_pos == 0 {
+                // Gets rid of the rounding errors of the running sum:
+                self.ramp_sum = self.ramp.iter().map(|r| *r as f64).sum();
+            }
+
+            let delayed = self.delay[self.delay_pos];
+            self.delay[self.delay_pos] = x;
+            self.delay_pos = (self.delay_pos + 1) % self.delay.len();
+
+            if self.mix < self.mix_target {
+                self.mix = (self.mix + self.mix_step).min(self.mix_target);
+            } else if self.mix > self.mix_target {
+                self.mix = (self.mix - self.mix_step).max(self.mix_target);
+            }
+
+            let gain = (self.ramp_sum / self.ramp.len() as f64) as f32;
+            *s = delayed * gain * self.mix + x * (1.0 - self.mix);
+        }
+    }
+}
+
+impl Default for TruePeakLimiter {
+    fn default() -> Self { Self::new() }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn true_peak(s: &[f32]) -> f32 {
+        let mut det = TruePeakDetector::new();
+        s.iter().chain([0.0; TP_TAPS].iter())
+            .fold(0.0_f32, |p, s| p.max(det.next(*s)))
+    }
+
+    #[test]
+    fn check_limiter_true_peak_detector() {
+        // A sine at a quarter of the sample rate, sampled at
+        // 45 degrees, has its peaks between the samples:
+        let s : Vec<f32> =
+            (0..200).map(|i| {
+                (std::f32::consts::PI * 0.5 * i as f32 + std::f32::consts::PI * 0.25).sin()
+            }).collect();
+
+        let sample_peak = s.iter().fold(0.0_f32, |p, s| p.max(s.abs()));
+        assert!((sample_peak - std::f32::consts::FRAC_1_SQRT_2).abs() < 0.001);
+
+        let tp = true_peak(&s[..]);
+        assert!(tp > 0.95 && tp < 1.05, "true peak {}", tp);
+    }
+
+    #[test]
+    fn check_limiter_keeps_ceiling() {
+        let mut lim = TruePeakLimiter::new();
+        lim.set_sample_rate(44100.0);
+
+        let mut s : Vec<f32> =
+            (0..4410).map(|i| {
+                let amp = if i < 2000 { 0.25 } else { 2.0 };
+                amp * (2.0 * std::f32::consts::PI * 60.0 * i as f32 / 44100.0).sin()
+            }).collect();
+        let input = s.clone();
+        lim.process(&mut s[..]);
+
+        let ceiling = 10.0_f32.powf(LIMITER_CEILING_DB / 20.0);
+        assert!(true_peak(&s[..]) <= ceiling * 1.01, "true peak {}", true_peak(&s[..]));
+
+        // Quiet parts pass unchanged, only delayed:
+        let lat = lim.latency();
+        for i in 0..(2000 - lat) {
+            assert!((s[i + lat] - input[i]).abs() < 1e-5, "sample {}", i);
+        }
+    }
+
+    #[test]
+    fn check_limiter_bypass_has_no_latency() {
+        let mut lim = TruePeakLimiter::new();
+        lim.set_sample_rate(44100.0);
+        lim.set_active(false);
+        lim.reset();
+
+        let mut s : Vec<f32> =
+            (0..4410).map(|i| {
+                2.0 * (2.0 * std::f32::consts::PI * 60.0 * i as f32 / 44100.0).sin()
+            }).collect();
+        let input = s.clone();
+
+        lim.process(&mut s[0..2000]);
+        lim.set_active(true);
+        lim.process(&mut s[2000..]);
+
+        // Bypassed the input passes unchanged:
+        assert_eq!(s[0..2000], input[0..2000]);
+
+        // Switching it on fades over to the delayed output, without a jump:
+        let max_step =
+            s.windows(2).fold(0.0_f32, |m, w| m.max((w[1] - w[0]).abs()));
+        assert!(max_step < 0.05, "step {}", max_step);
+
+        let ceiling = 10.0_f32.powf(LIMITER_CEILING_DB / 20.0);
+        assert!(true_peak(&s[3000..]) <= ceiling * 1.01);
+    }
+}
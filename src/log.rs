@@ -1,170 +1,576 @@
-use crate::ringbuf_shared::*;
+// Copyright (c) 2020-2021 Weird Constructor <weirdconstructor@gmail.com>
+// This is a part of Kickmess. See README.md and COPYING for details.
+
+//! Real-time safe logging.
+//!
+//! Messages are logged with the `klog!` macro, with a `Category` and a
+//! `Level`. Each category has its own level, which can be changed at
+//! any time with `set_level`. All levels are `Off` by default, then
+//! `klog!` costs only an atomic load.
+//!
+//! The message is formatted into a fixed size buffer on the stack and
+//! put into a lock-free queue, so logging never allocates or blocks,
+//! also not on the audio thread. Messages longer than `MSG_LEN` bytes
+//! are truncated, and messages are dropped when the queue is full.
+//! A writer thread passes the queued messages to the `LogSink`, which
+//! is set with `set_sink`.
+//!
+//! The plugins call `init_from_env`, which reads the levels from the
+//! `KICKMESS_LOG` environment variable, for example `KICKMESS_LOG=debug`
+//! or `KICKMESS_LOG=midi=trace,gui=info`. The messages go to stderr,
+//! or to the file in `KICKMESS_LOG_FILE`.
+
 use std::io::Write;
-use std::cell::RefCell;
+use std::sync::atomic::{AtomicU8, AtomicU64, AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+/// Maximum length of a message in bytes.
+pub const MSG_LEN : usize = 120;
+
+/// Number of messages the queue holds until the writer thread drains it,
+/// must be a power of two.
+const QUEUE_LEN : usize = 512;
+const WORDS     : usize = 1 + MSG_LEN / 8;
+
+/// How often the writer thread passes the messages to the sink.
+const WRITER_INTERVAL_MS : u64 = 50;
 
-thread_local! {
-    pub static LOG: RefCell<Option<LogHandle>> = RefCell::new(None);
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+pub enum Level {
+    Off   = 0,
+    Error = 1,
+    Warn  = 2,
+    Info  = 3,
+    Debug = 4,
+    Trace = 5,
 }
 
-pub fn global_set_log(l: &mut Log) {
-    let hdl = l.new_handle();
-    LOG.with(move |f| {
-        *f.borrow_mut() = Some(hdl);
-    });
+impl Level {
+    fn from_u8(l: u8) -> Self {
+        match l {
+            1 => Level::Error,
+            2 => Level::Warn,
+            3 => Level::Info,
+            4 => Level::Debug,
+            5 => Level::Trace,
+            _ => Level::Off,
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "off"   => Some(Level::Off),
+            "error" => Some(Level::Error),
+            "warn"  => Some(Level::Warn),
+            "info"  => Some(Level::Info),
+            "debug" => Some(Level::Debug),
+            "trace" => Some(Level::Trace),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Level::Off   => "off",
+            Level::Error => "error",
+            Level::Warn  => "warn",
+            Level::Info  => "info",
+            Level::Debug => "debug",
+            Level::Trace => "trace",
+        }
+    }
 }
 
-pub fn log<F: Fn(&mut std::io::BufWriter<&mut [u8]>)>(f: F) {
-    use std::borrow::Borrow;
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Category {
+    /// MIDI events from the host or the MIDI input.
+    Midi = 0,
+    /// The audio thread and the DSP code.
+    Dsp  = 1,
+    /// The editor, its window and the UI events.
+    Gui  = 2,
+    /// The calls from the plugin host.
+    Host = 3,
+}
+
+pub const CATEGORIES : [Category; 4] =
+    [Category::Midi, Category::Dsp, Category::Gui, Category::Host];
+
+impl Category {
+    fn from_u8(c: u8) -> Self {
+        CATEGORIES[(c as usize) % CATEGORIES.len()]
+    }
 
-    LOG.with(|l| {
-        let lh = l.borrow_mut();
-        if let Some(lh) = (*(*lh.borrow())).as_ref() {
-            lh.log(f);
+    pub fn from_name(name: &str) -> Option<Self> {
+        CATEGORIES.iter().copied().find(|c| c.name() == name)
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Category::Midi => "midi",
+            Category::Dsp  => "dsp",
+            Category::Gui  => "gui",
+            Category::Host => "host",
         }
-    });
+    }
 }
 
-struct LogEntry {
-    buf: [u8; 128],
+#[allow(clippy::declare_interior_mutable_const)]
+const LEVEL_OFF : AtomicU8 = AtomicU8::new(0);
+
+static LEVELS : [AtomicU8; 4] = [LEVEL_OFF; 4];
+
+/// Sets the most verbose level, that is logged in the category.
+pub fn set_level(cat: Category, level: Level) {
+    LEVELS[cat as usize].store(level as u8, Ordering::Relaxed);
 }
 
-pub struct Log {
-    rb: RingBuf<LogEntry>,
-    th: Option<std::thread::JoinHandle<()>>,
+/// Sets the level of all categories.
+pub fn set_all_levels(level: Level) {
+    for cat in CATEGORIES.iter() {
+        set_level(*cat, level);
+    }
 }
 
-pub struct LogHandle {
-    rb: RingBuf<LogEntry>,
+pub fn level(cat: Category) -> Level {
+    Level::from_u8(LEVELS[cat as usize].load(Ordering::Relaxed))
 }
 
-impl LogHandle {
-    pub fn log_str(&self, s: &str) {
-        let mut ent = LogEntry { buf: [0; 128] };
-        {
-            let mut bw = std::io::BufWriter::new(&mut ent.buf[..]);
-            use std::io::Write;
-            write!(bw, "{}", s).unwrap();
-        }
-        self.rb.push(ent);
+/// Whether a message of `level` in `cat` would be logged.
+#[inline]
+pub fn enabled(cat: Category, level: Level) -> bool {
+    level != Level::Off
+    && (level as u8) <= LEVELS[cat as usize].load(Ordering::Relaxed)
+}
+
+/// A logged message, as it is passed to the `LogSink`.
+#[derive(Clone)]
+pub struct LogRecord {
+    pub level:    Level,
+    pub category: Category,
+    len:          usize,
+    msg:          [u8; MSG_LEN],
+}
+
+impl LogRecord {
+    pub fn message(&self) -> &str {
+        // The message is truncated at a char boundary by `MsgWriter`:
+        std::str::from_utf8(&self.msg[..self.len]).unwrap_or("?")
     }
+}
 
-    pub fn log<F: Fn(&mut std::io::BufWriter<&mut [u8]>)>(&self, f: F) {
-        let mut ent = LogEntry { buf: [0; 128] };
-        {
-            let mut bw = std::io::BufWriter::new(&mut ent.buf[..]);
-            f(&mut bw);
-        }
-        self.rb.push(ent);
+impl std::fmt::Display for LogRecord {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "[{:5}] {:4}: {}", self.level.name(), self.category.name(), self.message())
     }
 }
 
-impl Log {
-    pub fn new() -> Self {
-        Self {
-            rb: RingBuf::new(1024),
-            th: None,
+/// Formats into a fixed buffer and silently drops what doesn't fit.
+struct MsgWriter {
+    buf: [u8; MSG_LEN],
+    len: usize,
+}
+
+impl std::fmt::Write for MsgWriter {
+    fn write_str(&mut self, s: &str) -> std::fmt::Result {
+        let mut n = s.len().min(MSG_LEN - self.len);
+        while !s.is_char_boundary(n) {
+            n -= 1;
         }
+
+        self.buf[self.len..(self.len + n)].copy_from_slice(&s.as_bytes()[..n]);
+        self.len += n;
+        Ok(())
     }
+}
 
-    pub fn log<F: Fn(&mut std::io::BufWriter<&mut [u8]>)>(&self, f: F) {
-        let mut ent = LogEntry { buf: [0; 128] };
-        {
-            let mut bw = std::io::BufWriter::new(&mut ent.buf[..]);
-            f(&mut bw);
-        }
-        self.rb.push(ent);
+/// A slot of the queue. The message is stored in atomic words,
+/// so that writing and reading a slot needs no unsafe code.
+struct Slot {
+    seq:   AtomicUsize,
+    words: [AtomicU64; WORDS],
+}
+
+#[allow(clippy::declare_interior_mutable_const)]
+const WORD : AtomicU64 = AtomicU64::new(0);
+#[allow(clippy::declare_interior_mutable_const)]
+const SLOT : Slot = Slot { seq: AtomicUsize::new(0), words: [WORD; WORDS] };
+
+/// A bounded lock-free multi producer queue, after Dmitry Vyukov.
+///
+/// Each slot has a sequence number, that tells whether it is free for
+/// the producer at position `pos` (`seq == pos`) or filled for the
+/// consumer (`seq == pos + 1`). To allow a constant initialization, the
+/// stored number is relative to the slot index: `seq = stored + index`.
+struct Queue {
+    slots:   [Slot; QUEUE_LEN],
+    enqueue: AtomicUsize,
+    dequeue: AtomicUsize,
+    dropped: AtomicUsize,
+}
+
+static QUEUE : Queue = Queue {
+    slots:   [SLOT; QUEUE_LEN],
+    enqueue: AtomicUsize::new(0),
+    dequeue: AtomicUsize::new(0),
+    dropped: AtomicUsize::new(0),
+};
+
+impl Queue {
+    fn seq(&self, pos: usize) -> usize {
+        let idx = pos % QUEUE_LEN;
+        self.slots[idx].seq.load(Ordering::Acquire).wrapping_add(idx)
     }
 
-    pub fn new_handle(&self) -> LogHandle {
-        LogHandle { rb: self.rb.clone() }
+    fn set_seq(&self, pos: usize, seq: usize) {
+        let idx = pos % QUEUE_LEN;
+        self.slots[idx].seq.store(seq.wrapping_sub(idx), Ordering::Release);
     }
 
-    pub fn collect(&self) -> Option<String> {
-        let mut out_bytes = vec![];
-        while let Some(le) = self.rb.pop() {
-            let mut end = 0;
-            for i in 0..le.buf.len() {
-                if le.buf[i] == 0 {
-                    end = i;
-                    break;
+    fn push(&self, cat: Category, level: Level, msg: &[u8]) -> bool {
+        let mut pos = self.enqueue.load(Ordering::Relaxed);
+
+        loop {
+            let dif = self.seq(pos).wrapping_sub(pos) as isize;
+
+            if dif == 0 {
+                if let Err(p) =
+                    self.enqueue.compare_exchange_weak(
+                        pos, pos.wrapping_add(1),
+                        Ordering::Relaxed, Ordering::Relaxed)
+                {
+                    pos = p;
+                    continue;
                 }
+
+                let words = &self.slots[pos % QUEUE_LEN].words;
+                words[0].store(
+                    (level as u64) | ((cat as u64) << 8) | ((msg.len() as u64) << 16),
+                    Ordering::Relaxed);
+
+                for (word, chunk) in words[1..].iter().zip(msg.chunks(8)) {
+                    let mut bytes = [0u8; 8];
+                    bytes[..chunk.len()].copy_from_slice(chunk);
+                    word.store(u64::from_le_bytes(bytes), Ordering::Relaxed);
+                }
+
+                self.set_seq(pos, pos.wrapping_add(1));
+                return true;
+
+            } else if dif < 0 {
+                self.dropped.fetch_add(1, Ordering::Relaxed);
+                return false;
+
+            } else {
+                pos = self.enqueue.load(Ordering::Relaxed);
             }
-            out_bytes.extend_from_slice(&le.buf[0..end]);
-            out_bytes.extend_from_slice(b"\r\n");
         }
-        if out_bytes.len() > 0 {
-            Some(String::from_utf8_lossy(&out_bytes).to_string())
-        } else {
-            None
+    }
+
+    fn pop(&self) -> Option<LogRecord> {
+        let mut pos = self.dequeue.load(Ordering::Relaxed);
+
+        loop {
+            let dif = self.seq(pos).wrapping_sub(pos.wrapping_add(1)) as isize;
+
+            if dif == 0 {
+                if let Err(p) =
+                    self.dequeue.compare_exchange_weak(
+                        pos, pos.wrapping_add(1),
+                        Ordering::Relaxed, Ordering::Relaxed)
+                {
+                    pos = p;
+                    continue;
+                }
+
+                let words  = &self.slots[pos % QUEUE_LEN].words;
+                let header = words[0].load(Ordering::Relaxed);
+
+                let mut rec = LogRecord {
+                    level:    Level::from_u8((header & 0xFF) as u8),
+                    category: Category::from_u8(((header >> 8) & 0xFF) as u8),
+                    len:      ((header >> 16) as usize).min(MSG_LEN),
+                    msg:      [0; MSG_LEN],
+                };
+
+                for (chunk, word) in rec.msg.chunks_mut(8).zip(words[1..].iter()) {
+                    let bytes = word.load(Ordering::Relaxed).to_le_bytes();
+                    let n = chunk.len();
+                    chunk.copy_from_slice(&bytes[..n]);
+                }
+
+                self.set_seq(pos, pos.wrapping_add(QUEUE_LEN));
+                return Some(rec);
+
+            } else if dif < 0 {
+                return None;
+
+            } else {
+                pos = self.dequeue.load(Ordering::Relaxed);
+            }
+        }
+    }
+}
+
+/// Formats and queues a message, use `klog!` instead, which checks
+/// with `enabled` first. Never allocates or blocks.
+pub fn write(cat: Category, level: Level, args: std::fmt::Arguments) {
+    let mut w = MsgWriter { buf: [0; MSG_LEN], len: 0 };
+    let _ = std::fmt::write(&mut w, args);
+    QUEUE.push(cat, level, &w.buf[..w.len]);
+}
+
+/// Logs a message, if the level of the category is enabled:
+///
+/// ```
+/// kickmessvst::klog!(Midi, Debug, "note on: {} vel={}", 36, 127);
+/// ```
+#[macro_export]
+macro_rules! klog {
+    ($cat: ident, $level: ident, $($arg: tt)*) => {
+        if $crate::log::enabled($crate::log::Category::$cat, $crate::log::Level::$level) {
+            $crate::log::write(
+                $crate::log::Category::$cat,
+                $crate::log::Level::$level,
+                format_args!($($arg)*));
         }
     }
+}
+
+/// Removes all queued messages and passes them to `f`.
+/// This is done by the writer thread, if a sink is set.
+pub fn drain<F: FnMut(&LogRecord)>(mut f: F) {
+    while let Some(rec) = QUEUE.pop() {
+        f(&rec);
+    }
+}
+
+/// Returns the number of messages dropped because the queue was full,
+/// and resets it.
+pub fn take_dropped() -> usize {
+    QUEUE.dropped.swap(0, Ordering::Relaxed)
+}
+
+/// Receives the logged messages on the writer thread.
+pub trait LogSink: Send {
+    fn write(&mut self, rec: &LogRecord);
+    fn flush(&mut self) {}
+}
+
+pub struct StderrSink;
+
+impl LogSink for StderrSink {
+    fn write(&mut self, rec: &LogRecord) {
+        eprintln!("kickmess {}", rec);
+    }
+}
 
-    pub fn start_writer_thread(&mut self) -> String {
-        use std::fs::OpenOptions;
+/// Appends the messages to a file.
+pub struct FileSink {
+    file: std::io::BufWriter<std::fs::File>,
+}
 
-        {
-            let mut file =
-                OpenOptions::new()
+impl FileSink {
+    pub fn open(path: &str) -> std::io::Result<Self> {
+        let file =
+            std::fs::OpenOptions::new()
                 .create(true)
-                .write(true)
                 .append(true)
-                .open("/tmp/kickmess.log");
-            let mut file =
-                match file {
-                    Ok(file) => file,
-                    Err(e) => return format!("File open error: {}", e),
-                };
+                .open(path)?;
+        Ok(Self { file: std::io::BufWriter::new(file) })
+    }
+}
 
-            match file.write_all(b"--------------- START -----------\r\n") {
-                Ok(_) => (),
-                Err(e) => {
-                    return format!("File open error: {}", e);
-                },
+impl LogSink for FileSink {
+    fn write(&mut self, rec: &LogRecord) {
+        let _ = writeln!(self.file, "{}", rec);
+    }
+
+    fn flush(&mut self) {
+        let _ = self.file.flush();
+    }
+}
+
+static SINK : Mutex<Option<Box<dyn LogSink>>> = Mutex::new(None);
+static START_WRITER : std::sync::Once = std::sync::Once::new();
+
+/// Passes the queued messages to the sink.
+fn write_to_sink() {
+    if let Ok(mut sink) = SINK.lock() {
+        if let Some(sink) = sink.as_mut() {
+            drain(|rec| sink.write(rec));
+
+            let dropped = take_dropped();
+            if dropped > 0 {
+                let mut w = MsgWriter { buf: [0; MSG_LEN], len: 0 };
+                let _ = std::fmt::write(
+                    &mut w, format_args!("{} messages dropped", dropped));
+                sink.write(&LogRecord {
+                    level:    Level::Warn,
+                    category: Category::Dsp,
+                    len:      w.len,
+                    msg:      w.buf,
+                });
             }
+
+            sink.flush();
+        }
+    }
+}
+
+/// Sets the sink for the messages and starts the writer thread,
+/// if it is not running yet.
+pub fn set_sink(sink: Box<dyn LogSink>) {
+    if let Ok(mut s) = SINK.lock() {
+        *s = Some(sink);
+    }
+
+    START_WRITER.call_once(|| {
+        let _ = std::thread::Builder::new()
+            .name("kickmess-log".to_string())
+            .spawn(|| loop {
+                std::thread::sleep(
+                    std::time::Duration::from_millis(WRITER_INTERVAL_MS));
+                write_to_sink();
+            });
+    });
+}
+
+/// Parses a level specification like `debug` or `midi=trace,gui=info`.
+/// A category without level is logged at `Debug`.
+pub fn parse_levels(spec: &str) -> Result<[Level; 4], String> {
+    let mut levels = [Level::Off; 4];
+
+    for item in spec.split(',').map(|s| s.trim()).filter(|s| !s.is_empty()) {
+        let mut parts = item.splitn(2, '=');
+        let name  = parts.next().unwrap_or("");
+        let value = parts.next();
+
+        if let Some(value) = value {
+            let cat   = Category::from_name(name)
+                .ok_or_else(|| format!("Unknown log category: '{}'", name))?;
+            let level = Level::from_name(value)
+                .ok_or_else(|| format!("Unknown log level: '{}'", value))?;
+            levels[cat as usize] = level;
+
+        } else if let Some(level) = Level::from_name(name) {
+            levels = [level; 4];
+
+        } else if let Some(cat) = Category::from_name(name) {
+            levels[cat as usize] = Level::Debug;
+
+        } else {
+            return Err(format!("Unknown log level or category: '{}'", name));
         }
+    }
+
+    Ok(levels)
+}
+
+/// Sets the levels from `KICKMESS_LOG` and the sink from
+/// `KICKMESS_LOG_FILE`, see the module documentation.
+pub fn init_from_env() {
+    let spec =
+        match std::env::var("KICKMESS_LOG") {
+            Ok(spec) => spec,
+            Err(_)   => return,
+        };
 
-        let rb = self.rb.clone();
-        let th = std::thread::spawn(move || {
-            std::thread::spawn(move || {
-                let mut i = 0;
-                loop {
-                    i += 1;
-                    let mut out_bytes = vec![];
-                    while let Some(le) = rb.pop() {
-                        let mut end = 0;
-                        for i in 0..le.buf.len() {
-                            if le.buf[i] == 0 {
-                                end = i;
-                                break;
-                            }
-                        }
-                        out_bytes.extend_from_slice(&le.buf[0..end]);
-                        out_bytes.extend_from_slice(b"\r\n");
-                    }
-
-                    if i % 10 == 0 {
-                        out_bytes.extend_from_slice(b"TICK\r\n");
-                    }
-
-                    if out_bytes.len() > 0 {
-                        let mut file =
-                            OpenOptions::new()
-                            .create(true)
-                            .write(true)
-                            .append(true)
-                            .open("/tmp/kickmess.log").unwrap();
-
-                        file.write_all(&out_bytes[..]).unwrap();
-                        file.flush();
-                    }
-
-                    std::thread::sleep(std::time::Duration::from_millis(500));
+    let levels =
+        match parse_levels(&spec) {
+            Ok(levels) => levels,
+            Err(e) => {
+                eprintln!("kickmess: KICKMESS_LOG: {}", e);
+                return;
+            }
+        };
+
+    for (cat, level) in CATEGORIES.iter().zip(levels.iter()) {
+        set_level(*cat, *level);
+    }
+
+    match std::env::var("KICKMESS_LOG_FILE") {
+        Ok(path) => {
+            match FileSink::open(&path) {
+                Ok(sink) => set_sink(Box::new(sink)),
+                Err(e) => {
+                    eprintln!("kickmess: Couldn't open log file '{}': {}", path, e);
+                    set_sink(Box::new(StderrSink));
                 }
-            })
+            }
+        },
+        Err(_) => set_sink(Box::new(StderrSink)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_log_parse_levels() {
+        use Level::*;
+
+        assert_eq!(parse_levels("").unwrap(),      [Off; 4]);
+        assert_eq!(parse_levels("debug").unwrap(), [Debug; 4]);
+        assert_eq!(parse_levels("info,midi=trace,gui").unwrap(),
+                   [Trace, Info, Debug, Info]);
+        assert!(parse_levels("midi=loud").is_err());
+        assert!(parse_levels("audio").is_err());
+    }
+
+    #[test]
+    fn check_log_queue() {
+        // The queue is global, other tests might log to the Host
+        // category at the same time:
+        set_level(Category::Host, Level::Info);
+        assert!(enabled(Category::Host, Level::Warn));
+        assert!(!enabled(Category::Host, Level::Debug));
+
+        klog!(Host, Info,  "check_log_queue {}", 44100);
+        klog!(Host, Debug, "check_log_queue not logged");
+        klog!(Host, Warn,  "check_log_queue {}", "ä".repeat(100));
+
+        let mut recs = vec![];
+        drain(|rec| {
+            if rec.message().starts_with("check_log_queue") {
+                recs.push(rec.clone());
+            }
         });
 
-        th.join();
+        assert_eq!(recs.len(), 2);
+        assert_eq!(recs[0].message(), "check_log_queue 44100");
+        assert_eq!(recs[0].level, Level::Info);
+        assert_eq!(recs[0].to_string(), "[info ] host: check_log_queue 44100");
+        // Truncated at a char boundary:
+        assert_eq!(recs[1].message().len(), MSG_LEN);
+        assert!(recs[1].message().ends_with("ää"));
+
+        set_level(Category::Host, Level::Off);
+    }
+
+    #[test]
+    fn check_log_queue_full() {
+        let q = Queue {
+            slots:   [SLOT; QUEUE_LEN],
+            enqueue: AtomicUsize::new(0),
+            dequeue: AtomicUsize::new(0),
+            dropped: AtomicUsize::new(0),
+        };
+
+        for round in 0..3 {
+            for i in 0..QUEUE_LEN {
+                assert!(q.push(Category::Dsp, Level::Info, format!("{}", i).as_bytes()));
+            }
+            assert!(!q.push(Category::Dsp, Level::Info, b"full"));
+
+            for i in 0..QUEUE_LEN {
+                let rec = q.pop().unwrap();
+                assert_eq!(rec.message(), format!("{}", i), "round {}", round);
+            }
+            assert!(q.pop().is_none());
+        }
 
-        String::from("ok")
+        assert_eq!(q.dropped.load(Ordering::Relaxed), 3);
     }
 }
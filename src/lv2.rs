@@ -0,0 +1,745 @@
+// Copyright (c) 2020-2021 Weird Constructor <weirdconstructor@gmail.com>
+// This is a part of Kickmess. See README.md and COPYING for details.
+
+//! LV2 plugin around the same DSP core as the VST plugin.
+//!
+//! There is one control port for every parameter of the `param_model!`
+//! table, followed by an atom port for MIDI input, the audio output and
+//! a control output, that reports the latency of the output limiter.
+//! The TTL files are generated from the same table by `plugin_ttl()` and
+//! `manifest_ttl()`, see the `kickmess-lv2-ttl` tool. The parameters are
+//! saved with the LV2 state extension in the format of `serialize_preset`.
+//!
+//! The `lv2_descriptor` entry point is only exported with the `lv2` feature.
+
+use std::os::raw::{c_char, c_void};
+use std::ffi::{CStr, CString};
+
+use crate::proc::{ParamSet, MonoProcessor};
+use crate::param_model::{PARAM_COUNT, param_infos, param_symbols, pid};
+use crate::engine::{Engine, EngineParams};
+use crate::OpKickmess;
+use std::sync::Arc;
+
+#[cfg(not(feature="mega"))]
+macro_rules! plugin_uri { () => { "https://github.com/WeirdConstructor/kickmess" } }
+#[cfg(feature="mega")]
+macro_rules! plugin_uri { () => { "https://github.com/WeirdConstructor/kickmess/megamess" } }
+
+pub const PLUGIN_URI  : &str = plugin_uri!();
+pub const PLUGIN_NAME : &str = if cfg!(feature="mega") { "Megamess" } else { "Kickmess" };
+/// File name of the shared library in the bundle.
+pub const BINARY      : &str = "libkickmessvst.so";
+
+pub const PORT_MIDI_IN : usize = PARAM_COUNT;
+pub const PORT_OUT     : usize = PARAM_COUNT + 1;
+pub const PORT_LATENCY : usize = PARAM_COUNT + 2;
+pub const PORT_COUNT   : usize = PARAM_COUNT + 3;
+
+const URID_MAP_URI        : &str = "http://lv2plug.in/ns/ext/urid#map";
+const MIDI_EVENT_URI      : &str = "http://lv2plug.in/ns/ext/midi#MidiEvent";
+const ATOM_STRING_URI     : &str = "http://lv2plug.in/ns/ext/atom#String";
+const STATE_INTERFACE_URI : &str = "http://lv2plug.in/ns/ext/state#interface";
+const STATE_PRESET_URI    : &str = concat!(plugin_uri!(), "#preset");
+
+const LV2_STATE_SUCCESS         : u32 = 0;
+const LV2_STATE_ERR_BAD_TYPE    : u32 = 2;
+const LV2_STATE_ERR_NO_PROPERTY : u32 = 5;
+const LV2_STATE_IS_POD          : u32 = 1;
+const LV2_STATE_IS_PORTABLE     : u32 = 2;
+
+#[repr(C)]
+pub struct Lv2Feature {
+    pub uri:  *const c_char,
+    pub data: *mut c_void,
+}
+
+#[repr(C)]
+pub struct Lv2UridMap {
+    pub handle: *mut c_void,
+    pub map:    unsafe extern "C" fn(handle: *mut c_void, uri: *const c_char) -> u32,
+}
+
+#[repr(C)]
+pub struct Lv2Atom {
+    pub size:  u32,
+    pub type_: u32,
+}
+
+#[repr(C)]
+pub struct Lv2AtomSequence {
+    pub atom: Lv2Atom,
+    pub unit: u32,
+    pub pad:  u32,
+}
+
+#[repr(C)]
+pub struct Lv2AtomEvent {
+    pub frames: i64,
+    pub body:   Lv2Atom,
+}
+
+pub type Lv2StateStore =
+    unsafe extern "C" fn(handle: *mut c_void, key: u32, value: *const c_void,
+                         size: usize, type_: u32, flags: u32) -> u32;
+pub type Lv2StateRetrieve =
+    unsafe extern "C" fn(handle: *mut c_void, key: u32, size: *mut usize,
+                         type_: *mut u32, flags: *mut u32) -> *const c_void;
+
+#[repr(C)]
+pub struct Lv2StateInterface {
+    pub save:
+        unsafe extern "C" fn(instance: *mut c_void, store: Lv2StateStore,
+                             handle: *mut c_void, flags: u32,
+                             features: *const *const Lv2Feature) -> u32,
+    pub restore:
+        unsafe extern "C" fn(instance: *mut c_void, retrieve: Lv2StateRetrieve,
+                             handle: *mut c_void, flags: u32,
+                             features: *const *const Lv2Feature) -> u32,
+}
+
+#[repr(C)]
+pub struct Lv2Descriptor {
+    pub uri: *const c_char,
+    pub instantiate:
+        unsafe extern "C" fn(descriptor: *const Lv2Descriptor, rate: f64,
+                             bundle_path: *const c_char,
+                             features: *const *const Lv2Feature) -> *mut c_void,
+    pub connect_port:
+        unsafe extern "C" fn(instance: *mut c_void, port: u32, data: *mut c_void),
+    pub activate:       Option<unsafe extern "C" fn(instance: *mut c_void)>,
+    pub run:            unsafe extern "C" fn(instance: *mut c_void, nframes: u32),
+    pub deactivate:     Option<unsafe extern "C" fn(instance: *mut c_void)>,
+    pub cleanup:        unsafe extern "C" fn(instance: *mut c_void),
+    pub extension_data: unsafe extern "C" fn(uri: *const c_char) -> *const c_void,
+}
+
+unsafe impl Sync for Lv2Descriptor {}
+
+static DESCRIPTOR : Lv2Descriptor = Lv2Descriptor {
+    uri:            concat!(plugin_uri!(), "\0").as_ptr() as *const c_char,
+    instantiate,
+    connect_port,
+    activate:       None,
+    run,
+    deactivate:     None,
+    cleanup,
+    extension_data,
+};
+
+static STATE_INTERFACE : Lv2StateInterface = Lv2StateInterface {
+    save:    state_save,
+    restore: state_restore,
+};
+
+/// Returns the descriptor, that is handed out by `lv2_descriptor`.
+pub fn descriptor() -> &'static Lv2Descriptor { &DESCRIPTOR }
+
+#[cfg(feature="lv2")]
+#[no_mangle]
+pub extern "C" fn lv2_descriptor(index: u32) -> *const Lv2Descriptor {
+    if index == 0 { &DESCRIPTOR } else { std::ptr::null() }
+}
+
+struct Urids {
+    midi_event:   u32,
+    atom_string:  u32,
+    preset:       u32,
+}
+
+struct Lv2Kickmess {
+    engine:         Engine,
+    controls:       Vec<*const f32>,
+    last_controls:  Vec<f32>,
+    midi_in:        *const Lv2AtomSequence,
+    out:            *mut f32,
+    latency:        *mut f32,
+    urids:          Urids,
+}
+
+impl Lv2Kickmess {
+    fn new(srate: f32, urids: Urids) -> Self {
+        let mut engine = Engine::new(Arc::new(EngineParams::new()));
+        engine.set_sample_rate(srate);
+        let param_count = engine.params().ps().param_count();
+
+        Self {
+            controls:      vec![std::ptr::null(); param_count],
+            // NaN never compares equal, so the first run() takes over
+            // all connected control ports, unless the state was restored:
+            last_controls: vec![std::f32::NAN; param_count],
+            midi_in:       std::ptr::null(),
+            out:           std::ptr::null_mut(),
+            latency:       std::ptr::null_mut(),
+            engine,
+            urids,
+        }
+    }
+
+    /// Remembers the current values of the control ports, so that
+    /// only the ports, that the host changes afterwards, are taken over.
+    /// Ports, that are not connected yet, take the current parameter value.
+    unsafe fn sync_controls(&mut self) {
+        let params = self.engine.params();
+
+        for (idx, port) in self.controls.iter().enumerate() {
+            self.last_controls[idx] =
+                if port.is_null() {
+                    params.ps().get(idx, &**params)
+                } else {
+                    **port
+                };
+        }
+    }
+
+    /// Takes over the control ports, that were changed by the host.
+    /// Values restored from the state stay until the port changes.
+    unsafe fn read_controls(&mut self) {
+        for (idx, port) in self.controls.iter().enumerate() {
+            if port.is_null() {
+                continue;
+            }
+
+            let v = **port;
+            if v != self.last_controls[idx] {
+                self.last_controls[idx] = v;
+                let p = self.engine.params().ps().definition(idx).map(|pd| pd.unmap(v));
+                if let Some(p) = p {
+                    self.engine.set_param_at(idx, p, 0);
+                }
+            }
+        }
+    }
+
+    unsafe fn run(&mut self, nframes: usize) {
+        if self.out.is_null() {
+            return;
+        }
+
+        self.read_controls();
+
+        if !self.midi_in.is_null() {
+            let engine = &mut self.engine;
+            for_each_midi_event(self.midi_in, self.urids.midi_event, |frame, data| {
+                engine.handle_midi(data, frame);
+            });
+        }
+
+        self.engine.process(std::slice::from_raw_parts_mut(self.out, nframes));
+
+        if !self.latency.is_null() {
+            *self.latency = self.engine.latency() as f32;
+        }
+    }
+}
+
+/// Calls `f` with the frame offset and the data of every
+/// MIDI event with at least 3 bytes in the sequence.
+unsafe fn for_each_midi_event<F: FnMut(usize, &[u8])>(
+    seq: *const Lv2AtomSequence, midi_type: u32, mut f: F)
+{
+    let base  = seq as *const u8;
+    let end   = base.add(std::mem::size_of::<Lv2Atom>() + (*seq).atom.size as usize);
+    let mut ev = base.add(std::mem::size_of::<Lv2AtomSequence>());
+
+    while ev.add(std::mem::size_of::<Lv2AtomEvent>()) <= end {
+        let event = &*(ev as *const Lv2AtomEvent);
+        let data  = ev.add(std::mem::size_of::<Lv2AtomEvent>());
+        let size  = event.body.size as usize;
+
+        if event.body.type_ == midi_type && size >= 3 {
+            f(event.frames.max(0) as usize, std::slice::from_raw_parts(data, size));
+        }
+
+        // The events are padded to 64 bits:
+        ev = data.add((size + 7) & !7);
+    }
+}
+
+unsafe fn find_feature(features: *const *const Lv2Feature, uri: &str) -> *mut c_void {
+    if features.is_null() {
+        return std::ptr::null_mut();
+    }
+
+    let mut i = 0;
+    while !(*features.add(i)).is_null() {
+        let feature = &**features.add(i);
+        if CStr::from_ptr(feature.uri).to_bytes() == uri.as_bytes() {
+            return feature.data;
+        }
+        i += 1;
+    }
+
+    std::ptr::null_mut()
+}
+
+unsafe fn map_uri(map: &Lv2UridMap, uri: &str) -> u32 {
+    let uri = CString::new(uri).unwrap();
+    (map.map)(map.handle, uri.as_ptr())
+}
+
+unsafe extern "C" fn instantiate(
+    _descriptor: *const Lv2Descriptor, rate: f64,
+    _bundle_path: *const c_char, features: *const *const Lv2Feature) -> *mut c_void
+{
+    crate::log::init_from_env();
+
+    let map = find_feature(features, URID_MAP_URI) as *const Lv2UridMap;
+    if map.is_null() {
+        klog!(Host, Error, "lv2 host doesn't provide {}", URID_MAP_URI);
+        return std::ptr::null_mut();
+    }
+
+    let urids = Urids {
+        midi_event:  map_uri(&*map, MIDI_EVENT_URI),
+        atom_string: map_uri(&*map, ATOM_STRING_URI),
+        preset:      map_uri(&*map, STATE_PRESET_URI),
+    };
+
+    Box::into_raw(Box::new(Lv2Kickmess::new(rate as f32, urids))) as *mut c_void
+}
+
+unsafe extern "C" fn connect_port(instance: *mut c_void, port: u32, data: *mut c_void) {
+    let plugin = &mut *(instance as *mut Lv2Kickmess);
+    let port   = port as usize;
+
+    if port < plugin.controls.len() {
+        plugin.controls[port] = data as *const f32;
+    } else if port == PORT_MIDI_IN {
+        plugin.midi_in = data as *const Lv2AtomSequence;
+    } else if port == PORT_OUT {
+        plugin.out = data as *mut f32;
+    } else if port == PORT_LATENCY {
+        plugin.latency = data as *mut f32;
+    }
+}
+
+unsafe extern "C" fn run(instance: *mut c_void, nframes: u32) {
+    (*(instance as *mut Lv2Kickmess)).run(nframes as usize);
+}
+
+unsafe extern "C" fn cleanup(instance: *mut c_void) {
+    drop(Box::from_raw(instance as *mut Lv2Kickmess));
+}
+
+unsafe extern "C" fn extension_data(uri: *const c_char) -> *const c_void {
+    if CStr::from_ptr(uri).to_bytes() == STATE_INTERFACE_URI.as_bytes() {
+        &STATE_INTERFACE as *const Lv2StateInterface as *const c_void
+    } else {
+        std::ptr::null()
+    }
+}
+
+unsafe extern "C" fn state_save(
+    instance: *mut c_void, store: Lv2StateStore, handle: *mut c_void,
+    _flags: u32, _features: *const *const Lv2Feature) -> u32
+{
+    let plugin = &*(instance as *const Lv2Kickmess);
+
+    // An atom:String includes the terminating 0 byte:
+    let mut data = plugin.engine.params().serialize_preset();
+    data.push(0);
+
+    store(handle, plugin.urids.preset,
+          data.as_ptr() as *const c_void, data.len(),
+          plugin.urids.atom_string,
+          LV2_STATE_IS_POD | LV2_STATE_IS_PORTABLE)
+}
+
+unsafe extern "C" fn state_restore(
+    instance: *mut c_void, retrieve: Lv2StateRetrieve, handle: *mut c_void,
+    _flags: u32, _features: *const *const Lv2Feature) -> u32
+{
+    let plugin = &mut *(instance as *mut Lv2Kickmess);
+
+    let mut size  : usize = 0;
+    let mut type_ : u32   = 0;
+    let mut flags : u32   = 0;
+    let value =
+        retrieve(handle, plugin.urids.preset, &mut size, &mut type_, &mut flags);
+
+    if value.is_null() {
+        return LV2_STATE_ERR_NO_PROPERTY;
+    }
+    if type_ != plugin.urids.atom_string {
+        return LV2_STATE_ERR_BAD_TYPE;
+    }
+
+    let data = std::slice::from_raw_parts(value as *const u8, size);
+    let data =
+        if let Some(0) = data.last() { &data[0..(size - 1)] }
+        else { data };
+
+    plugin.engine.params().load_preset(data);
+    // The control ports still have the values from before the restore:
+    plugin.sync_controls();
+
+    LV2_STATE_SUCCESS
+}
+
+/// Rounds away the noise of `map(default_p())`, like 150.00002.
+fn ttl_number(v: f32) -> String {
+    // Round to 6 significant digits:
+    let v : f64 = format!("{:.5e}", v).parse().unwrap_or(0.0);
+    let s = format!("{}", v);
+    if s.contains('.') { s } else { s + ".0" }
+}
+
+fn ttl_string(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// The `manifest.ttl` of the bundle.
+pub fn manifest_ttl() -> String {
+    let mut out = String::new();
+    out += "@prefix lv2:  <http://lv2plug.in/ns/lv2core#> .\n";
+    out += "@prefix rdfs: <http://www.w3.org/2000/01/rdf-schema#> .\n";
+    out += "\n";
+    out += &format!("<{}> a lv2:Plugin .\n", PLUGIN_URI);
+    out += &format!("<{}> lv2:binary <{}> .\n", PLUGIN_URI, BINARY);
+    out += &format!("<{}> rdfs:seeAlso <kickmess.ttl> .\n", PLUGIN_URI);
+    out
+}
+
+/// The plugin description with all ports, generated from the `param_model!` table.
+pub fn plugin_ttl() -> String {
+    let mut ps        = ParamSet::new();
+    let mut public_ps = ParamSet::new();
+    OpKickmess::init_params(&mut ps, &mut public_ps);
+
+    let mut out = String::new();
+    out += "@prefix atom:  <http://lv2plug.in/ns/ext/atom#> .\n";
+    out += "@prefix doap:  <http://usefulinc.com/ns/doap#> .\n";
+    out += "@prefix lv2:   <http://lv2plug.in/ns/lv2core#> .\n";
+    out += "@prefix midi:  <http://lv2plug.in/ns/ext/midi#> .\n";
+    out += "@prefix rdfs:  <http://www.w3.org/2000/01/rdf-schema#> .\n";
+    out += "@prefix state: <http://lv2plug.in/ns/ext/state#> .\n";
+    out += "@prefix urid:  <http://lv2plug.in/ns/ext/urid#> .\n";
+    out += "\n";
+    out += "# Generated by kickmess-lv2-ttl from the parameter table, do not edit!\n";
+    out += "\n";
+    out += &format!("<{}>\n", PLUGIN_URI);
+    out += "    a lv2:Plugin ,\n";
+    out += "        lv2:InstrumentPlugin ;\n";
+    out += "\n";
+    out += "    lv2:project <https://github.com/WeirdConstructor/kickmess> ;\n";
+    out += &format!("    doap:name {} ;\n", ttl_string(PLUGIN_NAME));
+    out += "    doap:license <https://www.gnu.org/licenses/agpl-3.0> ;\n";
+    out += "\n";
+    out += "    lv2:requiredFeature urid:map ;\n";
+    out += "    lv2:optionalFeature lv2:hardRTCapable ;\n";
+    out += "    lv2:extensionData state:interface ;\n";
+    out += "    lv2:port [\n";
+
+    for info in param_infos() {
+        let pd = ps.definition(info.id).unwrap();
+        let (min, max) = (pd.min().min(pd.max()), pd.min().max(pd.max()));
+
+        out += "        a lv2:InputPort ,\n";
+        out += "            lv2:ControlPort ;\n";
+        out += &format!("        lv2:index {} ;\n", info.id);
+        out += &format!("        lv2:symbol {} ;\n", ttl_string(info.symbol));
+        out += &format!("        lv2:name {} ;\n", ttl_string(info.label));
+        out += &format!("        rdfs:comment {} ;\n", ttl_string(info.title));
+        out += &format!("        lv2:default {} ;\n", ttl_number(pd.map(pd.default_p())));
+        out += &format!("        lv2:minimum {} ;\n", ttl_number(min));
+        out += &format!("        lv2:maximum {}\n", ttl_number(max));
+        out += "    ] , [\n";
+    }
+
+    out += "        a lv2:InputPort ,\n";
+    out += "            atom:AtomPort ;\n";
+    out += "        atom:bufferType atom:Sequence ;\n";
+    out += "        atom:supports midi:MidiEvent ;\n";
+    out += "        lv2:designation lv2:control ;\n";
+    out += &format!("        lv2:index {} ;\n", PORT_MIDI_IN);
+    out += "        lv2:symbol \"midi_in\" ;\n";
+    out += "        lv2:name \"MIDI In\"\n";
+    out += "    ] , [\n";
+    out += "        a lv2:OutputPort ,\n";
+    out += "            lv2:AudioPort ;\n";
+    out += &format!("        lv2:index {} ;\n", PORT_OUT);
+    out += "        lv2:symbol \"out\" ;\n";
+    out += "        lv2:name \"Out\"\n";
+    out += "    ] , [\n";
+    out += "        a lv2:OutputPort ,\n";
+    out += "            lv2:ControlPort ;\n";
+    out += "        lv2:designation lv2:latency ;\n";
+    out += "        lv2:portProperty lv2:reportsLatency ,\n";
+    out += "            lv2:integer ;\n";
+    out += &format!("        lv2:index {} ;\n", PORT_LATENCY);
+    out += "        lv2:symbol \"latency\" ;\n";
+    out += "        lv2:name \"Latency\" ;\n";
+    out += "        lv2:minimum 0 ;\n";
+    out += "        lv2:maximum 1024\n";
+    out += "    ] .\n";
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proc::ParamProvider;
+    use std::cell::RefCell;
+
+    unsafe extern "C" fn test_map(handle: *mut c_void, uri: *const c_char) -> u32 {
+        let uris = &*(handle as *const RefCell<Vec<String>>);
+        let uri  = CStr::from_ptr(uri).to_string_lossy().to_string();
+
+        let mut uris = uris.borrow_mut();
+        if let Some(pos) = uris.iter().position(|u| *u == uri) {
+            return pos as u32 + 1;
+        }
+        uris.push(uri);
+        uris.len() as u32
+    }
+
+    unsafe extern "C" fn test_store(
+        handle: *mut c_void, key: u32, value: *const c_void,
+        size: usize, type_: u32, _flags: u32) -> u32
+    {
+        let saved = &mut *(handle as *mut Vec<(u32, u32, Vec<u8>)>);
+        saved.push((key, type_,
+                    std::slice::from_raw_parts(value as *const u8, size).to_vec()));
+        LV2_STATE_SUCCESS
+    }
+
+    unsafe extern "C" fn test_retrieve(
+        handle: *mut c_void, key: u32, size: *mut usize,
+        type_: *mut u32, _flags: *mut u32) -> *const c_void
+    {
+        let saved = &*(handle as *const Vec<(u32, u32, Vec<u8>)>);
+        for (k, t, data) in saved.iter() {
+            if *k == key {
+                *size  = data.len();
+                *type_ = *t;
+                return data.as_ptr() as *const c_void;
+            }
+        }
+        std::ptr::null()
+    }
+
+    /// Writes an atom sequence with 3 byte MIDI events into `buf`.
+    fn write_midi_sequence(buf: &mut [u64], midi_type: u32, events: &[(i64, [u8; 3])]) {
+        // Header: atom size and type, unit and pad.
+        // Every event: frames, atom size and type, data.
+        buf[0] = (8 + events.len() * 24) as u64;
+        buf[1] = 0;
+        for (i, (frames, data)) in events.iter().enumerate() {
+            let ev = 2 + i * 3;
+            buf[ev]     = *frames as u64;
+            buf[ev + 1] = 3 | ((midi_type as u64) << 32);
+            buf[ev + 2] =
+                (data[0] as u64) | ((data[1] as u64) << 8) | ((data[2] as u64) << 16);
+        }
+    }
+
+    struct TestHost {
+        uris:     Box<RefCell<Vec<String>>>,
+        map:      Box<Lv2UridMap>,
+        feature:  Box<Lv2Feature>,
+    }
+
+    impl TestHost {
+        fn new() -> Self {
+            let uris = Box::new(RefCell::new(vec![]));
+            let map  = Box::new(Lv2UridMap {
+                handle: &*uris as *const RefCell<Vec<String>> as *mut c_void,
+                map:    test_map,
+            });
+            let feature = Box::new(Lv2Feature {
+                uri:  "http://lv2plug.in/ns/ext/urid#map\0".as_ptr() as *const c_char,
+                data: &*map as *const Lv2UridMap as *mut c_void,
+            });
+            Self { uris, map, feature }
+        }
+
+        fn instantiate(&self) -> *mut c_void {
+            let features = [&*self.feature as *const Lv2Feature, std::ptr::null()];
+            unsafe {
+                (descriptor().instantiate)(
+                    descriptor(), 44100.0, std::ptr::null(), features.as_ptr())
+            }
+        }
+
+        fn urid(&self, uri: &str) -> u32 {
+            unsafe { map_uri(&self.map, uri) }
+        }
+    }
+
+    #[test]
+    fn check_lv2_port_symbols() {
+        let symbols = param_symbols();
+        for (i, s) in symbols.iter().enumerate() {
+            assert!(!s.is_empty(), "parameter {} has no symbol", i);
+            assert!(s.chars().all(|c| c.is_ascii_alphanumeric() || c == '_'));
+            assert_eq!(symbols.iter().filter(|o| *o == s).count(), 1, "duplicate {}", s);
+        }
+    }
+
+    #[test]
+    #[cfg(not(feature="mega"))]
+    fn check_lv2_bundle_is_up_to_date() {
+        assert_eq!(
+            include_str!("../lv2/kickmess.lv2/kickmess.ttl"), plugin_ttl(),
+            "regenerate with: cargo run --bin kickmess-lv2-ttl");
+        assert_eq!(
+            include_str!("../lv2/kickmess.lv2/manifest.ttl"), manifest_ttl(),
+            "regenerate with: cargo run --bin kickmess-lv2-ttl");
+    }
+
+    #[test]
+    fn check_lv2_needs_urid_map() {
+        let features = [std::ptr::null()];
+        let instance = unsafe {
+            (descriptor().instantiate)(
+                descriptor(), 44100.0, std::ptr::null(), features.as_ptr())
+        };
+        assert!(instance.is_null());
+    }
+
+    #[test]
+    fn check_lv2_run_and_state() {
+        let host     = TestHost::new();
+        let instance = host.instantiate();
+        assert!(!instance.is_null());
+
+        let mut ps        = ParamSet::new();
+        let mut public_ps = ParamSet::new();
+        OpKickmess::init_params(&mut ps, &mut public_ps);
+
+        let mut controls : Vec<f32> =
+            (0..PARAM_COUNT).map(|idx| {
+                let pd = ps.definition(idx).unwrap();
+                pd.map(pd.default_p())
+            }).collect();
+        controls[pid::dist_on] = 1.0;
+
+        let mut midi = [0_u64; 64];
+        write_midi_sequence(
+            &mut midi[..], host.urid(MIDI_EVENT_URI),
+            &[(10, [0x90, 36, 127])]);
+
+        let mut out     = vec![0.0_f32; 512];
+        let mut latency = -1.0_f32;
+
+        unsafe {
+            for idx in 0..PARAM_COUNT {
+                (descriptor().connect_port)(
+                    instance, idx as u32,
+                    &mut controls[idx] as *mut f32 as *mut c_void);
+            }
+            (descriptor().connect_port)(
+                instance, PORT_MIDI_IN as u32, midi.as_mut_ptr() as *mut c_void);
+            (descriptor().connect_port)(
+                instance, PORT_OUT as u32, out.as_mut_ptr() as *mut c_void);
+            (descriptor().connect_port)(
+                instance, PORT_LATENCY as u32, &mut latency as *mut f32 as *mut c_void);
+
+            (descriptor().run)(instance, out.len() as u32);
+        }
+
+        assert!(out[0..10].iter().all(|s| *s == 0.0));
+        assert!(out[10..].iter().any(|s| s.abs() > 0.01));
+        // Without the limiter there is no latency:
+        assert_eq!(latency, 0.0);
+
+        unsafe {
+            controls[pid::limiter_on] = 1.0;
+            (descriptor().run)(instance, out.len() as u32);
+        }
+        assert!(latency >= 66.0, "latency {}", latency);
+
+        let state = unsafe {
+            &*((descriptor().extension_data)(
+                "http://lv2plug.in/ns/ext/state#interface\0".as_ptr() as *const c_char)
+               as *const Lv2StateInterface)
+        };
+
+        let mut saved : Vec<(u32, u32, Vec<u8>)> = vec![];
+        unsafe {
+            let ret =
+                (state.save)(instance, test_store,
+                             &mut saved as *mut Vec<(u32, u32, Vec<u8>)> as *mut c_void,
+                             0, std::ptr::null());
+            assert_eq!(ret, LV2_STATE_SUCCESS);
+        }
+        assert_eq!(saved.len(), 1);
+        assert_eq!(saved[0].1, host.urid(ATOM_STRING_URI));
+
+        let instance2 = host.instantiate();
+        unsafe {
+            let ret =
+                (state.restore)(instance2, test_retrieve,
+                                &mut saved as *mut Vec<(u32, u32, Vec<u8>)> as *mut c_void,
+                                0, std::ptr::null());
+            assert_eq!(ret, LV2_STATE_SUCCESS);
+
+            let p1 = (*(instance  as *const Lv2Kickmess)).engine.params();
+            let p2 = (*(instance2 as *const Lv2Kickmess)).engine.params();
+            assert_eq!(p2.param(pid::dist_on), 1.0);
+            assert_eq!(p1.serialize_preset(), p2.serialize_preset());
+
+            (descriptor().cleanup)(instance);
+            (descriptor().cleanup)(instance2);
+        }
+    }
+
+    #[test]
+    fn check_lv2_restore_keeps_state_over_ports() {
+        let host     = TestHost::new();
+        let instance = host.instantiate();
+
+        let mut ps        = ParamSet::new();
+        let mut public_ps = ParamSet::new();
+        OpKickmess::init_params(&mut ps, &mut public_ps);
+
+        // The ports are at their defaults, the state has other values:
+        let mut controls : Vec<f32> =
+            (0..PARAM_COUNT).map(|idx| {
+                let pd = ps.definition(idx).unwrap();
+                pd.map(pd.default_p())
+            }).collect();
+
+        let state_params = EngineParams::new();
+        state_params.set(pid::dist_on, 1.0);
+        state_params.set(pid::gain,    0.25);
+        let mut preset = state_params.serialize_preset();
+        preset.push(0);
+        let mut saved =
+            vec![(host.urid(STATE_PRESET_URI), host.urid(ATOM_STRING_URI), preset)];
+
+        let mut out = vec![0.0_f32; 64];
+
+        unsafe {
+            for idx in 0..PARAM_COUNT {
+                (descriptor().connect_port)(
+                    instance, idx as u32,
+                    &mut controls[idx] as *mut f32 as *mut c_void);
+            }
+            (descriptor().connect_port)(
+                instance, PORT_OUT as u32, out.as_mut_ptr() as *mut c_void);
+
+            let ret =
+                state_restore(instance, test_retrieve,
+                              &mut saved as *mut Vec<(u32, u32, Vec<u8>)> as *mut c_void,
+                              0, std::ptr::null());
+            assert_eq!(ret, LV2_STATE_SUCCESS);
+
+            (descriptor().run)(instance, out.len() as u32);
+
+            let params = (*(instance as *const Lv2Kickmess)).engine.params().clone();
+            assert_eq!(params.param(pid::dist_on), 1.0);
+            assert_eq!(params.param(pid::gain),    0.25);
+
+            // A port, that the host changes after the restore, is taken over:
+            controls[pid::gain] = 2.0;
+            (descriptor().run)(instance, out.len() as u32);
+            assert_eq!(params.param(pid::gain),    1.0);
+            assert_eq!(params.param(pid::dist_on), 1.0);
+
+            (descriptor().cleanup)(instance);
+        }
+    }
+}
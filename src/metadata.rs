@@ -0,0 +1,161 @@
+// Copyright (c) 2020-2021 Weird Constructor <weirdconstructor@gmail.com>
+// This is a part of Kickmess. See README.md and COPYING for details.
+
+//! Machine readable descriptions of the parameters, generated from
+//! the `param_model!` table with `param_model::param_infos`.
+//!
+//! The `kickmess-params` tool writes them, and the parameter
+//! reference in `doc/parameters.md` is generated with it too.
+
+use crate::param_model::{param_infos, ParamInfo};
+
+fn escape(s: &str) -> String {
+    let mut out = String::new();
+    for c in s.chars() {
+        match c {
+            '"'  => out += "\\\"",
+            '\\' => out += "\\\\",
+            '\n' => out += "\\n",
+            '\t' => out += "\\t",
+            c if (c as u32) < 0x20 => out += &format!("\\u{:04x}", c as u32),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// Formats a number, that is always read back as float.
+fn num(v: f32) -> String { format!("{:?}", v) }
+
+fn plugin_name() -> &'static str {
+    if cfg!(feature="mega") { "Megamess" } else { "Kickmess" }
+}
+
+/// All parameters as JSON document.
+pub fn params_json() -> String {
+    let mut out = String::new();
+    out += "{\n";
+    out += &format!("  \"plugin\": \"{}\",\n", plugin_name());
+    out += &format!("  \"version\": \"{}\",\n", crate::VERSION);
+    out += "  \"params\": [\n";
+
+    let infos = param_infos();
+    for (i, info) in infos.iter().enumerate() {
+        out += "    {\n";
+        out += &format!("      \"id\": {},\n",              info.id);
+        out += &format!("      \"symbol\": \"{}\",\n",      escape(info.symbol));
+        out += &format!("      \"name\": \"{}\",\n",        escape(info.label));
+        out += &format!("      \"title\": \"{}\",\n",       escape(info.title));
+        out += &format!("      \"help\": \"{}\",\n",        escape(info.help));
+        out += &format!("      \"public\": {},\n",          info.public);
+        out += &format!("      \"mapping\": \"{}\",\n",     info.mapping);
+        out += &format!("      \"smoothing\": \"{}\",\n",   info.smoothing);
+        out += &format!("      \"smooth_ms\": {},\n",       num(info.smooth_ms));
+        out += &format!("      \"min\": {},\n",             num(info.min));
+        out += &format!("      \"max\": {},\n",             num(info.max));
+        out += &format!("      \"default\": {}\n",          num(info.default));
+        out += if i + 1 < infos.len() { "    },\n" } else { "    }\n" };
+    }
+
+    out += "  ]\n";
+    out += "}\n";
+    out
+}
+
+/// All parameters as TOML document, with one `[[params]]` table each.
+pub fn params_toml() -> String {
+    let mut out = String::new();
+    out += &format!("plugin = \"{}\"\n", plugin_name());
+    out += &format!("version = \"{}\"\n", crate::VERSION);
+
+    for info in param_infos() {
+        out += "\n[[params]]\n";
+        out += &format!("id = {}\n",            info.id);
+        out += &format!("symbol = \"{}\"\n",    escape(info.symbol));
+        out += &format!("name = \"{}\"\n",      escape(info.label));
+        out += &format!("title = \"{}\"\n",     escape(info.title));
+        out += &format!("help = \"{}\"\n",      escape(info.help));
+        out += &format!("public = {}\n",        info.public);
+        out += &format!("mapping = \"{}\"\n",   info.mapping);
+        out += &format!("smoothing = \"{}\"\n", info.smoothing);
+        out += &format!("smooth_ms = {}\n",     num(info.smooth_ms));
+        out += &format!("min = {}\n",           num(info.min));
+        out += &format!("max = {}\n",           num(info.max));
+        out += &format!("default = {}\n",       num(info.default));
+    }
+
+    out
+}
+
+fn md_cell(s: &str) -> String {
+    s.split('\n')
+     .map(|l| l.trim())
+     .filter(|l| !l.is_empty())
+     .collect::<Vec<&str>>()
+     .join(" ")
+     .replace('|', "\\|")
+}
+
+fn md_row(info: &ParamInfo) -> String {
+    format!("| {} | `{}` | {} | {} - {} | {} | {} | {} |\n",
+        info.id, info.symbol, md_cell(info.label),
+        info.min, info.max, info.default, info.mapping,
+        md_cell(&format!("**{}** {}", info.title, info.help)))
+}
+
+/// The parameter reference for the documentation.
+pub fn params_markdown() -> String {
+    let mut out = String::new();
+    out += &format!("# {} Parameters\n\n", plugin_name());
+    out += "<!-- Generated by kickmess-params from the parameter table, do not edit! -->\n\n";
+    out += "The public parameters can be automated by the host, the private\n";
+    out += "parameters are only stored in the presets.\n";
+
+    for (public, heading) in [(true, "Public Parameters"), (false, "Private Parameters")].iter() {
+        out += &format!("\n## {}\n\n", heading);
+        out += "| Id | Symbol | Name | Range | Default | Mapping | Description |\n";
+        out += "|---:|--------|------|-------|--------:|---------|-------------|\n";
+
+        for info in param_infos().iter().filter(|i| i.public == *public) {
+            out += &md_row(info);
+        }
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::param_model::{PARAM_COUNT, pid};
+
+    #[test]
+    fn check_metadata_escape() {
+        assert_eq!(escape("a\"b\\c\nd\u{1}"), "a\\\"b\\\\c\\nd\\u0001");
+        assert_eq!(num(150.0), "150.0");
+        assert_eq!(num(-1.0),  "-1.0");
+    }
+
+    #[test]
+    fn check_metadata_json_and_toml() {
+        let json = params_json();
+        assert_eq!(json.matches("\"symbol\":").count(), PARAM_COUNT);
+        assert!(json.contains("\"symbol\": \"e1_release\",\n      \"name\": \"E1 Release\""));
+        // No trailing commas:
+        assert!(!json.contains(",\n    }"));
+        assert!(!json.contains("},\n  ]"));
+
+        let toml = params_toml();
+        assert_eq!(toml.matches("[[params]]").count(), PARAM_COUNT);
+        assert!(toml.contains(&format!("id = {}\nsymbol = \"midi_chan\"", pid::midi_chan)));
+        assert!(toml.contains("public = false"));
+    }
+
+    #[test]
+    #[cfg(not(feature="mega"))]
+    fn check_parameter_doc_is_up_to_date() {
+        assert_eq!(
+            include_str!("../doc/parameters.md"), params_markdown(),
+            "regenerate with: cargo run --bin kickmess-params -- --markdown > doc/parameters.md");
+    }
+}
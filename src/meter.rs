@@ -0,0 +1,248 @@
+// Copyright (c) 2020-2021 Weird Constructor <weirdconstructor@gmail.com>
+// This is a part of Kickmess. See README.md and COPYING for details.
+
+//! The output level meter.
+//!
+//! The `LevelRecorder` runs on the audio thread after the limiter and
+//! publishes the peak, the RMS level and the number of clipped samples
+//! in the `LevelMeter` of the `EngineParams`. The `MeterDisplay` of the
+//! editor reads them once per frame and adds the peak hold, the fall
+//! off of the peak and the clip hold.
+
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::Instant;
+
+/// The integration time of the RMS level.
+const METER_RMS_MS    : f32 = 300.0;
+/// Samples above this level count as clipped.
+const METER_CLIP_LEVEL: f32 = 1.0;
+/// How long the highest peak is held.
+const METER_HOLD_SECS : f32 = 1.5;
+/// How fast the peak falls after it was held.
+const METER_FALL_DB_PER_SEC : f32 = 20.0;
+
+/// The bottom and the top of the meter scale.
+pub const METER_MIN_DB : f32 = -60.0;
+pub const METER_MAX_DB : f32 = 6.0;
+
+fn gain_to_db(gain: f32) -> f32 {
+    (20.0 * gain.max(1e-10).log10()).max(METER_MIN_DB)
+}
+
+/// Maps a level in dB on the meter scale (0.0 to 1.0).
+pub fn db_to_meter(db: f32) -> f32 {
+    ((db - METER_MIN_DB) / (METER_MAX_DB - METER_MIN_DB)).clamp(0.0, 1.0)
+}
+
+/// The levels of the output, shared by the audio thread and the GUI.
+pub struct LevelMeter {
+    /// The highest absolute sample since the last `take_peak`.
+    /// The bits of positive floats are ordered like their values,
+    /// so `fetch_max` works on them.
+    peak:   AtomicU32,
+    rms:    AtomicU32,
+    clips:  AtomicU32,
+}
+
+impl LevelMeter {
+    pub fn new() -> Self {
+        Self {
+            peak:   AtomicU32::new(0),
+            rms:    AtomicU32::new(0),
+            clips:  AtomicU32::new(0),
+        }
+    }
+
+    /// Called by the audio thread, does not block or allocate.
+    fn publish(&self, peak: f32, rms: f32, clips: u32) {
+        self.peak.fetch_max(peak.to_bits(), Ordering::Relaxed);
+        self.rms.store(rms.to_bits(), Ordering::Relaxed);
+        if clips > 0 {
+            self.clips.fetch_add(clips, Ordering::Relaxed);
+        }
+    }
+
+    /// The sample peak since the last call.
+    pub fn take_peak(&self) -> f32 {
+        f32::from_bits(self.peak.swap(0, Ordering::Relaxed))
+    }
+
+    /// The current RMS level.
+    pub fn rms(&self) -> f32 {
+        f32::from_bits(self.rms.load(Ordering::Relaxed))
+    }
+
+    /// The number of clipped samples since the last call.
+    pub fn take_clips(&self) -> u32 {
+        self.clips.swap(0, Ordering::Relaxed)
+    }
+}
+
+impl Default for LevelMeter {
+    fn default() -> Self { Self::new() }
+}
+
+/// Measures the output on the audio thread.
+pub struct LevelRecorder {
+    rms_coef:   f32,
+    mean_sq:    f32,
+}
+
+impl LevelRecorder {
+    pub fn new() -> Self {
+        let mut this = Self { rms_coef: 0.0, mean_sq: 0.0 };
+        this.set_sample_rate(44100.0);
+        this
+    }
+
+    pub fn set_sample_rate(&mut self, srate: f32) {
+        self.rms_coef = 1.0 - (-1.0 / (srate * METER_RMS_MS / 1000.0)).exp();
+    }
+
+    /// Measures the rendered `samples`.
+    pub fn feed(&mut self, samples: &[f32], meter: &LevelMeter) {
+        let mut peak  = 0.0_f32;
+        let mut clips = 0;
+
+        for s in samples.iter() {
+            let a = s.abs();
+            peak = peak.max(a);
+            if a > METER_CLIP_LEVEL {
+                clips += 1;
+            }
+
+            self.mean_sq += (s * s - self.mean_sq) * self.rms_coef;
+        }
+
+        meter.publish(peak, self.mean_sq.max(0.0).sqrt(), clips);
+    }
+}
+
+impl Default for LevelRecorder {
+    fn default() -> Self { Self::new() }
+}
+
+/// The ballistics of the meter in the editor.
+pub struct MeterDisplay {
+    peak_db:    f32,
+    rms_db:     f32,
+    hold_db:    f32,
+    hold_time:  f32,
+    clip:       bool,
+    last:       Option<Instant>,
+}
+
+impl MeterDisplay {
+    pub fn new() -> Self {
+        Self {
+            peak_db:    METER_MIN_DB,
+            rms_db:     METER_MIN_DB,
+            hold_db:    METER_MIN_DB,
+            hold_time:  0.0,
+            clip:       false,
+            last:       None,
+        }
+    }
+
+    /// Reads the levels, that were published since the last update.
+    pub fn update(&mut self, meter: &LevelMeter, now: Instant) {
+        let dt =
+            self.last.map(|l| now.duration_since(l).as_secs_f32())
+                .unwrap_or(0.0);
+        self.last = Some(now);
+
+        let peak_db = gain_to_db(meter.take_peak());
+        self.peak_db = peak_db.max(self.peak_db - METER_FALL_DB_PER_SEC * dt);
+        self.rms_db  = gain_to_db(meter.rms());
+
+        if peak_db >= self.hold_db {
+            self.hold_db   = peak_db;
+            self.hold_time = 0.0;
+        } else {
+            self.hold_time += dt;
+            if self.hold_time > METER_HOLD_SECS {
+                self.hold_db =
+                    (self.hold_db - METER_FALL_DB_PER_SEC * dt).max(self.peak_db);
+            }
+        }
+
+        if meter.take_clips() > 0 {
+            self.clip = true;
+        }
+    }
+
+    /// The falling peak level in dB.
+    pub fn peak_db(&self) -> f32 { self.peak_db }
+
+    /// The RMS level in dB.
+    pub fn rms_db(&self) -> f32 { self.rms_db }
+
+    /// The held peak level in dB.
+    pub fn hold_db(&self) -> f32 { self.hold_db }
+
+    /// True if the output clipped since the last `reset_clip`.
+    pub fn clip(&self) -> bool { self.clip }
+
+    pub fn reset_clip(&mut self) { self.clip = false; }
+}
+
+impl Default for MeterDisplay {
+    fn default() -> Self { Self::new() }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn check_meter_levels() {
+        let meter   = LevelMeter::new();
+        let mut rec = LevelRecorder::new();
+        rec.set_sample_rate(1000.0);
+
+        let sine : Vec<f32> =
+            (0..1000).map(|i| 0.5 * (2.0 * std::f32::consts::PI * i as f32 / 20.0).sin())
+            .collect();
+        rec.feed(&sine[..500], &meter);
+        rec.feed(&sine[500..], &meter);
+
+        assert!((meter.take_peak() - 0.5).abs() < 1e-3);
+        assert_eq!(meter.take_peak(), 0.0);
+        assert!((meter.rms() - 0.5 / 2.0_f32.sqrt()).abs() < 0.02, "rms {}", meter.rms());
+        assert_eq!(meter.take_clips(), 0);
+
+        rec.feed(&[0.5, 1.2, -1.5, 0.9], &meter);
+        assert_eq!(meter.take_peak(), 1.5);
+        assert_eq!(meter.take_clips(), 2);
+        assert_eq!(meter.take_clips(), 0);
+    }
+
+    #[test]
+    fn check_meter_display_hold() {
+        let meter   = LevelMeter::new();
+        let mut rec = LevelRecorder::new();
+        let mut disp = MeterDisplay::new();
+        let start = Instant::now();
+
+        rec.feed(&[0.0, 2.0, 0.0], &meter);
+        disp.update(&meter, start);
+        assert!((disp.peak_db() - 6.02).abs() < 0.01);
+        assert!(disp.clip());
+
+        rec.feed(&[0.0; 10], &meter);
+        disp.update(&meter, start + Duration::from_millis(1000));
+        assert!((disp.hold_db() - 6.02).abs() < 0.01);
+        assert!((disp.peak_db() - (6.02 - METER_FALL_DB_PER_SEC)).abs() < 0.01);
+
+        // After the hold time the held peak falls too:
+        rec.feed(&[0.0; 10], &meter);
+        disp.update(&meter, start + Duration::from_millis(2000));
+        assert!(disp.hold_db() < 6.0 - 10.0);
+
+        // The clip indicator stays until it is reset:
+        assert!(disp.clip());
+        disp.reset_clip();
+        assert!(!disp.clip());
+    }
+}
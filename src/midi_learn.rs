@@ -0,0 +1,151 @@
+// Copyright (c) 2020-2021 Weird Constructor <weirdconstructor@gmail.com>
+// This is a part of Kickmess. See README.md and COPYING for details.
+
+//! MIDI learn of parameters.
+//!
+//! The editor starts learning a parameter with `MidiLearn::start`,
+//! the audio thread assigns the next MIDI CC, that arrives on the
+//! MIDI channel of the plugin, to it. After that the CC controls the
+//! parameter. The assignments are saved with the state of the plugin
+//! in the `!MIDI` section, see `EngineParams::serialize_preset`.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// The number of MIDI continuous controllers.
+pub const MIDI_CC_COUNT : usize = 128;
+
+const NO_PARAM : usize = usize::MAX;
+
+/// The assignments of the MIDI CCs to parameters, shared
+/// by the audio thread and the GUI.
+pub struct MidiLearn {
+    /// The parameter, that gets the next CC.
+    learn:      AtomicUsize,
+    cc_params:  Vec<AtomicUsize>,
+}
+
+impl MidiLearn {
+    pub fn new() -> Self {
+        Self {
+            learn:     AtomicUsize::new(NO_PARAM),
+            cc_params: (0..MIDI_CC_COUNT).map(|_| AtomicUsize::new(NO_PARAM)).collect(),
+        }
+    }
+
+    /// Assigns the next incoming CC to the parameter `id`.
+    pub fn start(&self, id: usize) {
+        self.learn.store(id, Ordering::Relaxed);
+    }
+
+    pub fn cancel(&self) {
+        self.learn.store(NO_PARAM, Ordering::Relaxed);
+    }
+
+    /// The parameter, that waits for a CC.
+    pub fn learning(&self) -> Option<usize> {
+        let id = self.learn.load(Ordering::Relaxed);
+        if id == NO_PARAM { None } else { Some(id) }
+    }
+
+    /// The CC, that controls the parameter `id`.
+    pub fn cc_of(&self, id: usize) -> Option<u8> {
+        self.cc_params.iter()
+            .position(|p| p.load(Ordering::Relaxed) == id)
+            .map(|cc| cc as u8)
+    }
+
+    /// Removes the CC assignment of the parameter `id`.
+    pub fn forget(&self, id: usize) {
+        for p in self.cc_params.iter() {
+            let _ = p.compare_exchange(id, NO_PARAM, Ordering::Relaxed, Ordering::Relaxed);
+        }
+    }
+
+    /// The (CC, parameter) pairs of all assignments, ordered by the CC.
+    pub fn assignments(&self) -> Vec<(u8, usize)> {
+        self.cc_params.iter()
+            .enumerate()
+            .filter_map(|(cc, p)| {
+                let id = p.load(Ordering::Relaxed);
+                if id == NO_PARAM { None } else { Some((cc as u8, id)) }
+            })
+            .collect()
+    }
+
+    /// Replaces all assignments, like from a loaded state.
+    /// CCs out of range are skipped.
+    pub fn set_assignments(&self, assignments: &[(u8, usize)]) {
+        for p in self.cc_params.iter() {
+            p.store(NO_PARAM, Ordering::Relaxed);
+        }
+
+        for (cc, id) in assignments.iter() {
+            if let Some(p) = self.cc_params.get(*cc as usize) {
+                self.forget(*id);
+                p.store(*id, Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// Called by the audio thread for the CC `cc`. Finishes a
+    /// running MIDI learn and returns the parameter, that is
+    /// controlled by the CC.
+    pub fn handle_cc(&self, cc: u8) -> Option<usize> {
+        let p = self.cc_params.get(cc as usize)?;
+
+        let learn = self.learn.swap(NO_PARAM, Ordering::Relaxed);
+        if learn != NO_PARAM {
+            self.forget(learn);
+            p.store(learn, Ordering::Relaxed);
+        }
+
+        let id = p.load(Ordering::Relaxed);
+        if id == NO_PARAM { None } else { Some(id) }
+    }
+}
+
+impl Default for MidiLearn {
+    fn default() -> Self { Self::new() }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_midi_learn_assigns_cc() {
+        let ml = MidiLearn::new();
+        assert_eq!(ml.handle_cc(7), None);
+
+        ml.start(3);
+        assert_eq!(ml.learning(), Some(3));
+        assert_eq!(ml.handle_cc(7), Some(3));
+        assert_eq!(ml.learning(), None);
+        assert_eq!(ml.cc_of(3), Some(7));
+        assert_eq!(ml.handle_cc(8), None);
+
+        // Learning again moves the parameter to the new CC:
+        ml.start(3);
+        assert_eq!(ml.handle_cc(10), Some(3));
+        assert_eq!(ml.cc_of(3), Some(10));
+        assert_eq!(ml.handle_cc(7), None);
+
+        ml.forget(3);
+        assert_eq!(ml.cc_of(3), None);
+        assert_eq!(ml.handle_cc(10), None);
+        assert_eq!(ml.handle_cc(200), None);
+    }
+
+    #[test]
+    fn check_midi_learn_set_assignments() {
+        let ml = MidiLearn::new();
+        ml.start(1);
+        ml.handle_cc(20);
+
+        ml.set_assignments(&[(7, 3), (200, 4), (1, 5), (9, 5)]);
+        assert_eq!(ml.assignments(), vec![(7, 3), (9, 5)]);
+        assert_eq!(ml.cc_of(1), None);
+        assert_eq!(ml.handle_cc(20), None);
+        assert_eq!(ml.handle_cc(7), Some(3));
+    }
+}
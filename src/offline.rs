@@ -0,0 +1,144 @@
+// Copyright (c) 2020-2021 Weird Constructor <weirdconstructor@gmail.com>
+// This is a part of Kickmess. See README.md and COPYING for details.
+
+//! Renders notes without a host. Used by the `kickmess-render` tool
+//! and by the golden audio regression tests.
+
+use crate::proc::ParamProvider;
+use crate::param_model::pid;
+use crate::engine::{Engine, EngineParams};
+use crate::MAX_BLOCKSIZE;
+use std::sync::Arc;
+
+#[derive(Debug, Clone, Copy)]
+pub struct RenderNote {
+    pub note:   u8,
+    pub vel:    u8,
+    pub len_ms: f32,
+}
+
+pub fn ms2frames(ms: f32, srate: u32) -> usize {
+    ((ms.max(0.0) * srate as f32) / 1000.0).round() as usize
+}
+
+/// The quality of the oversampling filters, see `OfflineRenderer`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RenderQuality {
+    /// The long filters for offline rendering.
+    Offline,
+    /// The short filters, that the plugin uses in realtime.
+    Realtime,
+    /// The filters selected by the "OS Quality" parameter.
+    Preset,
+}
+
+/// Holds the parameters for rendering notes in the same
+/// way the plugin renders them inside a host.
+///
+/// Other than the plugin, the renderer uses the offline quality
+/// of the oversampling filters by default.
+pub struct OfflineRenderer {
+    params:  Arc<EngineParams>,
+    quality: RenderQuality,
+}
+
+impl OfflineRenderer {
+    /// Creates a renderer with the default parameters.
+    pub fn new() -> Self {
+        Self {
+            params:  Arc::new(EngineParams::new()),
+            quality: RenderQuality::Offline,
+        }
+    }
+
+    pub fn set_quality(&mut self, quality: RenderQuality) {
+        self.quality = quality;
+    }
+
+    /// Loads a preset in the format of `serialize_preset`.
+    /// Parameters missing in the preset are reset to their default value.
+    pub fn load_preset(&mut self, data: &[u8]) {
+        self.params.load_preset(data);
+    }
+
+    /// Sets a parameter to the normalized value `p` (0.0 to 1.0).
+    pub fn set_param(&mut self, idx: usize, p: f32) {
+        self.params.set(idx, p);
+    }
+
+    /// Renders the notes one after another into a mono buffer. Every note
+    /// is held for its length and followed by `gap_ms` of silence. After
+    /// the last note the release tail is rendered for `tail_ms`.
+    pub fn render(&self, srate: u32, notes: &[RenderNote], gap_ms: f32, tail_ms: f32) -> Vec<f32> {
+        // (frame, midi data), note on and note off for every note:
+        let mut events : Vec<(usize, [u8; 3])> = vec![];
+        let chan = self.params.ps().get(pid::midi_chan, &*self.params).floor() as u8;
+
+        let mut pos = 0;
+        for n in notes.iter() {
+            let len = ms2frames(n.len_ms, srate);
+            events.push((pos,       [0x90 | chan, n.note, n.vel]));
+            events.push((pos + len, [0x80 | chan, n.note, 0]));
+            pos += len + ms2frames(gap_ms, srate);
+        }
+
+        match self.quality {
+            RenderQuality::Offline  => self.params.set(pid::os_quality, 1.0),
+            RenderQuality::Realtime => self.params.set(pid::os_quality, 0.0),
+            RenderQuality::Preset   => (),
+        }
+
+        let mut engine = Engine::new(self.params.clone());
+        engine.set_sample_rate(srate as f32);
+
+        // Renders the latency of the engine longer and skips it below:
+        let latency = engine.latency();
+        let nframes = pos + ms2frames(tail_ms, srate) + latency;
+
+        let mut out   = Vec::with_capacity(nframes);
+        let mut block = [0.0; MAX_BLOCKSIZE];
+        let mut ev_i  = 0;
+        let mut offs  = 0;
+
+        while offs < nframes {
+            let len = (nframes - offs).min(MAX_BLOCKSIZE);
+
+            while ev_i < events.len() && events[ev_i].0 < offs + len {
+                let (frame, data) = events[ev_i];
+                engine.handle_midi(&data[..], frame - offs);
+                ev_i += 1;
+            }
+
+            engine.process(&mut block[0..len]);
+
+            out.extend_from_slice(&block[0..len]);
+            offs += len;
+        }
+
+        out.drain(0..latency);
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_offline_quality() {
+        let render = |quality| {
+            let mut renderer = OfflineRenderer::new();
+            renderer.load_preset(
+                b"!PARAMS;pub : dist_on=1;pub : dist_start=1;pub : dist_end=0.5;\
+                  priv : os_factor=0.3;priv : os_quality=0.0;");
+            renderer.set_quality(quality);
+            renderer.render(44100, &[RenderNote { note: 36, vel: 127, len_ms: 50.0 }], 0.0, 10.0)
+        };
+
+        let offline = render(RenderQuality::Offline);
+        assert_ne!(offline, render(RenderQuality::Realtime));
+        // The preset selects the realtime quality:
+        assert_eq!(render(RenderQuality::Preset), render(RenderQuality::Realtime));
+        assert_eq!(offline, render(RenderQuality::Offline));
+    }
+}
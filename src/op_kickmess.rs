@@ -33,15 +33,21 @@ use crate::env::*;
 use crate::param_model::*;
 use crate::filter::{MoogFilter, FilterInputParams};
 use crate::oscillator::{UnisonBlep, FMOscillator, OscillatorInputParams};
-use crate::log::Log;
 use crate::lfo::{LFO, LFOInputParams};
+use crate::oversampling::{Oversampler, oversampling_factor};
+use crate::click::{ClickLayer, ClickInputParams};
 
 use crate::MAX_BLOCKSIZE;
 const PI2 : f64 = std::f64::consts::PI * 2.0;
 
+/// The pitch envelope stays below this fraction of the sample rate,
+/// key tracking would move high notes past the Nyquist frequency.
+const MAX_FREQ_SRATE_FACTOR : f64 = 0.45;
+
 struct F1Params<'a>(&'a ParamModelMut);
 struct O1Params<'a, 'b>(&'a ParamModelMut, &'b f64);
 struct LFO1Params<'a>(&'a ParamModelMut);
+struct ClickParams<'a>(&'a ParamModelMut);
 
 impl<'a> FilterInputParams for F1Params<'a> {
     fn freq(&self)  -> f32 { self.0.f1_cutoff() }
@@ -50,6 +56,13 @@ impl<'a> FilterInputParams for F1Params<'a> {
     fn drive(&self) -> f32 { self.0.f1_drive() }
 }
 
+impl<'a> ClickInputParams for ClickParams<'a> {
+    fn level(&self)         -> f32 { self.0.click_level() }
+    fn decay(&self)         -> f32 { self.0.click_decay() }
+    fn tone(&self)          -> f32 { self.0.click_tone() }
+    fn waveform(&self)      -> f32 { self.0.click_type() }
+}
+
 #[cfg(feature="mega")]
 impl<'a, 'b> OscillatorInputParams for O1Params<'a, 'b> {
     fn freq(&self)          -> f32 { *self.1 as f32 }
@@ -85,6 +98,7 @@ pub struct OpKickmess {
     note_freq:       f64,
     cur_phase:       f32,
     srate:           f32,
+    os_srate:        f32,
 
     rng:             RandGen,
     f_env:           REnv,
@@ -95,6 +109,28 @@ pub struct OpKickmess {
     lfo1:            LFO,
     mf1:             ModulatorFun,
     params:          ParamModelMut,
+    oversampler:     Oversampler,
+    click:           ClickLayer,
+}
+
+/// The multi segment shape of the frequency envelope.
+#[inline]
+fn freq_env_shape(params: &ParamModelMut) -> EnvShape {
+    EnvShape::from_params(
+        &FREQ_ENV_POINTS[..],
+        shape_point_count(params.fe_points()),
+        params.freq_slope() as f64,
+        |id| params.get(id) as f64)
+}
+
+/// The multi segment shape of the amplitude envelope.
+#[inline]
+fn amp_env_shape(params: &ParamModelMut) -> EnvShape {
+    EnvShape::from_params(
+        &AMP_ENV_POINTS[..],
+        shape_point_count(params.ae_points()),
+        params.env_slope() as f64,
+        |id| params.get(id) as f64)
 }
 
 impl OpKickmess {
@@ -105,9 +141,40 @@ impl OpKickmess {
                  + (0.25 * (params.phase_offs() as f64)))
                 * PI2);
         self.cur_phase +=
-            (self.note_freq / (self.srate as f64)) as f32;
+            (self.note_freq / (self.os_srate as f64)) as f32;
         s
     }
+
+    /// The start and end frequency of the pitch envelope for the played
+    /// note. The ratio between the played note and the root note is
+    /// scaled by the key tracking amount. An amount of 1.0 moves the
+    /// frequency one octave per played octave.
+    fn key_tracked_freqs(&self, params: &ParamModelMut) -> (f64, f64) {
+        let key_ratio =
+            self.init_note_freq
+            / note_to_freq(params.key_root().round()) as f64;
+        let max_freq = MAX_FREQ_SRATE_FACTOR * self.srate as f64;
+
+        let f_start =
+            params.freq_start() as f64
+            * key_ratio.powf(params.freq_note_start() as f64);
+        let f_end =
+            params.freq_end() as f64
+            * key_ratio.powf(params.freq_note_end() as f64);
+
+        (f_start.min(max_freq), f_end.min(max_freq))
+    }
+
+    /// The oscillators and the filter run at the oversampled rate,
+    /// the envelopes and the LFO stay at the host sample rate.
+    fn set_oversampled_rate(&mut self) {
+        self.os_srate = self.srate * self.oversampler.factor() as f32;
+        self.filter1.set_sample_rate(self.os_srate);
+        self.oscillator1.set_sample_rate(self.os_srate);
+        self.fm_oscillator.set_sample_rate(self.os_srate);
+        self.click.set_sample_rate(self.os_srate);
+        self.click.set_noise_hold(self.oversampler.factor());
+    }
 }
 
 impl MonoProcessor for OpKickmess {
@@ -120,10 +187,8 @@ impl MonoProcessor for OpKickmess {
         self.srate = sr;
         self.release.set_sample_rate(sr);
         self.f_env.set_sample_rate(sr);
-        self.filter1.set_sample_rate(sr);
-        self.oscillator1.set_sample_rate(sr);
-        self.fm_oscillator.set_sample_rate(sr);
         self.lfo1.set_sample_rate(sr);
+        self.set_oversampled_rate();
     }
 
     fn process(&mut self, smth_params: &SmoothParameters, proc_offs: usize, out: &mut [f32]) {
@@ -131,6 +196,14 @@ impl MonoProcessor for OpKickmess {
         self.f_env.set_release(block_params.f_env_release());
         self.release.set_release(block_params.env_release());
 
+        let os_changed =
+            self.oversampler.set_mode(
+                oversampling_factor(block_params.os_factor()),
+                block_params.os_quality() > 0.5);
+        if os_changed {
+            self.set_oversampled_rate();
+        }
+
         let mut params = ParamModelMut::new();
 
         for (offs, os) in out.iter_mut().enumerate() {
@@ -166,74 +239,81 @@ impl MonoProcessor for OpKickmess {
                     self.oscillator1.reset();
                     self.fm_oscillator.reset();
                     self.lfo1.reset();
+                    // The decimator keeps the tail of the last hit:
+                    self.click.trigger();
 
                     self.cur_phase = 0.0;
 
-                    if params.freq_note_start() >= 0.5 {
-                        self.cur_f_start = self.init_note_freq as f64;
-                    } else {
-                        self.cur_f_start = params.freq_start() as f64;
-                    }
-
-                    if params.freq_note_end() >= 0.5 {
-                        self.cur_f_end = self.init_note_freq as f64;
-                    } else {
-                        self.cur_f_end = params.freq_end() as f64;
-                    }
+                    let (f_start, f_end) = self.key_tracked_freqs(&params);
+                    self.cur_f_start = f_start;
+                    self.cur_f_end   = f_end;
 
                     self.note_freq = self.cur_f_start as f64;
                 }
 
-                let amp_gain : f64 = 1.0 - env_value.powf(params.env_slope() as f64);
+                let amp_gain : f64 = amp_env_shape(&params).value(env_value);
 
-                let sine = self.next_sine_sample(&params);
+                let dist_thres =
+                    lerp(
+                        env_value as f32,
+                        params.dist_start(),
+                        params.dist_end());
 
+                // The noise is drawn at the host rate and held for the
+                // oversampled samples. Otherwise the decimator would filter
+                // out more of it the higher the oversampling factor is:
                 let noise =
                     (((self.rng.next_open01() * 2.0) - 1.0)
                      * amp_gain * amp_gain)
                     .max(-0.99).min(0.99);
 
-                let mut s = lerp64(params.noise() as f64, sine, noise);
+                // The nonlinear part of the voice is rendered at the
+                // oversampled rate and decimated afterwards:
+                for i in 0..self.oversampler.factor() {
+                    let sine = self.next_sine_sample(&params);
 
-                kick_sample = s * amp_gain * params.gain() as f64;
+                    let mut s = lerp64(params.noise() as f64, sine, noise);
 
-                #[cfg(feature="mega")]
-                if params.o1_gain() > 0.001 {
-                    kick_sample +=
-                        (params.o1_gain()
-                         * amp_gain as f32
-                         * self.oscillator1.next(&O1Params(&params, &self.note_freq))) as f64;
-                }
+                    let mut os_sample = s * amp_gain * params.gain() as f64;
 
-                #[cfg(feature="mega")]
-                if params.o2fm_gain() > 0.001 {
-                    kick_sample +=
-                        (params.o2fm_gain()
-                         * amp_gain as f32
-                         * self.fm_oscillator.next(&O1Params(&params, &self.note_freq))) as f64;
-                }
+                    #[cfg(feature="mega")]
+                    if params.o1_gain() > 0.001 {
+                        os_sample +=
+                            (params.o1_gain()
+                             * amp_gain as f32
+                             * self.oscillator1.next(&O1Params(&params, &self.note_freq))) as f64;
+                    }
 
-                if params.f1_on() > 0.5 {
-                    kick_sample =
-                        self.filter1.next(kick_sample as f32, &F1Params(&params)) as f64;
-                }
+                    #[cfg(feature="mega")]
+                    if params.o2fm_gain() > 0.001 {
+                        os_sample +=
+                            (params.o2fm_gain()
+                             * amp_gain as f32
+                             * self.fm_oscillator.next(&O1Params(&params, &self.note_freq))) as f64;
+                    }
 
-                if params.dist_on() > 0.5 {
-                    let thres =
-                        lerp(
-                            env_value as f32,
-                            params.dist_start(),
-                            params.dist_end());
+                    if params.f1_on() > 0.5 {
+                        os_sample =
+                            self.filter1.next(os_sample as f32, &F1Params(&params)) as f64;
+                    }
+
+                    os_sample += self.click.next(&ClickParams(&params)) as f64;
+
+                    if params.dist_on() > 0.5 {
+                        os_sample =
+                            f_distort(0.7, dist_thres, os_sample as f32) as f64;
+                    }
 
-                    kick_sample =
-                        f_distort(0.7, thres, kick_sample as f32) as f64;
+                    self.oversampler.buffer()[i] = os_sample as f32;
                 }
 
+                kick_sample = self.oversampler.decimate() as f64;
+
                 kick_sample *= params.main_gain() as f64;
 
                 let freq_change : f64 =
                     (self.cur_f_start - self.cur_f_end) as f64
-                    * (1.0 - env_value.powf(params.freq_slope() as f64));
+                    * freq_env_shape(&params).value(env_value);
 
                 self.note_freq = self.cur_f_end as f64 + freq_change;
             }
@@ -269,6 +349,7 @@ impl MonoVoice for OpKickmess {
             note_freq:       0.0,
             cur_phase:       0.0,
             srate:           0.0,
+            os_srate:        0.0,
 
             rng:             RandGen::new(),
             f_env:           REnv::new(),
@@ -279,6 +360,8 @@ impl MonoVoice for OpKickmess {
             params:          ParamModelMut::new(),
             lfo1:            LFO::new(),
             mf1:             ModulatorFun::new(),
+            oversampler:     Oversampler::new(),
+            click:           ClickLayer::new(),
         }
     }
 
@@ -309,3 +392,42 @@ impl MonoVoice for OpKickmess {
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn params_with(values: &[(usize, f32)]) -> ParamModelMut {
+        let mut frame = [0.0; PARAM_COUNT];
+        for (id, v) in values.iter() {
+            frame[*id] = *v;
+        }
+        let mut params = ParamModelMut::new();
+        params.swap(&frame[..]);
+        params
+    }
+
+    #[test]
+    fn check_key_tracking_stays_below_nyquist() {
+        let params = params_with(&[
+            (pid::freq_start,      3000.0),
+            (pid::freq_end,        2000.0),
+            (pid::freq_note_start, 1.0),
+            (pid::freq_note_end,   1.0),
+            (pid::key_root,        0.0),
+        ]);
+
+        let mut op = OpKickmess::new();
+        op.set_sample_rate(44100.0);
+
+        op.start_note(0, 0, note_to_freq(127.0), 1.0);
+        let (f_start, f_end) = op.key_tracked_freqs(&params);
+        assert!((f_start - 0.45 * 44100.0).abs() < 1e-6, "{}", f_start);
+        assert!((f_end   - 0.45 * 44100.0).abs() < 1e-6, "{}", f_end);
+
+        // The root note plays the frequencies of the parameters:
+        op.start_note(0, 0, note_to_freq(0.0), 1.0);
+        let (f_start, f_end) = op.key_tracked_freqs(&params);
+        assert!((f_start - 3000.0).abs() < 1e-3, "{}", f_start);
+        assert!((f_end   - 2000.0).abs() < 1e-3, "{}", f_end);
+    }
+}
@@ -1,4 +1,5 @@
 use crate::helpers::*;
+use crate::denormal::flush_denormal_f64;
 
 pub trait OscillatorInputParams {
     fn freq(&self)          -> f32 { 0.0 }
@@ -256,8 +257,8 @@ impl FMOscillator {
         let s_op1 = fast_sin(self.phase1 * 2.0 * std::f64::consts::PI);
         let s_op2 = fast_sin(self.phase2 * 2.0 * std::f64::consts::PI);
 
-        self.op1_fb = s_op1;
-        self.op2_fb = s_op2;
+        self.op1_fb = flush_denormal_f64(s_op1);
+        self.op2_fb = flush_denormal_f64(s_op2);
 
         self.phase1 += phase1_inc;
         self.phase1 = self.phase1.fract();
@@ -267,3 +268,34 @@ impl FMOscillator {
         s_op2 as f32
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct FMParams;
+
+    impl OscillatorInputParams for FMParams {
+        fn op1_ratio(&self) -> f32 { 1.0 }
+        fn op1_self(&self)  -> f32 { 1000.0 }
+        fn op2_self(&self)  -> f32 { 1000.0 }
+        fn op1_op2(&self)   -> f32 { 1000.0 }
+        fn op2_op1(&self)   -> f32 { 1000.0 }
+    }
+
+    #[test]
+    fn check_fm_feedback_flushes_denormals() {
+        let mut osc = FMOscillator::new();
+
+        // A phase just above zero yields a tiny feedback value,
+        // which must not circulate in the feedback paths:
+        osc.phase1 = 1e-310;
+        osc.phase2 = 1e-310;
+
+        for _ in 0..100 {
+            osc.next(&FMParams);
+            assert!(osc.op1_fb == 0.0 || osc.op1_fb.is_normal());
+            assert!(osc.op2_fb == 0.0 || osc.op2_fb.is_normal());
+        }
+    }
+}
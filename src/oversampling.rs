@@ -0,0 +1,241 @@
+// Copyright (c) 2020-2021 Weird Constructor <weirdconstructor@gmail.com>
+// This is a part of Kickmess. See README.md and COPYING for details.
+
+const PI : f64 = std::f64::consts::PI;
+
+/// Number of non zero coefficients on one side of the realtime halfband filter.
+const RT_HALF_TAPS : usize = 8;
+/// Number of non zero coefficients on one side of the offline halfband filter.
+const HQ_HALF_TAPS : usize = 24;
+
+pub const MAX_OVERSAMPLING : usize = 8;
+
+/// Maps the oversampling parameter to the oversampling factor 1, 2, 4 or 8.
+pub fn oversampling_factor(p: f32) -> usize {
+    match (p * 4.0).floor() as usize {
+        0 => 1,
+        1 => 2,
+        2 => 4,
+        _ => 8,
+    }
+}
+
+/// Windowed sinc halfband lowpass, that halves the sample rate.
+///
+/// Every second coefficient of a halfband filter is zero, only the
+/// ones at odd distances to the center tap are stored and computed.
+#[derive(Debug, Clone)]
+struct HalfbandDecimator {
+    coefs:  Vec<f32>,
+    hist:   Vec<f32>,
+    taps:   usize,
+    pos:    usize,
+}
+
+impl HalfbandDecimator {
+    fn new(half_taps: usize) -> Self {
+        let taps   = 4 * half_taps - 1;
+        let center = (taps / 2) as f64;
+
+        let mut coefs = Vec::with_capacity(half_taps);
+        for k in 0..half_taps {
+            let d = (2 * k + 1) as f64;
+            let sinc = (PI * d * 0.5).sin() / (PI * d);
+
+            // Blackman window, that does not waste the outermost taps on 0.0:
+            let x = (center + d + 1.0) / (taps + 1) as f64;
+            let w =
+                0.42
+                - 0.5  * (2.0 * PI * x).cos()
+                + 0.08 * (4.0 * PI * x).cos();
+
+            coefs.push(sinc * w);
+        }
+
+        // Normalize for a DC gain of 1.0, the center tap is fixed at 0.5:
+        let sum : f64 = coefs.iter().sum::<f64>() * 2.0;
+
+        Self {
+            coefs: coefs.iter().map(|c| (c * 0.5 / sum) as f32).collect(),
+            hist:  vec![0.0; 2 * taps],
+            taps,
+            pos:   0,
+        }
+    }
+
+    fn reset(&mut self) {
+        for h in self.hist.iter_mut() { *h = 0.0; }
+        self.pos = 0;
+    }
+
+    #[inline]
+    fn push(&mut self, x: f32) {
+        // The history is stored twice, so that hist[pos + i]
+        // is always the sample from i samples ago:
+        self.pos = if self.pos == 0 { self.taps - 1 } else { self.pos - 1 };
+        self.hist[self.pos]             = x;
+        self.hist[self.pos + self.taps] = x;
+    }
+
+    #[inline]
+    fn next(&mut self, a: f32, b: f32) -> f32 {
+        self.push(a);
+        self.push(b);
+
+        let h = &self.hist[self.pos..(self.pos + self.taps)];
+        let c = self.taps / 2;
+
+        let mut y = 0.5 * h[c];
+        for (k, coef) in self.coefs.iter().enumerate() {
+            let d = 2 * k + 1;
+            y += coef * (h[c - d] + h[c + d]);
+        }
+
+        y
+    }
+}
+
+/// Brings a signal that was rendered at 2, 4 or 8 times the
+/// sample rate back down to the sample rate.
+///
+/// The decimation is done by a cascade of halfband filters. The
+/// realtime quality uses short filters, the offline quality long
+/// filters with a steeper transition band and less aliasing.
+///
+/// The only allocation happens in new().
+pub struct Oversampler {
+    factor:     usize,
+    offline:    bool,
+    rt_stages:  [HalfbandDecimator; 3],
+    hq_stages:  [HalfbandDecimator; 3],
+    buf:        [f32; MAX_OVERSAMPLING],
+}
+
+impl Oversampler {
+    pub fn new() -> Self {
+        let rt = HalfbandDecimator::new(RT_HALF_TAPS);
+        let hq = HalfbandDecimator::new(HQ_HALF_TAPS);
+
+        Self {
+            factor:    1,
+            offline:   false,
+            rt_stages: [rt.clone(), rt.clone(), rt],
+            hq_stages: [hq.clone(), hq.clone(), hq],
+            buf:       [0.0; MAX_OVERSAMPLING],
+        }
+    }
+
+    /// Sets the oversampling factor (1, 2, 4 or 8) and
+    /// the filter quality. Returns true if anything changed.
+    pub fn set_mode(&mut self, factor: usize, offline: bool) -> bool {
+        let factor = factor.max(1).min(MAX_OVERSAMPLING).next_power_of_two();
+        if factor == self.factor && offline == self.offline {
+            return false;
+        }
+
+        self.factor  = factor;
+        self.offline = offline;
+        self.reset();
+        true
+    }
+
+    pub fn factor(&self) -> usize { self.factor }
+
+    pub fn reset(&mut self) {
+        for s in self.rt_stages.iter_mut() { s.reset(); }
+        for s in self.hq_stages.iter_mut() { s.reset(); }
+        for b in self.buf.iter_mut() { *b = 0.0; }
+    }
+
+    /// The buffer for the factor() samples, that are
+    /// rendered at the oversampled rate.
+    #[inline]
+    pub fn buffer(&mut self) -> &mut [f32] {
+        &mut self.buf[0..self.factor]
+    }
+
+    /// Filters and decimates the samples in buffer() down
+    /// to one sample at the original sample rate.
+    #[inline]
+    pub fn decimate(&mut self) -> f32 {
+        let stages =
+            if self.offline { &mut self.hq_stages }
+            else            { &mut self.rt_stages };
+
+        let mut len = self.factor;
+        let mut stage_idx = 0;
+        while len > 1 {
+            let stage = &mut stages[stage_idx];
+            for i in 0..(len / 2) {
+                self.buf[i] = stage.next(self.buf[2 * i], self.buf[2 * i + 1]);
+            }
+
+            len /= 2;
+            stage_idx += 1;
+        }
+
+        self.buf[0]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run_sine(os: &mut Oversampler, freq: f64, srate: f64, len: usize) -> f32 {
+        let factor = os.factor();
+        let os_srate = srate * factor as f64;
+        let mut phase : f64 = 0.0;
+        let mut peak : f32 = 0.0;
+
+        for i in 0..len {
+            for s in os.buffer().iter_mut() {
+                *s = (phase * 2.0 * PI).sin() as f32;
+                phase += freq / os_srate;
+            }
+
+            let out = os.decimate();
+            // skip the filter delay:
+            if i > len / 2 {
+                peak = peak.max(out.abs());
+            }
+        }
+
+        peak
+    }
+
+    #[test]
+    fn check_oversampler_dc() {
+        for &offline in [false, true].iter() {
+            for &factor in [1, 2, 4, 8].iter() {
+                let mut os = Oversampler::new();
+                os.set_mode(factor, offline);
+                assert_eq!(os.factor(), factor);
+
+                let mut out = 0.0;
+                for _ in 0..200 {
+                    for s in os.buffer().iter_mut() { *s = 0.5; }
+                    out = os.decimate();
+                }
+
+                assert!((out - 0.5).abs() < 0.0001, "factor={} out={}", factor, out);
+            }
+        }
+    }
+
+    #[test]
+    fn check_oversampler_aliasing() {
+        let mut os = Oversampler::new();
+        os.set_mode(2, false);
+        // A tone in the passband stays:
+        assert!(run_sine(&mut os, 1000.0, 44100.0, 1000) > 0.99);
+        // A tone, that would alias down to 14.1kHz is suppressed:
+        assert!(run_sine(&mut os, 30000.0, 44100.0, 1000) < 0.01);
+
+        let mut os = Oversampler::new();
+        os.set_mode(4, true);
+        assert!(run_sine(&mut os, 1000.0, 44100.0, 1000) > 0.99);
+        assert!(run_sine(&mut os, 30000.0, 44100.0, 1000) < 0.001);
+        assert!(run_sine(&mut os, 80000.0, 44100.0, 1000) < 0.001);
+    }
+}
@@ -1,117 +1,347 @@
 use crate::proc::*;
+use crate::env::SHAPE_POINTS;
+
+const ENV_POINT_TIME_HELP : &str =
+    "The position of the breakpoint in the envelope,\n\
+     from the start (0.0) to the end (1.0).\n\
+     It can't be placed before the previous breakpoint.";
+const ENV_POINT_LEVEL_HELP : &str =
+    "The level of the envelope at the breakpoint. For the frequency\n\
+     envelope 1.0 is the start and 0.0 the end frequency.";
+const ENV_POINT_CURVE_HELP : &str =
+    "The curvature of the segment, that ends in the breakpoint.\n\
+     1.0 is linear, smaller values move fast at the start of\n\
+     the segment, bigger values move slow at the start.";
+
+/// The help title and text of the parameter `id`, as shown in the
+/// F1 help mode of the GUI.
+pub fn help_text(id: usize) -> (&'static str, &'static str) {
+    match id {
+        pid::freq_start      => ("Start Frequency",
+            "This is the starting frequency of the frequency envelope."),
+        pid::freq_end        => ("End Frequency",
+            "This is the ending frequency of the frequency envelope."),
+        pid::f_env_release   => ("Length",
+            "The lengths of the frequency and amplitude envelope in milliseconds."),
+        pid::dist_start      => ("Distortion start amount",
+            "Distortion has it's own linear envelope.\n\
+             You can have different start and ending amount of\n\
+             the distortion envelope."),
+        pid::dist_end        => ("Distortion end amount",
+            "Distortion has it's own linear envelope.\n\
+             You can have different start and ending amount of\n\
+             the distortion envelope."),
+        pid::gain            => ("Gain",
+            "Additional gain applied to the output of the synthesizer."),
+        pid::env_slope       => ("Envelope Slope",
+            "The slope of the amplitude envelope.\n\
+             You can go from linear to exponential."),
+        pid::freq_slope      => ("Frequency Envelope Slope",
+            "The slope of the frequency envelope.\n\
+             You can go from linear to exponential."),
+        pid::noise           => ("Noise/Tone Balance",
+            "The balance between tone (0.0) and noise (1.0)."),
+        pid::freq_note_start => ("Key tracking of Start frequency",
+            "Defines how much the start frequency follows the played\n\
+             MIDI note, relative to the 'Key Root' note.\n\
+             0.0 means no tracking, 1.0 means the start frequency\n\
+             goes up one octave when the note goes up one octave."),
+        pid::freq_note_end   => ("Key tracking of End frequency",
+            "Defines how much the end frequency follows the played\n\
+             MIDI note, relative to the 'Key Root' note.\n\
+             0.0 means no tracking, 1.0 means the end frequency\n\
+             goes up one octave when the note goes up one octave."),
+        pid::env_release     => ("Env Release",
+            "There is a second release envelope that affects the amplifier.\n\
+             It is started when the MIDI note off event is received.\n\
+             This parameter defines the length of that release."),
+        pid::phase_offs      => ("Click Amount",
+            "This value will cut the phase of the sine wave,\n\
+             causing an audible extra 'click' at the start of the note."),
+        pid::dist_on         => ("Distortion",
+            "If the distortion is enabled, the 'Start' and 'End' amounts will\n\
+             define the amount of distortion at the beginning and end of the\n\
+             envelope."),
+        pid::f1_cutoff       => ("Filter 1 Cutoff Frequency",
+            "The cutoff frequency of the filter."),
+        pid::f1_res          => ("Filter 1 Resonance",
+            "The resonance of the filter, be careful!\n\
+             this thing will self oscillate a lot!"),
+        pid::f1_drive        => ("Filter 1 Overdrive",
+            "A kind of pre-gain for the filter input.\n"),
+        pid::main_gain       => ("Main Gain",
+            "The main output gain."),
+        pid::e1_attack       => ("Env 1 Attack",    ""),
+        pid::e1_decay        => ("Env 1 Decay",     ""),
+        pid::e1_sustain      => ("Env 1 Sustain",   ""),
+        pid::e1_release      => ("Env 1 Release",   ""),
+        pid::click_level     => ("Click Layer Level",
+            "Level of the transient click layer, that is mixed\n\
+             on top of the kick before the distortion."),
+        pid::click_decay     => ("Click Layer Decay",
+            "Time in milliseconds until the click layer\n\
+             has decayed to -60dB."),
+        pid::click_tone      => ("Click Layer Tone",
+            "Cutoff frequency of the lowpass filter of the click layer.\n\
+             Lower values make the click duller."),
+
+        // Private Parameters
+        pid::f1_type         => ("Filter 1 Type",
+            "low pass:  0.0  - 0.33\n\
+             high pass: 0.33 - 0.66\n\
+             band pass: 0.66 - 1.0 \n\
+            "),
+        pid::f1_on           => ("Filter 1 On/Off", ""),
+        pid::midi_chan       => ("MIDI Channel",    ""),
+        pid::key_root        => ("Key Tracking Root Note",
+            "The MIDI note at which the start and end frequencies\n\
+             are played unchanged by the key tracking."),
+        pid::os_factor       => ("Oversampling",
+            "Renders the oscillators, the filter and the distortion\n\
+             at 2, 4 or 8 times the sample rate. This reduces aliasing\n\
+             at high distortion settings, but costs more CPU."),
+        pid::os_quality      => ("Oversampling Quality",
+            "Realtime uses short anti-aliasing filters.\n\
+             Offline uses long filters with less aliasing,\n\
+             meant for rendering and bouncing tracks."),
+        pid::click_type      => ("Click Layer Type",
+            "The source of the click layer:\n\
+             Noise:  A filtered noise burst\n\
+             Sine:   A short 3kHz sine burst\n\
+             Square: A short 1.5kHz square burst\n\
+             Beater: A fast sine sweep down from 8kHz"),
+        pid::limiter_on      => ("Output Limiter",
+            "A safety limiter, that keeps the true peak level of\n\
+             the output below -1dBFS. It looks 1.5ms ahead and\n\
+             delays the output by that time. The host compensates\n\
+             the delay, while the limiter is off there is none."),
+        pid::fe_points       => ("Frequency Envelope Points",
+            "The number of breakpoints between the start and the end\n\
+             of the frequency envelope. Drag the breakpoints in the\n\
+             'Freq. Env' graph to make double drops or holds."),
+        pid::fe_p1_time      => ("Frequency Envelope Point 1 Time",  ENV_POINT_TIME_HELP),
+        pid::fe_p1_level     => ("Frequency Envelope Point 1 Level", ENV_POINT_LEVEL_HELP),
+        pid::fe_p1_curve     => ("Frequency Envelope Point 1 Curve", ENV_POINT_CURVE_HELP),
+        pid::fe_p2_time      => ("Frequency Envelope Point 2 Time",  ENV_POINT_TIME_HELP),
+        pid::fe_p2_level     => ("Frequency Envelope Point 2 Level", ENV_POINT_LEVEL_HELP),
+        pid::fe_p2_curve     => ("Frequency Envelope Point 2 Curve", ENV_POINT_CURVE_HELP),
+        pid::fe_p3_time      => ("Frequency Envelope Point 3 Time",  ENV_POINT_TIME_HELP),
+        pid::fe_p3_level     => ("Frequency Envelope Point 3 Level", ENV_POINT_LEVEL_HELP),
+        pid::fe_p3_curve     => ("Frequency Envelope Point 3 Curve", ENV_POINT_CURVE_HELP),
+        pid::ae_points       => ("Amplitude Envelope Points",
+            "The number of breakpoints between the start and the end\n\
+             of the amplitude envelope. Drag the breakpoints in the\n\
+             'Amp Env' graph to hold the level or make a second decay."),
+        pid::ae_p1_time      => ("Amplitude Envelope Point 1 Time",  ENV_POINT_TIME_HELP),
+        pid::ae_p1_level     => ("Amplitude Envelope Point 1 Level", ENV_POINT_LEVEL_HELP),
+        pid::ae_p1_curve     => ("Amplitude Envelope Point 1 Curve", ENV_POINT_CURVE_HELP),
+        pid::ae_p2_time      => ("Amplitude Envelope Point 2 Time",  ENV_POINT_TIME_HELP),
+        pid::ae_p2_level     => ("Amplitude Envelope Point 2 Level", ENV_POINT_LEVEL_HELP),
+        pid::ae_p2_curve     => ("Amplitude Envelope Point 2 Curve", ENV_POINT_CURVE_HELP),
+        pid::ae_p3_time      => ("Amplitude Envelope Point 3 Time",  ENV_POINT_TIME_HELP),
+        pid::ae_p3_level     => ("Amplitude Envelope Point 3 Level", ENV_POINT_LEVEL_HELP),
+        pid::ae_p3_curve     => ("Amplitude Envelope Point 3 Curve", ENV_POINT_CURVE_HELP),
+        _ => mega_help_text(id),
+    }
+}
 
-pub const help_texts : [(&str, &str); 48] = [
-    ("Start Frequency",
-        "This is the starting frequency of the frequency envelope."),
-    ("End Frequency",
-        "This is the ending frequency of the frequency envelope."),
-    ("Length",
-        "The lengths of the frequency and amplitude envelope in milliseconds."),
-    ("Distortion start amount",
-        "Distortion has it's own linear envelope.\n\
-         You can have different start and ending amount of\n\
-         the distortion envelope."),
-    ("Distortion end amount",
-        "Distortion has it's own linear envelope.\n\
-         You can have different start and ending amount of\n\
-         the distortion envelope."),
-    ("Gain",
-        "Additional gain applied to the output of the synthesizer."),
-    ("Envelope Slope",
-        "The slope of the amplitude envelope.\n\
-         You can go from linear to exponential."),
-    ("Frequency Envelope Slope",
-        "The slope of the frequency envelope.\n\
-         You can go from linear to exponential."),
-    ("Noise/Tone Balance",
-        "The balance between tone (0.0) and noise (1.0)."),
-    ("Note pitch is Start frequency",
-        "If you enable this, the frequency will start with the\n\
-         pitch of the played MIDI note."),
-    ("Note pitch is End frequency",
-        "If you enable this, the frequency will end with the\n\
-         pitch of the played MIDI note."),
-    ("Env Release",
-        "There is a second release envelope that affects the amplifier.\n\
-         It is started when the MIDI note off event is received.\n\
-         This parameter defines the length of that release."),
-    ("Click Amount",
-        "This value will cut the phase of the sine wave,\n\
-         causing an audible extra 'click' at the start of the note."),
-    ("Distortion",
-        "If the distortion is enabled, the 'Start' and 'End' amounts will\n\
-         define the amount of distortion at the beginning and end of the\n\
-         envelope."),
-    ("Filter 1 Cutoff Frequency", "The cutoff frequency of the filter."),
-    ("Filter 1 Resonance",
-        "The resonance of the filter, be careful!\n\
-         this thing will self oscillate a lot!"),
-    ("Filter 1 Overdrive",
-        "A kind of pre-gain for the filter input.\n"),
-    ("Main Gain",                 "The main output gain."),
-    ("Env 1 Attack",              ""),
-    ("Env 1 Decay",               ""),
-    ("Env 1 Sustain",             ""),
-    ("Env 1 Release",             ""),
-
-    // Megamess Parameters
-    ("Oscillator 1 Gain",            ""),
-    ("Oscillator 1 Wave",            ""),
-    ("Oscillator 1 Pulsewidth",      ""),
-    ("Oscillator 1 Unison",          ""),
-    ("Oscillator 1 Detune",          ""),
-    ("FM OP1 Frequency Ratio",       ""),
-    ("FM OP1 Self Modulation",       ""),
-    ("FM OP1 -> OP2 Modulation",     ""),
-    ("FM OP2 -> OP1 Modulation",     ""),
-    ("FM OP2 Fixed Frequency",       ""),
-    ("FM OP2 Self Modulation",       ""),
-    ("FM OP2 Gain",                  ""),
-    ("LFO 1 - Frequency",            ""),
-    ("LFO 1 - Frequency Multiplier", ""),
-    ("LFO 1 - Waveform",             ""),
-    ("LFO 1 - Pulsewidth",           ""),
-    ("LFO 1 - Phase Offset",         ""),
-    ("Modulator 1 - Amount",         ""),
-    ("Modulator 1 - Slope",          ""),
-
-    // Private Parameters
-    ("Filter 1 Type",
-        "low pass:  0.0  - 0.33\n\
-         high pass: 0.33 - 0.66\n\
-         band pass: 0.66 - 1.0 \n\
-        "),
-    ("Filter 1 On/Off",                      ""),
-    ("MIDI Channel",                         ""),
-    ("FM OP2 Frequency Mode",                ""),
-    ("Modulator 1 - Modulation Source",      ""),
-    ("Modulator 1 - Modulation Destination", ""),
-    ("Modulator 1 - Modulation Function",    ""),
-];
+#[cfg(feature="mega")]
+fn mega_help_text(id: usize) -> (&'static str, &'static str) {
+    match id {
+        pid::o1_gain     => ("Oscillator 1 Gain",                     ""),
+        pid::o1_waveform => ("Oscillator 1 Wave",                     ""),
+        pid::o1_pw       => ("Oscillator 1 Pulsewidth",               ""),
+        pid::o1_unison   => ("Oscillator 1 Unison",                   ""),
+        pid::o1_detune   => ("Oscillator 1 Detune",                   ""),
+        pid::o1fm_ratio  => ("FM OP1 Frequency Ratio",                ""),
+        pid::o1fm_self   => ("FM OP1 Self Modulation",                ""),
+        pid::o1fm_o2_mod => ("FM OP1 -> OP2 Modulation",              ""),
+        pid::o2fm_o1_mod => ("FM OP2 -> OP1 Modulation",              ""),
+        pid::o2fm_freq   => ("FM OP2 Fixed Frequency",                ""),
+        pid::o2fm_self   => ("FM OP2 Self Modulation",                ""),
+        pid::o2fm_gain   => ("FM OP2 Gain",                           ""),
+        pid::lfo1_freq   => ("LFO 1 - Frequency",                     ""),
+        pid::lfo1_fmul   => ("LFO 1 - Frequency Multiplier",          ""),
+        pid::lfo1_wave   => ("LFO 1 - Waveform",                      ""),
+        pid::lfo1_pw     => ("LFO 1 - Pulsewidth",                    ""),
+        pid::lfo1_phase  => ("LFO 1 - Phase Offset",                  ""),
+        pid::m1_amount   => ("Modulator 1 - Amount",                  ""),
+        pid::m1_slope    => ("Modulator 1 - Slope",                   ""),
+
+        // Private Parameters
+        pid::o2fm_mode   => ("FM OP2 Frequency Mode",                 ""),
+        pid::m1_src_id   => ("Modulator 1 - Modulation Source",       ""),
+        pid::m1_dest_id  => ("Modulator 1 - Modulation Destination",  ""),
+        pid::m1_fun      => ("Modulator 1 - Modulation Function",     ""),
+        _ => ("", ""),
+    }
+}
+
+#[cfg(not(feature="mega"))]
+fn mega_help_text(_id: usize) -> (&'static str, &'static str) { ("", "") }
+
+/// The unit of the parameter `id`, as shown in the value tooltip
+/// of the GUI. Empty for parameters without a unit.
+pub fn param_unit(id: usize) -> &'static str {
+    match id {
+        pid::freq_start
+        | pid::freq_end
+        | pid::f1_cutoff
+        | pid::click_tone    => "Hz",
+        pid::f_env_release
+        | pid::env_release
+        | pid::e1_attack
+        | pid::e1_decay
+        | pid::e1_release
+        | pid::click_decay   => "ms",
+        _ => mega_param_unit(id),
+    }
+}
+
+#[cfg(feature="mega")]
+fn mega_param_unit(id: usize) -> &'static str {
+    match id {
+        pid::o1fm_self
+        | pid::o1fm_o2_mod
+        | pid::o2fm_o1_mod
+        | pid::o2fm_freq
+        | pid::o2fm_self
+        | pid::lfo1_freq     => "Hz",
+        _ => "",
+    }
+}
+
+#[cfg(not(feature="mega"))]
+fn mega_param_unit(_id: usize) -> &'static str { "" }
+
+/// The section of the editor, that contains the parameter `id`.
+/// The CLAP plugin reports it as module of the parameter.
+pub fn param_module(id: usize) -> &'static str {
+    match id {
+        pid::freq_start
+        | pid::freq_end
+        | pid::f_env_release
+        | pid::env_slope
+        | pid::freq_slope
+        | pid::noise
+        | pid::freq_note_start
+        | pid::freq_note_end
+        | pid::env_release
+        | pid::key_root       => "Main Oscillator",
+        pid::dist_start
+        | pid::dist_end
+        | pid::dist_on
+        | pid::os_factor
+        | pid::os_quality     => "Distortion",
+        pid::gain
+        | pid::main_gain
+        | pid::midi_chan
+        | pid::limiter_on     => "Mixer",
+        pid::phase_offs
+        | pid::click_level
+        | pid::click_decay
+        | pid::click_tone
+        | pid::click_type     => "Click",
+        pid::f1_cutoff
+        | pid::f1_res
+        | pid::f1_drive
+        | pid::f1_type
+        | pid::f1_on          => "Filter 1",
+        pid::e1_attack
+        | pid::e1_decay
+        | pid::e1_sustain
+        | pid::e1_release     => "Env 1",
+        // The envelope breakpoints:
+        _ if id >= pid::fe_points && id <= pid::ae_p3_curve
+                              => "Main Oscillator",
+        _ => mega_param_module(id),
+    }
+}
+
+#[cfg(feature="mega")]
+fn mega_param_module(id: usize) -> &'static str {
+    match id {
+        pid::o1_gain
+        | pid::o1_waveform
+        | pid::o1_pw
+        | pid::o1_unison
+        | pid::o1_detune      => "Oscillator 1",
+        pid::o1fm_ratio
+        | pid::o1fm_self
+        | pid::o1fm_o2_mod
+        | pid::o2fm_o1_mod
+        | pid::o2fm_freq
+        | pid::o2fm_self
+        | pid::o2fm_gain
+        | pid::o2fm_mode      => "FM Oscillator",
+        pid::lfo1_freq
+        | pid::lfo1_fmul
+        | pid::lfo1_wave
+        | pid::lfo1_pw
+        | pid::lfo1_phase     => "LFO 1",
+        pid::m1_amount
+        | pid::m1_slope
+        | pid::m1_src_id
+        | pid::m1_dest_id
+        | pid::m1_fun         => "Mod1",
+        _ => "",
+    }
+}
+
+#[cfg(not(feature="mega"))]
+fn mega_param_module(_id: usize) -> &'static str { "" }
 
 #[cfg(not(feature="mega"))]
 macro_rules! define_constants {
     () => {
-        pub const PUB_PARAM_COUNT : usize = 22;
-        pub const PARAM_COUNT     : usize = 25;
+        pub const PUB_PARAM_COUNT : usize = 25;
+        pub const PARAM_COUNT     : usize = 53;
+        /// Public parameters added after the Megamess parameters start
+        /// here, so the Megamess parameters keep their host indices.
+        pub const PUB_EXT_OFFS    : usize = 22;
+        /// Private parameters added after the Megamess parameters start
+        /// here, so the Megamess parameters keep their ids.
+        pub const PRIV_EXT_OFFS   : usize = PUB_PARAM_COUNT + 8;
         macro_rules! ppc {
             ($x: expr) => {
                 ($x + crate::param_model::PUB_PARAM_COUNT)
             }
         }
+        macro_rules! pec {
+            ($x: expr) => {
+                ($x + crate::param_model::PUB_EXT_OFFS)
+            }
+        }
+        macro_rules! ppec {
+            ($x: expr) => {
+                ($x + crate::param_model::PRIV_EXT_OFFS)
+            }
+        }
     }
 }
 
 #[cfg(feature="mega")]
 macro_rules! define_constants {
     () => {
-        pub const PUB_PARAM_COUNT : usize = 41;
-        pub const PARAM_COUNT     : usize = 48;
+        pub const PUB_PARAM_COUNT : usize = 44;
+        pub const PARAM_COUNT     : usize = 76;
+        pub const PUB_EXT_OFFS    : usize = 41;
+        pub const PRIV_EXT_OFFS   : usize = PUB_PARAM_COUNT + 12;
         macro_rules! ppc {
             ($x: expr) => {
                 ($x + crate::param_model::PUB_PARAM_COUNT)
             }
         }
+        macro_rules! pec {
+            ($x: expr) => {
+                ($x + crate::param_model::PUB_EXT_OFFS)
+            }
+        }
+        macro_rules! ppec {
+            ($x: expr) => {
+                ($x + crate::param_model::PRIV_EXT_OFFS)
+            }
+        }
     }
 }
 
@@ -120,34 +350,34 @@ define_constants!{}
 
 macro_rules! mega_params {
     ($x: ident) => {
-        //  scope   name         exp/lin smooth        idx  min    max     def    width  prec  label
-        $x!{public  o1_gain         exp smooth         22,  0.0,   2.0,      0.0,     4,    2, "O1 Gain"}
-        $x!{public  o1_waveform     lin no_smooth      23,  0.0,   1.0,      0.0,     4,    2, "O1 Wave"}
-        $x!{public  o1_pw           lin smooth         24,  0.0,   1.0,      1.0,     4,    2, "O1 PW"}
-        $x!{public  o1_unison       lin no_smooth      25,  0.0,  10.5,      0.0,     1,    0, "O1 Unison"}
-        $x!{public  o1_detune       lin smooth         26,  0.0,   1.0,      0.01,    5,    3, "O1 Detune"}
-
-        $x!{public  o1fm_ratio      exp smooth         27,  0.0,  30.0,       2.0,    5,    3, "OP1 Ratio"}
-        $x!{public  o1fm_self       exp smooth         28,  0.0,30000.0,      0.0,    4,    2, "OP1 Self Hz"}
-        $x!{public  o1fm_o2_mod     exp smooth         29,  0.0,30000.0,    100.0,    4,    2, "OP1>OP2 Hz"}
-        $x!{public  o2fm_o1_mod     exp smooth         30,  0.0,30000.0,      0.0,    4,    2, "OP2>OP1 Hz"}
-        $x!{public  o2fm_freq       exp smooth         31,  0.0,30000.0,    500.0,    4,    2, "OP2 Freq Hz"}
-        $x!{public  o2fm_self       exp smooth         32,  0.0,30000.0,      0.0,    4,    2, "OP2 Self Hz"}
-        $x!{public  o2fm_gain       lin smooth         33,  0.0,   2.0,       0.0,    5,    3, "OP2 Gain"}
-        $x!{private o2fm_mode       lin no_smooth ppc!(3),  0.0,   1.0,       0.0,    3,    1, "OP2 Mode"}
-
-        $x!{public  lfo1_freq      exp4 smooth         34,  0.0, 100.0,       1.0,    5,    3, "LFO1 Freq"}
-        $x!{public  lfo1_fmul       lin smooth         35,  0.1, 100.0,       1.0,    5,    3, "LFO1 FMul"}
-        $x!{public  lfo1_wave       lin no_smooth      36,  0.0,   1.0,       0.0,    3,    1, "LFO1 Wave"}
-        $x!{public  lfo1_pw         lin smooth         37,  0.01, 0.99,       0.5,    3,    1, "LFO1 PW"}
-        $x!{public  lfo1_phase      lin smooth         38,  0.0,   1.0,       0.0,    3,    1, "LFO1 Phase"}
-
-        $x!{public   m1_amount      lin smooth         39,  0.0,   1.0,       1.0,    4,    2, "Mod1 Amt"}
-        $x!{public   m1_slope       lin smooth         40,  0.0,   1.0,       0.0,    5,    3, "Mod1 Slope"}
-
-        $x!{private  m1_src_id      lin no_smooth ppc!(4),  0.0,1000.0,      -1.0,    1,    0, "Mod1 Src"}
-        $x!{private  m1_dest_id     lin no_smooth ppc!(5),  0.0,   1.0,       0.0,    1,    0, "Mod1 Dest"}
-        $x!{private  m1_fun         lin no_smooth ppc!(6),  0.0,   1.0,       0.0,    3,    1, "Mod1 Fun"}
+        //  scope   name         exp/lin smooth      ms     idx  min    max     def    width  prec  label
+        $x!{public  o1_gain         exp smooth_1p  10.0      22,  0.0,   2.0,      0.0,     4,    2, "O1 Gain"}
+        $x!{public  o1_waveform     lin no_smooth   0.0      23,  0.0,   1.0,      0.0,     4,    2, "O1 Wave"}
+        $x!{public  o1_pw           lin smooth     10.0      24,  0.0,   1.0,      1.0,     4,    2, "O1 PW"}
+        $x!{public  o1_unison       lin no_smooth   0.0      25,  0.0,  10.5,      0.0,     1,    0, "O1 Unison"}
+        $x!{public  o1_detune       lin smooth     20.0      26,  0.0,   1.0,      0.01,    5,    3, "O1 Detune"}
+
+        $x!{public  o1fm_ratio      exp smooth     20.0      27,  0.0,  30.0,       2.0,    5,    3, "OP1 Ratio"}
+        $x!{public  o1fm_self       exp smooth_1p  10.0      28,  0.0,30000.0,      0.0,    4,    2, "OP1 Self Hz"}
+        $x!{public  o1fm_o2_mod     exp smooth_1p  10.0      29,  0.0,30000.0,    100.0,    4,    2, "OP1>OP2 Hz"}
+        $x!{public  o2fm_o1_mod     exp smooth_1p  10.0      30,  0.0,30000.0,      0.0,    4,    2, "OP2>OP1 Hz"}
+        $x!{public  o2fm_freq       exp smooth_1p  10.0      31,  0.0,30000.0,    500.0,    4,    2, "OP2 Freq Hz"}
+        $x!{public  o2fm_self       exp smooth_1p  10.0      32,  0.0,30000.0,      0.0,    4,    2, "OP2 Self Hz"}
+        $x!{public  o2fm_gain       lin smooth_1p  10.0      33,  0.0,   2.0,       0.0,    5,    3, "OP2 Gain"}
+        $x!{private o2fm_mode       lin no_smooth   0.0 ppc!(8),  0.0,   1.0,       0.0,    3,    1, "OP2 Mode"}
+
+        $x!{public  lfo1_freq      exp4 smooth     20.0      34,  0.0, 100.0,       1.0,    5,    3, "LFO1 Freq"}
+        $x!{public  lfo1_fmul       lin smooth     20.0      35,  0.1, 100.0,       1.0,    5,    3, "LFO1 FMul"}
+        $x!{public  lfo1_wave       lin no_smooth   0.0      36,  0.0,   1.0,       0.0,    3,    1, "LFO1 Wave"}
+        $x!{public  lfo1_pw         lin smooth     10.0      37,  0.01, 0.99,       0.5,    3,    1, "LFO1 PW"}
+        $x!{public  lfo1_phase      lin smooth     10.0      38,  0.0,   1.0,       0.0,    3,    1, "LFO1 Phase"}
+
+        $x!{public   m1_amount      lin smooth     10.0      39,  0.0,   1.0,       1.0,    4,    2, "Mod1 Amt"}
+        $x!{public   m1_slope       lin smooth     10.0      40,  0.0,   1.0,       0.0,    5,    3, "Mod1 Slope"}
+
+        $x!{private  m1_src_id      lin no_smooth   0.0 ppc!(9),  0.0,1000.0,      -1.0,    1,    0, "Mod1 Src"}
+        $x!{private  m1_dest_id     lin no_smooth   0.0 ppc!(10), 0.0,   1.0,       0.0,    1,    0, "Mod1 Dest"}
+        $x!{private  m1_fun         lin no_smooth   0.0 ppc!(11), 0.0,   1.0,       0.0,    3,    1, "Mod1 Fun"}
     }
 }
 
@@ -155,35 +385,66 @@ macro_rules! param_model {
     ($x: ident) => {
 //        use crate::param_model::PUB_PARAM_COUNT as PPC;
 
-        //  scope   name         exp/lin smooth        idx  min    max     def    width  prec  "mod./vst label"
-        $x!{public  freq_start      exp no_smooth      0,   5.0,   3000.0, 150.0,     4,    2, "Start Freq."}
-        $x!{public  freq_end        exp no_smooth      1,   5.0,   2000.0,  40.0,     4,    2, "End Freq."}
-        $x!{public  f_env_release   exp no_smooth      2,   5.0,   5000.0, 440.0,     3,    1, "Length"}
-        $x!{public  dist_start      lin smooth         3,   0.0,   100.0,    0.8,     4,    2, "Dist. Start"}
-        $x!{public  dist_end        lin smooth         4,   0.0,   100.0,    0.8,     4,    2, "Dist. End"}
-        $x!{public  gain            lin smooth         5,   0.0,   2.0,      1.0,     4,    2, "Gain"}
-        $x!{public  env_slope       lin smooth         6,   0.01,  1.0,    0.163,     5,    3, "Env. slope"}
-        $x!{public  freq_slope      lin smooth         7,   0.001, 1.0,     0.06,     5,    3, "Freq. slope"}
-        $x!{public  noise           exp smooth         8,   0.0,   1.0,      0.0,     4,    2, "Tone/Noise"}
-        $x!{public  freq_note_start lin no_smooth      9,   0.0,   1.0,      0.0,     3,    1, "Note > Start Freq"}
-        $x!{public  freq_note_end   lin no_smooth      10,  0.0,   1.0,      0.0,     3,    1, "Note > End Freq"}
-        $x!{public  env_release     lin no_smooth      11,  1.0,1000.0,      5.0,     4,    2, "Env Release"}
-        $x!{public  phase_offs      lin smooth         12,  0.0,   1.0,      0.0,     4,    2, "Click"}
-        $x!{public  dist_on         lin no_smooth      13,  0.0,   1.0,      0.0,     3,    1, "Dist. On"}
-
-        $x!{public  f1_cutoff       exp smooth         14, 20.0,   22050.0,  5000.0,  3,    1, "F1 Cutoff"}
-        $x!{public  f1_res          lin smooth         15,  0.0,   1.0,      0.0,     4,    2, "F1 Res"}
-        $x!{public  f1_drive        lin smooth         16,  0.0,   5.0,      1.0,     4,    2, "F1 Drive"}
-        $x!{public  main_gain       exp smooth         17,  0.0,   2.0,      1.0,     5,    3, "Main Gain"}
-
-        $x!{public  e1_attack       exp smooth         18,  0.0,5000.0,     50.0,     5,    2, "E1 Attack"}
-        $x!{public  e1_decay        exp smooth         19,  0.0,5000.0,    100.0,     5,    2, "E1 Decay"}
-        $x!{public  e1_sustain      lin smooth         20,  0.0,   1.0,     0.75,     4,    2, "E1 Sustain"}
-        $x!{public  e1_release      exp smooth         21,  0.0,5000.0,     50.0,     5,    2, "E1 Decay"}
-
-        $x!{private f1_type         lin no_smooth ppc!(0),  0.0,   1.0,      0.0,     3,    1, "F1 Type"}
-        $x!{private f1_on           lin no_smooth ppc!(1),  0.0,   1.0,      0.0,     3,    1, "F1 On"}
-        $x!{private midi_chan       lin no_smooth ppc!(2),  0.0,  15.9,       0.0,    2,    0, "Midi Chan"}
+        //  scope   name         exp/lin smooth      ms     idx  min    max     def    width  prec  "mod./vst label"
+        $x!{public  freq_start      exp no_smooth   0.0       0,   5.0,   3000.0, 150.0,     4,    2, "Start Freq."}
+        $x!{public  freq_end        exp no_smooth   0.0       1,   5.0,   2000.0,  40.0,     4,    2, "End Freq."}
+        $x!{public  f_env_release   exp no_smooth   0.0       2,   5.0,   5000.0, 440.0,     3,    1, "Length"}
+        $x!{public  dist_start      lin smooth     10.0       3,   0.0,   100.0,    0.8,     4,    2, "Dist. Start"}
+        $x!{public  dist_end        lin smooth     10.0       4,   0.0,   100.0,    0.8,     4,    2, "Dist. End"}
+        $x!{public  gain            lin smooth_1p  10.0       5,   0.0,   2.0,      1.0,     4,    2, "Gain"}
+        $x!{public  env_slope       lin smooth     20.0       6,   0.01,  1.0,    0.163,     5,    3, "Env. slope"}
+        $x!{public  freq_slope      lin smooth     20.0       7,   0.001, 1.0,     0.06,     5,    3, "Freq. slope"}
+        $x!{public  noise           exp smooth     10.0       8,   0.0,   1.0,      0.0,     4,    2, "Tone/Noise"}
+        $x!{public  freq_note_start lin no_smooth   0.0       9,   0.0,   1.0,      0.0,     4,    2, "Start Key Trk"}
+        $x!{public  freq_note_end   lin no_smooth   0.0      10,  0.0,   1.0,      0.0,     4,    2, "End Key Trk"}
+        $x!{public  env_release     lin no_smooth   0.0      11,  1.0,1000.0,      5.0,     4,    2, "Env Release"}
+        $x!{public  phase_offs      lin smooth     10.0      12,  0.0,   1.0,      0.0,     4,    2, "Click"}
+        $x!{public  dist_on         lin no_smooth   0.0      13,  0.0,   1.0,      0.0,     3,    1, "Dist. On"}
+
+        $x!{public  f1_cutoff       exp smooth_1p  15.0      14, 20.0,   22050.0,  5000.0,  3,    1, "F1 Cutoff"}
+        $x!{public  f1_res          lin smooth     10.0      15,  0.0,   1.0,      0.0,     4,    2, "F1 Res"}
+        $x!{public  f1_drive        lin smooth     10.0      16,  0.0,   5.0,      1.0,     4,    2, "F1 Drive"}
+        $x!{public  main_gain       exp smooth_1p  10.0      17,  0.0,   2.0,      1.0,     5,    3, "Main Gain"}
+
+        $x!{public  e1_attack       exp smooth     20.0      18,  0.0,5000.0,     50.0,     5,    2, "E1 Attack"}
+        $x!{public  e1_decay        exp smooth     20.0      19,  0.0,5000.0,    100.0,     5,    2, "E1 Decay"}
+        $x!{public  e1_sustain      lin smooth     20.0      20,  0.0,   1.0,     0.75,     4,    2, "E1 Sustain"}
+        $x!{public  e1_release      exp smooth     20.0      21,  0.0,5000.0,     50.0,     5,    2, "E1 Release"}
+
+        $x!{public  click_level     lin smooth     10.0 pec!(0),  0.0,   1.0,      0.0,     4,    2, "Click Level"}
+        $x!{public  click_decay     exp no_smooth   0.0 pec!(1),  1.0, 200.0,     20.0,     4,    1, "Click Decay"}
+        $x!{public  click_tone      exp smooth_1p  10.0 pec!(2), 100.0,20000.0, 5000.0,     4,    0, "Click Tone"}
+
+        $x!{private f1_type         lin no_smooth   0.0 ppc!(0),  0.0,   1.0,      0.0,     3,    1, "F1 Type"}
+        $x!{private f1_on           lin no_smooth   0.0 ppc!(1),  0.0,   1.0,      0.0,     3,    1, "F1 On"}
+        $x!{private midi_chan       lin no_smooth   0.0 ppc!(2),  0.0,  15.9,       0.0,    2,    0, "Midi Chan"}
+        $x!{private key_root        lin no_smooth   0.0 ppc!(3),  0.0, 127.0,      36.0,    3,    0, "Key Root"}
+        $x!{private os_factor       lin no_smooth   0.0 ppc!(4),  0.0,   1.0,       0.0,    3,    1, "Oversample"}
+        $x!{private os_quality      lin no_smooth   0.0 ppc!(5),  0.0,   1.0,       0.0,    3,    1, "OS Quality"}
+        $x!{private click_type      lin no_smooth   0.0 ppc!(6),  0.0,   1.0,       0.0,    3,    1, "Click Type"}
+        $x!{private limiter_on      lin no_smooth   0.0 ppc!(7),  0.0,   1.0,       0.0,    3,    1, "Limiter"}
+
+        $x!{private fe_points       lin no_smooth   0.0 ppec!(0), 0.0,   1.0,       0.0,    3,    1, "FEnv Points"}
+        $x!{private fe_p1_time      lin no_smooth   0.0 ppec!(1), 0.0,   1.0,       0.1,    4,    2, "FEnv P1 Time"}
+        $x!{private fe_p1_level     lin no_smooth   0.0 ppec!(2), 0.0,   1.0,       0.5,    4,    2, "FEnv P1 Level"}
+        $x!{private fe_p1_curve     exp no_smooth   0.0 ppec!(3),0.01,  10.0,       1.0,    4,    2, "FEnv P1 Curve"}
+        $x!{private fe_p2_time      lin no_smooth   0.0 ppec!(4), 0.0,   1.0,       0.3,    4,    2, "FEnv P2 Time"}
+        $x!{private fe_p2_level     lin no_smooth   0.0 ppec!(5), 0.0,   1.0,      0.25,    4,    2, "FEnv P2 Level"}
+        $x!{private fe_p2_curve     exp no_smooth   0.0 ppec!(6),0.01,  10.0,       1.0,    4,    2, "FEnv P2 Curve"}
+        $x!{private fe_p3_time      lin no_smooth   0.0 ppec!(7), 0.0,   1.0,       0.6,    4,    2, "FEnv P3 Time"}
+        $x!{private fe_p3_level     lin no_smooth   0.0 ppec!(8), 0.0,   1.0,       0.1,    4,    2, "FEnv P3 Level"}
+        $x!{private fe_p3_curve     exp no_smooth   0.0 ppec!(9),0.01,  10.0,       1.0,    4,    2, "FEnv P3 Curve"}
+
+        $x!{private ae_points       lin no_smooth   0.0 ppec!(10), 0.0,   1.0,       0.0,    3,    1, "AEnv Points"}
+        $x!{private ae_p1_time      lin no_smooth   0.0 ppec!(11), 0.0,   1.0,       0.1,    4,    2, "AEnv P1 Time"}
+        $x!{private ae_p1_level     lin no_smooth   0.0 ppec!(12), 0.0,   1.0,       0.9,    4,    2, "AEnv P1 Level"}
+        $x!{private ae_p1_curve     exp no_smooth   0.0 ppec!(13),0.01,  10.0,       1.0,    4,    2, "AEnv P1 Curve"}
+        $x!{private ae_p2_time      lin no_smooth   0.0 ppec!(14), 0.0,   1.0,       0.3,    4,    2, "AEnv P2 Time"}
+        $x!{private ae_p2_level     lin no_smooth   0.0 ppec!(15), 0.0,   1.0,       0.5,    4,    2, "AEnv P2 Level"}
+        $x!{private ae_p2_curve     exp no_smooth   0.0 ppec!(16),0.01,  10.0,       1.0,    4,    2, "AEnv P2 Curve"}
+        $x!{private ae_p3_time      lin no_smooth   0.0 ppec!(17), 0.0,   1.0,       0.6,    4,    2, "AEnv P3 Time"}
+        $x!{private ae_p3_level     lin no_smooth   0.0 ppec!(18), 0.0,   1.0,       0.2,    4,    2, "AEnv P3 Level"}
+        $x!{private ae_p3_curve     exp no_smooth   0.0 ppec!(19),0.01,  10.0,       1.0,    4,    2, "AEnv P3 Curve"}
 
         #[cfg(feature="mega")]
         mega_params!{$x}
@@ -200,7 +461,7 @@ pub struct ParamModelMut {
 }
 
 macro_rules! param_impl_accessors {
-    ($_:ident $name:ident $e:ident $s:ident $idx:expr, $($tt:tt)*) => {
+    ($_:ident $name:ident $e:ident $s:ident $ms:literal $idx:expr, $($tt:tt)*) => {
         impl ParamModel<'_> {
             pub fn $name(&self) -> f32 { self.v[$idx] }
         }
@@ -213,23 +474,39 @@ macro_rules! param_impl_accessors {
 
 pub mod pid {
     macro_rules! param_ids {
-        ($_:ident $name:ident $e:ident $s:ident $idx:expr, $($tt:tt)*) => {
+        ($_:ident $name:ident $e:ident $s:ident $ms:literal $idx:expr, $($tt:tt)*) => {
             pub const $name : usize = $idx;
         }
     }
     param_model!{param_ids}
 }
 
+/// The (time, level, curve) parameters of the breakpoints of the
+/// frequency envelope, see `crate::env::EnvShape`.
+pub const FREQ_ENV_POINTS : [(usize, usize, usize); SHAPE_POINTS] = [
+    (pid::fe_p1_time, pid::fe_p1_level, pid::fe_p1_curve),
+    (pid::fe_p2_time, pid::fe_p2_level, pid::fe_p2_curve),
+    (pid::fe_p3_time, pid::fe_p3_level, pid::fe_p3_curve),
+];
+
+/// The (time, level, curve) parameters of the breakpoints of the
+/// amplitude envelope, see `crate::env::EnvShape`.
+pub const AMP_ENV_POINTS : [(usize, usize, usize); SHAPE_POINTS] = [
+    (pid::ae_p1_time, pid::ae_p1_level, pid::ae_p1_curve),
+    (pid::ae_p2_time, pid::ae_p2_level, pid::ae_p2_curve),
+    (pid::ae_p3_time, pid::ae_p3_level, pid::ae_p3_curve),
+];
+
 pub fn create_mod_params() -> Vec<(usize, &'static str)> {
     let mut ret = vec![];
 
     macro_rules! param_deserialize {
-        ($_:ident $name:ident $e:ident smooth $idx:expr, $min:expr, $max:expr, $def:expr, $width:expr, $prec:expr, $lbl:expr) => {
-            ret.push(($idx, $lbl));
-        };
-        ($_:ident $name:ident $e:ident no_smooth $idx:expr, $min:expr, $max:expr, $def:expr, $width:expr, $prec:expr, $lbl:expr) => {
+        ($_:ident $name:ident $e:ident no_smooth $ms:literal $idx:expr, $min:expr, $max:expr, $def:expr, $width:expr, $prec:expr, $lbl:expr) => {
             {}
         };
+        ($_:ident $name:ident $e:ident $s:ident $ms:literal $idx:expr, $min:expr, $max:expr, $def:expr, $width:expr, $prec:expr, $lbl:expr) => {
+            ret.push(($idx, $lbl));
+        };
     }
 
     param_model!{param_deserialize}
@@ -237,43 +514,190 @@ pub fn create_mod_params() -> Vec<(usize, &'static str)> {
     ret
 }
 
-pub fn deserialize_preset<F: Fn(usize, f32)>(preset: &[u8], out: F) {
-    let mut preset_data : Vec<(String, f32)> = vec![];
+/// The identifiers of all parameters, indexed by the parameter id.
+pub fn param_symbols() -> [&'static str; PARAM_COUNT] {
+    let mut syms = [""; PARAM_COUNT];
+
+    macro_rules! param_symbol {
+        ($_:ident $name:ident $e:ident $s:ident $ms:literal $idx:expr, $($tt:tt)*) => {
+            syms[$idx] = stringify!($name);
+        }
+    }
+
+    param_model!{param_symbol}
+
+    syms
+}
+
+/// The description of a parameter for the metadata export,
+/// see `crate::metadata`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParamInfo {
+    pub id:         usize,
+    pub symbol:     &'static str,
+    pub label:      &'static str,
+    pub title:      &'static str,
+    pub help:       &'static str,
+    /// The section of the editor, see `param_module`.
+    pub module:     &'static str,
+    /// See `param_unit`.
+    pub unit:       &'static str,
+    pub public:     bool,
+    /// "lin", "exp" or "exp4".
+    pub mapping:    &'static str,
+    /// "no_smooth", "smooth" or "smooth_1p".
+    pub smoothing:  &'static str,
+    pub smooth_ms:  f32,
+    pub min:        f32,
+    pub max:        f32,
+    pub default:    f32,
+}
+
+/// All parameters of the `param_model!` table, ordered by their id.
+pub fn param_infos() -> Vec<ParamInfo> {
+    let mut infos = vec![];
+
+    macro_rules! param_info {
+        ($scope:ident $name:ident $e:ident $s:ident $ms:literal $idx:expr, $min:expr, $max:expr, $def:expr, $width:expr, $prec:expr, $lbl:expr) => {
+            let (title, help) = help_text($idx);
+            infos.push(ParamInfo {
+                id:         $idx,
+                symbol:     stringify!($name),
+                label:      $lbl,
+                title,
+                help,
+                module:     param_module($idx),
+                unit:       param_unit($idx),
+                public:     stringify!($scope) == "public",
+                mapping:    stringify!($e),
+                smoothing:  stringify!($s),
+                smooth_ms:  $ms,
+                min:        $min,
+                max:        $max,
+                default:    $def,
+            });
+        }
+    }
+
+    param_model!{param_info}
+
+    infos.sort_by_key(|info| info.id);
+    infos
+}
+
+/// Compares the `name` of a preset field with `field`, ignoring the
+/// whitespace, that `stringify!` puts around the colon.
+fn preset_field_is(name: &str, field: &str) -> bool {
+    name.chars().filter(|c| !c.is_whitespace()).eq(field.chars())
+}
+
+/// Converts the values of presets, that were saved before the key
+/// tracking amounts. These presets have no `priv:key_root`.
+/// The old "Note > Start Freq" and "Note > End Freq" toggles replaced the
+/// frequency with the note frequency. With a tracking amount of 1.0 and the
+/// frequency of the default root note the played note sounds the same.
+fn migrate_preset<F: FnMut(usize, f32)>(
+    preset_data: &mut Vec<(String, f32)>, out: &mut F)
+{
+    if preset_data.iter().any(|(name, _)| preset_field_is(name, "priv:key_root")) {
+        return;
+    }
+
+    let mut ps = ParamSet::new();
+    ParamModel::init_private_set(&mut ps);
+
+    let key_root  = ps.definition(pid::key_root).unwrap().default_p();
+    let root_freq =
+        crate::helpers::note_to_freq(
+            ps.definition(pid::key_root).unwrap().map(key_root).round());
+
+    let mut migrated = false;
+
+    for (toggle, freq, freq_id) in [
+        ("pub:freq_note_start", "pub:freq_start", pid::freq_start),
+        ("pub:freq_note_end",   "pub:freq_end",   pid::freq_end),
+    ].iter() {
+        let note_on =
+            match preset_data.iter().find(|(name, _)| preset_field_is(name, toggle)) {
+                Some((_, v)) => *v >= 0.5,
+                None         => continue,
+            };
+
+        for (name, value) in preset_data.iter_mut() {
+            if preset_field_is(name, toggle) {
+                *value = if note_on { 1.0 } else { 0.0 };
+            } else if note_on && preset_field_is(name, freq) {
+                *value = ps.definition(*freq_id).unwrap().unmap(root_freq);
+            }
+        }
+
+        migrated = true;
+    }
+
+    if migrated {
+        (out)(pid::key_root, key_root);
+    }
+}
+
+/// True if the `section`, like `"!EDITOR"`, is in the `preset`,
+/// also if it is empty.
+pub fn has_preset_section(preset: &[u8], section: &str) -> bool {
+    String::from_utf8_lossy(preset).split(';').any(|f| f.trim() == section)
+}
+
+/// Returns the `name=value` fields of the `section` (e.g. `"!PARAMS"`)
+/// of a serialized preset. A section ends where the next one starts.
+pub fn preset_section(preset: &[u8], section: &str) -> Vec<(String, String)> {
+    let mut fields = vec![];
 
     let mut data = String::from_utf8_lossy(preset);
-    let fields : Vec<&str> = data.split(";").collect();
 
-    let mut start_params = false;
-    for f in fields.iter() {
+    let mut in_section = false;
+    for f in data.split(";") {
         let part = f.trim();
 
-        if start_params {
+        if part.starts_with("!") {
+            in_section = part == section;
+
+        } else if in_section {
             let par : Vec<&str> = part.split("=").collect();
-            preset_data.push((
+            fields.push((
                 (*par.get(0).unwrap_or_else(|| &"?")).to_string(),
-                par.get(1).unwrap_or_else(|| &"0").parse::<f32>().unwrap_or(0.0)
-            ));
-
-        } else {
-            if f == &"!PARAMS" {
-                start_params = true;
-            }
+                (*par.get(1).unwrap_or_else(|| &"0")).to_string()));
         }
     }
 
+    fields
+}
+
+/// Calls `out` with the normalized value of every parameter of the
+/// `!PARAMS` section of `preset`. The parameters, that are missing in
+/// the preset, get their default value. This way a preset, that was
+/// saved before a parameter was added, sounds like it did back then.
+pub fn deserialize_preset<F: FnMut(usize, f32)>(preset: &[u8], mut out: F) {
+    let mut ps = ParamSet::new();
+    ParamModel::init_private_set(&mut ps);
+    let default = |idx: usize| ps.definition(idx).map(|pd| pd.default_p()).unwrap_or(0.0);
+
+    let mut preset_data : Vec<(String, f32)> =
+        preset_section(preset, "!PARAMS")
+            .into_iter()
+            .map(|(name, value)| (name, value.parse::<f32>().unwrap_or(0.0)))
+            .collect();
+
+    migrate_preset(&mut preset_data, &mut out);
+
     macro_rules! param_deserialize {
-        (public $name:ident $e:ident $s:ident $idx:expr, $min:expr, $max:expr, $def:expr, $width:expr, $prec:expr, $lbl:expr) => {
-            for (name, value) in preset_data.iter() {
-                if name == stringify!(pub:$name) {
-                    (out)($idx, *value);
-                }
+        (public $name:ident $e:ident $s:ident $ms:literal $idx:expr, $min:expr, $max:expr, $def:expr, $width:expr, $prec:expr, $lbl:expr) => {
+            match preset_data.iter().rev().find(|(name, _)| name == stringify!(pub:$name)) {
+                Some((_, value)) => (out)($idx, *value),
+                None             => (out)($idx, default($idx)),
             }
         };
-        (private $name:ident $e:ident $s:ident $idx:expr, $min:expr, $max:expr, $def:expr, $width:expr, $prec:expr, $lbl:expr) => {
-            for (name, value) in preset_data.iter() {
-                if name == stringify!(priv:$name) {
-                    (out)($idx, *value);
-                }
+        (private $name:ident $e:ident $s:ident $ms:literal $idx:expr, $min:expr, $max:expr, $def:expr, $width:expr, $prec:expr, $lbl:expr) => {
+            match preset_data.iter().rev().find(|(name, _)| name == stringify!(priv:$name)) {
+                Some((_, value)) => (out)($idx, *value),
+                None             => (out)($idx, default($idx)),
             }
         };
     }
@@ -287,13 +711,13 @@ pub fn serialize_preset(pp: &dyn ParamProvider) -> Vec<u8> {
     out += "!PARAMS;\n";
 
     macro_rules! param_serialize {
-        (public $name:ident $e:ident $s:ident $idx:expr, $min:expr, $max:expr, $def:expr, $width:expr, $prec:expr, $lbl:expr) => {
+        (public $name:ident $e:ident $s:ident $ms:literal $idx:expr, $min:expr, $max:expr, $def:expr, $width:expr, $prec:expr, $lbl:expr) => {
             out += stringify!(pub:$name);
             out += "=";
             out += &pp.param($idx).to_string();
             out += ";\n";
         };
-        (private $name:ident $e:ident $s:ident $idx:expr, $min:expr, $max:expr, $def:expr, $width:expr, $prec:expr, $lbl:expr) => {
+        (private $name:ident $e:ident $s:ident $ms:literal $idx:expr, $min:expr, $max:expr, $def:expr, $width:expr, $prec:expr, $lbl:expr) => {
             out += stringify!(priv:$name);
             out += "=";
             out += &pp.param($idx).to_string();
@@ -313,19 +737,22 @@ impl<'a> ParamModel<'a> {
 
     pub fn is_public(id: usize) -> bool {
         macro_rules! param_add_ps {
-            (private $name:ident $e:ident $s:ident $idx:expr, $min:expr, $max:expr, $def:expr, $width:expr, $prec:expr, $lbl:expr) => {
+            (private $name:ident $e:ident $s:ident $ms:literal $idx:expr, $min:expr, $max:expr, $def:expr, $width:expr, $prec:expr, $lbl:expr) => {
                 if id == $idx { return false; }
             };
             (public $($tt:tt)*) => {
             }
         }
+
+        param_model!{param_add_ps}
+
         true
     }
 
     pub fn init_public_set(ps: &mut ParamSet) {
         macro_rules! param_add_ps {
-            (public $name:ident $e:ident $s:ident $idx:expr, $min:expr, $max:expr, $def:expr, $width:expr, $prec:expr, $lbl:expr) => {
-                ps.add(ParamDefinition::from($idx, $min, $max, $def, $width, $prec, $lbl).$e().$s());
+            (public $name:ident $e:ident $s:ident $ms:literal $idx:expr, $min:expr, $max:expr, $def:expr, $width:expr, $prec:expr, $lbl:expr) => {
+                ps.add(ParamDefinition::from($idx, $min, $max, $def, $width, $prec, $lbl).$e().$s().smooth_ms($ms));
             };
             (private $($tt:tt)*) => {
             }
@@ -336,8 +763,8 @@ impl<'a> ParamModel<'a> {
 
     pub fn init_private_set(ps: &mut ParamSet) {
         macro_rules! param_add_ps_priv {
-            ($_:ident $name:ident $e:ident $s:ident $idx:expr, $min:expr, $max:expr, $def:expr, $width:expr, $prec:expr, $lbl:expr) => {
-                ps.add(ParamDefinition::from($idx, $min, $max, $def, $width, $prec, $lbl).$e().$s());
+            ($_:ident $name:ident $e:ident $s:ident $ms:literal $idx:expr, $min:expr, $max:expr, $def:expr, $width:expr, $prec:expr, $lbl:expr) => {
+                ps.add(ParamDefinition::from($idx, $min, $max, $def, $width, $prec, $lbl).$e().$s().smooth_ms($ms));
             }
         }
 
@@ -460,6 +887,11 @@ impl ParamModelMut {
         }
     }
 
+    #[inline]
+    pub fn get(&self, id: usize) -> f32 {
+        self.v[self.idx][id]
+    }
+
     #[inline]
     pub fn getf(&mut self, id: f32) -> f32 {
         self.v[self.idx][(id + 0.1).floor() as usize]
@@ -472,3 +904,96 @@ impl ParamModelMut {
 }
 
 param_model!{param_impl_accessors}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_param_table_ids() {
+        let infos = param_infos();
+        assert_eq!(infos.len(), PARAM_COUNT);
+
+        for (i, info) in infos.iter().enumerate() {
+            assert_eq!(info.id, i, "gap or duplicate id at {}", info.symbol);
+            assert_eq!(info.public, i < PUB_PARAM_COUNT,
+                       "public parameters must come first: {}", info.symbol);
+            assert_eq!(ParamModel::is_public(i), info.public, "{}", info.symbol);
+            assert_eq!(param_symbols()[i], info.symbol);
+        }
+
+        assert_eq!(infos.iter().filter(|i| i.public).count(), PUB_PARAM_COUNT);
+    }
+
+    #[test]
+    fn check_param_table_labels_and_help() {
+        let infos = param_infos();
+
+        for info in infos.iter() {
+            assert!(!info.label.is_empty(), "{} has no label", info.symbol);
+            assert!(!info.title.is_empty(), "{} has no help title", info.symbol);
+            assert!(info.min <= info.max, "{} has an empty range", info.symbol);
+
+            for other in infos.iter().filter(|o| o.id != info.id) {
+                assert_ne!(info.label, other.label,
+                           "{} and {} share a label", info.symbol, other.symbol);
+                assert_ne!(info.title, other.title,
+                           "{} and {} share a help title", info.symbol, other.symbol);
+            }
+        }
+
+        assert_eq!(help_text(pid::e1_release).0, "Env 1 Release");
+        assert_eq!(help_text(PARAM_COUNT), ("", ""));
+    }
+
+    #[test]
+    fn check_preset_migrates_note_toggles() {
+        let mut ps = ParamSet::new();
+        ParamModel::init_private_set(&mut ps);
+
+        let mut values = vec![0.0; PARAM_COUNT];
+        deserialize_preset(
+            b"!PARAMS;pub : freq_start=0.5;pub : freq_end=0.4;\
+              pub : freq_note_start=1;pub:freq_note_end=0;",
+            |idx, v| values[idx] = v);
+
+        assert_eq!(values[pid::freq_note_start], 1.0);
+        assert_eq!(values[pid::freq_note_end],   0.0);
+        assert_eq!(values[pid::freq_end],        0.4);
+
+        let key_root = ps.definition(pid::key_root).unwrap().map(values[pid::key_root]);
+        assert_eq!(key_root.round(), 36.0);
+        let freq = ps.definition(pid::freq_start).unwrap().map(values[pid::freq_start]);
+        assert!((freq - crate::helpers::note_to_freq(36.0)).abs() < 0.01, "{}", freq);
+
+        // Presets with a root note already have tracking amounts:
+        let mut values = vec![0.0; PARAM_COUNT];
+        deserialize_preset(
+            b"!PARAMS;pub : freq_start=0.5;pub : freq_note_start=0.5;\
+              priv : key_root=0.4;",
+            |idx, v| values[idx] = v);
+
+        assert_eq!(values[pid::freq_note_start], 0.5);
+        assert_eq!(values[pid::freq_start],      0.5);
+        assert_eq!(values[pid::key_root],        0.4);
+    }
+
+    #[test]
+    fn check_param_table_matches_param_set() {
+        let mut ps        = ParamSet::new();
+        let mut public_ps = ParamSet::new();
+        ParamModel::init_private_set(&mut ps);
+        ParamModel::init_public_set(&mut public_ps);
+
+        assert_eq!(ps.param_count(),        PARAM_COUNT);
+        assert_eq!(public_ps.param_count(), PUB_PARAM_COUNT);
+
+        for info in param_infos() {
+            let pd = ps.definition(info.id).unwrap();
+            assert_eq!(pd.idx(),  info.id);
+            assert_eq!(pd.name(), info.label);
+            assert_eq!(pd.min(),  info.min);
+            assert_eq!(pd.max(),  info.max);
+        }
+    }
+}
@@ -24,12 +24,27 @@ pub enum ParamRMode {
     Exp4,
 }
 
+/// The curve a parameter follows when it changes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ParamSmooth {
+    /// The new value is used immediately.
+    None,
+    /// Linear ramp, that reaches the new value after the smoothing time.
+    Lin,
+    /// One-pole lowpass, that gets within 1% of the new value
+    /// after the smoothing time.
+    OnePole,
+}
+
+/// Smoothing time for parameters, that don't define their own.
+pub const DEFAULT_SMOOTH_MS : f32 = 10.0;
+
 #[derive(Debug, Clone, Copy)]
-pub struct ParamDefinition(usize, f32, f32, f32, &'static str, ParamRMode, bool, usize, usize);
+pub struct ParamDefinition(usize, f32, f32, f32, &'static str, ParamRMode, ParamSmooth, usize, usize, f32);
 
 impl ParamDefinition {
     pub fn new() -> Self {
-        Self(0, 0.0, 0.0, 0.0, "", ParamRMode::Lin, false, 5, 2)
+        Self(0, 0.0, 0.0, 0.0, "", ParamRMode::Lin, ParamSmooth::None, 5, 2, 0.0)
     }
 
     pub fn to_ui_value_spec(&self) -> UIValueSpec {
@@ -50,7 +65,7 @@ impl ParamDefinition {
     }
 
     pub fn from(idx: usize, min: f32, max: f32, def: f32, width: usize, prec: usize, desc: &'static str) -> Self {
-        Self(idx, min, max, def, desc, ParamRMode::Lin, true, width, prec)
+        Self(idx, min, max, def, desc, ParamRMode::Lin, ParamSmooth::Lin, width, prec, DEFAULT_SMOOTH_MS)
     }
 
     pub fn lin(mut self) -> Self {
@@ -69,12 +84,23 @@ impl ParamDefinition {
     }
 
     pub fn smooth(mut self) -> Self {
-        self.6 = true;
+        self.6 = ParamSmooth::Lin;
+        self
+    }
+
+    pub fn smooth_1p(mut self) -> Self {
+        self.6 = ParamSmooth::OnePole;
         self
     }
 
     pub fn no_smooth(mut self) -> Self {
-        self.6 = false;
+        self.6 = ParamSmooth::None;
+        self
+    }
+
+    /// Sets the smoothing time in milliseconds.
+    pub fn smooth_ms(mut self, ms: f32) -> Self {
+        self.9 = ms;
         self
     }
 
@@ -93,7 +119,11 @@ impl ParamDefinition {
         }
     }
 
-    pub fn is_smooth(&self) -> bool { self.6 }
+    pub fn is_smooth(&self) -> bool {
+        self.6 != ParamSmooth::None && self.9 > 0.0
+    }
+
+    pub fn smoothing(&self) -> (ParamSmooth, f32) { (self.6, self.9) }
 
     pub fn map(&self, p: f32) -> f32 {
         match self.5 {
@@ -102,6 +132,16 @@ impl ParamDefinition {
             ParamRMode::Exp4 => crate::helpers::p2range_exp4(p, self.1, self.2),
         }
     }
+
+    /// The inverse of `map()`, returns the normalized value for `v`.
+    pub fn unmap(&self, v: f32) -> f32 {
+        let v = v.max(self.1.min(self.2)).min(self.1.max(self.2));
+        match self.5 {
+            ParamRMode::Lin  => crate::helpers::range2p(v, self.1, self.2),
+            ParamRMode::Exp  => crate::helpers::range2p_exp(v, self.1, self.2),
+            ParamRMode::Exp4 => crate::helpers::range2p_exp4(v, self.1, self.2),
+        }
+    }
 }
 
 pub struct ParamSet {
@@ -145,6 +185,16 @@ impl ParamSet {
         false
     }
 
+    pub fn smoothing(&self, idx: usize) -> (ParamSmooth, f32) {
+        if let Some(pd) = self.definition(idx) {
+            if pd.is_smooth() {
+                return pd.smoothing();
+            }
+        }
+
+        (ParamSmooth::None, 0.0)
+    }
+
     pub fn get(&self, idx: usize, pp: &dyn ParamProvider) -> f32 {
         if let Some(pd) = self.definition(idx) {
             pd.map(pp.param(pd.0))
@@ -171,11 +221,13 @@ pub trait MonoVoice : MonoProcessor {
 
 pub struct SmoothParameters {
     current:        Vec<f32>,
-    last:           Vec<f32>,
+    values:         Vec<f32>,
+    targets:        Vec<f32>,
+    incs:           Vec<f32>,
+    steps_left:     Vec<usize>,
     framesize:      usize,
     param_count:    usize,
-pub last_frame_cnt: usize,
-pub last_frame_idx: usize,
+    srate:          f32,
     uninitialized:  bool,
 }
 
@@ -186,121 +238,204 @@ impl SmoothParameters {
     /// The only allocation will happend in new() here.
     pub fn new(framesize: usize, param_count: usize) -> Self {
         let mut v1 = Vec::with_capacity(framesize * param_count);
-        let mut v2 = Vec::with_capacity(framesize * param_count);
         v1.resize(framesize * param_count, 0.0);
-        v2.resize(framesize * param_count, 0.0);
 
         Self {
             current:       v1,
-            last:          v2,
-            last_frame_cnt: 0,
-            last_frame_idx: 0,
+            values:        vec![0.0; param_count],
+            targets:       vec![0.0; param_count],
+            incs:          vec![0.0; param_count],
+            steps_left:    vec![0; param_count],
             uninitialized: true,
+            srate:         44100.0,
             framesize,
             param_count,
         }
     }
 
-    /// Initialize the current frame values, in case we never ran a single frame.
-    fn init_params(&mut self, frames: usize, ps: &ParamSet, pp: &dyn ParamProvider) {
-        let v           = &mut self.current;
-        let framesize   = self.framesize;
-        let param_count = self.param_count;
+    pub fn set_sample_rate(&mut self, srate: f32) {
+        self.srate = srate;
+    }
 
+    /// Initialize the current values, in case we never ran a single frame.
+    fn init_params(&mut self, ps: &ParamSet, pp: &dyn ParamProvider) {
         for pi in 0..self.param_count {
             let pv = ps.get(pi, pp);
 
-            for i in 0..framesize {
-                v[i * param_count + pi] = pv;
-            }
+            self.values[pi]     = pv;
+            self.targets[pi]    = pv;
+            self.steps_left[pi] = 0;
         }
 
-        self.last_frame_cnt = frames;
-        self.last_frame_idx = (frames - 1) * param_count;
-        self.uninitialized  = false;
+        self.uninitialized = false;
     }
 
-    /// Advance the parameter interpolation.
+    /// Advance the parameter interpolation by the given number of frames.
     ///
-    /// - frames contains the number of frames to interpolate from the last
-    ///   call to either init_params() or advance_params()
-    /// - total_nframes is the number of frames the given set of parameters
-    ///   provide the value for. After total_nframes the values of the
-    ///   parameters need to be reached.
+    /// The parameters move towards the values provided by `pp` with
+    /// the smoothing curve and time their definition in `ps` declares.
+    /// How the frames are split up into calls does not
+    /// change the outcome.
     pub fn advance_params(&mut self,
                           frames: usize,
-                          total_nframes: usize,
                           ps: &ParamSet,
                           pp: &dyn ParamProvider) {
 
         if self.uninitialized {
-            self.init_params(frames, ps, pp);
-            return;
+            self.init_params(ps, pp);
         }
 
-        self.swap();
+        let v           = &mut self.current;
+        let param_count = self.param_count;
 
-        let v                = &mut self.current;
-        let param_count      = self.param_count;
-        let last_frame_cnt   = self.last_frame_cnt;
-        let last_frame_idx   = self.last_frame_idx;
+        for pi in 0..param_count {
+            let target    = ps.get(pi, pp);
+            let (curve, ms) = ps.smoothing(pi);
+            let nsteps    = (ms * self.srate / 1000.0).round() as usize;
 
-        let last_v = &self.last[last_frame_idx..(last_frame_idx + param_count)];
+            let mut value = self.values[pi];
 
-        for pi in 0..param_count {
-            let end_param_val = ps.get(pi, pp) as f32;
-            let last_val      = last_v[pi] as f32;
-//            println!("LASTVAL[pi={}]: {:?} => target={} | f={}, tot={}",
-//                     pi, last_val, end_param_val, frames, total_nframes);
-
-            if (end_param_val - last_val).abs() > std::f32::EPSILON
-                && ps.is_smooth(pi) {
-
-                let inc_val =
-                    (end_param_val - last_val)
-                    / (total_nframes - last_frame_cnt) as f32;
-
-                for i in 0..frames {
-                    v[i * param_count + pi] =
-                        last_val + (inc_val as f32 * (i + 1) as f32);
-//                    println!(
-//                        "[pi={}] i={}, lfc={}, iv={} => {}",
-//                        pi, i, last_frame_cnt, inc_val,
-//                        v[i * param_count + pi]);
-
-//                    println!("[{} @ {},{}] x= {} | {} => {} :::=> {}",
-//                             last_frame_cnt + i, last_frame_cnt,
-//                             frames, x, last_val, end_param_val,
-//                             v[i * param_count + pi]);
-                }
-            } else {
-                for i in 0..frames {
-                    v[i * param_count + pi] = end_param_val;
-                }
+            match curve {
+                ParamSmooth::Lin if nsteps > 0 => {
+                    if (target - self.targets[pi]).abs() > std::f32::EPSILON {
+                        self.steps_left[pi] = nsteps;
+                        self.incs[pi]       = (target - value) / nsteps as f32;
+                    }
+
+                    let inc       = self.incs[pi];
+                    let mut left  = self.steps_left[pi];
+
+                    for i in 0..frames {
+                        if left > 0 {
+                            left -= 1;
+                            value = if left == 0 { target } else { value + inc };
+                        }
+
+                        v[i * param_count + pi] = value;
+                    }
+
+                    self.steps_left[pi] = left;
+                },
+                ParamSmooth::OnePole if nsteps > 0 => {
+                    let coef = (0.01_f32.ln() / nsteps as f32).exp();
+
+                    for i in 0..frames {
+                        value = target + (value - target) * coef;
+                        if (value - target).abs() < 0.000_001 {
+                            value = target;
+                        }
+
+                        v[i * param_count + pi] = value;
+                    }
+                },
+                _ => {
+                    value = target;
+                    self.steps_left[pi] = 0;
+
+                    for i in 0..frames {
+                        v[i * param_count + pi] = value;
+                    }
+                },
             }
-        }
 
-        self.last_frame_cnt = self.last_frame_cnt + frames;
-        if self.last_frame_cnt >= total_nframes {
-            self.last_frame_cnt = 0;
+            self.values[pi]  = value;
+            self.targets[pi] = target;
         }
-        self.last_frame_idx = (frames - 1) * param_count;
     }
 
-    pub fn get_last_frame(&self) -> &[f32] {
-        let frame_idx = self.last_frame_idx;
-        &self.last[frame_idx..(frame_idx + self.param_count)]
-    }
-
-    /// Returns a reference to the parameters of the recently
-    /// init_params() or advance_params() frames.
+    /// Returns a reference to the parameters of the frames
+    /// of the recent advance_params() call.
     pub fn get_frame(&self, idx: usize) -> &[f32] {
         let frame_idx = idx * self.param_count;
         &self.current[frame_idx..(frame_idx + self.param_count)]
     }
+}
+
+/// A parameter change at the frame `offs` of the next block.
+///
+/// Only the host events have a position inside of the block. The
+/// changes from the GUI and the host changes without a position, like
+/// VST `set_parameter`, are applied at the start of the next block,
+/// so their timing is only as exact as the block size.
+#[derive(Debug, Clone, Copy)]
+pub struct ParamChange {
+    pub idx:    usize,
+    pub value:  f32,
+    pub offs:   usize,
+}
 
-    fn swap(&mut self) {
-        std::mem::swap(&mut self.last, &mut self.current);
+#[derive(Debug, Clone, Copy)]
+struct QueuedParamChange {
+    offs:   usize,
+    seq:    usize,
+    change: ParamChange,
+}
+
+/// Holds the parameter changes for one block of audio and splits
+/// the block into segments at the offsets of the changes.
+///
+/// The only allocation happens in new().
+pub struct ParamChangeQueue {
+    changes:    Vec<QueuedParamChange>,
+    next:       usize,
+}
+
+impl ParamChangeQueue {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            changes: Vec::with_capacity(capacity),
+            next:    0,
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.changes.clear();
+        self.next = 0;
+    }
+
+    /// Adds a change, changes that don't fit anymore are dropped.
+    /// Returns false if the change was dropped.
+    pub fn push(&mut self, change: ParamChange) -> bool {
+        if self.changes.len() < self.changes.capacity() {
+            self.changes.push(QueuedParamChange { offs: 0, seq: 0, change });
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Sorts the changes by their offset. Changes that lie
+    /// after the block are moved to its end.
+    pub fn prepare(&mut self, nframes: usize) {
+        let last_offs = nframes.max(1) - 1;
+
+        for (i, qc) in self.changes.iter_mut().enumerate() {
+            qc.offs = qc.change.offs.min(last_offs);
+            qc.seq = i;
+        }
+
+        // The sequence number keeps the order of changes with
+        // the same offset, without needing the allocating stable sort.
+        self.changes.sort_unstable_by_key(|qc| (qc.offs, qc.seq));
+        self.next = 0;
+    }
+
+    /// Writes the changes at `offs` to `targets` and returns the
+    /// end of the segment starting there, which is the offset of
+    /// the next change.
+    pub fn next_segment(&mut self, offs: usize, nframes: usize, targets: &mut [f32]) -> usize {
+        while let Some(qc) = self.changes.get(self.next) {
+            if qc.offs > offs {
+                return qc.offs;
+            }
+
+            if let Some(t) = targets.get_mut(qc.change.idx) {
+                *t = qc.change.value;
+            }
+            self.next += 1;
+        }
+
+        nframes
     }
 }
 
@@ -341,7 +476,8 @@ impl<T: MonoVoice> VoiceManager<T> {
         let cmd  = (data[0] & 0xF0) >> 4;
         let chan = data[0] & 0x0F;
 
-        println!("MIDI {} {}: {} DT: {}", chan, my_channel, cmd, delta_frames);
+        klog!(Midi, Debug, "chan={} my_chan={} cmd={:x} note={} dt={}",
+              chan, my_channel, cmd, data.get(1).copied().unwrap_or(0), delta_frames);
 
         if my_channel != chan {
             return;
@@ -422,14 +558,16 @@ mod tests {
     #[test]
     fn check_init() {
         let mut smooth = SmoothParameters::new(64, 4);
+        // 1 ms is one frame at 1kHz:
+        smooth.set_sample_rate(1000.0);
 
         let mut ps = ParamSet::new();
-        ps.add(ParamDefinition::from(1,  5.0, 3000.0, 150.0, 3, 1, "Start Freq.").exp().smooth());
+        ps.add(ParamDefinition::from(1,  5.0, 3000.0, 150.0, 3, 1, "Start Freq.").exp().smooth().smooth_ms(10.0));
         ps.add(ParamDefinition::from(2,  5.0, 2000.0,  40.0, 3, 1, "End Freq.").exp().no_smooth());
-        ps.add(ParamDefinition::from(3,  5.0, 5000.0, 440.0, 3, 1, "Length").exp().smooth());
+        ps.add(ParamDefinition::from(3,  5.0, 5000.0, 440.0, 3, 1, "Length").exp().smooth_1p().smooth_ms(10.0));
 
         let new_params = vec![0.0, 0.3, 0.4, 0.5];
-        smooth.advance_params(2, 66, &ps, &new_params);
+        smooth.advance_params(2, &ps, &new_params);
 
         assert_eq!(
             &fmt_vec(&smooth.get_frame(0)),
@@ -439,97 +577,101 @@ mod tests {
             "[0.00, 274.55, 324.20, 1253.75]");
 
         let new_params = vec![0.0, 1.0, 1.0, 1.0];
-        smooth.advance_params(64, 66, &ps, &new_params);
+        smooth.advance_params(64, &ps, &new_params);
 
         assert_eq!(
             &fmt_vec(&smooth.get_frame(0)),
-            "[0.00, 317.14, 2000.00, 1312.29]");
-//            "[0.00, 358.41, 2000.00, 1369.02]");
+            "[0.00, 547.09, 2000.00, 2636.28]");
+        assert_eq!(
+            &fmt_vec(&smooth.get_frame(4)),
+            "[0.00, 1637.27, 2000.00, 4625.38]");
+        assert_eq!(
+            &fmt_vec(&smooth.get_frame(9)),
+            "[0.00, 3000.00, 2000.00, 4962.54]");
         assert_eq!(
             &fmt_vec(&smooth.get_frame(63)),
             "[0.00, 3000.00, 2000.00, 5000.00]");
 
-
+        // the ramps don't depend on how the frames are split up:
         let new_params = vec![0.0, 0.0, 0.0, 0.0];
-        smooth.advance_params(64, 64, &ps, &new_params);
+        smooth.advance_params(5, &ps, &new_params);
 
         assert_eq!(
-            &fmt_vec(&smooth.get_frame(0)),
-            "[0.00, 2953.20, 5.00, 4921.95]");
-        assert_eq!(
-            &fmt_vec(&smooth.get_frame(63)),
-            "[0.00, 5.00, 5.00, 5.00]");
+            &fmt_vec(&smooth.get_frame(4)),
+            "[0.00, 1502.50, 5.00, 504.50]");
 
-        // new run...
-        let new_params = vec![1.0, 1.0, 1.0, 1.0];
-        smooth.advance_params(64, 256, &ps, &new_params);
+        smooth.advance_params(5, &ps, &new_params);
 
         assert_eq!(
-            &fmt_vec(&smooth.get_frame(0)),
-            "[0.00, 16.70, 2000.00, 24.51]");
+            &fmt_vec(&smooth.get_frame(4)),
+            "[0.00, 5.00, 5.00, 54.95]");
+
+        smooth.advance_params(64, &ps, &new_params);
+
         assert_eq!(
             &fmt_vec(&smooth.get_frame(63)),
-            "[0.00, 753.75, 2000.00, 1253.75]");
+            "[0.00, 5.00, 5.00, 5.00]");
 
-        smooth.advance_params(64, 256, &ps, &new_params);
+        // a change in the middle of a ramp starts from the current value:
+        let new_params = vec![0.0, 1.0, 1.0, 1.0];
+        smooth.advance_params(5, &ps, &new_params);
 
         assert_eq!(
-            &fmt_vec(&smooth.get_last_frame()),
-            "[0.00, 753.75, 2000.00, 1253.75]");
-        assert_eq!(
-            &fmt_vec(&smooth.get_frame(0)),
-            "[0.00, 765.45, 2000.00, 1273.26]");
-        assert_eq!(
-            &fmt_vec(&smooth.get_frame(63)),
-            "[0.00, 1502.50, 2000.00, 2502.50]");
+            &fmt_vec(&smooth.get_frame(4)),
+            "[0.00, 1502.50, 2000.00, 4500.50]");
 
-        smooth.advance_params(64, 256, &ps, &new_params);
+        let new_params = vec![0.0, 0.5, 0.5, 0.5];
+        smooth.advance_params(10, &ps, &new_params);
 
         assert_eq!(
             &fmt_vec(&smooth.get_frame(0)),
-            "[0.00, 1514.20, 2000.00, 2522.01]");
+            "[0.00, 1427.62, 503.75, 3302.31]");
         assert_eq!(
-            &fmt_vec(&smooth.get_frame(63)),
-            "[0.00, 2251.25, 2000.00, 3751.25]");
-
-        smooth.advance_params(64, 256, &ps, &new_params);
+            &fmt_vec(&smooth.get_frame(9)),
+            "[0.00, 753.75, 503.75, 1286.22]");
+
+        // sample accurate changes, the ramps start
+        // at the offsets of the changes:
+        let mut queue = ParamChangeQueue::new(16);
+        let mut targets = vec![0.0, 0.5, 0.5, 0.5];
+        queue.push(ParamChange { idx: 1, value: 1.0, offs: 10 });
+        queue.push(ParamChange { idx: 3, value: 0.0, offs: 0 });
+        queue.push(ParamChange { idx: 1, value: 0.0, offs: 40 });
+        queue.push(ParamChange { idx: 2, value: 0.0, offs: 1000 });
+        queue.prepare(64);
+
+        assert_eq!(queue.next_segment(0, 64, &mut targets), 10);
+        smooth.advance_params(10, &ps, &targets);
 
         assert_eq!(
             &fmt_vec(&smooth.get_frame(0)),
-            "[0.00, 2262.95, 2000.00, 3770.76]");
+            "[0.00, 753.75, 503.75, 813.39]");
         assert_eq!(
-            &fmt_vec(&smooth.get_frame(63)),
-            "[0.00, 3000.00, 2000.00, 5000.00]");
+            &fmt_vec(&smooth.get_frame(9)),
+            "[0.00, 753.75, 503.75, 17.81]");
 
-        // run with return to original
-        let new_params = vec![0.5, 0.5, 0.5, 0.5];
-        smooth.advance_params(64, 256, &ps, &new_params);
+        assert_eq!(queue.next_segment(10, 64, &mut targets), 40);
+        smooth.advance_params(30, &ps, &targets);
 
         assert_eq!(
             &fmt_vec(&smooth.get_frame(0)),
-            "[0.00, 2991.23, 503.75, 4985.37]");
+            "[0.00, 978.38, 503.75, 13.08]");
         assert_eq!(
-            &fmt_vec(&smooth.get_frame(63)),
-            "[0.00, 2438.44, 503.75, 4063.44]");
+            &fmt_vec(&smooth.get_frame(9)),
+            "[0.00, 3000.00, 503.75, 5.13]");
 
-        let new_params = vec![1.0, 1.0, 1.0, 1.0];
-        smooth.advance_params(64, 256, &ps, &new_params);
+        assert_eq!(queue.next_segment(40, 64, &mut targets), 63);
+        smooth.advance_params(23, &ps, &targets);
 
         assert_eq!(
-            &fmt_vec(&smooth.get_frame(0)),
-            "[0.00, 2441.36, 2000.00, 4068.32]");
-        assert_eq!(
-            &fmt_vec(&smooth.get_frame(63)),
-            "[0.00, 2625.62, 2000.00, 4375.62]");
+            &fmt_vec(&smooth.get_frame(9)),
+            "[0.00, 5.00, 503.75, 5.00]");
 
-        smooth.advance_params(64, 256, &ps, &new_params);
-        smooth.advance_params(64, 256, &ps, &new_params);
+        assert_eq!(queue.next_segment(63, 64, &mut targets), 64);
+        smooth.advance_params(1, &ps, &targets);
 
         assert_eq!(
             &fmt_vec(&smooth.get_frame(0)),
-            "[0.00, 2815.74, 2000.00, 4692.69]");
-        assert_eq!(
-            &fmt_vec(&smooth.get_frame(63)),
-            "[0.00, 3000.00, 2000.00, 5000.00]");
+            "[0.00, 5.00, 5.00, 5.00]");
     }
 }
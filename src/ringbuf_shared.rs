@@ -23,9 +23,13 @@ impl<T> RingBuf<T> {
         }
     }
 
-    pub fn push(&self, item: T) {
+    /// Returns false if the item was dropped, because the
+    /// buffer is full or another thread is pushing right now.
+    pub fn push(&self, item: T) -> bool {
         if let Ok(ref mut prod) = self.prod.try_lock() {
-            prod.push(item).is_ok();
+            prod.push(item).is_ok()
+        } else {
+            false
         }
     }
 
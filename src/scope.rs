@@ -0,0 +1,214 @@
+// Copyright (c) 2020-2021 Weird Constructor <weirdconstructor@gmail.com>
+// This is a part of Kickmess. See README.md and COPYING for details.
+
+//! The oscilloscope of the rendered kick.
+//!
+//! The `ScopeRecorder` runs on the audio thread. It starts recording
+//! with each note on and decimates the output of the whole hit to
+//! `SCOPE_POINTS` min/max pairs. When the hit is done, or the next note
+//! starts, they are published in the `ScopeBuffer` of the `EngineParams`,
+//! where the editor reads them. The buffer is a sequence lock over atomic
+//! values, the audio thread never waits for the GUI.
+
+use std::sync::atomic::{AtomicU32, AtomicU64, AtomicUsize, Ordering, fence};
+
+/// The number of min/max pairs of a recorded hit.
+pub const SCOPE_POINTS : usize = 256;
+
+/// The shortest and longest recorded hit in milliseconds.
+const SCOPE_MIN_MS : f32 = 20.0;
+const SCOPE_MAX_MS : f32 = 5000.0;
+
+/// Holds the last recorded hit, shared by the audio thread and the GUI.
+pub struct ScopeBuffer {
+    /// Odd while the audio thread writes, incremented by two
+    /// for each published hit.
+    seq:    AtomicU64,
+    len:    AtomicUsize,
+    points: Vec<(AtomicU32, AtomicU32)>,
+}
+
+impl ScopeBuffer {
+    pub fn new() -> Self {
+        Self {
+            seq:    AtomicU64::new(0),
+            len:    AtomicUsize::new(0),
+            points:
+                (0..SCOPE_POINTS)
+                .map(|_| (AtomicU32::new(0), AtomicU32::new(0)))
+                .collect(),
+        }
+    }
+
+    /// Called by the audio thread, does not block or allocate.
+    fn publish(&self, points: &[(f32, f32)]) {
+        let seq = self.seq.load(Ordering::Relaxed);
+        self.seq.store(seq + 1, Ordering::Relaxed);
+        fence(Ordering::Release);
+
+        for (p, (min, max)) in self.points.iter().zip(points.iter()) {
+            p.0.store(min.to_bits(), Ordering::Relaxed);
+            p.1.store(max.to_bits(), Ordering::Relaxed);
+        }
+        self.len.store(points.len().min(SCOPE_POINTS), Ordering::Relaxed);
+
+        self.seq.store(seq + 2, Ordering::Release);
+    }
+
+    /// The number of hits published so far, 0 if there was none yet.
+    pub fn generation(&self) -> u64 {
+        self.seq.load(Ordering::Acquire) / 2
+    }
+
+    /// Copies the min/max pairs of the last hit into `out` and returns
+    /// its generation. Returns `None` if the audio thread was publishing
+    /// a new hit at the same time, then it is read in the next frame.
+    pub fn read(&self, out: &mut Vec<(f32, f32)>) -> Option<u64> {
+        let seq = self.seq.load(Ordering::Acquire);
+        if seq % 2 == 1 {
+            return None;
+        }
+
+        out.clear();
+        let len = self.len.load(Ordering::Relaxed);
+        for p in self.points.iter().take(len) {
+            out.push((
+                f32::from_bits(p.0.load(Ordering::Relaxed)),
+                f32::from_bits(p.1.load(Ordering::Relaxed))));
+        }
+
+        fence(Ordering::Acquire);
+        if self.seq.load(Ordering::Relaxed) != seq {
+            return None;
+        }
+
+        Some(seq / 2)
+    }
+}
+
+impl Default for ScopeBuffer {
+    fn default() -> Self { Self::new() }
+}
+
+/// Records the hits on the audio thread.
+pub struct ScopeRecorder {
+    srate:            f32,
+    points:           Vec<(f32, f32)>,
+    cur:              (f32, f32),
+    frames:           usize,
+    frames_per_point: usize,
+    recording:        bool,
+}
+
+impl ScopeRecorder {
+    pub fn new() -> Self {
+        Self {
+            srate:            44100.0,
+            points:           Vec::with_capacity(SCOPE_POINTS),
+            cur:              (0.0, 0.0),
+            frames:           0,
+            frames_per_point: 1,
+            recording:        false,
+        }
+    }
+
+    pub fn set_sample_rate(&mut self, srate: f32) {
+        self.srate = srate;
+    }
+
+    /// Starts recording a hit of `len_ms`. The hit that is still
+    /// recorded is published as far as it got.
+    pub fn start(&mut self, len_ms: f32, scope: &ScopeBuffer) {
+        if self.recording && !self.points.is_empty() {
+            scope.publish(&self.points[..]);
+        }
+
+        let len_ms = len_ms.clamp(SCOPE_MIN_MS, SCOPE_MAX_MS);
+        let frames = self.srate * len_ms / 1000.0;
+
+        self.frames_per_point = ((frames / SCOPE_POINTS as f32).ceil() as usize).max(1);
+        self.points.clear();
+        self.frames    = 0;
+        self.recording = true;
+    }
+
+    /// Records the rendered `samples` of the current hit.
+    pub fn feed(&mut self, samples: &[f32], scope: &ScopeBuffer) {
+        if !self.recording {
+            return;
+        }
+
+        for s in samples.iter() {
+            if self.frames == 0 {
+                self.cur = (*s, *s);
+            } else {
+                self.cur = (self.cur.0.min(*s), self.cur.1.max(*s));
+            }
+            self.frames += 1;
+
+            if self.frames >= self.frames_per_point {
+                self.points.push(self.cur);
+                self.frames = 0;
+
+                if self.points.len() >= SCOPE_POINTS {
+                    scope.publish(&self.points[..]);
+                    self.recording = false;
+                    return;
+                }
+            }
+        }
+    }
+}
+
+impl Default for ScopeRecorder {
+    fn default() -> Self { Self::new() }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_scope_decimates_hit() {
+        let scope   = ScopeBuffer::new();
+        let mut rec = ScopeRecorder::new();
+        rec.set_sample_rate(1000.0);
+
+        let mut out = vec![];
+        assert_eq!(scope.generation(), 0);
+        assert_eq!(scope.read(&mut out), Some(0));
+        assert!(out.is_empty());
+
+        // Nothing is recorded without a note:
+        rec.feed(&[1.0; 100], &scope);
+        assert_eq!(scope.generation(), 0);
+
+        // 512 frames, 2 frames per point:
+        rec.start(512.0, &scope);
+        let samples : Vec<f32> =
+            (0..600).map(|i| if i % 2 == 0 { 0.5 } else { -0.25 }).collect();
+        rec.feed(&samples[..100], &scope);
+        assert_eq!(scope.generation(), 0);
+        rec.feed(&samples[100..], &scope);
+
+        assert_eq!(scope.read(&mut out), Some(1));
+        assert_eq!(out.len(), SCOPE_POINTS);
+        assert!(out.iter().all(|p| *p == (-0.25, 0.5)));
+    }
+
+    #[test]
+    fn check_scope_publishes_interrupted_hit() {
+        let scope   = ScopeBuffer::new();
+        let mut rec = ScopeRecorder::new();
+        rec.set_sample_rate(1000.0);
+
+        rec.start(2560.0, &scope);
+        rec.feed(&[0.1; 100], &scope);
+        rec.start(2560.0, &scope);
+        rec.feed(&[0.2; 100], &scope);
+
+        let mut out = vec![];
+        assert_eq!(scope.read(&mut out), Some(1));
+        assert_eq!(out, vec![(0.1, 0.1); 10]);
+    }
+}
@@ -0,0 +1,372 @@
+// Copyright (c) 2020-2021 Weird Constructor <weirdconstructor@gmail.com>
+// This is a part of Kickmess. See README.md and COPYING for details.
+
+//! The spectrum analyzer of the editor.
+//!
+//! The audio thread writes its output into the `SpectrumTap` of the
+//! `EngineParams`. The `SpectrumAnalyzer` runs in the GUI thread, it
+//! reads the last `SPECTRUM_FFT_LEN` samples from the tap, and computes
+//! the levels of logarithmic spaced bands, their peak hold and the
+//! fundamental frequency of the kick.
+
+use crate::helpers::freq_to_note;
+use rustfft::{Fft, FftPlanner};
+use rustfft::num_complex::Complex;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+
+/// About 190ms at 44.1kHz, long enough to resolve the low
+/// fundamentals of a kick.
+pub const SPECTRUM_FFT_LEN : usize = 8192;
+/// The number of bands shown by the analyzer.
+pub const SPECTRUM_BANDS   : usize = 96;
+
+pub const SPECTRUM_MIN_FREQ : f32 = 20.0;
+pub const SPECTRUM_MAX_FREQ : f32 = 20000.0;
+/// The level shown at the bottom of the analyzer.
+pub const SPECTRUM_MIN_DB   : f32 = -90.0;
+
+/// How fast the peak hold falls, in dB per analyzed frame.
+const PEAK_FALL_DB : f32 = 0.25;
+
+/// Fundamentals are searched in this range.
+const FUNDAMENTAL_MIN_FREQ : f32 = 20.0;
+const FUNDAMENTAL_MAX_FREQ : f32 = 2000.0;
+/// Quieter fundamentals are not detected.
+const FUNDAMENTAL_MIN_DB   : f32 = -60.0;
+
+const NOTE_NAMES : [&str; 12] =
+    ["C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B"];
+
+/// The note name for the frequency, with the deviation
+/// in cents, for example `"E1 +12c"`.
+pub fn note_name(freq: f32) -> String {
+    let note  = freq_to_note(freq);
+    let round = note.round();
+    let cents = ((note - round) * 100.0).round() as i32;
+    let round = round as i32;
+
+    format!("{}{} {:+}c",
+        NOTE_NAMES[round.rem_euclid(12) as usize],
+        round.div_euclid(12) - 1,
+        cents)
+}
+
+/// Maps a frequency on the logarithmic X axis of the analyzer (0.0 to 1.0).
+pub fn freq_to_x(freq: f32) -> f32 {
+    ((freq / SPECTRUM_MIN_FREQ).ln()
+     / (SPECTRUM_MAX_FREQ / SPECTRUM_MIN_FREQ).ln())
+    .clamp(0.0, 1.0)
+}
+
+fn x_to_freq(x: f32) -> f32 {
+    SPECTRUM_MIN_FREQ * (SPECTRUM_MAX_FREQ / SPECTRUM_MIN_FREQ).powf(x)
+}
+
+/// The last output samples, shared by the audio thread and the GUI.
+pub struct SpectrumTap {
+    samples: Vec<AtomicU32>,
+    /// The number of samples written so far.
+    written: AtomicUsize,
+    srate:   AtomicU32,
+}
+
+impl SpectrumTap {
+    pub fn new() -> Self {
+        Self {
+            samples: (0..SPECTRUM_FFT_LEN).map(|_| AtomicU32::new(0)).collect(),
+            written: AtomicUsize::new(0),
+            srate:   AtomicU32::new(44100.0_f32.to_bits()),
+        }
+    }
+
+    pub fn set_sample_rate(&self, srate: f32) {
+        self.srate.store(srate.to_bits(), Ordering::Relaxed);
+    }
+
+    pub fn sample_rate(&self) -> f32 {
+        f32::from_bits(self.srate.load(Ordering::Relaxed))
+    }
+
+    /// Called by the audio thread, does not block or allocate.
+    pub fn write(&self, out: &[f32]) {
+        let mut pos = self.written.load(Ordering::Relaxed);
+
+        for s in out.iter() {
+            self.samples[pos % SPECTRUM_FFT_LEN].store(s.to_bits(), Ordering::Relaxed);
+            pos = pos.wrapping_add(1);
+        }
+
+        self.written.store(pos, Ordering::Release);
+    }
+
+    pub fn written(&self) -> usize { self.written.load(Ordering::Acquire) }
+
+    /// Copies the last samples into `out`, the oldest first.
+    /// The audio thread doesn't wait for the reader, so the oldest
+    /// samples might already be overwritten by new ones.
+    pub fn read(&self, out: &mut [f32]) -> usize {
+        let pos = self.written();
+
+        for (i, o) in out.iter_mut().take(SPECTRUM_FFT_LEN).enumerate() {
+            let idx = pos.wrapping_add(i) % SPECTRUM_FFT_LEN;
+            *o = f32::from_bits(self.samples[idx].load(Ordering::Relaxed));
+        }
+
+        pos
+    }
+}
+
+impl Default for SpectrumTap {
+    fn default() -> Self { Self::new() }
+}
+
+/// Computes the spectrum in the GUI thread.
+pub struct SpectrumAnalyzer {
+    fft:         Arc<dyn Fft<f32>>,
+    window:      Vec<f32>,
+    input:       Vec<f32>,
+    buf:         Vec<Complex<f32>>,
+    scratch:     Vec<Complex<f32>>,
+    mags:        Vec<f32>,
+    levels:      Vec<f32>,
+    peaks:       Vec<f32>,
+    fundamental: Option<f32>,
+    last_pos:    usize,
+}
+
+impl SpectrumAnalyzer {
+    pub fn new() -> Self {
+        let fft = FftPlanner::new().plan_fft_forward(SPECTRUM_FFT_LEN);
+        let scratch_len = fft.get_inplace_scratch_len();
+
+        // Hann window:
+        let window =
+            (0..SPECTRUM_FFT_LEN).map(|i| {
+                let x = i as f32 / SPECTRUM_FFT_LEN as f32;
+                0.5 - 0.5 * (2.0 * std::f32::consts::PI * x).cos()
+            }).collect();
+
+        Self {
+            fft,
+            window,
+            input:       vec![0.0; SPECTRUM_FFT_LEN],
+            buf:         vec![Complex::new(0.0, 0.0); SPECTRUM_FFT_LEN],
+            scratch:     vec![Complex::new(0.0, 0.0); scratch_len],
+            mags:        vec![0.0; SPECTRUM_FFT_LEN / 2],
+            levels:      vec![SPECTRUM_MIN_DB; SPECTRUM_BANDS],
+            peaks:       vec![SPECTRUM_MIN_DB; SPECTRUM_BANDS],
+            fundamental: None,
+            last_pos:    0,
+        }
+    }
+
+    /// Analyzes the last samples of the `tap`. Returns false if
+    /// no new samples were written since the last call.
+    pub fn analyze(&mut self, tap: &SpectrumTap) -> bool {
+        if tap.written() == self.last_pos {
+            return false;
+        }
+
+        self.last_pos = tap.read(&mut self.input[..]);
+        self.analyze_samples(tap.sample_rate());
+        true
+    }
+
+    fn analyze_samples(&mut self, srate: f32) {
+        for ((b, s), w) in self.buf.iter_mut().zip(self.input.iter()).zip(self.window.iter()) {
+            *b = Complex::new(s * w, 0.0);
+        }
+
+        self.fft.process_with_scratch(&mut self.buf[..], &mut self.scratch[..]);
+
+        // Scaled, so that a sine with amplitude 1.0 is at 0dB,
+        // the coherent gain of the Hann window is 0.5:
+        let scale = 4.0 / SPECTRUM_FFT_LEN as f32;
+        for (m, b) in self.mags.iter_mut().zip(self.buf.iter()) {
+            *m = b.norm() * scale;
+        }
+
+        let bin_hz = srate / SPECTRUM_FFT_LEN as f32;
+
+        for band in 0..SPECTRUM_BANDS {
+            let lo = x_to_freq(band as f32 / SPECTRUM_BANDS as f32);
+            let hi = x_to_freq((band + 1) as f32 / SPECTRUM_BANDS as f32);
+
+            let lo_bin = (lo / bin_hz).ceil() as usize;
+            let hi_bin = ((hi / bin_hz).floor() as usize).min(self.mags.len() - 1);
+
+            // The low bands are narrower than the bins:
+            let mag =
+                if lo_bin <= hi_bin {
+                    self.mags[lo_bin..=hi_bin].iter().fold(0.0_f32, |a, m| a.max(*m))
+                } else {
+                    self.mag_at(((lo * hi).sqrt()) / bin_hz)
+                };
+
+            let db = gain_to_db(mag);
+            self.levels[band] = db;
+            self.peaks[band]  = db.max(self.peaks[band] - PEAK_FALL_DB);
+        }
+
+        self.fundamental = self.find_fundamental(bin_hz);
+    }
+
+    /// Interpolates the magnitude at the fractional bin.
+    fn mag_at(&self, bin: f32) -> f32 {
+        let i = (bin.floor() as usize).min(self.mags.len() - 2);
+        let f = (bin - i as f32).clamp(0.0, 1.0);
+        self.mags[i] * (1.0 - f) + self.mags[i + 1] * f
+    }
+
+    /// The strongest peak is taken as fundamental, unless there are
+    /// strong peaks at a half or a third of its frequency.
+    fn find_fundamental(&self, bin_hz: f32) -> Option<f32> {
+        let lo = ((FUNDAMENTAL_MIN_FREQ / bin_hz).ceil() as usize).max(1);
+        let hi = ((FUNDAMENTAL_MAX_FREQ / bin_hz) as usize).min(self.mags.len() - 2);
+
+        let mut max_bin = lo;
+        for i in lo..=hi {
+            if self.mags[i] > self.mags[max_bin] {
+                max_bin = i;
+            }
+        }
+
+        if gain_to_db(self.mags[max_bin]) < FUNDAMENTAL_MIN_DB {
+            return None;
+        }
+
+        let mut fund_bin = max_bin;
+        for div in [3, 2].iter() {
+            let sub = (max_bin as f32 / *div as f32).round() as usize;
+            if sub < lo {
+                continue;
+            }
+
+            // Allows for the peak to be one bin off:
+            let sub = (sub - 1..=sub + 1).max_by(|a, b| {
+                self.mags[*a].partial_cmp(&self.mags[*b]).unwrap()
+            }).unwrap();
+
+            if self.mags[sub] > self.mags[max_bin] * 0.5 && self.is_peak(sub) {
+                fund_bin = sub;
+                break;
+            }
+        }
+
+        Some((fund_bin as f32 + self.peak_offset(fund_bin)) * bin_hz)
+    }
+
+    fn is_peak(&self, i: usize) -> bool {
+        self.mags[i] >= self.mags[i - 1] && self.mags[i] >= self.mags[i + 1]
+    }
+
+    /// Parabolic interpolation of the peak position on the log magnitudes.
+    fn peak_offset(&self, i: usize) -> f32 {
+        let a = gain_to_db(self.mags[i - 1]);
+        let b = gain_to_db(self.mags[i]);
+        let c = gain_to_db(self.mags[i + 1]);
+
+        let d = a - 2.0 * b + c;
+        if d.abs() < 1e-9 { 0.0 } else { (0.5 * (a - c) / d).clamp(-0.5, 0.5) }
+    }
+
+    /// The levels of the bands in dB.
+    pub fn levels(&self) -> &[f32] { &self.levels[..] }
+
+    /// The peak hold of the bands in dB.
+    pub fn peaks(&self) -> &[f32] { &self.peaks[..] }
+
+    /// The detected fundamental frequency in Hz.
+    pub fn fundamental(&self) -> Option<f32> { self.fundamental }
+}
+
+impl Default for SpectrumAnalyzer {
+    fn default() -> Self { Self::new() }
+}
+
+fn gain_to_db(gain: f32) -> f32 {
+    (20.0 * gain.max(1e-10).log10()).max(SPECTRUM_MIN_DB)
+}
+
+/// Maps the level in dB to the Y axis of the analyzer (0.0 to 1.0).
+pub fn db_to_y(db: f32) -> f32 {
+    ((db - SPECTRUM_MIN_DB) / -SPECTRUM_MIN_DB).clamp(0.0, 1.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sine_tap(freqs: &[(f32, f32)]) -> SpectrumTap {
+        let tap = SpectrumTap::new();
+        let samples : Vec<f32> =
+            (0..(SPECTRUM_FFT_LEN + 100)).map(|i| {
+                freqs.iter().map(|(f, a)| {
+                    a * (2.0 * std::f32::consts::PI * f * i as f32 / 44100.0).sin()
+                }).sum()
+            }).collect();
+        tap.write(&samples[..]);
+        tap
+    }
+
+    #[test]
+    fn check_spectrum_note_name() {
+        assert_eq!(note_name(440.0),  "A4 +0c");
+        assert_eq!(note_name(65.41),  "C2 +0c");
+        assert_eq!(note_name(42.0),   "E1 +33c");
+        assert_eq!(note_name(8.0),    "C-1 -38c");
+        assert!((freq_to_x(SPECTRUM_MIN_FREQ)).abs() < 1e-6);
+        assert!((freq_to_x(SPECTRUM_MAX_FREQ) - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn check_spectrum_tap_order() {
+        let tap = SpectrumTap::new();
+        let samples : Vec<f32> = (0..(SPECTRUM_FFT_LEN + 10)).map(|i| i as f32).collect();
+        tap.write(&samples[..]);
+
+        let mut out = vec![0.0; SPECTRUM_FFT_LEN];
+        assert_eq!(tap.read(&mut out[..]), SPECTRUM_FFT_LEN + 10);
+        assert_eq!(out[0], 10.0);
+        assert_eq!(out[SPECTRUM_FFT_LEN - 1], (SPECTRUM_FFT_LEN + 9) as f32);
+    }
+
+    #[test]
+    fn check_spectrum_fundamental() {
+        let mut an = SpectrumAnalyzer::new();
+        let tap = sine_tap(&[(55.0, 0.5), (110.0, 0.8), (165.0, 0.3)]);
+
+        assert!(an.analyze(&tap));
+        assert!(!an.analyze(&tap));
+
+        let fund = an.fundamental().unwrap();
+        assert!((fund - 55.0).abs() < 1.0, "fundamental {}", fund);
+        let name = note_name(fund);
+        assert!(name.starts_with("A1 "), "note {}", name);
+
+        // The level of the loudest band is about the amplitude of the sine,
+        // minus up to 1.5dB if it falls between two bins:
+        let max = an.levels().iter().fold(SPECTRUM_MIN_DB, |a, l| a.max(*l));
+        let db  = gain_to_db(0.8);
+        assert!(max <= db + 0.1 && max > db - 1.5, "max level {}", max);
+    }
+
+    #[test]
+    fn check_spectrum_peak_hold() {
+        let mut an = SpectrumAnalyzer::new();
+        an.analyze(&sine_tap(&[(1000.0, 1.0)]));
+
+        let band = an.levels().iter().enumerate()
+            .fold(0, |m, (i, l)| if *l > an.levels()[m] { i } else { m });
+        let peak = an.peaks()[band];
+
+        let silence = SpectrumTap::new();
+        silence.write(&[0.0; 10]);
+        an.analyze(&silence);
+
+        assert_eq!(an.levels()[band], SPECTRUM_MIN_DB);
+        assert_eq!(an.peaks()[band], peak - PEAK_FALL_DB);
+        assert_eq!(an.fundamental(), None);
+    }
+}
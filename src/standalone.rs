@@ -0,0 +1,320 @@
+// Copyright (c) 2020-2021 Weird Constructor <weirdconstructor@gmail.com>
+// This is a part of Kickmess. See README.md and COPYING for details.
+
+//! The host independent parts of the standalone application.
+//!
+//! The audio and MIDI backends live in `src/bin/kickmess_standalone.rs`.
+//! This module provides the `StandaloneController`, which runs the
+//! normal editor and turns the computer keyboard into a MIDI keyboard.
+//! The notes are sent to the audio thread with a `MidiQueue`.
+
+use crate::engine::{EngineParams, NoHost};
+use crate::editor::KickmessEditorController;
+use crate::param_model::pid;
+use crate::proc::ParamProvider;
+use crate::ringbuf_shared::RingBuf;
+use crate::ui::protocol::{UI, UIController, UIMenuAction, UIMenuState};
+use keyboard_types::{Code, KeyState, KeyboardEvent, Modifiers};
+use std::sync::{Arc, Mutex};
+
+const MIDI_QUEUE_SIZE : usize = 256;
+
+/// The computer keyboard always plays with full velocity.
+const KEYBOARD_VELOCITY : u8 = 127;
+
+/// The octave of the lowest note on the computer keyboard,
+/// the default octave starts at note 36 (C2).
+const DEFAULT_OCTAVE : u8 = 2;
+/// The highest octave, the top key still needs to be a valid MIDI note.
+const MAX_OCTAVE     : u8 = 7;
+
+/// Transports the MIDI messages from the GUI thread to the audio thread.
+#[derive(Clone)]
+pub struct MidiQueue {
+    buf: Arc<RingBuf<[u8; 3]>>,
+}
+
+impl MidiQueue {
+    pub fn new() -> Self {
+        Self { buf: Arc::new(RingBuf::new(MIDI_QUEUE_SIZE)) }
+    }
+
+    /// Returns false if the message was dropped.
+    pub fn push(&self, msg: [u8; 3]) -> bool { self.buf.push(msg) }
+
+    pub fn pop(&self) -> Option<[u8; 3]> { self.buf.pop() }
+}
+
+impl Default for MidiQueue {
+    fn default() -> Self { Self::new() }
+}
+
+/// Maps the physical key to a semitone offset from the current
+/// octave. The layout is the one known from trackers: The bottom
+/// two rows play the lower octave, the two rows above it the next one.
+fn key_to_semitone(code: Code) -> Option<u8> {
+    let semitone =
+        match code {
+            Code::KeyZ      => 0,
+            Code::KeyS      => 1,
+            Code::KeyX      => 2,
+            Code::KeyD      => 3,
+            Code::KeyC      => 4,
+            Code::KeyV      => 5,
+            Code::KeyG      => 6,
+            Code::KeyB      => 7,
+            Code::KeyH      => 8,
+            Code::KeyN      => 9,
+            Code::KeyJ      => 10,
+            Code::KeyM      => 11,
+            Code::Comma     => 12,
+            Code::KeyL      => 13,
+            Code::Period    => 14,
+            Code::Semicolon => 15,
+            Code::Slash     => 16,
+
+            Code::KeyQ      => 12,
+            Code::Digit2    => 13,
+            Code::KeyW      => 14,
+            Code::Digit3    => 15,
+            Code::KeyE      => 16,
+            Code::KeyR      => 17,
+            Code::Digit5    => 18,
+            Code::KeyT      => 19,
+            Code::Digit6    => 20,
+            Code::KeyY      => 21,
+            Code::Digit7    => 22,
+            Code::KeyU      => 23,
+            Code::KeyI      => 24,
+            Code::Digit9    => 25,
+            Code::KeyO      => 26,
+            Code::Digit0    => 27,
+            Code::KeyP      => 28,
+            _ => return None,
+        };
+
+    Some(semitone)
+}
+
+/// Turns key events into MIDI note on and off messages.
+///
+/// The arrow keys up and down change the octave. Notes that are
+/// held while the octave changes are still released correctly.
+#[derive(Debug, Clone)]
+pub struct KeyboardNotes {
+    octave: u8,
+    held:   Vec<(Code, u8)>,
+}
+
+impl KeyboardNotes {
+    pub fn new() -> Self {
+        Self {
+            octave: DEFAULT_OCTAVE,
+            held:   vec![],
+        }
+    }
+
+    pub fn octave(&self) -> u8 { self.octave }
+
+    /// Returns the MIDI message for the key event on the MIDI
+    /// channel `chan`, if the key plays a note.
+    pub fn key_event(&mut self, kev: &KeyboardEvent, chan: u8) -> Option<[u8; 3]> {
+        let chan = chan & 0x0F;
+
+        match kev.state {
+            KeyState::Down => {
+                // Keep the shortcuts of the window manager out of the notes:
+                if kev.modifiers.intersects(Modifiers::CONTROL | Modifiers::ALT) {
+                    return None;
+                }
+
+                match kev.code {
+                    Code::ArrowUp => {
+                        if self.octave < MAX_OCTAVE { self.octave += 1; }
+                        return None;
+                    },
+                    Code::ArrowDown => {
+                        if self.octave > 0 { self.octave -= 1; }
+                        return None;
+                    },
+                    _ => (),
+                }
+
+                let semitone = key_to_semitone(kev.code)?;
+
+                // Some windowing systems don't mark the auto repeated
+                // key presses, so look at the held keys too:
+                if kev.repeat || self.held.iter().any(|(c, _)| *c == kev.code) {
+                    return None;
+                }
+
+                let note = (self.octave + 1) * 12 + semitone;
+                self.held.push((kev.code, note));
+
+                Some([0x90 | chan, note, KEYBOARD_VELOCITY])
+            },
+            KeyState::Up => {
+                let pos  = self.held.iter().position(|(c, _)| *c == kev.code)?;
+                let note = self.held.remove(pos).1;
+
+                Some([0x80 | chan, note, 0])
+            },
+        }
+    }
+}
+
+impl Default for KeyboardNotes {
+    fn default() -> Self { Self::new() }
+}
+
+/// The editor of the standalone application. It behaves like the
+/// plugin editor, and additionally plays the computer keyboard notes.
+pub struct StandaloneController {
+    editor: KickmessEditorController,
+    params: Arc<EngineParams>,
+    keys:   Mutex<KeyboardNotes>,
+    midi:   MidiQueue,
+}
+
+impl StandaloneController {
+    pub fn new(params: Arc<EngineParams>, midi: MidiQueue) -> Self {
+        Self {
+            editor: KickmessEditorController::new(params.clone(), Arc::new(NoHost)),
+            params,
+            keys:   Mutex::new(KeyboardNotes::new()),
+            midi,
+        }
+    }
+}
+
+impl UIController for StandaloneController {
+    fn init(&self, ui: &mut dyn UI) { self.editor.init(ui) }
+
+    fn value_change_start(&self, ui: &mut dyn UI, id: usize, value: f32) {
+        self.editor.value_change_start(ui, id, value)
+    }
+
+    fn value_change(&self, ui: &mut dyn UI, id: usize, value: f32, single_change: bool) {
+        self.editor.value_change(ui, id, value, single_change)
+    }
+
+    fn value_change_stop(&self, ui: &mut dyn UI, id: usize, value: f32) {
+        self.editor.value_change_stop(ui, id, value)
+    }
+
+    fn window_closed(&self, ui: &mut dyn UI) { self.editor.window_closed(ui) }
+    fn pre_frame(&self, ui: &mut dyn UI)     { self.editor.pre_frame(ui) }
+    fn post_frame(&self, ui: &mut dyn UI)    { self.editor.post_frame(ui) }
+    fn is_active(&self) -> bool              { self.editor.is_active() }
+    fn window_zoom(&self) -> f64             { self.editor.window_zoom() }
+
+    fn menu_entry_state(&self, id: usize, action: UIMenuAction) -> UIMenuState {
+        // There is no host with automation lanes:
+        if action == UIMenuAction::ShowAutomation {
+            return UIMenuState::Disabled;
+        }
+        self.editor.menu_entry_state(id, action)
+    }
+
+    fn menu_action(&self, ui: &mut dyn UI, id: usize, action: UIMenuAction) -> bool {
+        self.editor.menu_action(ui, id, action)
+    }
+
+    fn key_event(&self, _ui: &mut dyn UI, kev: &KeyboardEvent) {
+        let chan = self.params.ps().get(pid::midi_chan, &*self.params).floor() as u8;
+
+        if let Ok(mut keys) = self.keys.lock() {
+            if let Some(msg) = keys.key_event(kev, chan) {
+                self.midi.push(msg);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use keyboard_types::{Key, Location};
+
+    fn kev(code: Code, state: KeyState) -> KeyboardEvent {
+        KeyboardEvent {
+            state,
+            key:          Key::Unidentified,
+            code,
+            location:     Location::Standard,
+            modifiers:    Modifiers::empty(),
+            repeat:       false,
+            is_composing: false,
+        }
+    }
+
+    #[test]
+    fn check_keyboard_notes_layout() {
+        let mut kn = KeyboardNotes::new();
+
+        assert_eq!(kn.key_event(&kev(Code::KeyZ, KeyState::Down), 0), Some([0x90, 36, 127]));
+        assert_eq!(kn.key_event(&kev(Code::KeyS, KeyState::Down), 0), Some([0x90, 37, 127]));
+        assert_eq!(kn.key_event(&kev(Code::KeyQ, KeyState::Down), 0), Some([0x90, 48, 127]));
+        assert_eq!(kn.key_event(&kev(Code::KeyP, KeyState::Down), 3), Some([0x93, 64, 127]));
+        assert_eq!(kn.key_event(&kev(Code::KeyA, KeyState::Down), 0), None);
+        assert_eq!(kn.key_event(&kev(Code::KeyZ, KeyState::Up),   0), Some([0x80, 36, 0]));
+        assert_eq!(kn.key_event(&kev(Code::KeyA, KeyState::Up),   0), None);
+    }
+
+    #[test]
+    fn check_keyboard_notes_repeat_and_octave() {
+        let mut kn = KeyboardNotes::new();
+
+        assert!(kn.key_event(&kev(Code::KeyZ, KeyState::Down), 0).is_some());
+        assert_eq!(kn.key_event(&kev(Code::KeyZ, KeyState::Down), 0), None);
+
+        let mut rep = kev(Code::KeyX, KeyState::Down);
+        rep.repeat = true;
+        assert_eq!(kn.key_event(&rep, 0), None);
+
+        // The held note is released with the note it was started with:
+        assert_eq!(kn.key_event(&kev(Code::ArrowUp, KeyState::Down), 0), None);
+        assert_eq!(kn.octave(), 3);
+        assert_eq!(kn.key_event(&kev(Code::KeyZ, KeyState::Up),   0), Some([0x80, 36, 0]));
+        assert_eq!(kn.key_event(&kev(Code::KeyZ, KeyState::Down), 0), Some([0x90, 48, 127]));
+
+        for _ in 0..20 { kn.key_event(&kev(Code::ArrowUp, KeyState::Down), 0); }
+        assert_eq!(kn.octave(), MAX_OCTAVE);
+        assert_eq!(kn.key_event(&kev(Code::KeyP, KeyState::Down), 0), Some([0x90, 124, 127]));
+
+        for _ in 0..20 { kn.key_event(&kev(Code::ArrowDown, KeyState::Down), 0); }
+        assert_eq!(kn.octave(), 0);
+        assert_eq!(kn.key_event(&kev(Code::KeyX, KeyState::Down), 0), Some([0x90, 14, 127]));
+    }
+
+    #[test]
+    fn check_standalone_controller_queues_notes() {
+        let params = Arc::new(EngineParams::new());
+        let chan   = params.ps().definition(pid::midi_chan).unwrap().unmap(1.5);
+        params.set(pid::midi_chan, chan);
+
+        let midi = MidiQueue::new();
+        let ctrl = StandaloneController::new(params, midi.clone());
+
+        // The GUI is not needed for the key handling:
+        struct NoUI;
+        impl UI for NoUI {
+            fn define_layout(&mut self, _: Vec<crate::ui::protocol::UILayout>) {}
+            fn define_value_spec(&mut self, _: Vec<crate::ui::protocol::UIValueSpec>) {}
+            fn set_values(&mut self, _: &[crate::ui::protocol::UIInputValue]) {}
+            fn set_default_values(&mut self) {}
+            fn set_version(&mut self, _: &'static str) {}
+            fn set_scope_data(&mut self, _: usize, _: &[(f32, f32)]) {}
+            fn set_spectrum_data(&mut self, _: usize, _: &crate::ui::protocol::UISpectrum) {}
+            fn set_meter_data(&mut self, _: usize, _: &crate::ui::protocol::UIMeter) {}
+            fn key_pressed(&mut self, _: KeyboardEvent) {}
+            fn key_released(&mut self, _: KeyboardEvent) {}
+        }
+
+        ctrl.key_event(&mut NoUI, &kev(Code::KeyC, KeyState::Down));
+        ctrl.key_event(&mut NoUI, &kev(Code::KeyC, KeyState::Up));
+        assert_eq!(midi.pop(), Some([0x91, 40, 127]));
+        assert_eq!(midi.pop(), Some([0x81, 40, 0]));
+        assert_eq!(midi.pop(), None);
+    }
+}
@@ -21,7 +21,7 @@ impl Button {
 }
 
 impl Button {
-    fn draw_border(&self, p: &mut dyn Painter, width: f64, clr: (f64, f64, f64), x: f64, y: f64, w: f64, h: f64, fill: bool) {
+    fn draw_border(&self, p: &mut dyn Painter, width: f64, clr: UIColor, x: f64, y: f64, w: f64, h: f64, fill: bool) {
         let path = &[
             (x,                      y + UI_BTN_BEVEL),
             (x + UI_BTN_BEVEL,       y),
@@ -42,7 +42,7 @@ impl Button {
 }
 
 impl Button {
-    fn draw_divider(&self, p: &mut dyn Painter, width: f64, color: (f64, f64, f64), x: f64, y: f64) {
+    fn draw_divider(&self, p: &mut dyn Painter, width: f64, color: UIColor, x: f64, y: f64) {
         let (x, y) = (
             x + (UI_BTN_BORDER_WIDTH / 2.0).round(),
             y + (UI_BTN_BORDER_WIDTH / 2.0).round(),
@@ -1,68 +1,71 @@
 // Copyright (c) 2020-2021 Weird Constructor <weirdconstructor@gmail.com>
 // This is a part of Kickmess. See README.md and COPYING for details.
 
-pub fn lighten_clr(depth: u32, clr: (f64, f64, f64)) -> (f64, f64, f64) {
-    (clr.0 * (1.2_f64).powf(depth as f64),
-     clr.1 * (1.2_f64).powf(depth as f64),
-     clr.2 * (1.2_f64).powf(depth as f64))
+use crate::ui::painting::{UIColor, ColorRole};
+
+pub fn lighten_clr(depth: u32, clr: UIColor) -> UIColor {
+    UIColor::rgb(
+        clr.rgb.0 * (1.2_f64).powf(depth as f64),
+        clr.rgb.1 * (1.2_f64).powf(depth as f64),
+        clr.rgb.2 * (1.2_f64).powf(depth as f64))
 }
-pub const UI_BG_CLR               : (f64, f64, f64) = ( 71.0 / 255.0,   63.0 / 255.0,   73.0 / 255.0);
+pub const UI_BG_CLR               : UIColor = UIColor::base(ColorRole::Background, ( 71.0 / 255.0,   63.0 / 255.0,   73.0 / 255.0));
 //pub const UI_BG2_CLR              : (f64, f64, f64) = ( 89.0 / 255.0,   79.0 / 255.0,   93.0 / 255.0);
 //pub const UI_BG3_CLR              : (f64, f64, f64) = ( 98.0 / 255.0,   87.0 / 255.0,  102.0 / 255.0);
-pub const UI_BG3_CLR              : (f64, f64, f64) = (100.0 / 255.0,   88.0 / 255.0,  104.0 / 255.0);
-pub const UI_TXT_CLR              : (f64, f64, f64) = (220.0 / 255.0,  220.0 / 255.0,  240.0 / 255.0);
+pub const UI_BG3_CLR              : UIColor = UIColor::base(ColorRole::Background3, (100.0 / 255.0,   88.0 / 255.0,  104.0 / 255.0));
+pub const UI_TXT_CLR              : UIColor = UIColor::base(ColorRole::Text, (220.0 / 255.0,  220.0 / 255.0,  240.0 / 255.0));
 //pub const UI_LBL_BG_CLR           : (f64, f64, f64) = ( 31.0 / 255.0,   27.0 / 255.0,   33.0 / 255.0);
 //pub const UI_LBL_BG_CLR           : (f64, f64, f64) = ( 63.0 / 255.0,   18.0 / 255.0,   61.0 / 255.0);
 //pub const UI_BORDER_CLR           : (f64, f64, f64) = ( 54.0 / 255.0,   45.0 / 255.0,   56.0 / 255.0);
 //pub const UI_BORDER_CLR           : (f64, f64, f64) = ( 47.0 / 255.0,   39.0 / 255.0,   48.0 / 255.0);
-pub const UI_BORDER_CLR           : (f64, f64, f64) = ( 43.0 / 255.0,    5.0 / 255.0,   48.0 / 255.0);
-pub const UI_LBL_BG_CLR           : (f64, f64, f64) = ( 32.0 / 255.0,   14.0 / 255.0,   31.0 / 255.0);
-pub const UI_ACCENT_CLR           : (f64, f64, f64) = (179.0 / 255.0,   20.0 / 255.0,  170.0 / 255.0);
+pub const UI_BORDER_CLR           : UIColor = UIColor::base(ColorRole::Border, ( 43.0 / 255.0,    5.0 / 255.0,   48.0 / 255.0));
+pub const UI_LBL_BG_CLR           : UIColor = UIColor::base(ColorRole::LabelBg, ( 32.0 / 255.0,   14.0 / 255.0,   31.0 / 255.0));
+pub const UI_ACCENT_CLR           : UIColor = UIColor::base(ColorRole::Accent, (179.0 / 255.0,   20.0 / 255.0,  170.0 / 255.0));
 //pub const UI_ACCENT2_CLR          : (f64, f64, f64) = ( 63.0 / 255.0,   18.0 / 255.0,   61.0 / 255.0);
-pub const UI_PRIM_CLR             : (f64, f64, f64) = (105.0 / 255.0,  232.0 / 255.0,  237.0 / 255.0);
-pub const UI_PRIM2_CLR            : (f64, f64, f64) = (26.0  / 255.0,  174.0 / 255.0,  179.0 / 255.0);
-pub const UI_HLIGHT_CLR           : (f64, f64, f64) = (233.0 / 255.0,  248.0 / 255.0,   64.0 / 255.0);
-pub const UI_HLIGHT2_CLR          : (f64, f64, f64) = (181.0 / 255.0,  196.0 / 255.0,   18.0 / 255.0);
+pub const UI_PRIM_CLR             : UIColor = UIColor::base(ColorRole::Primary, (105.0 / 255.0,  232.0 / 255.0,  237.0 / 255.0));
+pub const UI_PRIM2_CLR            : UIColor = UIColor::base(ColorRole::Primary2, (26.0  / 255.0,  174.0 / 255.0,  179.0 / 255.0));
+pub const UI_HLIGHT_CLR           : UIColor = UIColor::base(ColorRole::Highlight, (233.0 / 255.0,  248.0 / 255.0,   64.0 / 255.0));
+pub const UI_HLIGHT2_CLR          : UIColor = UIColor::base(ColorRole::Highlight2, (181.0 / 255.0,  196.0 / 255.0,   18.0 / 255.0));
 
-pub const UI_INACTIVE_CLR         : (f64, f64, f64) = (111.0 / 255.0,   99.0 / 255.0,  116.0 / 255.0);
-pub const UI_INACTIVE2_CLR        : (f64, f64, f64) = (167.0 / 255.0,  148.0 / 255.0,  174.0 / 255.0);
+pub const UI_INACTIVE_CLR         : UIColor = UIColor::base(ColorRole::Inactive, (111.0 / 255.0,   99.0 / 255.0,  116.0 / 255.0));
+pub const UI_INACTIVE2_CLR        : UIColor = UIColor::base(ColorRole::Inactive2, (167.0 / 255.0,  148.0 / 255.0,  174.0 / 255.0));
 
 pub const UI_VERSION_FONT_SIZE    : f64 = 10.0;
 
 pub const UI_HELP_FONT_SIZE       : f64 = 16.0;
-pub const UI_HELP_TXT_CLR         : (f64, f64, f64) = UI_TXT_CLR;
+pub const UI_HELP_TXT_CLR         : UIColor = UI_TXT_CLR;
 
-pub const UI_LBL_TXT_CLR          : (f64, f64, f64) = UI_TXT_CLR;
+pub const UI_LBL_TXT_CLR          : UIColor = UI_TXT_CLR;
 
 pub const UI_CONT_FONT_SIZE       : f64 = 14.0;
-pub const UI_CONT_FONT_CLR        : (f64, f64, f64) = UI_PRIM_CLR;
+pub const UI_CONT_FONT_CLR        : UIColor = UI_PRIM_CLR;
 
 pub const UI_BG_KNOB_STROKE       : f64 = 8.0;
 pub const UI_MG_KNOB_STROKE       : f64 = 3.0;
 pub const UI_FG_KNOB_STROKE       : f64 = 5.0;
-pub const UI_BG_KNOB_STROKE_CLR   : (f64, f64, f64) = UI_LBL_BG_CLR;
-pub const UI_MG_KNOB_STROKE_CLR   : (f64, f64, f64) = UI_ACCENT_CLR;
-pub const UI_FG_KNOB_STROKE_CLR   : (f64, f64, f64) = UI_PRIM_CLR;
-pub const UI_TXT_KNOB_CLR         : (f64, f64, f64) = UI_PRIM_CLR;
-pub const UI_TXT_KNOB_HOVER_CLR   : (f64, f64, f64) = UI_HLIGHT_CLR;
-pub const UI_TXT_KNOB_HLIGHT_CLR  : (f64, f64, f64) = UI_HLIGHT_CLR;
-pub const UI_TXT_KNOB_HLHOVR_CLR  : (f64, f64, f64) = UI_HLIGHT2_CLR;
-pub const UI_GUI_BG_CLR           : (f64, f64, f64) = UI_BG_CLR;
+pub const UI_BG_KNOB_STROKE_CLR   : UIColor = UI_LBL_BG_CLR;
+pub const UI_MG_KNOB_STROKE_CLR   : UIColor = UI_ACCENT_CLR;
+pub const UI_FG_KNOB_STROKE_CLR   : UIColor = UI_PRIM_CLR;
+pub const UI_TXT_KNOB_CLR         : UIColor = UI_PRIM_CLR;
+pub const UI_TXT_KNOB_HOVER_CLR   : UIColor = UI_HLIGHT_CLR;
+pub const UI_TXT_KNOB_HLIGHT_CLR  : UIColor = UI_HLIGHT_CLR;
+pub const UI_TXT_KNOB_HLHOVR_CLR  : UIColor = UI_HLIGHT2_CLR;
+pub const UI_GUI_BG_CLR           : UIColor = UI_BG_CLR;
 //pub const UI_GUI_BG2_CLR          : (f64, f64, f64) = UI_BG2_CLR;
 //pub const UI_GUI_BG3_CLR          : (f64, f64, f64) = UI_BG3_CLR;
-pub const UI_GUI_CLEAR_CLR        : (f64, f64, f64) = UI_LBL_BG_CLR;
+pub const UI_GUI_CLEAR_CLR        : UIColor = UI_LBL_BG_CLR;
 pub const UI_BORDER_WIDTH         : f64 = 2.0;
 pub const UI_KNOB_RADIUS          : f64 = 25.0;
 pub const UI_KNOB_SMALL_RADIUS    : f64 = 14.0;
 pub const UI_KNOB_FONT_SIZE       : f64 = 11.0;
 
-pub const UI_BTN_BORDER_CLR       : (f64, f64, f64) = UI_BG_KNOB_STROKE_CLR;
-pub const UI_BTN_BORDER2_CLR      : (f64, f64, f64) = UI_ACCENT_CLR;
-pub const UI_BTN_BG_CLR           : (f64, f64, f64) = UI_BG_KNOB_STROKE_CLR;
-pub const UI_BTN_TXT_CLR          : (f64, f64, f64) = UI_TXT_KNOB_CLR;
-pub const UI_BTN_TXT_HOVER_CLR    : (f64, f64, f64) = UI_TXT_KNOB_HOVER_CLR;
-pub const UI_BTN_TXT_HLIGHT_CLR   : (f64, f64, f64) = UI_TXT_KNOB_HLIGHT_CLR;
-pub const UI_BTN_TXT_HLHOVR_CLR   : (f64, f64, f64) = UI_TXT_KNOB_HLHOVR_CLR;
+pub const UI_BTN_BORDER_CLR       : UIColor = UI_BG_KNOB_STROKE_CLR;
+pub const UI_BTN_BORDER2_CLR      : UIColor = UI_ACCENT_CLR;
+pub const UI_BTN_BG_CLR           : UIColor = UI_BG_KNOB_STROKE_CLR;
+pub const UI_BTN_TXT_CLR          : UIColor = UI_TXT_KNOB_CLR;
+pub const UI_BTN_TXT_HOVER_CLR    : UIColor = UI_TXT_KNOB_HOVER_CLR;
+pub const UI_BTN_TXT_HLIGHT_CLR   : UIColor = UI_TXT_KNOB_HLIGHT_CLR;
+pub const UI_BTN_TXT_HLHOVR_CLR   : UIColor = UI_TXT_KNOB_HLHOVR_CLR;
 pub const UI_BTN_WIDTH            : f64 = 3.0 * UI_KNOB_RADIUS;
 pub const UI_BTN_BORDER_WIDTH     : f64 = 6.0;
 pub const UI_BTN_BORDER2_WIDTH    : f64 = 2.0;
@@ -71,17 +74,41 @@ pub const UI_BTN_BEVEL            : f64 = UI_ELEM_TXT_H / 4.0;
 pub const UI_GRPH_W               : f64 = 60.0;
 pub const UI_GRPH_H               : f64 = 30.0;
 pub const UI_GRPH_BORDER          : f64 = 2.0;
-pub const UI_GRPH_BORDER_CLR      : (f64, f64, f64) = UI_ACCENT_CLR;
+pub const UI_GRPH_BORDER_CLR      : UIColor = UI_ACCENT_CLR;
 pub const UI_GRPH_FONT_SIZE       : f64 = UI_KNOB_FONT_SIZE;
+pub const UI_GRPH_HANDLE_SIZE     : f64 = 8.0;
+pub const UI_GRPH_HANDLE_CLR      : UIColor = UI_BTN_TXT_CLR;
+pub const UI_GRPH_HANDLE_HOVER_CLR: UIColor = UI_HLIGHT_CLR;
+/// Pixels to drag a graph handle over the whole range of a parameter:
+pub const UI_GRPH_HANDLE_DRAG_PX  : f64 = 200.0;
+pub const UI_GRPH_HANDLE_FINE_PX  : f64 = 1000.0;
+/// Pixels a graph handle moves with one arrow key press:
+pub const UI_KEY_HANDLE_STEP_PX   : f64 = 10.0;
+pub const UI_FOCUS_WIDTH          : f64 = 2.0;
+pub const UI_FOCUS_CLR            : UIColor = UI_HLIGHT_CLR;
+pub const UI_SCOPE_AXIS_CLR       : UIColor = UI_INACTIVE_CLR;
+pub const UI_SCOPE_WAVE_CLR       : UIColor = UI_PRIM2_CLR;
+pub const UI_SPECTRUM_GRID_CLR    : UIColor = UI_INACTIVE_CLR;
+pub const UI_SPECTRUM_LVL_CLR     : UIColor = UI_PRIM2_CLR;
+pub const UI_SPECTRUM_PEAK_CLR    : UIColor = UI_PRIM_CLR;
+pub const UI_SPECTRUM_FUND_CLR    : UIColor = UI_HLIGHT_CLR;
+pub const UI_METER_W              : f64 = 24.0;
+pub const UI_METER_H              : f64 = 100.0;
+pub const UI_METER_CLIP_H         : f64 = 10.0;
+pub const UI_METER_GRID_CLR       : UIColor = UI_INACTIVE_CLR;
+pub const UI_METER_RMS_CLR        : UIColor = UI_PRIM_CLR;
+pub const UI_METER_PEAK_CLR       : UIColor = UI_PRIM2_CLR;
+pub const UI_METER_HOLD_CLR       : UIColor = UI_HLIGHT_CLR;
+pub const UI_METER_CLIP_CLR       : UIColor = UIColor::base(ColorRole::Clip, (240.0 / 255.0,   40.0 / 255.0,   40.0 / 255.0));
 
 pub const UI_TAB_WIDTH            : f64 = 90.0;
 pub const UI_TAB_FONT_SIZE        : f64 = UI_KNOB_FONT_SIZE;
-pub const UI_TAB_BG_CLR           : (f64, f64, f64) = UI_LBL_BG_CLR;
+pub const UI_TAB_BG_CLR           : UIColor = UI_LBL_BG_CLR;
 pub const UI_TAB_DIV_WIDTH        : f64 = 2.0;
-pub const UI_TAB_DIV_CLR          : (f64, f64, f64) = UI_PRIM_CLR;
-pub const UI_TAB_TXT_CLR          : (f64, f64, f64) = UI_PRIM_CLR;
-pub const UI_TAB_TXT2_CLR         : (f64, f64, f64) = UI_PRIM2_CLR;
-pub const UI_TAB_TXT_HOVER_CLR    : (f64, f64, f64) = UI_BTN_TXT_HOVER_CLR;
+pub const UI_TAB_DIV_CLR          : UIColor = UI_PRIM_CLR;
+pub const UI_TAB_TXT_CLR          : UIColor = UI_PRIM_CLR;
+pub const UI_TAB_TXT2_CLR         : UIColor = UI_PRIM2_CLR;
+pub const UI_TAB_TXT_HOVER_CLR    : UIColor = UI_BTN_TXT_HOVER_CLR;
 
 pub const UI_BOX_H          : f64 = 200.0;
 pub const UI_BOX_BORD       : f64 =   3.0;
@@ -95,3 +122,23 @@ pub const UI_INPUT_BOX_FONT_SIZE : f64 = 16.0;
 
 pub const UI_DRAG_INFO_W         : f64 = 70.0;
 pub const UI_DRAG_INFO_FONT_SIZE : f64 = 10.0;
+
+pub const UI_MENU_W              : f64 = 180.0;
+pub const UI_MENU_ENTRY_H        : f64 = 20.0;
+pub const UI_MENU_CHECK_W        : f64 = 20.0;
+pub const UI_MENU_FONT_SIZE      : f64 = 12.0;
+pub const UI_MENU_BG_CLR         : UIColor = UI_LBL_BG_CLR;
+pub const UI_MENU_BORDER_CLR     : UIColor = UI_ACCENT_CLR;
+pub const UI_MENU_HOVER_BG_CLR   : UIColor = UI_BG3_CLR;
+pub const UI_MENU_TXT_CLR        : UIColor = UI_TXT_CLR;
+pub const UI_MENU_TXT_HOVER_CLR  : UIColor = UI_HLIGHT_CLR;
+pub const UI_MENU_INACTIVE_CLR   : UIColor = UI_INACTIVE2_CLR;
+pub const UI_MENU_CHECK_CLR      : UIColor = UI_PRIM_CLR;
+
+pub const UI_TOOLTIP_W           : f64 = 200.0;
+pub const UI_TOOLTIP_OFFS        : f64 = 14.0;
+pub const UI_TOOLTIP_FONT_SIZE   : f64 = 11.0;
+pub const UI_TOOLTIP_BG_CLR      : UIColor = UI_LBL_BG_CLR;
+pub const UI_TOOLTIP_BORDER_CLR  : UIColor = UI_ACCENT_CLR;
+pub const UI_TOOLTIP_NAME_CLR    : UIColor = UI_PRIM_CLR;
+pub const UI_TOOLTIP_TXT_CLR     : UIColor = UI_TXT_CLR;
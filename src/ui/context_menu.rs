@@ -0,0 +1,132 @@
+// Copyright (c) 2020-2021 Weird Constructor <weirdconstructor@gmail.com>
+// This is a part of Kickmess. See README.md and COPYING for details.
+
+use crate::ui::painting::*;
+use crate::ui::constants::*;
+use crate::ui::protocol::{UIMenuAction, UIMenuState};
+use super::Rect;
+
+/// The context menu of a knob, that opens with the right mouse button.
+/// The entries are laid out in a column below the mouse position,
+/// moved inside the window if they would not fit.
+#[derive(Debug, Clone)]
+pub struct ContextMenu {
+    entries:    Vec<(UIMenuAction, UIMenuState)>,
+    rect:       Rect,
+}
+
+impl ContextMenu {
+    pub fn new(entries: Vec<(UIMenuAction, UIMenuState)>,
+               pos: (f64, f64), window_size: (f64, f64)) -> Self {
+
+        let rect = Rect {
+            x: pos.0,
+            y: pos.1,
+            w: UI_MENU_W,
+            h: entries.len() as f64 * UI_MENU_ENTRY_H + 2.0 * UI_MARGIN,
+        };
+
+        Self {
+            entries,
+            rect: rect.move_inside(window_size.0, window_size.1),
+        }
+    }
+
+    pub fn is_inside(&self, x: f64, y: f64) -> bool {
+           x >= self.rect.x && x < (self.rect.x + self.rect.w)
+        && y >= self.rect.y && y < (self.rect.y + self.rect.h)
+    }
+
+    /// The index of the entry at the position `x`/`y`.
+    pub fn entry_at(&self, x: f64, y: f64) -> Option<usize> {
+        if !self.is_inside(x, y) {
+            return None;
+        }
+
+        let idx = ((y - self.rect.y - UI_MARGIN) / UI_MENU_ENTRY_H).floor();
+        if idx < 0.0 || idx as usize >= self.entries.len() {
+            None
+        } else {
+            Some(idx as usize)
+        }
+    }
+
+    pub fn entry(&self, idx: usize) -> Option<(UIMenuAction, UIMenuState)> {
+        self.entries.get(idx).copied()
+    }
+
+    /// The rectangle of the entry `idx`, as `(x, y, w, h)`.
+    pub fn entry_rect(&self, idx: usize) -> (f64, f64, f64, f64) {
+        (self.rect.x + UI_MARGIN,
+         self.rect.y + UI_MARGIN + idx as f64 * UI_MENU_ENTRY_H,
+         self.rect.w - 2.0 * UI_MARGIN,
+         UI_MENU_ENTRY_H)
+    }
+
+    /// Draws the menu with the entry at the mouse position `hover`
+    /// highlighted.
+    pub fn draw(&self, p: &mut dyn Painter, hover: (f64, f64)) {
+        let r = &self.rect;
+        p.rect_fill(UI_MENU_BG_CLR, r.x, r.y, r.w, r.h);
+        p.rect_stroke(
+            UI_BORDER_WIDTH, UI_MENU_BORDER_CLR,
+            r.x + UI_BORDER_WIDTH / 2.0, r.y + UI_BORDER_WIDTH / 2.0,
+            r.w - UI_BORDER_WIDTH, r.h - UI_BORDER_WIDTH);
+
+        let hover_idx = self.entry_at(hover.0, hover.1);
+
+        for (idx, (action, state)) in self.entries.iter().enumerate() {
+            let (x, y, w, h) = self.entry_rect(idx);
+
+            let clr =
+                if *state == UIMenuState::Disabled {
+                    UI_MENU_INACTIVE_CLR
+                } else if hover_idx == Some(idx) {
+                    p.rect_fill(UI_MENU_HOVER_BG_CLR, x, y, w, h);
+                    UI_MENU_TXT_HOVER_CLR
+                } else {
+                    UI_MENU_TXT_CLR
+                };
+
+            if *state == UIMenuState::Checked {
+                let (cx, cy) = (x + UI_MENU_CHECK_W / 2.0, y + h / 2.0);
+                p.path_stroke(
+                    2.0, UI_MENU_CHECK_CLR,
+                    &mut [
+                        (cx - 5.0, cy),
+                        (cx - 1.5, cy + 4.0),
+                        (cx + 5.0, cy - 4.0),
+                    ].iter().copied(),
+                    false);
+            }
+
+            p.label(
+                UI_MENU_FONT_SIZE, -1, clr,
+                x + UI_MENU_CHECK_W, y, w - UI_MENU_CHECK_W, h,
+                action.label());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entries() -> Vec<(UIMenuAction, UIMenuState)> {
+        UIMenuAction::ALL.iter().map(|a| (*a, UIMenuState::Enabled)).collect()
+    }
+
+    #[test]
+    fn check_context_menu_stays_inside_window() {
+        let menu = ContextMenu::new(entries(), (790.0, 590.0), (800.0, 600.0));
+        let (x, y, w, h) = menu.entry_rect(UIMenuAction::ALL.len() - 1);
+        assert!(x + w <= 800.0);
+        assert!(y + h <= 600.0);
+
+        assert_eq!(menu.entry_at(x + 1.0, y + 1.0), Some(UIMenuAction::ALL.len() - 1));
+        assert_eq!(menu.entry_at(799.0, 599.0), None);
+        assert_eq!(
+            menu.entry(1),
+            Some((UIMenuAction::ResetDefault, UIMenuState::Enabled)));
+    }
+}
@@ -5,6 +5,9 @@ use crate::ui::painting::*;
 use crate::ui::protocol::UIKnobData;
 use crate::ui::protocol::UIBtnData;
 use crate::ui::protocol::UIGraphData;
+use crate::ui::protocol::UIScopeData;
+use crate::ui::protocol::UISpectrumData;
+use crate::ui::protocol::UIMeterData;
 
 //#[derive(Debug, Clone, Copy)]
 //pub enum UIElementSize {
@@ -24,6 +27,9 @@ pub trait UIElementData {
     fn as_knob_data(&self) -> Option<&UIKnobData> { None }
     fn as_graph_data(&self) -> Option<&UIGraphData> { None }
     fn as_btn_data(&self) -> Option<&UIBtnData> { None }
+    fn as_scope_data(&self) -> Option<&UIScopeData> { None }
+    fn as_spectrum_data(&self) -> Option<&UISpectrumData> { None }
+    fn as_meter_data(&self) -> Option<&UIMeterData> { None }
     fn value_id(&self) -> usize;
 //    fn size(&self) -> UIElementSize { UIElementSize::Normal };
 }
@@ -20,6 +20,20 @@ impl Graph {
             font_size,
         }
     }
+
+    /// Maps a point of the graph data to a position relative to the element.
+    fn data_to_xy(&self, p: (f64, f64)) -> (f64, f64) {
+        let w = self.w - 2.0 * UI_GRPH_BORDER;
+        let h = self.h - UI_ELEM_TXT_H - 2.0 * UI_GRPH_BORDER;
+        (p.0 * w + UI_GRPH_BORDER, (h - p.1 * h) + UI_GRPH_BORDER)
+    }
+
+    /// The rectangle of a handle, relative to the element.
+    fn handle_rect(&self, p: (f64, f64)) -> (f64, f64, f64, f64) {
+        let (hx, hy) = self.data_to_xy(p);
+        let s = UI_GRPH_HANDLE_SIZE;
+        ((hx - s * 0.5).round(), (hy - s * 0.5).round(), s, s)
+    }
 }
 
 
@@ -28,9 +42,22 @@ impl UIElement for Graph {
         (self.w, self.h)
     }
 
-    fn define_active_zones(&self, _x: f64, _y: f64,
-                           _elem_data: &dyn UIElementData,
-                           _f: &mut dyn FnMut(ActiveZone)) {
+    fn define_active_zones(&self, x: f64, y: f64,
+                           elem_data: &dyn UIElementData,
+                           f: &mut dyn FnMut(ActiveZone)) {
+
+        let graph = elem_data.as_graph_data().unwrap();
+        let handle_pos = graph.handle_pos.borrow();
+
+        for (handle, pos) in graph.handles.iter().zip(handle_pos.iter()) {
+            let pos = if let Some(pos) = pos { pos } else { continue };
+            let mut z =
+                ActiveZone::from_rect(
+                    x, y, AZ_GRAPH_HANDLE, self.handle_rect(*pos));
+            z.id       = handle.y_id.or(handle.x_id).unwrap_or(0);
+            z.drag_ids = (handle.x_id, handle.y_id);
+            (f)(z);
+        }
     }
 
     fn draw_value(&self, p: &mut dyn Painter, x: f64, y: f64,
@@ -43,16 +70,32 @@ impl UIElement for Graph {
         let h = h - 2.0 * UI_GRPH_BORDER;
         let (xo, yo) = (UI_GRPH_BORDER, UI_GRPH_BORDER);
 
-        let name = &data.as_graph_data().unwrap().label;
-        let data = data.as_graph_data().unwrap().data.borrow();
+        let graph = data.as_graph_data().unwrap();
+        let name = &graph.label;
+        let data = graph.data.borrow();
+
+        // A NaN point separates multiple lines in the same graph:
+        for line in data.split(|p| p.0.is_nan()) {
+            p.path_stroke(
+                1.0, UI_BTN_TXT_CLR,
+                &mut (line.iter().map(
+                    |p: &(f64, f64)|
+                        (p.0 * w       + xo + x,
+                         (h - p.1 * h) + yo + y))),
+                false);
+        }
+
+        let hover_handle = graph.hover_handle.get();
+        for (i, pos) in graph.handle_pos.borrow().iter().enumerate() {
+            let pos = if let Some(pos) = pos { pos } else { continue };
+            let (hx, hy, hw, hh) = self.handle_rect(*pos);
+            let clr =
+                if hover_handle == Some(i) { UI_GRPH_HANDLE_HOVER_CLR }
+                else                       { UI_GRPH_HANDLE_CLR };
+            p.rect_fill(UI_BTN_BG_CLR, x + hx, y + hy, hw, hh);
+            p.rect_stroke(1.0, clr, x + hx + 0.5, y + hy + 0.5, hw - 1.0, hh - 1.0);
+        }
 
-        p.path_stroke(
-            1.0, UI_BTN_TXT_CLR,
-            &mut (data.iter().map(
-                |p: &(f64, f64)|
-                    (p.0 * w       + xo + x,
-                     (h - p.1 * h) + yo + y))),
-            false);
         p.label(
             self.font_size, 0, UI_BTN_TXT_CLR,
             x, y + self.size().1 - UI_ELEM_TXT_H,
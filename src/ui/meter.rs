@@ -0,0 +1,119 @@
+// Copyright (c) 2020-2021 Weird Constructor <weirdconstructor@gmail.com>
+// This is a part of Kickmess. See README.md and COPYING for details.
+
+use crate::ui::painting::*;
+use crate::ui::element::{UIElement, UIElementData};
+use crate::ui::constants::*;
+
+/// Draws a vertical level meter with the peak, the RMS level,
+/// the held peak and a clip indicator on top. Clicking it
+/// sets its value to 1.0, which resets the clip indicator.
+pub struct Meter {
+    w:          f64,
+    h:          f64,
+    font_size:  f64,
+}
+
+impl Meter {
+    pub fn new(w: f64, h: f64, font_size: f64) -> Self {
+        Self {
+            w,
+            h: h + UI_METER_CLIP_H + UI_GRPH_BORDER + UI_ELEM_TXT_H,
+            font_size,
+        }
+    }
+
+    /// The rectangle of the level bar, relative to the element.
+    fn bar_rect(&self) -> (f64, f64, f64, f64) {
+        let y = UI_METER_CLIP_H + UI_GRPH_BORDER;
+        (0.0, y, self.w, self.h - y - UI_ELEM_TXT_H)
+    }
+}
+
+impl UIElement for Meter {
+    fn size(&self) -> (f64, f64) {
+        (self.w, self.h)
+    }
+
+    fn define_active_zones(&self, x: f64, y: f64,
+                           _elem_data: &dyn UIElementData,
+                           f: &mut dyn FnMut(ActiveZone)) {
+        let mut z =
+            ActiveZone::from_rect(x, y, AZ_SET_VALUE, (0.0, 0.0, self.w, self.h));
+        z.set_val = 1.0;
+        (f)(z);
+    }
+
+    fn draw_value(&self, p: &mut dyn Painter, x: f64, y: f64,
+                  highlight: HLStyle, data: &dyn UIElementData,
+                  _value: f64, _val_s: &str) {
+
+        let meter  = data.as_meter_data().unwrap();
+        let levels = meter.data.borrow();
+
+        let clip_clr =
+            if levels.clip { UI_METER_CLIP_CLR } else { UI_BTN_BG_CLR };
+        p.rect_fill(
+            clip_clr,
+            x + UI_GRPH_BORDER, y + UI_GRPH_BORDER,
+            self.w - 2.0 * UI_GRPH_BORDER, UI_METER_CLIP_H - 2.0 * UI_GRPH_BORDER);
+
+        let (bx, by, bw, bh) = self.bar_rect();
+        let (bx, by) = (x + bx + UI_GRPH_BORDER, y + by + UI_GRPH_BORDER);
+        let bw = bw - 2.0 * UI_GRPH_BORDER;
+        let bh = bh - 2.0 * UI_GRPH_BORDER;
+        let level_y = |v: f32| by + bh - (v.clamp(0.0, 1.0) as f64) * bh;
+
+        let peak_y = level_y(levels.peak);
+        p.rect_fill(UI_METER_PEAK_CLR, bx, peak_y, bw, by + bh - peak_y);
+
+        let rms_y = level_y(levels.rms);
+        let rms_w = (bw * 0.5).round();
+        p.rect_fill(
+            UI_METER_RMS_CLR,
+            bx + ((bw - rms_w) * 0.5).round(), rms_y, rms_w, by + bh - rms_y);
+
+        for gy in levels.grid.iter() {
+            let gy = level_y(*gy).round() + 0.5;
+            p.path_stroke(
+                1.0, UI_METER_GRID_CLR,
+                &mut [(bx, gy), (bx + bw, gy)].iter().copied(),
+                false);
+        }
+
+        if levels.hold > 0.0 {
+            let hold_y = level_y(levels.hold).round() + 0.5;
+            p.path_stroke(
+                2.0, UI_METER_HOLD_CLR,
+                &mut [(bx, hold_y), (bx + bw, hold_y)].iter().copied(),
+                false);
+        }
+
+        let lbl_clr =
+            match highlight {
+                HLStyle::Hover(_) => UI_BTN_TXT_HOVER_CLR,
+                _                 => UI_BTN_TXT_CLR,
+            };
+        p.label(
+            self.font_size, 0, lbl_clr,
+            x, y + self.h - UI_ELEM_TXT_H,
+            self.w, UI_ELEM_TXT_H, &meter.label);
+    }
+
+    fn draw_bg(&self, p: &mut dyn Painter, x: f64, y: f64) {
+        let mid_border = UI_GRPH_BORDER / 2.0;
+
+        p.rect_fill(UI_BTN_BG_CLR, x, y, self.w, UI_METER_CLIP_H);
+        p.rect_stroke(
+            UI_GRPH_BORDER, UI_GRPH_BORDER_CLR,
+            x + mid_border, y + mid_border,
+            self.w - UI_GRPH_BORDER, UI_METER_CLIP_H - UI_GRPH_BORDER);
+
+        let (bx, by, bw, bh) = self.bar_rect();
+        p.rect_fill(UI_BTN_BG_CLR, x + bx, y + by, bw, bh);
+        p.rect_stroke(
+            UI_GRPH_BORDER, UI_GRPH_BORDER_CLR,
+            x + bx + mid_border, y + by + mid_border,
+            bw - UI_GRPH_BORDER, bh - UI_GRPH_BORDER);
+    }
+}
@@ -6,24 +6,33 @@ mod button;
 mod draw_cache;
 mod element;
 mod graph;
+mod scope;
+mod spectrum;
+mod meter;
+mod context_menu;
 
 pub mod painting;
 pub mod constants;
 pub mod protocol;
+pub mod recording;
+pub mod theme;
 
 use std::rc::Rc;
 use std::cell::RefCell;
 use std::sync::Arc;
 
 use crate::ui::element::*;
-use crate::ui::painting::{ActiveZone, HLStyle, Painter};
+use crate::ui::painting::{ActiveZone, HLStyle, Painter, UIColor};
 use crate::ui::draw_cache::{DrawCache};
+use crate::ui::theme::{Theme, ThemedPainter};
+use crate::ui::context_menu::ContextMenu;
 use crate::ui::protocol::{UIPos, UIKnobData,
                           UITabData, UILayout, UIBtnData, UIInput,
                           UIValueSpec, UIValueSource,
-                          UIInputValue, UI, UIController};
+                          UIInputValue, UI, UIController, UISpectrum,
+                          UIMeter, UIMenuAction, UIMenuState};
 use crate::ui::constants::*;
-use keyboard_types::{Key, KeyboardEvent};
+use keyboard_types::{Key, KeyboardEvent, Modifiers};
 
 /*
 
@@ -71,6 +80,9 @@ pub enum ElementType {
     Graph,
     GraphHuge,
     GraphSmall,
+    Scope,
+    Spectrum,
+    Meter,
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -95,10 +107,12 @@ pub enum UIEvent {
 enum InputMode {
     None,
     ValueDrag  { zone: ActiveZone, orig_pos: (f64, f64), pre_fine_delta: f64, fine_key: bool },
+    HandleDrag { zone: ActiveZone, orig_pos: (f64, f64), orig: (f32, f32), fine_key: bool },
     SelectMod  { zone: ActiveZone },
     InputValue { zone: ActiveZone,
                  value: String,
                  input: std::rc::Rc<std::cell::RefCell<std::io::BufWriter<Vec<u8>>>> },
+    ContextMenu { zone: ActiveZone, menu: ContextMenu },
     GetHelp,
 }
 
@@ -108,8 +122,10 @@ impl InputMode {
             InputMode::None                    => IMAGINARY_MAX_ID,
             InputMode::GetHelp                 => IMAGINARY_MAX_ID,
             InputMode::ValueDrag  { zone, .. } => zone.id,
+            InputMode::HandleDrag { zone, .. } => zone.id,
             InputMode::SelectMod  { zone, .. } => zone.id,
             InputMode::InputValue { zone, .. } => zone.id,
+            InputMode::ContextMenu { zone, .. } => zone.id,
         }
     }
 }
@@ -122,12 +138,19 @@ pub struct WValuePlugUI {
     element_values: Vec<f32>,
     value_specs:    Vec<UIValueSpec>,
     window_size:    (f64, f64),
+    theme:          Theme,
 
     zones:          Vec<ActiveZone>,
     cache:          DrawCache,
 
     hover_zone:     Option<ActiveZone>,
+    /// The zone, that is controlled with the keyboard.
+    focus_zone:     Option<ActiveZone>,
     drag_tmp_value: Option<(usize, f64)>,
+    /// The value copied with the context menu, as the value of the
+    /// parameter and the normalized value. It is internal to the GUI
+    /// and not put on the clipboard of the system.
+    clipboard:      Option<(f32, f32)>,
     last_mouse_pos: (f64, f64),
     input_mode:     InputMode,
     fine_drag_key_held: bool,
@@ -137,6 +160,10 @@ pub struct WValuePlugUI {
     needs_redraw_flag: bool,
 
     version_label:  &'static str,
+
+    scope_data:     Vec<Vec<(f32, f32)>>,
+    spectrum_data:  Vec<UISpectrum>,
+    meter_data:     Vec<UIMeter>,
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -250,6 +277,34 @@ impl UI for WValuePlugUI {
         self.queue_redraw();
     }
 
+    fn set_scope_data(&mut self, id: usize, data: &[(f32, f32)]) {
+        if self.scope_data.len() <= id {
+            self.scope_data.resize(id + 1, vec![]);
+        }
+
+        self.scope_data[id].clear();
+        self.scope_data[id].extend_from_slice(data);
+        self.queue_redraw();
+    }
+
+    fn set_spectrum_data(&mut self, id: usize, data: &UISpectrum) {
+        if self.spectrum_data.len() <= id {
+            self.spectrum_data.resize(id + 1, UISpectrum::default());
+        }
+
+        self.spectrum_data[id].clone_from(data);
+        self.queue_redraw();
+    }
+
+    fn set_meter_data(&mut self, id: usize, data: &UIMeter) {
+        if self.meter_data.len() <= id {
+            self.meter_data.resize(id + 1, UIMeter::default());
+        }
+
+        self.meter_data[id].clone_from(data);
+        self.queue_redraw();
+    }
+
     fn key_pressed(&mut self, keev: KeyboardEvent) {
         self.handle_ui_event(UIEvent::KeyPressed(keev));
     }
@@ -265,12 +320,15 @@ impl WValuePlugUI {
                 controller,
                 layout:             Rc::new(RefCell::new(vec![])),
                 window_size:        (0.0, 0.0),
+                theme:              Theme::default_theme(),
                 zones:              vec![],
                 cache:              DrawCache::new(),
                 element_values:     vec![],
                 value_specs:        vec![],
                 hover_zone:         None,
+                focus_zone:         None,
                 drag_tmp_value:     None,
+                clipboard:          None,
                 fine_drag_key_held: false,
                 last_mouse_pos:     (0.0, 0.0),
                 needs_redraw_flag:  true,
@@ -278,6 +336,9 @@ impl WValuePlugUI {
                 help_id:            None,
                 help_texts:         vec![],
                 version_label:      "",
+                scope_data:         vec![],
+                spectrum_data:      vec![],
+                meter_data:         vec![],
             };
         this.init_draw_cache();
         this.controller.clone().init(&mut this);
@@ -288,6 +349,9 @@ impl WValuePlugUI {
         use crate::ui::segmented_knob::SegmentedKnob;
         use crate::ui::button::Button;
         use crate::ui::graph::Graph;
+        use crate::ui::scope::Scope;
+        use crate::ui::spectrum::Spectrum;
+        use crate::ui::meter::Meter;
 
         // ElementType::Knob
         self.cache.push_element(
@@ -339,6 +403,26 @@ impl WValuePlugUI {
                 (UI_GRPH_H * 0.6).round(),
                 (UI_GRPH_FONT_SIZE * 0.8).round())));
 
+        // ElementType::Scope
+        self.cache.push_element(
+            Box::new(Scope::new(
+                (UI_GRPH_W * 2.4).round(),
+                (UI_GRPH_H * 2.2).round(),
+                UI_GRPH_FONT_SIZE)));
+
+        // ElementType::Spectrum
+        self.cache.push_element(
+            Box::new(Spectrum::new(
+                (UI_GRPH_W * 2.4).round(),
+                (UI_GRPH_H * 2.2).round(),
+                UI_GRPH_FONT_SIZE)));
+
+        // ElementType::Meter
+        self.cache.push_element(
+            Box::new(Meter::new(
+                UI_METER_W, UI_METER_H,
+                (UI_KNOB_FONT_SIZE * 0.8).round())));
+
 //            button: SegmentedButton::new(UI_KNOB_FONT_SIZE),
     }
 
@@ -354,6 +438,13 @@ impl WValuePlugUI {
         self.window_size = (w, h);
     }
 
+    pub fn theme(&self) -> &Theme { &self.theme }
+
+    pub fn set_theme(&mut self, theme: Theme) {
+        self.theme = theme;
+        self.queue_redraw();
+    }
+
     fn hover_zone_submode(&self) -> i8 {
         if let Some(hz) = self.hover_zone { hz.subtype as i8 } else { -1 }
     }
@@ -390,6 +481,12 @@ impl WValuePlugUI {
             }
         }
 
+        // A handle drag continues from the current values
+        // with the new resolution:
+        if let InputMode::HandleDrag { .. } = self.input_mode {
+            self.start_handle_drag(pressed);
+        }
+
         if let Some(id) = change_drag_id {
             self.recalc_drag_value();
             let value = self.get_element_value(id);
@@ -423,6 +520,290 @@ impl WValuePlugUI {
         }
     }
 
+    fn handle_drag_ids(zone: &ActiveZone) -> impl Iterator<Item = usize> {
+        zone.drag_ids.0.into_iter().chain(zone.drag_ids.1.into_iter())
+    }
+
+    /// Enters the `HandleDrag` mode for the graph handle under the mouse,
+    /// or restarts it from the current values.
+    fn start_handle_drag(&mut self, fine_key: bool) {
+        let zone =
+            match self.input_mode {
+                InputMode::HandleDrag { zone, .. } => zone,
+                _ => {
+                    match self.hover_zone {
+                        Some(zone) => zone,
+                        None       => return,
+                    }
+                },
+            };
+
+        let orig = (
+            zone.drag_ids.0.map(|id| self.get_element_value(id)).unwrap_or(0.0),
+            zone.drag_ids.1.map(|id| self.get_element_value(id)).unwrap_or(0.0),
+        );
+
+        self.input_mode =
+            InputMode::HandleDrag {
+                zone,
+                orig_pos: self.last_mouse_pos,
+                orig,
+                fine_key,
+            };
+    }
+
+    /// Moves the parameters of the dragged graph handle with the mouse.
+    fn recalc_handle_drag(&mut self) {
+        if let InputMode::HandleDrag { zone, orig_pos, orig, fine_key }
+            = self.input_mode {
+
+            let range =
+                if fine_key { UI_GRPH_HANDLE_FINE_PX }
+                else        { UI_GRPH_HANDLE_DRAG_PX };
+            let xd = (self.last_mouse_pos.0 - orig_pos.0) / range;
+            let yd = (orig_pos.1 - self.last_mouse_pos.1) / range;
+
+            let changes = [
+                zone.drag_ids.0.map(|id| (id, clamp01(orig.0 + xd as f32))),
+                zone.drag_ids.1.map(|id| (id, clamp01(orig.1 + yd as f32))),
+            ];
+
+            for (id, value) in changes.iter().flatten() {
+                self.set_element_value(*id, *value);
+                self.controller.clone().value_change(
+                    self, *id, *value, false);
+            }
+        }
+    }
+
+    /// Opens the value input box for the value of `zone`.
+    fn start_value_input(&mut self, zone: ActiveZone) {
+        let mut bw = std::io::BufWriter::new(vec![]);
+        self.get_formatted_value(zone.id, &mut bw);
+        let value =
+            String::from_utf8(bw.into_inner().unwrap()).unwrap();
+        self.input_mode =
+            InputMode::InputValue {
+                zone,
+                value: value.trim().to_string(),
+                input:
+                    std::rc::Rc::new(
+                        std::cell::RefCell::new(
+                            std::io::BufWriter::new(vec![]))),
+            };
+        self.queue_redraw();
+    }
+
+    /// Opens the context menu of the value of `zone` at the mouse position.
+    fn open_context_menu(&mut self, zone: ActiveZone) {
+        let ctrl = self.controller.clone();
+        let entries =
+            UIMenuAction::ALL.iter().map(|action| {
+                let state =
+                    if *action == UIMenuAction::PasteValue && self.clipboard.is_none() {
+                        UIMenuState::Disabled
+                    } else {
+                        ctrl.menu_entry_state(zone.id, *action)
+                    };
+                (*action, state)
+            }).collect();
+
+        self.input_mode =
+            InputMode::ContextMenu {
+                zone,
+                menu: ContextMenu::new(entries, self.last_mouse_pos, self.window_size),
+            };
+        self.queue_redraw();
+    }
+
+    /// Does the context menu entry `action` for the value of `zone`,
+    /// if the controller does not handle it.
+    fn do_menu_action(&mut self, zone: ActiveZone, action: UIMenuAction) {
+        let id = zone.id;
+        if self.controller.clone().menu_action(self, id, action) {
+            return;
+        }
+
+        match action {
+            UIMenuAction::TypeValue => {
+                self.start_value_input(zone);
+            },
+            UIMenuAction::ResetDefault => {
+                let value = self.get_element_default_value(id);
+                self.change_value(id, value);
+            },
+            UIMenuAction::CopyValue => {
+                self.clipboard =
+                    Some((self.get_element_value_denorm(id),
+                          self.get_element_value(id)));
+            },
+            UIMenuAction::PasteValue => {
+                // The value is pasted with the same unit, if the knob
+                // can parse it. Otherwise the knob gets the same position:
+                if let Some((denorm, value)) = self.clipboard {
+                    let value =
+                        self.parse_element_value(id, &denorm.to_string())
+                            .map(|v| v as f32)
+                            .unwrap_or(value);
+                    self.change_value(id, value);
+                }
+            },
+            _ => {
+                klog!(Gui, Debug, "menu entry {:?} not handled for {}", action, id);
+            },
+        }
+    }
+
+    /// The index of the focused zone in the zones of the last frame.
+    /// The zones are defined anew with each frame, so they are
+    /// compared by what they control, not by their position.
+    fn focus_index(&self) -> Option<usize> {
+        let fz = self.focus_zone?;
+        self.zones.iter().position(|z| {
+               z.id       == fz.id
+            && z.subtype  == fz.subtype
+            && z.drag_ids == fz.drag_ids
+            && (z.subtype != (painting::AZ_SET_VALUE as usize)
+                || z.set_val == fz.set_val)
+        })
+    }
+
+    /// Moves the keyboard focus to the next or previous active zone.
+    fn move_focus(&mut self, forward: bool) {
+        let len = self.zones.len();
+        if len == 0 {
+            return;
+        }
+
+        let next =
+            match self.focus_index() {
+                None    if forward => 0,
+                None               => len - 1,
+                Some(i) if forward => (i + 1) % len,
+                Some(i)            => (i + len - 1) % len,
+            };
+        self.focus_zone = Some(self.zones[next]);
+    }
+
+    /// Sets the value `id` to `value` and reports it as single change.
+    fn change_value(&mut self, id: usize, value: f32) {
+        self.set_element_value(id, value);
+        self.controller.clone().value_change(self, id, value, true);
+    }
+
+    /// Handles the keyboard navigation: Tab and Shift+Tab move the focus,
+    /// the arrow keys change the value of the focused zone in coarse or
+    /// (with Shift) fine steps, Enter opens the value input box or presses
+    /// the button and Delete resets the value to its default.
+    /// Returns true if the key was used.
+    fn handle_focus_key(&mut self, key_event: &KeyboardEvent) -> bool {
+        if self.help_id.is_some() {
+            return false;
+        }
+        if let InputMode::None = self.input_mode { } else {
+            return false;
+        }
+
+        let fine =
+            self.fine_drag_key_held
+            || key_event.modifiers.contains(Modifiers::SHIFT);
+
+        if let Key::Tab = key_event.key {
+            self.move_focus(!fine);
+            return true;
+        }
+
+        let zone =
+            match self.focus_index() {
+                Some(idx) => self.zones[idx],
+                None => {
+                    // The focused element is not visible anymore:
+                    self.focus_zone = None;
+                    return false;
+                },
+            };
+        self.focus_zone = Some(zone);
+
+        let subtype = zone.subtype as i8;
+        let is_drag =
+               subtype == painting::AZ_COARSE_DRAG
+            || subtype == painting::AZ_FINE_DRAG;
+
+        match key_event.key {
+            Key::ArrowUp | Key::ArrowDown | Key::ArrowLeft | Key::ArrowRight => {
+                let steps =
+                    match key_event.key {
+                        Key::ArrowUp | Key::ArrowRight =>  1.0,
+                        _                              => -1.0,
+                    };
+
+                if is_drag {
+                    let adj =
+                        if fine { self.calc_fine_step(zone.id, steps) }
+                        else    { self.calc_coarse_step(zone.id, steps) };
+                    let value = clamp01(self.get_element_value(zone.id) + adj as f32);
+                    self.change_value(zone.id, value);
+
+                } else if subtype == painting::AZ_TOGGLE {
+                    let value =
+                        if steps > 0.0 { self.get_next_toggle_value(zone.id) }
+                        else           { self.get_prev_toggle_value(zone.id) };
+                    self.change_value(zone.id, value);
+
+                } else if subtype == painting::AZ_GRAPH_HANDLE {
+                    let range =
+                        if fine { UI_GRPH_HANDLE_FINE_PX }
+                        else    { UI_GRPH_HANDLE_DRAG_PX };
+                    let id =
+                        match key_event.key {
+                            Key::ArrowLeft | Key::ArrowRight => zone.drag_ids.0,
+                            _                                => zone.drag_ids.1,
+                        };
+
+                    if let Some(id) = id {
+                        let delta = (steps * UI_KEY_HANDLE_STEP_PX / range) as f32;
+                        let value = clamp01(self.get_element_value(id) + delta);
+                        self.change_value(id, value);
+                    }
+                } else {
+                    return false;
+                }
+            },
+            Key::Enter => {
+                if is_drag {
+                    self.start_value_input(zone);
+
+                } else if subtype == painting::AZ_TOGGLE {
+                    let value = self.get_next_toggle_value(zone.id);
+                    self.change_value(zone.id, value);
+
+                } else if subtype == painting::AZ_SET_VALUE {
+                    self.change_value(zone.id, zone.set_val as f32);
+
+                } else {
+                    return false;
+                }
+            },
+            Key::Delete => {
+                if is_drag || subtype == painting::AZ_TOGGLE {
+                    let value = self.get_element_default_value(zone.id);
+                    self.change_value(zone.id, value);
+
+                } else if subtype == painting::AZ_GRAPH_HANDLE {
+                    for id in Self::handle_drag_ids(&zone) {
+                        let value = self.get_element_default_value(id);
+                        self.change_value(id, value);
+                    }
+                } else {
+                    return false;
+                }
+            },
+            _ => { return false; },
+        }
+
+        true
+    }
+
     pub fn handle_ui_event(&mut self, ev: UIEvent) {
         match ev {
             UIEvent::MousePosition(x, y) => {
@@ -437,6 +818,9 @@ impl WValuePlugUI {
                         self.controller.clone().value_change(
                             self, id, value, false);
                     },
+                    InputMode::HandleDrag { .. } => {
+                        self.recalc_handle_drag();
+                    },
                     _ => {
                         self.hover_zone = None;
 
@@ -493,6 +877,19 @@ impl WValuePlugUI {
                                     _ => {}
                                 }
                             },
+                            painting::AZ_GRAPH_HANDLE => {
+                                if let MouseButton::Left = btn {
+                                    self.start_handle_drag(self.fine_drag_key_held);
+
+                                    let zone = self.hover_zone.unwrap();
+                                    for id in Self::handle_drag_ids(&zone) {
+                                        let value = self.get_element_value(id);
+                                        self.controller.clone().value_change_start(
+                                            self, id, value);
+                                    }
+                                    self.queue_redraw();
+                                }
+                            },
                             _ => {}
                         }
                     },
@@ -543,21 +940,7 @@ impl WValuePlugUI {
                                 MouseButton::Right => {
                                     if    self.hover_zone_submode() == painting::AZ_COARSE_DRAG
                                        || self.hover_zone_submode() == painting::AZ_FINE_DRAG {
-                                        let mut buf = vec![];
-                                        let mut bw = std::io::BufWriter::new(buf);
-                                        self.get_formatted_value(hz.id, &mut bw);
-                                        let value = 
-                                            String::from_utf8(bw.into_inner().unwrap()).unwrap();
-                                        self.input_mode =
-                                            InputMode::InputValue {
-                                                zone: hz,
-                                                value: value.trim().to_string(),
-                                                input:
-                                                    std::rc::Rc::new(
-                                                        std::cell::RefCell::new(
-                                                            std::io::BufWriter::new(vec![]))),
-                                            };
-                                        self.queue_redraw();
+                                        self.open_context_menu(hz);
                                         return;
                                     }
                                 },
@@ -604,7 +987,7 @@ impl WValuePlugUI {
                                 return;
                             },
                             _ => {
-                                println!("BUTTON PRESS: {:?} @{:?}", btn, self.last_mouse_pos);
+                                klog!(Gui, Trace, "button press: {:?} @{:?}", btn, self.last_mouse_pos);
                             }
                         }
                     },
@@ -619,9 +1002,19 @@ impl WValuePlugUI {
                         self.controller.clone().value_change_stop(self, id, v);
                         self.queue_redraw();
                     },
+                    InputMode::HandleDrag { zone, .. } => {
+                        self.recalc_handle_drag();
+
+                        for id in Self::handle_drag_ids(&zone) {
+                            let value = self.get_element_value(id);
+                            self.controller.clone().value_change_stop(
+                                self, id, value);
+                        }
+                        self.queue_redraw();
+                    },
                     InputMode::GetHelp => {
                         if let Some(hover_zone) = self.hover_zone {
-                            println!("HOVERZOME: {:?}", hover_zone);
+                            klog!(Gui, Trace, "help for: {:?}", hover_zone);
                             if let Some(_) = self.get_element_help(hover_zone.id) {
                                 self.help_id = Some(hover_zone.id);
                                 self.queue_redraw();
@@ -639,9 +1032,8 @@ impl WValuePlugUI {
                                 self.queue_redraw();
 
                             } else if self.is_mod_target_value(zone.id, hover_zone.id) {
-                                println!("****** MOD TARGET FOR {} FOUND: {}",
-                                         zone.id,
-                                         hover_zone.id);
+                                klog!(Gui, Debug, "mod target for {}: {}",
+                                      zone.id, hover_zone.id);
                                 self.set_element_value(zone.id, hover_zone.id as f32);
                                 self.controller.clone().value_change(
                                     self, zone.id, hover_zone.id as f32, true);
@@ -659,6 +1051,25 @@ impl WValuePlugUI {
                         // nop
                         return;
                     },
+                    InputMode::ContextMenu { zone, ref menu } => {
+                        let (x, y) = self.last_mouse_pos;
+                        match menu.entry_at(x, y).and_then(|idx| menu.entry(idx)) {
+                            Some((_, UIMenuState::Disabled)) => {
+                                // do not close the menu
+                                return;
+                            },
+                            Some((action, _)) => {
+                                self.input_mode = InputMode::None;
+                                self.do_menu_action(zone, action);
+                                self.queue_redraw();
+                                return;
+                            },
+                            None => {
+                                // a click outside closes the menu
+                                self.queue_redraw();
+                            },
+                        }
+                    },
                 }
 
                 //d// println!("BUTTON RELEASE: {:?} @{:?} / {:?}",
@@ -668,6 +1079,17 @@ impl WValuePlugUI {
                 self.drag_tmp_value = None;
             },
             UIEvent::KeyPressed(key_event) => {
+                let is_text_input =
+                    matches!(self.input_mode, InputMode::InputValue { .. });
+                if !is_text_input {
+                    if self.handle_focus_key(&key_event) {
+                        self.queue_redraw();
+                        return;
+                    }
+
+                    self.controller.clone().key_event(self, &key_event);
+                }
+
                 match key_event.key {
                     Key::Shift => {
                         self.handle_fine_drag_key(true);
@@ -737,6 +1159,8 @@ impl WValuePlugUI {
                 }
             },
             UIEvent::KeyReleased(key_event) => {
+                self.controller.clone().key_event(self, &key_event);
+
                 match key_event.key {
                     Key::Shift  => {
                         self.handle_fine_drag_key(false);
@@ -757,6 +1181,13 @@ impl WValuePlugUI {
                         self.queue_redraw();
                     },
                     Key::Escape => {
+                        // Without anything else to exit, the
+                        // keyboard focus is removed:
+                        if    self.help_id.is_none()
+                           && matches!(self.input_mode, InputMode::None) {
+                            self.focus_zone = None;
+                        }
+
                         self.input_mode = InputMode::None;
                         self.help_id    = None;
                         self.queue_redraw();
@@ -808,6 +1239,75 @@ impl WValuePlugUI {
         self.value_specs[id].fmt(self.get_element_value(id) as f64, writer)
     }
 
+    /// Formats the normalized value `v` of the element `id`
+    /// followed by the unit of its value spec.
+    fn format_with_unit(&self, id: usize, v: f64) -> String {
+        self.value_specs[id].fmt_with_unit(v)
+    }
+
+    /// The lines of the tooltip for the knob under the mouse:
+    /// The name of the parameter, its value, the default value
+    /// and while dragging the change since the drag started.
+    fn value_tooltip(&self) -> Option<Vec<String>> {
+        if self.help_id.is_some() {
+            return None;
+        }
+
+        let zone =
+            match self.input_mode {
+                InputMode::None                  => self.hover_zone?,
+                InputMode::ValueDrag { zone, .. } => zone,
+                _ => return None,
+            };
+
+        if    zone.subtype != (painting::AZ_COARSE_DRAG as usize)
+           && zone.subtype != (painting::AZ_FINE_DRAG as usize) {
+            return None;
+        }
+
+        let id = zone.id;
+        if id >= self.value_specs.len() {
+            return None;
+        }
+
+        let mut lines = vec![];
+
+        let name =
+            self.get_element_help(id)
+                .map(|(name, _)| name)
+                .unwrap_or_else(|| self.value_specs[id].get_help_name());
+        if !name.is_empty() {
+            lines.push(name.to_string());
+        }
+
+        lines.push(
+            self.format_with_unit(id, self.get_element_value(id) as f64));
+        lines.push(format!("Default: {}",
+            self.format_with_unit(id, self.get_element_default_value(id) as f64)));
+
+        if let InputMode::ValueDrag { .. } = self.input_mode {
+            let spec  = &self.value_specs[id];
+            let delta =
+                  spec.v2v(self.get_element_value(id) as f64)
+                - spec.v2v(self.element_values.get(id).copied().unwrap_or(0.0) as f64);
+
+            let mut buf : Vec<u8> = vec![];
+            spec.fmt_delta(delta, &mut buf);
+
+            let delta_str = String::from_utf8_lossy(&buf).trim().to_string();
+            let sign = if delta_str.starts_with('-') { "" } else { "+" };
+            let unit = spec.get_unit();
+            lines.push(
+                if unit.is_empty() {
+                    format!("Delta: {}{}", sign, delta_str)
+                } else {
+                    format!("Delta: {}{} {}", sign, delta_str, unit)
+                });
+        }
+
+        Some(lines)
+    }
+
     fn get_prev_toggle_value(&self, id: usize) -> f32 {
         let cur = self.get_element_value(id);
         self.value_specs[id].toggle_prev(cur)
@@ -896,7 +1396,10 @@ impl WValuePlugUI {
     }
 
     fn add_active_zone(&mut self, id: usize, mut az: ActiveZone) {
-        az.id = id;
+        // Graph handles carry the ids of the parameters they change:
+        if az.subtype != (painting::AZ_GRAPH_HANDLE as usize) {
+            az.id = id;
+        }
         self.zones.push(az);
     }
 
@@ -926,11 +1429,11 @@ impl WValuePlugUI {
 
         let id = element_data.value_id();
 
-        let mut zones : [Option<ActiveZone>; 4] = [None; 4];
+        let mut zones : [Option<ActiveZone>; 16] = [None; 16];
         let mut z_idx = 0;
 
         if false {
-            p.rect_stroke(1.0, (1.0, 0.0, 1.0), xe, ye, size.0, size.1);
+            p.rect_stroke(1.0, UIColor::rgb(1.0, 0.0, 1.0), xe, ye, size.0, size.1);
         }
 
         let az = self.cache.draw_bg(p, xe, ye, cache_idx as usize);
@@ -1145,7 +1648,7 @@ impl WValuePlugUI {
                     crect.h + 2.0 * UI_BORDER_WIDTH);
 
                 p.rect_fill(
-                    lighten_clr(depth, UI_GUI_BG_CLR),
+                    lighten_clr(depth, self.theme.color(UI_GUI_BG_CLR)),
                     crect.x,
                     crect.y,
                     crect.w,
@@ -1235,8 +1738,22 @@ impl WValuePlugUI {
                             let mut data_buf = graph_data.data.borrow_mut();
                             data_buf.clear();
                             (graph_data.fun)(graph_data.id, self, &mut data_buf);
+
+                            let mut handle_pos = graph_data.handle_pos.borrow_mut();
+                            handle_pos.clear();
+                            for handle in graph_data.handles.iter() {
+                                handle_pos.push((handle.pos)(self));
+                            }
                         }
 
+                        let hover_ids =
+                            self.hover_zone
+                                .filter(|z| z.subtype == (painting::AZ_GRAPH_HANDLE as usize))
+                                .map(|z| z.drag_ids);
+                        graph_data.hover_handle.set(
+                            graph_data.handles.iter().position(|h|
+                                Some((h.x_id, h.y_id)) == hover_ids));
+
                         self.draw_element(
                             p, &el_rect, pos.alignment(),
                             graph_data,
@@ -1246,6 +1763,40 @@ impl WValuePlugUI {
                                 _                      => ElementType::GraphHuge,
                             });
                     },
+                    UIInput::Scope(scope_data) => {
+                        {
+                            let mut data_buf = scope_data.data.borrow_mut();
+                            data_buf.clear();
+                            if let Some(data) = self.scope_data.get(scope_data.id) {
+                                data_buf.extend_from_slice(&data[..]);
+                            }
+                        }
+
+                        self.draw_element(
+                            p, &el_rect, pos.alignment(),
+                            scope_data,
+                            ElementType::Scope);
+                    },
+                    UIInput::Spectrum(spectrum_data) => {
+                        if let Some(data) = self.spectrum_data.get(spectrum_data.id) {
+                            spectrum_data.data.borrow_mut().clone_from(data);
+                        }
+
+                        self.draw_element(
+                            p, &el_rect, pos.alignment(),
+                            spectrum_data,
+                            ElementType::Spectrum);
+                    },
+                    UIInput::Meter(meter_data) => {
+                        if let Some(data) = self.meter_data.get(meter_data.id) {
+                            meter_data.data.borrow_mut().clone_from(data);
+                        }
+
+                        self.draw_element(
+                            p, &el_rect, pos.alignment(),
+                            meter_data,
+                            ElementType::Meter);
+                    },
                     UIInput::Label(_, font_size, label) => {
                         let crect = el_rect;
                         self.draw_text_lines(
@@ -1307,7 +1858,7 @@ impl WValuePlugUI {
     }
 
     pub fn draw_text_lines(&self, p: &mut dyn Painter, s: &str, font_size: f64,
-                           color: (f64, f64, f64),
+                           color: UIColor,
                            x: f64, mut y: f64, w: f64, h: f64,
                            mono: bool, title: Option<&str>) {
         let y_increment = p.font_height(font_size as f32, mono) as f64;
@@ -1340,6 +1891,11 @@ impl WValuePlugUI {
         }
     }
 
+    /// The zoom factor of the window, see `UIController::window_zoom`.
+    pub fn window_zoom(&self) -> f64 {
+        self.controller.window_zoom()
+    }
+
     pub fn window_is_active(&self) -> bool {
         let ctrl = self.controller.clone();
         ctrl.is_active()
@@ -1354,7 +1910,13 @@ impl WValuePlugUI {
         self.controller.clone().post_frame(self);
     }
 
+    /// Draws the GUI with the colours of the current `Theme`.
     pub fn draw(&mut self, p: &mut dyn Painter) {
+        let theme = self.theme.clone();
+        self.draw_themed(&mut ThemedPainter::new(p, &theme));
+    }
+
+    fn draw_themed(&mut self, p: &mut dyn Painter) {
         let (ww, wh) = self.window_size;
 
         p.rect_fill(UI_GUI_BG_CLR, 0.0, 0.0, ww, wh);
@@ -1385,6 +1947,14 @@ impl WValuePlugUI {
             }
         }
 
+        if let Some(idx) = self.focus_index() {
+            let z = self.zones[idx];
+            p.rect_stroke(
+                UI_FOCUS_WIDTH, UI_FOCUS_CLR,
+                z.x - UI_FOCUS_WIDTH, z.y - UI_FOCUS_WIDTH,
+                z.w + 2.0 * UI_FOCUS_WIDTH, z.h + 2.0 * UI_FOCUS_WIDTH);
+        }
+
         if let Some(help_id) = self.help_id {
             p.rect_fill(UI_GUI_BG_CLR, 0.0, 0.0, ww, wh);
             p.rect_stroke(
@@ -1405,7 +1975,10 @@ impl WValuePlugUI {
             }
         }
 
-        if let InputMode::InputValue { zone, value, input } = &self.input_mode {
+        if let InputMode::ContextMenu { menu, .. } = &self.input_mode {
+            menu.draw(p, self.last_mouse_pos);
+
+        } else if let InputMode::InputValue { zone, value, input } = &self.input_mode {
             let height = p.font_height(16.0, true) as f64;
 
             let zone_center =
@@ -1504,6 +2077,34 @@ impl WValuePlugUI {
                 p.label(UI_DRAG_INFO_FONT_SIZE, 0, UI_LBL_TXT_CLR,
                         x, y, UI_DRAG_INFO_W, UI_ELEM_TXT_H, lbl);
             }
+
+            if let Some(lines) = self.value_tooltip() {
+                let (mx, my) = self.last_mouse_pos;
+                let tip = Rect {
+                    x: mx + UI_TOOLTIP_OFFS,
+                    y: my + UI_TOOLTIP_OFFS,
+                    w: UI_TOOLTIP_W,
+                    h: lines.len() as f64 * UI_ELEM_TXT_H + 2.0 * UI_MARGIN,
+                }.move_inside(ww, wh);
+
+                p.rect_fill(UI_TOOLTIP_BG_CLR, tip.x, tip.y, tip.w, tip.h);
+                p.rect_stroke(
+                    UI_BORDER_WIDTH, UI_TOOLTIP_BORDER_CLR,
+                    tip.x + UI_BORDER_WIDTH / 2.0, tip.y + UI_BORDER_WIDTH / 2.0,
+                    tip.w - UI_BORDER_WIDTH, tip.h - UI_BORDER_WIDTH);
+
+                for (i, line) in lines.iter().enumerate() {
+                    let clr =
+                        if i == 0 { UI_TOOLTIP_NAME_CLR }
+                        else      { UI_TOOLTIP_TXT_CLR };
+                    p.label(
+                        UI_TOOLTIP_FONT_SIZE, -1, clr,
+                        tip.x + UI_MARGIN,
+                        tip.y + UI_MARGIN + i as f64 * UI_ELEM_TXT_H,
+                        tip.w - 2.0 * UI_MARGIN, UI_ELEM_TXT_H,
+                        line);
+                }
+            }
         }
 
         if self.version_label.len() > 0 {
@@ -4,11 +4,52 @@
 use crate::ui::constants::*;
 use crate::ui::draw_cache::DrawCache;
 
+/// The base colours of the GUI, that a `Theme` defines.
+/// Their names in the theme files are in `crate::ui::theme::PALETTE`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorRole {
+    Background,
+    Background3,
+    Text,
+    Border,
+    LabelBg,
+    Accent,
+    Primary,
+    Primary2,
+    Highlight,
+    Highlight2,
+    Inactive,
+    Inactive2,
+    Clip,
+}
+
+/// A colour for the `Painter`. The base colours carry their role, so
+/// that the `ThemedPainter` draws them in the colours of the theme.
+/// Colours without a role, like the lightened backgrounds, are drawn
+/// as they are.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct UIColor {
+    pub rgb:  (f64, f64, f64),
+    pub role: Option<ColorRole>,
+}
+
+impl UIColor {
+    pub const fn rgb(r: f64, g: f64, b: f64) -> Self {
+        Self { rgb: (r, g, b), role: None }
+    }
+
+    /// The base colour `role` with its colour `rgb` in the default theme.
+    pub const fn base(role: ColorRole, rgb: (f64, f64, f64)) -> Self {
+        Self { rgb, role: Some(role) }
+    }
+}
+
 pub const AZ_COARSE_DRAG : i8 = 0;
 pub const AZ_FINE_DRAG   : i8 = 1;
 pub const AZ_MOD_SELECT  : i8 = 2;
 pub const AZ_TOGGLE      : i8 = 3;
 pub const AZ_SET_VALUE   : i8 = 4;
+pub const AZ_GRAPH_HANDLE: i8 = 5;
 
 #[derive(Debug, Clone, Copy)]
 pub struct ActiveZone {
@@ -19,6 +60,9 @@ pub struct ActiveZone {
     pub y:       f64,
     pub w:       f64,
     pub h:       f64,
+    /// The parameters changed by dragging a `AZ_GRAPH_HANDLE`
+    /// horizontally and vertically.
+    pub drag_ids: (Option<usize>, Option<usize>),
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -40,6 +84,7 @@ impl ActiveZone {
             y: r.1 + yo,
             w: r.2,
             h: r.3,
+            drag_ids: (None, None),
         }
     }
 
@@ -50,12 +95,12 @@ impl ActiveZone {
 }
 
 pub trait Painter {
-    fn path_fill(&mut self, color: (f64, f64, f64), segments: &mut dyn std::iter::Iterator<Item = (f64, f64)>, closed: bool);
-    fn path_stroke(&mut self, width: f64, color: (f64, f64, f64), segments: &mut dyn std::iter::Iterator<Item = (f64, f64)>, closed: bool);
-    fn arc_stroke(&mut self, width: f64, color: (f64, f64, f64), radius: f64, from_rad: f64, to_rad: f64, x: f64, y: f64);
-    fn rect_fill(&mut self, color: (f64, f64, f64), x: f64, y: f64, w: f64, h: f64);
-    fn rect_stroke(&mut self, width: f64, color: (f64, f64, f64), x: f64, y: f64, w: f64, h: f64);
-    fn label(&mut self, size: f64, align: i8, color: (f64, f64, f64), x: f64, y: f64, w: f64, h: f64, text: &str);
-    fn label_mono(&mut self, size: f64, align: i8, color: (f64, f64, f64), x: f64, y: f64, w: f64, h: f64, text: &str);
+    fn path_fill(&mut self, color: UIColor, segments: &mut dyn std::iter::Iterator<Item = (f64, f64)>, closed: bool);
+    fn path_stroke(&mut self, width: f64, color: UIColor, segments: &mut dyn std::iter::Iterator<Item = (f64, f64)>, closed: bool);
+    fn arc_stroke(&mut self, width: f64, color: UIColor, radius: f64, from_rad: f64, to_rad: f64, x: f64, y: f64);
+    fn rect_fill(&mut self, color: UIColor, x: f64, y: f64, w: f64, h: f64);
+    fn rect_stroke(&mut self, width: f64, color: UIColor, x: f64, y: f64, w: f64, h: f64);
+    fn label(&mut self, size: f64, align: i8, color: UIColor, x: f64, y: f64, w: f64, h: f64, text: &str);
+    fn label_mono(&mut self, size: f64, align: i8, color: UIColor, x: f64, y: f64, w: f64, h: f64, text: &str);
     fn font_height(&mut self, size: f32, mono: bool) -> f32;
 }
@@ -23,6 +23,7 @@ pub struct UIValueSpec {
     default:        f64,
     help_name:      String,
     help_text:      String,
+    unit:           String,
 }
 
 impl std::fmt::Debug for UIValueSpec {
@@ -57,6 +58,7 @@ impl UIValueSpec {
             default:     0.0,
             help_name:   "".to_string(),
             help_text:   "".to_string(),
+            unit:        "".to_string(),
         }
     }
 
@@ -76,6 +78,7 @@ impl UIValueSpec {
             default:     0.0,
             help_name:   "".to_string(),
             help_text:   "".to_string(),
+            unit:        "".to_string(),
         }
     }
 
@@ -85,6 +88,12 @@ impl UIValueSpec {
         self
     }
 
+    /// The unit of the value, shown after it in the tooltip.
+    pub fn unit(mut self, unit: &str) -> Self {
+        self.unit = unit.to_string();
+        self
+    }
+
     pub fn new_toggle(targets: &[&str]) -> Self {
         let num_targets = targets.len() as f64;
         let increment   = 1.0 / num_targets;
@@ -112,7 +121,9 @@ impl UIValueSpec {
                 }
             }),
             fmt: Arc::new(move |v, _, writer| {
-                let mut idx : usize = (v * num_targets).floor() as usize;
+                // Hosts can set the maximum 1.0, which is the last target:
+                let mut idx : usize =
+                    (v * num_targets).floor().min(num_targets - 1.0) as usize;
                 if idx >= strings.len() {
                     write!(writer, "?").is_ok()
                 } else {
@@ -126,6 +137,7 @@ impl UIValueSpec {
             default:     increment * 0.5,
             help_name:   "".to_string(),
             help_text:   "".to_string(),
+            unit:        "".to_string(),
         }
     }
 
@@ -137,6 +149,10 @@ impl UIValueSpec {
         (self.fun)(-1.0 * v as f64) as f32
     }
 
+    pub fn get_help_name(&self) -> &str { &self.help_name }
+
+    pub fn get_unit(&self) -> &str { &self.unit }
+
     pub fn get_help_tuple(&self) -> Option<(String, String)> {
         if self.help_text.len() > 0 {
             Some((self.help_name.clone(), self.help_text.clone()))
@@ -184,6 +200,7 @@ impl UIValueSpec {
             default:    -1.0,
             help_name:   "".to_string(),
             help_text:   "".to_string(),
+            unit:        "".to_string(),
         }
     }
 
@@ -209,6 +226,7 @@ impl UIValueSpec {
             default:     0.0,
             help_name:   "".to_string(),
             help_text:   "".to_string(),
+            unit:        "".to_string(),
         }
     }
 
@@ -234,6 +252,7 @@ impl UIValueSpec {
             default:     0.0,
             help_name:   "".to_string(),
             help_text:   "".to_string(),
+            unit:        "".to_string(),
         }
     }
 
@@ -255,6 +274,7 @@ impl UIValueSpec {
             default:     0.0,
             help_name:   "".to_string(),
             help_text:   "".to_string(),
+            unit:        "".to_string(),
         }
     }
 
@@ -293,6 +313,7 @@ impl UIValueSpec {
             default:     0.0,
             help_name:   "".to_string(),
             help_text:   "".to_string(),
+            unit:        "".to_string(),
         }
     }
 
@@ -302,6 +323,21 @@ impl UIValueSpec {
     pub fn parse(&self, s: &str) -> Option<f64> { (self.parse)(s) }
     pub fn get_default(&self) -> f64        { self.default }
     pub fn fmt(&self, x: f64, writer: &mut std::io::Write) -> bool { (self.fmt)(x, self.v2v(x), writer) }
+    /// Formats the normalized value `x` followed by the unit, like
+    /// the tooltips of the editor and the value texts for the hosts.
+    pub fn fmt_with_unit(&self, x: f64) -> String {
+        let mut buf : Vec<u8> = vec![];
+        self.fmt(x, &mut buf);
+
+        let value = String::from_utf8_lossy(&buf).trim().to_string();
+        if self.unit.is_empty() {
+            value
+        } else {
+            format!("{} {}", value, self.unit)
+        }
+    }
+    /// Formats the difference of two (not normalized) values like the values.
+    pub fn fmt_delta(&self, delta: f64, writer: &mut std::io::Write) -> bool { (self.fmt)(0.0, delta, writer) }
     pub fn is_active(&self, id: usize, valsrc: &dyn UIValueSource) -> bool { (self.active)(id, valsrc) }
 }
 
@@ -363,6 +399,25 @@ pub trait UIValueSource {
     fn param_value_denorm(&self, idx: usize) -> f64;
 }
 
+/// A breakpoint of a graph, that can be dragged with the mouse.
+/// Dragging it horizontally changes the parameter `x_id`,
+/// dragging it vertically changes the parameter `y_id`.
+#[derive(Clone)]
+pub struct UIGraphHandle {
+    pub x_id:   Option<usize>,
+    pub y_id:   Option<usize>,
+    /// Calculates the position of the handle in the coordinates
+    /// of the graph data (0.0 to 1.0). `None` hides the handle.
+    pub pos:    Arc<dyn Fn(&mut dyn UIValueSource) -> Option<(f64, f64)> + Send + Sync>,
+}
+
+impl UIGraphHandle {
+    pub fn new(x_id: Option<usize>, y_id: Option<usize>,
+               pos: Arc<dyn Fn(&mut dyn UIValueSource) -> Option<(f64, f64)> + Send + Sync>) -> Self {
+        Self { x_id, y_id, pos }
+    }
+}
+
 #[derive(Clone)]
 pub struct UIGraphData {
     pub pos:         UIPos,
@@ -370,6 +425,11 @@ pub struct UIGraphData {
     pub label:       String,
     pub data:        Box<std::cell::RefCell<Vec<(f64,f64)>>>,
     pub fun:         Arc<dyn Fn(usize, &mut dyn UIValueSource, &mut Vec<(f64,f64)>) + Send + Sync>,
+    pub handles:     Vec<UIGraphHandle>,
+    /// The positions of the `handles`, updated before drawing.
+    pub handle_pos:  Box<std::cell::RefCell<Vec<Option<(f64,f64)>>>>,
+    /// The index of the handle under the mouse.
+    pub hover_handle: std::cell::Cell<Option<usize>>,
 }
 
 impl std::fmt::Debug for UIGraphData {
@@ -386,6 +446,9 @@ impl UIGraphData {
             pos,
             data: Box::new(std::cell::RefCell::new(vec![])),
             fun,
+            handles:      vec![],
+            handle_pos:   Box::new(std::cell::RefCell::new(vec![])),
+            hover_handle: std::cell::Cell::new(None),
         }
     }
 }
@@ -395,6 +458,81 @@ impl UIElementData for UIGraphData {
     fn value_id(&self) -> usize { self.id }
 }
 
+/// An oscilloscope, that shows the min/max pairs set with
+/// `UI::set_scope_data` for its `id`.
+#[derive(Debug, Clone)]
+pub struct UIScopeData {
+    pub pos:         UIPos,
+    pub id:          usize,
+    pub label:       String,
+    pub data:        Box<std::cell::RefCell<Vec<(f32,f32)>>>,
+}
+
+impl UIElementData for UIScopeData {
+    fn as_scope_data(&self) -> Option<&UIScopeData> { Some(self) }
+    fn value_id(&self) -> usize { self.id }
+}
+
+/// The levels of a spectrum analyzer, as set with
+/// `UI::set_spectrum_data`.
+#[derive(Debug, Clone, Default)]
+pub struct UISpectrum {
+    /// The levels of the bands from 0.0 (bottom) to 1.0 (top).
+    pub levels:      Vec<f32>,
+    /// The peak hold of the bands from 0.0 to 1.0.
+    pub peaks:       Vec<f32>,
+    /// The X positions (0.0 to 1.0) of the frequency grid lines.
+    pub grid:        Vec<f32>,
+    /// The X position (0.0 to 1.0) and the note name of the fundamental.
+    pub fundamental: Option<(f32, String)>,
+}
+
+/// A spectrum analyzer, that shows the `UISpectrum` set with
+/// `UI::set_spectrum_data` for its `id`.
+#[derive(Debug, Clone)]
+pub struct UISpectrumData {
+    pub pos:         UIPos,
+    pub id:          usize,
+    pub label:       String,
+    pub data:        Box<std::cell::RefCell<UISpectrum>>,
+}
+
+impl UIElementData for UISpectrumData {
+    fn as_spectrum_data(&self) -> Option<&UISpectrumData> { Some(self) }
+    fn value_id(&self) -> usize { self.id }
+}
+
+/// The levels of a level meter, as set with `UI::set_meter_data`.
+#[derive(Debug, Clone, Default)]
+pub struct UIMeter {
+    /// The peak level from 0.0 (bottom) to 1.0 (top).
+    pub peak:        f32,
+    /// The RMS level from 0.0 to 1.0.
+    pub rms:         f32,
+    /// The held peak level from 0.0 to 1.0.
+    pub hold:        f32,
+    /// Lights the clip indicator.
+    pub clip:        bool,
+    /// The Y positions (0.0 to 1.0) of the scale marks.
+    pub grid:        Vec<f32>,
+}
+
+/// A level meter, that shows the `UIMeter` set with
+/// `UI::set_meter_data` for its `id`. The `id` is a value id too,
+/// it is set to 1.0 when the meter is clicked.
+#[derive(Debug, Clone)]
+pub struct UIMeterData {
+    pub pos:         UIPos,
+    pub id:          usize,
+    pub label:       String,
+    pub data:        Box<std::cell::RefCell<UIMeter>>,
+}
+
+impl UIElementData for UIMeterData {
+    fn as_meter_data(&self) -> Option<&UIMeterData> { Some(self) }
+    fn value_id(&self) -> usize { self.id }
+}
+
 #[derive(Debug, Clone)]
 pub struct UITabData {
     pub pos:         UIPos,
@@ -418,6 +556,9 @@ pub enum UIInput {
     Graph(UIGraphData),
     GraphHuge(UIGraphData),
     GraphSmall(UIGraphData),
+    Scope(UIScopeData),
+    Spectrum(UISpectrumData),
+    Meter(UIMeterData),
 }
 
 impl UIInput {
@@ -438,6 +579,9 @@ impl UIInput {
             UIInput::Graph(UIGraphData { pos, .. })      => *pos,
             UIInput::GraphHuge(UIGraphData { pos, .. })  => *pos,
             UIInput::GraphSmall(UIGraphData { pos, .. }) => *pos,
+            UIInput::Scope(UIScopeData { pos, .. })      => *pos,
+            UIInput::Spectrum(UISpectrumData { pos, .. }) => *pos,
+            UIInput::Meter(UIMeterData { pos, .. })      => *pos,
         }
     }
 
@@ -513,6 +657,45 @@ impl UIInput {
         UIInput::GraphSmall(UIGraphData::new(id, label, pos, fun))
     }
 
+    /// Adds draggable breakpoints to a graph. Other inputs
+    /// are returned unchanged.
+    pub fn with_handles(mut self, handles: Vec<UIGraphHandle>) -> Self {
+        match &mut self {
+            UIInput::Graph(data)
+            | UIInput::GraphHuge(data)
+            | UIInput::GraphSmall(data) => { data.handles = handles; },
+            _ => (),
+        }
+        self
+    }
+
+    pub fn scope(id: usize, label: String, pos: UIPos) -> Self {
+        UIInput::Scope(UIScopeData {
+            id,
+            label,
+            pos,
+            data: Box::new(std::cell::RefCell::new(vec![])),
+        })
+    }
+
+    pub fn spectrum(id: usize, label: String, pos: UIPos) -> Self {
+        UIInput::Spectrum(UISpectrumData {
+            id,
+            label,
+            pos,
+            data: Box::new(std::cell::RefCell::new(UISpectrum::default())),
+        })
+    }
+
+    pub fn meter(id: usize, label: String, pos: UIPos) -> Self {
+        UIInput::Meter(UIMeterData {
+            id,
+            label,
+            pos,
+            data: Box::new(std::cell::RefCell::new(UIMeter::default())),
+        })
+    }
+
     pub fn knob_small(id: usize, label: String, pos: UIPos) -> Self {
         UIInput::KnobSmall(UIKnobData { id, label, pos })
     }
@@ -542,12 +725,73 @@ pub enum UILayout {
     },
 }
 
+/// The entries of the context menu of a knob,
+/// see `UIController::menu_action`.
+///
+/// `CopyValue` and `PasteValue` use a clipboard inside of the
+/// plugin GUI, not the clipboard of the system. The copied value
+/// can only be pasted into the knobs of the same GUI.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UIMenuAction {
+    TypeValue,
+    ResetDefault,
+    CopyValue,
+    PasteValue,
+    MidiLearn,
+    ShowAutomation,
+    LockRandomize,
+    Randomize,
+}
+
+impl UIMenuAction {
+    /// The entries in the order of the menu.
+    pub const ALL : [UIMenuAction; 8] = [
+        UIMenuAction::TypeValue,
+        UIMenuAction::ResetDefault,
+        UIMenuAction::CopyValue,
+        UIMenuAction::PasteValue,
+        UIMenuAction::MidiLearn,
+        UIMenuAction::ShowAutomation,
+        UIMenuAction::LockRandomize,
+        UIMenuAction::Randomize,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            UIMenuAction::TypeValue      => "Type Value...",
+            UIMenuAction::ResetDefault   => "Reset to Default",
+            UIMenuAction::CopyValue      => "Copy Value (Internal)",
+            UIMenuAction::PasteValue     => "Paste Value (Internal)",
+            UIMenuAction::MidiLearn      => "MIDI Learn",
+            UIMenuAction::ShowAutomation => "Show Automation Lane",
+            UIMenuAction::LockRandomize  => "Lock from Randomize",
+            UIMenuAction::Randomize      => "Randomize Unlocked",
+        }
+    }
+}
+
+/// How an entry of the context menu is shown.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UIMenuState {
+    Enabled,
+    Disabled,
+    /// Enabled, with a check mark.
+    Checked,
+}
+
 pub trait UI {
     fn define_layout(&mut self, layout: Vec<UILayout>);
     fn define_value_spec(&mut self, spec: Vec<UIValueSpec>);
     fn set_values(&mut self, vals: &[UIInputValue]);
     fn set_default_values(&mut self);
     fn set_version(&mut self, version_label: &'static str);
+    /// Sets the min/max pairs of the samples, that the
+    /// oscilloscope `id` shows.
+    fn set_scope_data(&mut self, id: usize, data: &[(f32, f32)]);
+    /// Sets the levels, that the spectrum analyzer `id` shows.
+    fn set_spectrum_data(&mut self, id: usize, data: &UISpectrum);
+    /// Sets the levels, that the level meter `id` shows.
+    fn set_meter_data(&mut self, id: usize, data: &UIMeter);
     fn key_pressed(&mut self, kev: KeyboardEvent);
     fn key_released(&mut self, kev: KeyboardEvent);
 }
@@ -561,4 +805,26 @@ pub trait UIController : Send + Sync {
     fn pre_frame(&self, ui: &mut dyn UI) {}
     fn post_frame(&self, ui: &mut dyn UI) {}
     fn is_active(&self) -> bool { true }
+    /// The zoom factor of the window. The layout is done for the
+    /// window size divided by it, and then drawn scaled up by it.
+    fn window_zoom(&self) -> f64 { 1.0 }
+    /// Receives all key releases and the key presses, that are
+    /// not typed into a value input field.
+    fn key_event(&self, ui: &mut dyn UI, kev: &KeyboardEvent) {}
+    /// The state of the context menu entry `action` of the value `id`.
+    /// Only the entries, that the UI can do by itself, are enabled
+    /// by default.
+    fn menu_entry_state(&self, id: usize, action: UIMenuAction) -> UIMenuState {
+        match action {
+            UIMenuAction::MidiLearn
+            | UIMenuAction::ShowAutomation
+            | UIMenuAction::LockRandomize
+            | UIMenuAction::Randomize     => UIMenuState::Disabled,
+            _                             => UIMenuState::Enabled,
+        }
+    }
+    /// Called when the context menu entry `action` of the value `id`
+    /// was chosen. Returns false if the UI should handle the entry,
+    /// which it does for typing, resetting, copying and pasting the value.
+    fn menu_action(&self, ui: &mut dyn UI, id: usize, action: UIMenuAction) -> bool { false }
 }
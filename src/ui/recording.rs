@@ -0,0 +1,182 @@
+// Copyright (c) 2020-2021 Weird Constructor <weirdconstructor@gmail.com>
+// This is a part of Kickmess. See README.md and COPYING for details.
+
+//! A headless `Painter`, that records the draw calls into a display list.
+//!
+//! It needs no GPU and no window, which makes it possible to drive
+//! `WValuePlugUI::handle_ui_event` with synthetic events in tests and
+//! to compare the drawn result with a stored snapshot.
+
+use crate::ui::painting::{Painter, UIColor};
+
+/// Height of a line of text relative to the font size. `font_height()` of
+/// the real painter depends on the font metrics, this approximates it
+/// without loading a font.
+const FONT_HEIGHT_FACTOR : f64 = 1.2;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum DrawCmd {
+    PathFill    { color: (f64, f64, f64), points: Vec<(f64, f64)>, closed: bool },
+    PathStroke  { width: f64, color: (f64, f64, f64), points: Vec<(f64, f64)>, closed: bool },
+    ArcStroke   { width: f64, color: (f64, f64, f64), radius: f64,
+                  from_rad: f64, to_rad: f64, x: f64, y: f64 },
+    RectFill    { color: (f64, f64, f64), x: f64, y: f64, w: f64, h: f64 },
+    RectStroke  { width: f64, color: (f64, f64, f64), x: f64, y: f64, w: f64, h: f64 },
+    Label       { size: f64, align: i8, color: (f64, f64, f64),
+                  x: f64, y: f64, w: f64, h: f64, text: String, mono: bool },
+}
+
+fn write_clr(out: &mut String, clr: (f64, f64, f64)) {
+    out.push_str(&format!(" clr={:.3},{:.3},{:.3}", clr.0, clr.1, clr.2));
+}
+
+fn write_points(out: &mut String, points: &[(f64, f64)], closed: bool) {
+    out.push_str(if closed { " closed" } else { " open" });
+    for (x, y) in points.iter() {
+        out.push_str(&format!(" {:.1},{:.1}", x, y));
+    }
+}
+
+impl DrawCmd {
+    /// Writes the command as one line of text. The numbers are rounded,
+    /// so that tiny floating point differences don't change the line.
+    pub fn write_line(&self, out: &mut String) {
+        match self {
+            DrawCmd::PathFill { color, points, closed } => {
+                out.push_str("path_fill");
+                write_clr(out, *color);
+                write_points(out, points, *closed);
+            },
+            DrawCmd::PathStroke { width, color, points, closed } => {
+                out.push_str(&format!("path_stroke w={:.1}", width));
+                write_clr(out, *color);
+                write_points(out, points, *closed);
+            },
+            DrawCmd::ArcStroke { width, color, radius, from_rad, to_rad, x, y } => {
+                out.push_str(&format!("arc_stroke w={:.1}", width));
+                write_clr(out, *color);
+                out.push_str(&format!(
+                    " r={:.1} from={:.3} to={:.3} at={:.1},{:.1}",
+                    radius, from_rad, to_rad, x, y));
+            },
+            DrawCmd::RectFill { color, x, y, w, h } => {
+                out.push_str("rect_fill");
+                write_clr(out, *color);
+                out.push_str(&format!(" {:.1},{:.1} {:.1}x{:.1}", x, y, w, h));
+            },
+            DrawCmd::RectStroke { width, color, x, y, w, h } => {
+                out.push_str(&format!("rect_stroke w={:.1}", width));
+                write_clr(out, *color);
+                out.push_str(&format!(" {:.1},{:.1} {:.1}x{:.1}", x, y, w, h));
+            },
+            DrawCmd::Label { size, align, color, x, y, w, h, text, mono } => {
+                out.push_str(if *mono { "label_mono" } else { "label" });
+                out.push_str(&format!(" size={:.1} align={}", size, align));
+                write_clr(out, *color);
+                out.push_str(&format!(
+                    " {:.1},{:.1} {:.1}x{:.1} {:?}", x, y, w, h, text));
+            },
+        }
+    }
+}
+
+/// Records all draw calls of a frame, see also `DrawCmd`.
+#[derive(Debug, Clone, Default)]
+pub struct RecordingPainter {
+    cmds: Vec<DrawCmd>,
+}
+
+impl RecordingPainter {
+    pub fn new() -> Self {
+        Self { cmds: vec![] }
+    }
+
+    /// Forgets the recorded commands, call this before drawing a new frame.
+    pub fn clear(&mut self) {
+        self.cmds.clear();
+    }
+
+    pub fn commands(&self) -> &[DrawCmd] {
+        &self.cmds[..]
+    }
+
+    /// The display list as text with one command per line.
+    /// Suitable for storing as snapshot and for diffing.
+    pub fn display_list(&self) -> String {
+        let mut out = String::new();
+        for cmd in self.cmds.iter() {
+            cmd.write_line(&mut out);
+            out.push('\n');
+        }
+        out
+    }
+
+    /// All drawn text labels in drawing order.
+    pub fn labels(&self) -> Vec<&str> {
+        self.cmds.iter().filter_map(|cmd| {
+            if let DrawCmd::Label { text, .. } = cmd {
+                Some(&text[..])
+            } else {
+                None
+            }
+        }).collect()
+    }
+
+    /// Returns the rectangle `(x, y, w, h)` of the last drawn label,
+    /// that matches `text` exactly.
+    pub fn find_label(&self, text: &str) -> Option<(f64, f64, f64, f64)> {
+        self.cmds.iter().rev().find_map(|cmd| {
+            match cmd {
+                DrawCmd::Label { x, y, w, h, text: t, .. } if t == text =>
+                    Some((*x, *y, *w, *h)),
+                _ => None,
+            }
+        })
+    }
+}
+
+impl Painter for RecordingPainter {
+    fn path_fill(&mut self, color: UIColor, segments: &mut dyn std::iter::Iterator<Item = (f64, f64)>, closed: bool) {
+        self.cmds.push(DrawCmd::PathFill {
+            color: color.rgb, points: segments.collect(), closed,
+        });
+    }
+
+    fn path_stroke(&mut self, width: f64, color: UIColor, segments: &mut dyn std::iter::Iterator<Item = (f64, f64)>, closed: bool) {
+        self.cmds.push(DrawCmd::PathStroke {
+            width, color: color.rgb, points: segments.collect(), closed,
+        });
+    }
+
+    fn arc_stroke(&mut self, width: f64, color: UIColor, radius: f64, from_rad: f64, to_rad: f64, x: f64, y: f64) {
+        self.cmds.push(DrawCmd::ArcStroke {
+            width, color: color.rgb, radius, from_rad, to_rad, x, y,
+        });
+    }
+
+    fn rect_fill(&mut self, color: UIColor, x: f64, y: f64, w: f64, h: f64) {
+        self.cmds.push(DrawCmd::RectFill { color: color.rgb, x, y, w, h });
+    }
+
+    fn rect_stroke(&mut self, width: f64, color: UIColor, x: f64, y: f64, w: f64, h: f64) {
+        self.cmds.push(DrawCmd::RectStroke { width, color: color.rgb, x, y, w, h });
+    }
+
+    fn label(&mut self, size: f64, align: i8, color: UIColor, x: f64, y: f64, w: f64, h: f64, text: &str) {
+        self.cmds.push(DrawCmd::Label {
+            size, align, color: color.rgb, x, y, w, h,
+            text: text.to_string(), mono: false,
+        });
+    }
+
+    fn label_mono(&mut self, size: f64, align: i8, color: UIColor, x: f64, y: f64, w: f64, h: f64, text: &str) {
+        self.cmds.push(DrawCmd::Label {
+            size, align, color: color.rgb, x, y, w, h,
+            text: text.to_string(), mono: true,
+        });
+    }
+
+    fn font_height(&mut self, size: f32, _mono: bool) -> f32 {
+        (size as f64 * FONT_HEIGHT_FACTOR) as f32
+    }
+}
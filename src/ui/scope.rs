@@ -0,0 +1,88 @@
+// Copyright (c) 2020-2021 Weird Constructor <weirdconstructor@gmail.com>
+// This is a part of Kickmess. See README.md and COPYING for details.
+
+use crate::ui::painting::*;
+use crate::ui::element::{UIElement, UIElementData};
+use crate::ui::constants::*;
+
+/// Draws the min/max pairs of a recorded hit as filled waveform.
+pub struct Scope {
+    w:          f64,
+    h:          f64,
+    font_size:  f64,
+}
+
+impl Scope {
+    pub fn new(w: f64, h: f64, font_size: f64) -> Self {
+        Self {
+            w,
+            h: h + UI_ELEM_TXT_H,
+            font_size,
+        }
+    }
+}
+
+impl UIElement for Scope {
+    fn size(&self) -> (f64, f64) {
+        (self.w, self.h)
+    }
+
+    fn define_active_zones(&self, _x: f64, _y: f64,
+                           _elem_data: &dyn UIElementData,
+                           _f: &mut dyn FnMut(ActiveZone)) {
+    }
+
+    fn draw_value(&self, p: &mut dyn Painter, x: f64, y: f64,
+                  _highlight: HLStyle, data: &dyn UIElementData,
+                  _value: f64, _val_s: &str) {
+
+        let (w, h) = self.size();
+        let h = h - UI_ELEM_TXT_H;
+        let w = w - 2.0 * UI_GRPH_BORDER;
+        let h = h - 2.0 * UI_GRPH_BORDER;
+        let (xo, yo) = (x + UI_GRPH_BORDER, y + UI_GRPH_BORDER);
+
+        let scope  = data.as_scope_data().unwrap();
+        let points = scope.data.borrow();
+
+        let mid_y = (yo + h * 0.5).round() + 0.5;
+        p.path_stroke(
+            1.0, UI_SCOPE_AXIS_CLR,
+            &mut [(xo, mid_y), (xo + w, mid_y)].iter().copied(),
+            false);
+
+        if points.len() > 1 {
+            let x_step = w / ((points.len() - 1) as f64);
+            let amp_y  = |v: f32| mid_y - (v.clamp(-1.0, 1.0) as f64) * h * 0.5;
+
+            // The upper edge of the waveform forward and the
+            // lower edge backward give its outline:
+            let outline =
+                points.iter().enumerate()
+                    .map(|(i, pt)| (xo + i as f64 * x_step, amp_y(pt.1)))
+                    .chain(
+                        points.iter().enumerate().rev()
+                            .map(|(i, pt)| (xo + i as f64 * x_step, amp_y(pt.0))));
+
+            p.path_fill(UI_SCOPE_WAVE_CLR, &mut outline.clone(), true);
+            p.path_stroke(1.0, UI_BTN_TXT_CLR, &mut outline.clone(), true);
+        }
+
+        p.label(
+            self.font_size, 0, UI_BTN_TXT_CLR,
+            x, y + self.size().1 - UI_ELEM_TXT_H,
+            w, UI_ELEM_TXT_H, &scope.label);
+    }
+
+    fn draw_bg(&self, p: &mut dyn Painter, x: f64, y: f64) {
+        let (w, h) = self.size();
+        let h = h - UI_ELEM_TXT_H;
+
+        p.rect_fill(UI_BTN_BG_CLR, x, y, w, h + UI_ELEM_TXT_H);
+
+        let mid_border = UI_GRPH_BORDER / 2.0;
+        p.rect_stroke(
+            UI_GRPH_BORDER, UI_GRPH_BORDER_CLR,
+            x + mid_border, y + mid_border, w - UI_GRPH_BORDER, h - UI_GRPH_BORDER);
+    }
+}
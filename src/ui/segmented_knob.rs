@@ -280,7 +280,7 @@ impl SegmentedKnob {
             r.3, s);
     }
 
-    pub fn draw_oct_arc(&self, p: &mut dyn Painter, x: f64, y: f64, line_w: f64, color: (f64, f64, f64), with_dot: bool, value: f64) {
+    pub fn draw_oct_arc(&self, p: &mut dyn Painter, x: f64, y: f64, line_w: f64, color: UIColor, with_dot: bool, value: f64) {
         let arc_len = &self.arc_len;
 
         let (next_idx, segment_len, prev_arc_len) =
@@ -0,0 +1,115 @@
+// Copyright (c) 2020-2021 Weird Constructor <weirdconstructor@gmail.com>
+// This is a part of Kickmess. See README.md and COPYING for details.
+
+use crate::ui::painting::*;
+use crate::ui::element::{UIElement, UIElementData};
+use crate::ui::constants::*;
+
+/// Draws the band levels of a spectrum analyzer with their peak hold
+/// and a marker for the fundamental.
+pub struct Spectrum {
+    w:          f64,
+    h:          f64,
+    font_size:  f64,
+}
+
+impl Spectrum {
+    pub fn new(w: f64, h: f64, font_size: f64) -> Self {
+        Self {
+            w,
+            h: h + UI_ELEM_TXT_H,
+            font_size,
+        }
+    }
+}
+
+impl UIElement for Spectrum {
+    fn size(&self) -> (f64, f64) {
+        (self.w, self.h)
+    }
+
+    fn define_active_zones(&self, _x: f64, _y: f64,
+                           _elem_data: &dyn UIElementData,
+                           _f: &mut dyn FnMut(ActiveZone)) {
+    }
+
+    fn draw_value(&self, p: &mut dyn Painter, x: f64, y: f64,
+                  _highlight: HLStyle, data: &dyn UIElementData,
+                  _value: f64, _val_s: &str) {
+
+        let (w, h) = self.size();
+        let h = h - UI_ELEM_TXT_H;
+        let w = w - 2.0 * UI_GRPH_BORDER;
+        let h = h - 2.0 * UI_GRPH_BORDER;
+        let (xo, yo) = (x + UI_GRPH_BORDER, y + UI_GRPH_BORDER);
+
+        let spec  = data.as_spectrum_data().unwrap();
+        let bands = spec.data.borrow();
+
+        for gx in bands.grid.iter() {
+            let gx = (xo + *gx as f64 * w).round() + 0.5;
+            p.path_stroke(
+                1.0, UI_SPECTRUM_GRID_CLR,
+                &mut [(gx, yo), (gx, yo + h)].iter().copied(),
+                false);
+        }
+
+        let band_y = |v: f32| yo + h - (v.clamp(0.0, 1.0) as f64) * h;
+
+        if bands.levels.len() > 1 {
+            let x_step = w / ((bands.levels.len() - 1) as f64);
+
+            let floor = [(xo + w, yo + h), (xo, yo + h)];
+            p.path_fill(
+                UI_SPECTRUM_LVL_CLR,
+                &mut bands.levels.iter().enumerate()
+                    .map(|(i, l)| (xo + i as f64 * x_step, band_y(*l)))
+                    .chain(floor.iter().copied()),
+                true);
+
+            let x_step = w / ((bands.peaks.len().max(2) - 1) as f64);
+            p.path_stroke(
+                1.0, UI_SPECTRUM_PEAK_CLR,
+                &mut bands.peaks.iter().enumerate()
+                    .map(|(i, l)| (xo + i as f64 * x_step, band_y(*l))),
+                false);
+        }
+
+        if let Some((fx, note)) = &bands.fundamental {
+            let fx = (xo + *fx as f64 * w).round() + 0.5;
+            p.path_stroke(
+                1.0, UI_SPECTRUM_FUND_CLR,
+                &mut [(fx, yo), (fx, yo + h)].iter().copied(),
+                false);
+
+            // The note name is put on the side of the marker with more space:
+            let lbl_w = w * 0.5;
+            if fx < xo + w * 0.5 {
+                p.label(
+                    self.font_size, -1, UI_SPECTRUM_FUND_CLR,
+                    fx + 3.0, yo, lbl_w, UI_ELEM_TXT_H, note);
+            } else {
+                p.label(
+                    self.font_size, 1, UI_SPECTRUM_FUND_CLR,
+                    fx - 3.0 - lbl_w, yo, lbl_w, UI_ELEM_TXT_H, note);
+            }
+        }
+
+        p.label(
+            self.font_size, 0, UI_BTN_TXT_CLR,
+            x, y + self.size().1 - UI_ELEM_TXT_H,
+            w, UI_ELEM_TXT_H, &spec.label);
+    }
+
+    fn draw_bg(&self, p: &mut dyn Painter, x: f64, y: f64) {
+        let (w, h) = self.size();
+        let h = h - UI_ELEM_TXT_H;
+
+        p.rect_fill(UI_BTN_BG_CLR, x, y, w, h + UI_ELEM_TXT_H);
+
+        let mid_border = UI_GRPH_BORDER / 2.0;
+        p.rect_stroke(
+            UI_GRPH_BORDER, UI_GRPH_BORDER_CLR,
+            x + mid_border, y + mid_border, w - UI_GRPH_BORDER, h - UI_GRPH_BORDER);
+    }
+}
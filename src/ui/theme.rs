@@ -0,0 +1,334 @@
+// Copyright (c) 2020-2021 Weird Constructor <weirdconstructor@gmail.com>
+// This is a part of Kickmess. See README.md and COPYING for details.
+
+//! Colour themes of the GUI.
+//!
+//! The elements draw with the palette colours of `ui::constants`, which
+//! all derive from the few base colours in `PALETTE`. These carry their
+//! `ColorRole`, and the `ThemedPainter` replaces them by the colour of
+//! that role in a `Theme` on the way to the real painter, so the elements
+//! don't need to know about themes.
+//!
+//! A theme is loaded from a TOML file like this, missing colours are
+//! taken from the `base` theme:
+//!
+//! ```toml
+//! base = "high-contrast"
+//!
+//! [colors]
+//! primary   = "#00ffff"
+//! highlight = "#ffff00"
+//! ```
+
+use crate::ui::constants::*;
+use crate::ui::painting::{Painter, UIColor};
+use std::path::{Path, PathBuf};
+
+pub type Color = (f64, f64, f64);
+
+/// The names of the base colours in a theme file
+/// and their colours in the default theme.
+pub const PALETTE : [(&str, UIColor); 13] = [
+    ("background",  UI_BG_CLR),
+    ("background3", UI_BG3_CLR),
+    ("text",        UI_TXT_CLR),
+    ("border",      UI_BORDER_CLR),
+    ("label_bg",    UI_LBL_BG_CLR),
+    ("accent",      UI_ACCENT_CLR),
+    ("primary",     UI_PRIM_CLR),
+    ("primary2",    UI_PRIM2_CLR),
+    ("highlight",   UI_HLIGHT_CLR),
+    ("highlight2",  UI_HLIGHT2_CLR),
+    ("inactive",    UI_INACTIVE_CLR),
+    ("inactive2",   UI_INACTIVE2_CLR),
+    ("clip",        UI_METER_CLIP_CLR),
+];
+
+/// The names of the built-in themes.
+pub const BUILTIN_THEMES : [&str; 2] = ["default", "high-contrast"];
+
+fn rgb(r: u8, g: u8, b: u8) -> Color {
+    (r as f64 / 255.0, g as f64 / 255.0, b as f64 / 255.0)
+}
+
+/// Parses a colour in the form `#rrggbb`.
+fn parse_color(s: &str) -> Option<Color> {
+    let hex = s.strip_prefix('#')?;
+    if hex.len() != 6 {
+        return None;
+    }
+
+    let chan = |i: usize| u8::from_str_radix(hex.get(i..(i + 2))?, 16).ok();
+    Some(rgb(chan(0)?, chan(2)?, chan(4)?))
+}
+
+/// The base colours of the GUI, in the order of `PALETTE`
+/// and of `ColorRole`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Theme {
+    colors: [Color; 13],
+}
+
+impl Theme {
+    pub fn default_theme() -> Self {
+        let mut colors = [(0.0, 0.0, 0.0); 13];
+        for (c, (_, def)) in colors.iter_mut().zip(PALETTE.iter()) {
+            *c = def.rgb;
+        }
+        Self { colors }
+    }
+
+    /// Light text and controls on black, for accessibility.
+    pub fn high_contrast() -> Self {
+        Self {
+            colors: [
+                rgb(  0,   0,   0), // background
+                rgb( 80,  80,  80), // background3
+                rgb(255, 255, 255), // text
+                rgb(200, 200, 200), // border
+                rgb( 56,  56,  56), // label_bg
+                rgb(255, 255, 255), // accent
+                rgb(  0, 255, 255), // primary
+                rgb(  0, 200, 255), // primary2
+                rgb(255, 255,   0), // highlight
+                rgb(255, 200,   0), // highlight2
+                rgb(128, 128, 128), // inactive
+                rgb(190, 190, 190), // inactive2
+                rgb(255,  40,  40), // clip
+            ],
+        }
+    }
+
+    pub fn builtin(name: &str) -> Option<Self> {
+        match name {
+            "default"       => Some(Self::default_theme()),
+            "high-contrast" => Some(Self::high_contrast()),
+            _               => None,
+        }
+    }
+
+    /// Parses a theme file, see the module documentation.
+    pub fn from_toml(s: &str) -> Result<Self, String> {
+        let doc : toml::Value = s.parse().map_err(|e| format!("{}", e))?;
+
+        let mut theme =
+            match doc.get("base") {
+                None => Self::default_theme(),
+                Some(toml::Value::String(name)) =>
+                    Self::builtin(name)
+                        .ok_or_else(|| format!(
+                            "Unknown base theme '{}', known are: {}",
+                            name, BUILTIN_THEMES.join(", ")))?,
+                Some(_) => return Err("'base' is not a string".to_string()),
+            };
+
+        let colors =
+            match doc.get("colors") {
+                None => return Ok(theme),
+                Some(toml::Value::Table(colors)) => colors,
+                Some(_) => return Err("'colors' is not a table".to_string()),
+            };
+
+        for (name, value) in colors.iter() {
+            let idx =
+                PALETTE.iter().position(|(n, _)| n == name)
+                    .ok_or_else(|| format!("Unknown colour '{}'", name))?;
+            theme.colors[idx] =
+                value.as_str().and_then(parse_color)
+                    .ok_or_else(|| format!(
+                        "Colour '{}' is not in the form \"#rrggbb\"", name))?;
+        }
+
+        Ok(theme)
+    }
+
+    pub fn load_file(path: &Path) -> Result<Self, String> {
+        let s =
+            std::fs::read_to_string(path)
+                .map_err(|e| format!("Couldn't read {:?}: {}", path, e))?;
+        Self::from_toml(&s).map_err(|e| format!("{:?}: {}", path, e))
+    }
+
+    /// The path of the user theme: `kickmess/theme.toml`
+    /// in the config directory of the user.
+    pub fn user_theme_path() -> Option<PathBuf> {
+        let mut path = dirs::config_dir()?;
+        path.push("kickmess");
+        path.push("theme.toml");
+        Some(path)
+    }
+
+    /// Loads the theme named by `KICKMESS_THEME`, which is the name of a
+    /// built-in theme or the path of a theme file. Without it, the user
+    /// theme file is loaded if it exists. Falls back to the default theme
+    /// if the theme can't be loaded.
+    pub fn load_user_theme() -> Self {
+        let res =
+            match std::env::var("KICKMESS_THEME") {
+                Ok(name) => {
+                    if let Some(theme) = Self::builtin(&name) {
+                        Ok(theme)
+                    } else {
+                        Self::load_file(Path::new(&name))
+                    }
+                },
+                Err(_) => {
+                    match Self::user_theme_path() {
+                        Some(path) if path.exists() => Self::load_file(&path),
+                        _ => Ok(Self::default_theme()),
+                    }
+                },
+            };
+
+        match res {
+            Ok(theme) => theme,
+            Err(e) => {
+                klog!(Gui, Warn, "Using the default theme: {}", e);
+                Self::default_theme()
+            },
+        }
+    }
+
+    /// The base colour `name`, see `PALETTE`.
+    pub fn get(&self, name: &str) -> Option<Color> {
+        let idx = PALETTE.iter().position(|(n, _)| *n == name)?;
+        Some(self.colors[idx])
+    }
+
+    /// The colour of this theme for the role of `clr`.
+    /// Colours without a role are returned unchanged.
+    #[inline]
+    pub fn color(&self, clr: UIColor) -> UIColor {
+        match clr.role {
+            Some(role) => UIColor { rgb: self.colors[role as usize], role: Some(role) },
+            None       => clr,
+        }
+    }
+}
+
+impl Default for Theme {
+    fn default() -> Self { Self::default_theme() }
+}
+
+/// Draws with the colours of a `Theme`.
+pub struct ThemedPainter<'a> {
+    p:      &'a mut dyn Painter,
+    theme:  &'a Theme,
+}
+
+impl<'a> ThemedPainter<'a> {
+    pub fn new(p: &'a mut dyn Painter, theme: &'a Theme) -> Self {
+        Self { p, theme }
+    }
+}
+
+impl<'a> Painter for ThemedPainter<'a> {
+    fn path_fill(&mut self, color: UIColor, segments: &mut dyn std::iter::Iterator<Item = (f64, f64)>, closed: bool) {
+        self.p.path_fill(self.theme.color(color), segments, closed)
+    }
+
+    fn path_stroke(&mut self, width: f64, color: UIColor, segments: &mut dyn std::iter::Iterator<Item = (f64, f64)>, closed: bool) {
+        self.p.path_stroke(width, self.theme.color(color), segments, closed)
+    }
+
+    fn arc_stroke(&mut self, width: f64, color: UIColor, radius: f64, from_rad: f64, to_rad: f64, x: f64, y: f64) {
+        self.p.arc_stroke(width, self.theme.color(color), radius, from_rad, to_rad, x, y)
+    }
+
+    fn rect_fill(&mut self, color: UIColor, x: f64, y: f64, w: f64, h: f64) {
+        self.p.rect_fill(self.theme.color(color), x, y, w, h)
+    }
+
+    fn rect_stroke(&mut self, width: f64, color: UIColor, x: f64, y: f64, w: f64, h: f64) {
+        self.p.rect_stroke(width, self.theme.color(color), x, y, w, h)
+    }
+
+    fn label(&mut self, size: f64, align: i8, color: UIColor, x: f64, y: f64, w: f64, h: f64, text: &str) {
+        self.p.label(size, align, self.theme.color(color), x, y, w, h, text)
+    }
+
+    fn label_mono(&mut self, size: f64, align: i8, color: UIColor, x: f64, y: f64, w: f64, h: f64, text: &str) {
+        self.p.label_mono(size, align, self.theme.color(color), x, y, w, h, text)
+    }
+
+    fn font_height(&mut self, size: f32, mono: bool) -> f32 {
+        self.p.font_height(size, mono)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The contrast ratio of two colours, as defined by WCAG 2.0.
+    fn contrast(a: Color, b: Color) -> f64 {
+        let lum = |c: Color| {
+            let lin = |v: f64| {
+                if v <= 0.03928 { v / 12.92 }
+                else { ((v + 0.055) / 1.055).powf(2.4) }
+            };
+            0.2126 * lin(c.0) + 0.7152 * lin(c.1) + 0.0722 * lin(c.2)
+        };
+        let (la, lb) = (lum(a), lum(b));
+        (la.max(lb) + 0.05) / (la.min(lb) + 0.05)
+    }
+
+    #[test]
+    fn check_theme_palette_roles() {
+        for (i, (name, clr)) in PALETTE.iter().enumerate() {
+            assert_eq!(clr.role.map(|r| r as usize), Some(i), "role of {}", name);
+        }
+
+        let theme = Theme::default_theme();
+        assert_eq!(theme.color(UI_BTN_TXT_CLR), UI_BTN_TXT_CLR);
+        assert_eq!(theme.color(UIColor::rgb(0.1, 0.2, 0.3)), UIColor::rgb(0.1, 0.2, 0.3));
+
+        // Colours without a role are not themed, even if they
+        // happen to look like a base colour:
+        let theme = Theme::high_contrast();
+        assert_eq!(theme.color(UIColor::rgb(UI_TXT_CLR.rgb.0, UI_TXT_CLR.rgb.1, UI_TXT_CLR.rgb.2)).rgb, UI_TXT_CLR.rgb);
+    }
+
+    #[test]
+    fn check_theme_from_toml() {
+        let theme =
+            Theme::from_toml(
+                "base = \"high-contrast\"\n\
+                 [colors]\n\
+                 primary = \"#102030\"\n").unwrap();
+
+        assert_eq!(theme.get("primary"), Some(rgb(0x10, 0x20, 0x30)));
+        assert_eq!(theme.get("text"), Theme::high_contrast().get("text"));
+        // The derived colours of the GUI follow their base colour:
+        assert_eq!(theme.color(UI_TAB_TXT_CLR).rgb, rgb(0x10, 0x20, 0x30));
+
+        assert_eq!(Theme::from_toml("").unwrap(), Theme::default_theme());
+
+        assert!(Theme::from_toml("base = \"pink\"").unwrap_err().contains("pink"));
+        assert!(Theme::from_toml("[colors]\nfoo = \"#000000\"").unwrap_err().contains("foo"));
+        assert!(Theme::from_toml("[colors]\ntext = \"#00000\"").unwrap_err().contains("text"));
+        assert!(Theme::from_toml("[colors]\ntext = 12").is_err());
+    }
+
+    #[test]
+    fn check_theme_high_contrast() {
+        let theme = Theme::high_contrast();
+        let clr   = |c| theme.color(c).rgb;
+
+        // WCAG AAA asks for 7:1 for normal text:
+        for (fg, bg) in [
+            (UI_LBL_TXT_CLR,        UI_GUI_BG_CLR),
+            (UI_CONT_FONT_CLR,      UI_LBL_BG_CLR),
+            (UI_TXT_KNOB_CLR,       UI_GUI_BG_CLR),
+            (UI_BTN_TXT_CLR,        UI_BTN_BG_CLR),
+            (UI_BTN_TXT_HLIGHT_CLR, UI_BTN_BG_CLR),
+            (UI_TAB_TXT_CLR,        UI_TAB_BG_CLR),
+        ].iter() {
+            let ratio = contrast(clr(*fg), clr(*bg));
+            assert!(ratio >= 7.0, "contrast {:?} on {:?}: {}", fg, bg, ratio);
+        }
+
+        // The knob track must stand out from the background:
+        assert!(contrast(clr(UI_BG_KNOB_STROKE_CLR), clr(UI_GUI_BG_CLR)) >= 1.5);
+    }
+}
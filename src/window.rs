@@ -26,7 +26,7 @@ use vst::editor::Editor;
 
 use crate::ui::protocol::UIController;
 use crate::ui::constants::*;
-use crate::ui::painting::Painter;
+use crate::ui::painting::{Painter, UIColor};
 use crate::ui::{WValuePlugUI, UIEvent};
 use crate::ui;
 
@@ -64,7 +64,7 @@ impl FrameTimeMeasurement {
                     avg += *b;
                 }
                 avg /= self.buf.len() as u128;
-                println!("Frame time [{:10}]: min={:5.3}, max={:5.3}, avg={:5.3}", self.lbl, min as f64 / 1000.0, max as f64 / 1000.0, avg as f64 / 1000.0);
+                klog!(Gui, Debug, "Frame time [{:10}]: min={:5.3}, max={:5.3}, avg={:5.3}", self.lbl, min as f64 / 1000.0, max as f64 / 1000.0, avg as f64 / 1000.0);
                 self.idx = 0;
             } else {
                 self.idx += 1;
@@ -83,12 +83,28 @@ pub struct GUIWindowHandler {
     ftm:        FrameTimeMeasurement,
     ftm_redraw: FrameTimeMeasurement,
     ui:         WValuePlugUI,
+    /// The scale factor of the canvas, the system
+    /// scale factor multiplied by the `zoom`.
     scale:      f32,
-    size:       (f64, f64),
+    zoom:       f32,
+    sys_scale:  f32,
     focused:    bool,
     counter:    usize,
 }
 
+impl GUIWindowHandler {
+    /// Scales the UI by the zoom factor and lets the layout
+    /// reflow to the window size at that zoom.
+    fn apply_zoom(&mut self) {
+        self.zoom  = self.ui.window_zoom() as f32;
+        self.scale = self.zoom * self.sys_scale;
+        self.ui.set_window_size(
+            (self.canvas.width()  / self.scale) as f64,
+            (self.canvas.height() / self.scale) as f64);
+        self.ui.queue_redraw();
+    }
+}
+
 struct MyPainter<'a> {
     canvas:     &'a mut Canvas<OpenGl>,
     font:       FontId,
@@ -96,16 +112,16 @@ struct MyPainter<'a> {
     scale:      f32,
 }
 
-fn color_paint(color: (f64, f64, f64)) -> femtovg::Paint {
+fn color_paint(color: UIColor) -> femtovg::Paint {
     femtovg::Paint::color(
         Color::rgbf(
-            color.0 as f32,
-            color.1 as f32,
-            color.2 as f32))
+            color.rgb.0 as f32,
+            color.rgb.1 as f32,
+            color.rgb.2 as f32))
 }
 
 impl<'a> MyPainter<'a> {
-    fn label_with_font(&mut self, size: f64, align: i8, color: (f64, f64, f64), x: f64, y: f64, w: f64, h: f64, text: &str, font: FontId) {
+    fn label_with_font(&mut self, size: f64, align: i8, color: UIColor, x: f64, y: f64, w: f64, h: f64, text: &str, font: FontId) {
         let mut paint = color_paint(color);
         paint.set_font(&[font]);
         paint.set_font_size(size as f32);
@@ -129,7 +145,7 @@ impl<'a> MyPainter<'a> {
 }
 
 impl<'a> Painter for MyPainter<'a> {
-    fn path_fill(&mut self, color: (f64, f64, f64), segments: &mut dyn std::iter::Iterator<Item = (f64, f64)>, closed: bool) {
+    fn path_fill(&mut self, color: UIColor, segments: &mut dyn std::iter::Iterator<Item = (f64, f64)>, closed: bool) {
         let mut p = femtovg::Path::new();
         let mut paint = color_paint(color);
 
@@ -148,7 +164,7 @@ impl<'a> Painter for MyPainter<'a> {
         self.canvas.fill_path(&mut p, paint);
     }
 
-    fn path_stroke(&mut self, width: f64, color: (f64, f64, f64), segments: &mut dyn std::iter::Iterator<Item = (f64, f64)>, closed: bool) {
+    fn path_stroke(&mut self, width: f64, color: UIColor, segments: &mut dyn std::iter::Iterator<Item = (f64, f64)>, closed: bool) {
         let mut p = femtovg::Path::new();
         let mut paint = color_paint(color);
         paint.set_line_join(femtovg::LineJoin::Round);
@@ -169,7 +185,7 @@ impl<'a> Painter for MyPainter<'a> {
         self.canvas.stroke_path(&mut p, paint);
     }
 
-    fn arc_stroke(&mut self, width: f64, color: (f64, f64, f64), radius: f64, from_rad: f64, to_rad: f64, x: f64, y: f64) {
+    fn arc_stroke(&mut self, width: f64, color: UIColor, radius: f64, from_rad: f64, to_rad: f64, x: f64, y: f64) {
         let mut p = femtovg::Path::new();
         let mut paint = color_paint(color);
         paint.set_line_width(width as f32);
@@ -177,13 +193,13 @@ impl<'a> Painter for MyPainter<'a> {
         self.canvas.stroke_path(&mut p, paint);
     }
 
-    fn rect_fill(&mut self, color: (f64, f64, f64), x: f64, y: f64, w: f64, h: f64) {
+    fn rect_fill(&mut self, color: UIColor, x: f64, y: f64, w: f64, h: f64) {
         let mut p = femtovg::Path::new();
         p.rect(x as f32, y as f32, w as f32, h as f32);
         self.canvas.fill_path(&mut p, color_paint(color));
     }
 
-    fn rect_stroke(&mut self, width: f64, color: (f64, f64, f64), x: f64, y: f64, w: f64, h: f64) {
+    fn rect_stroke(&mut self, width: f64, color: UIColor, x: f64, y: f64, w: f64, h: f64) {
         let mut p = femtovg::Path::new();
         p.rect(x as f32, y as f32, w as f32, h as f32);
         let mut paint = color_paint(color);
@@ -191,11 +207,11 @@ impl<'a> Painter for MyPainter<'a> {
         self.canvas.stroke_path(&mut p, paint);
     }
 
-    fn label(&mut self, size: f64, align: i8, color: (f64, f64, f64), x: f64, y: f64, w: f64, h: f64, text: &str) {
+    fn label(&mut self, size: f64, align: i8, color: UIColor, x: f64, y: f64, w: f64, h: f64, text: &str) {
         self.label_with_font(size, align, color, x, y, w, h, text, self.font);
     }
 
-    fn label_mono(&mut self, size: f64, align: i8, color: (f64, f64, f64), x: f64, y: f64, w: f64, h: f64, text: &str) {
+    fn label_mono(&mut self, size: f64, align: i8, color: UIColor, x: f64, y: f64, w: f64, h: f64, text: &str) {
         self.label_with_font(size, align, color, x, y, w, h, text, self.font_mono);
     }
 
@@ -222,8 +238,8 @@ impl WindowHandler for GUIWindowHandler {
             Event::Mouse(MouseEvent::CursorMoved { position: p }) => {
                 self.ui.handle_ui_event(
                     UIEvent::MousePosition(
-                        p.x / self.scale as f64,
-                        p.y / self.scale as f64));
+                        p.x / self.zoom as f64,
+                        p.y / self.zoom as f64));
             },
             Event::Mouse(MouseEvent::ButtonPressed(btn)) => {
                 let ev_btn =
@@ -276,8 +292,9 @@ impl WindowHandler for GUIWindowHandler {
                 self.focused = false;
             },
             Event::Window(WindowEvent::Resized(info)) => {
-                let size = info.logical_size();
+                let size = info.physical_size();
 
+                self.sys_scale = info.scale() as f32;
                 self.canvas.set_size(size.width as u32, size.height as u32, 1.0);
                 let (w, h) = (self.canvas.width(), self.canvas.height());
                 self.canvas.delete_image(self.img_buf);
@@ -287,24 +304,19 @@ impl WindowHandler for GUIWindowHandler {
                         femtovg::PixelFormat::Rgb8,
                         femtovg::ImageFlags::FLIP_Y).expect("making image buffer");
 
-                let ri = (w as f32) / (h as f32);
-                let rs = (self.size.0 as f32) / (self.size.1 as f32);
-
-                if rs > ri {
-                    self.scale = (w as f32) / (self.size.0 as f32);
-                } else {
-                    self.scale = (h as f32) / (self.size.1 as f32);
-                }
-
-                self.ui.queue_redraw();
+                self.apply_zoom();
             },
             _ => {
-                println!("UNHANDLED EVENT: {:?}", event);
+                klog!(Gui, Trace, "unhandled event: {:?}", event);
             },
         }
     }
 
     fn on_frame(&mut self) {
+        if self.ui.window_zoom() as f32 != self.zoom {
+            self.apply_zoom();
+        }
+
         self.counter += 1;
         if self.counter % 500 == 0 {
 //            println!("REDRAW.....");
@@ -328,14 +340,15 @@ impl WindowHandler for GUIWindowHandler {
             self.canvas.set_render_target(femtovg::RenderTarget::Image(self.img_buf));
             self.canvas.save();
             self.canvas.scale(self.scale, self.scale);
+            let bg_clr = self.ui.theme().color(UI_GUI_BG_CLR).rgb;
             self.canvas.clear_rect(
                 0, 0,
                 self.canvas.width() as u32,
                 self.canvas.height() as u32,
                 Color::rgbf(
-                    UI_GUI_BG_CLR.0 as f32,
-                    UI_GUI_BG_CLR.1 as f32,
-                    UI_GUI_BG_CLR.2 as f32));
+                    bg_clr.0 as f32,
+                    bg_clr.1 as f32,
+                    bg_clr.2 as f32));
             self.ui.draw(&mut MyPainter {
                 canvas:     &mut self.canvas,
                 font:       self.font,
@@ -382,12 +395,12 @@ unsafe impl raw_window_handle::HasRawWindowHandle for StupidWindowHandleHolder {
 }
 
 pub fn open_window(title: &str, window_width: i32, window_height: i32, parent: Option<*mut ::std::ffi::c_void>, controller: std::sync::Arc<dyn UIController>) {
-    println!("*** OPEN WINDOW ***");
+    klog!(Gui, Info, "open window: {} {}x{}", title, window_width, window_height);
     let options =
         WindowOpenOptions {
             title:  title.to_string(),
             size:   Size::new(window_width as f64, window_height as f64),
-            scale:  WindowScalePolicy::ScaleFactor(1.0),
+            scale:  WindowScalePolicy::SystemScaleFactor,
         };
 
     let window_create_fun = move |win: &mut Window| {
@@ -427,12 +440,10 @@ pub fn open_window(title: &str, window_width: i32, window_height: i32, parent: O
                 femtovg::ImageFlags::FLIP_Y).expect("making image buffer");
 
         let mut ui = WValuePlugUI::new(controller);
+        ui.set_theme(ui::theme::Theme::load_user_theme());
 
-        ui.set_window_size(window_width as f64, window_height as f64);
-
-        GUIWindowHandler {
+        let mut handler = GUIWindowHandler {
             ui,
-            size: (window_width as f64, window_height as f64),
             context,
             canvas,
             font,
@@ -441,9 +452,13 @@ pub fn open_window(title: &str, window_width: i32, window_height: i32, parent: O
             ftm:        FrameTimeMeasurement::new("img"),
             ftm_redraw: FrameTimeMeasurement::new("redraw"),
             scale:      1.0,
+            zoom:       1.0,
+            sys_scale:  1.0,
             focused:    false,
             counter:    0,
-        }
+        };
+        handler.apply_zoom();
+        handler
     };
 
     if let Some(parent) = parent {
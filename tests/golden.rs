@@ -0,0 +1,207 @@
+// Copyright (c) 2020-2021 Weird Constructor <weirdconstructor@gmail.com>
+// This is a part of Kickmess. See README.md and COPYING for details.
+
+//! Golden audio regression tests for the DSP core.
+//!
+//! Every case renders a reference preset and compares the result with the
+//! reference buffer in `res/golden/kickmess/` (or `res/golden/megamess/` for
+//! the `mega` feature). After an intended change of the sound the references
+//! are regenerated with:
+//!
+//!     KICKMESS_BLESS=1 cargo test --test golden
+//!     KICKMESS_BLESS=1 cargo test --all-features --test golden
+
+use kickmessvst::offline::{OfflineRenderer, RenderNote};
+use std::path::PathBuf;
+
+const SRATE : u32 = 44100;
+
+/// Maximum difference of a single sample, about -80dB.
+const MAX_PEAK_DIFF : f32 = 0.0001;
+/// Maximum RMS of the difference signal, about -100dB.
+const MAX_RMS_DIFF  : f32 = 0.00001;
+
+struct GoldenCase {
+    name:   &'static str,
+    preset: &'static str,
+    notes:  &'static [RenderNote],
+}
+
+const KICK : &[RenderNote] = &[RenderNote { note: 36, vel: 127, len_ms: 250.0 }];
+
+const KICKMESS_CASES : &[GoldenCase] = &[
+    GoldenCase { name: "default", preset: "", notes: KICK },
+    GoldenCase {
+        name: "distortion",
+        preset: "!PARAMS;pub : dist_on=1;pub : dist_start=0.5;pub : dist_end=0.2;pub : noise=0.2;",
+        notes: KICK,
+    },
+    GoldenCase {
+        name: "filter",
+        preset: "!PARAMS;priv : f1_on=1;priv : f1_type=0.5;pub : f1_cutoff=0.4;\
+                 pub : f1_res=0.6;pub : f1_drive=0.4;",
+        notes: KICK,
+    },
+    GoldenCase {
+        name: "click_noise",
+        preset: "!PARAMS;pub : click_level=0.8;pub : click_decay=0.5;pub : click_tone=0.6;\
+                 priv : click_type=0.1;",
+        notes: KICK,
+    },
+    GoldenCase {
+        name: "click_beater",
+        preset: "!PARAMS;pub : click_level=0.8;pub : click_decay=0.3;pub : click_tone=0.8;\
+                 priv : click_type=0.9;pub : phase_offs=0.5;",
+        notes: KICK,
+    },
+    GoldenCase {
+        name: "oversampled",
+        preset: "!PARAMS;pub : dist_on=1;pub : dist_start=1;pub : dist_end=0.5;\
+                 priv : os_factor=0.6;priv : os_quality=0.9;",
+        notes: KICK,
+    },
+    GoldenCase {
+        name: "key_tracking",
+        preset: "!PARAMS;pub : freq_note_start=1;pub : freq_note_end=0.5;\
+                 priv : key_root=0.28346458;",
+        notes: &[
+            RenderNote { note: 36, vel: 127, len_ms: 150.0 },
+            RenderNote { note: 48, vel: 127, len_ms: 150.0 },
+            RenderNote { note: 60, vel: 127, len_ms: 150.0 },
+        ],
+    },
+];
+
+#[cfg(feature="mega")]
+const MEGAMESS_CASES : &[GoldenCase] = &[
+    GoldenCase {
+        name: "mega_osc1",
+        preset: "!PARAMS;pub : o1_gain=0.5;pub : o1_waveform=0.6;pub : o1_unison=0.3;\
+                 pub : o1_detune=0.1;",
+        notes: KICK,
+    },
+    GoldenCase {
+        name: "mega_fm",
+        preset: "!PARAMS;pub : o2fm_gain=0.5;pub : o1fm_ratio=0.3;pub : o1fm_o2_mod=0.4;\
+                 pub : o2fm_self=0.2;",
+        notes: KICK,
+    },
+];
+
+#[cfg(not(feature="mega"))]
+const MEGAMESS_CASES : &[GoldenCase] = &[];
+
+fn golden_path(name: &str) -> PathBuf {
+    let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    path.push("res");
+    path.push("golden");
+    path.push(if cfg!(feature="mega") { "megamess" } else { "kickmess" });
+    path.push(format!("{}.wav", name));
+    path
+}
+
+fn render_case(case: &GoldenCase) -> Vec<f32> {
+    let mut renderer = OfflineRenderer::new();
+    renderer.load_preset(case.preset.as_bytes());
+    renderer.render(SRATE, case.notes, 0.0, 100.0)
+}
+
+fn write_golden(path: &PathBuf, samples: &[f32]) {
+    std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+
+    let spec = hound::WavSpec {
+        channels:        1,
+        sample_rate:     SRATE,
+        bits_per_sample: 32,
+        sample_format:   hound::SampleFormat::Float,
+    };
+
+    let mut writer = hound::WavWriter::create(path, spec).unwrap();
+    for s in samples.iter() {
+        writer.write_sample(*s).unwrap();
+    }
+    writer.finalize().unwrap();
+}
+
+fn read_golden(path: &PathBuf) -> Result<Vec<f32>, String> {
+    let mut reader =
+        hound::WavReader::open(path)
+            .map_err(|e| format!("Can't read {:?}: {}", path, e))?;
+
+    reader.samples::<f32>()
+        .collect::<Result<Vec<f32>, hound::Error>>()
+        .map_err(|e| format!("Can't read {:?}: {}", path, e))
+}
+
+fn db(v: f32) -> f32 { 20.0 * v.max(0.000_000_1).log10() }
+
+fn compare(name: &str, reference: &[f32], rendered: &[f32]) -> Result<(), String> {
+    if reference.len() != rendered.len() {
+        return Err(format!(
+            "{}: length differs, reference={} rendered={}",
+            name, reference.len(), rendered.len()));
+    }
+
+    let mut peak_diff  = 0.0;
+    let mut peak_frame = 0;
+    let mut sum_sq     = 0.0;
+
+    for (i, (r, s)) in reference.iter().zip(rendered.iter()).enumerate() {
+        let diff = (r - s).abs();
+        if diff.is_nan() || diff > peak_diff {
+            peak_diff  = diff;
+            peak_frame = i;
+        }
+        sum_sq += (diff * diff) as f64;
+    }
+
+    let rms_diff = (sum_sq / (reference.len().max(1) as f64)).sqrt() as f32;
+
+    if peak_diff.is_nan() || peak_diff > MAX_PEAK_DIFF || rms_diff > MAX_RMS_DIFF {
+        return Err(format!(
+            "{}: peak diff {:.6} ({:.1} dB) at frame {}, rms diff {:.6} ({:.1} dB)",
+            name,
+            peak_diff, db(peak_diff), peak_frame,
+            rms_diff,  db(rms_diff)));
+    }
+
+    Ok(())
+}
+
+#[test]
+fn check_golden_audio() {
+    let bless = std::env::var("KICKMESS_BLESS").is_ok();
+
+    let mut errors = vec![];
+
+    for case in KICKMESS_CASES.iter().chain(MEGAMESS_CASES.iter()) {
+        let path     = golden_path(case.name);
+        let rendered = render_case(case);
+
+        assert!(rendered.iter().any(|s| s.abs() > 0.01),
+                "{}: rendered silence", case.name);
+
+        if bless {
+            write_golden(&path, &rendered);
+            continue;
+        }
+
+        match read_golden(&path) {
+            Ok(reference) => {
+                if let Err(e) = compare(case.name, &reference, &rendered) {
+                    errors.push(e);
+                }
+            },
+            Err(e) => errors.push(format!("{}: {} (run with KICKMESS_BLESS=1?)", case.name, e)),
+        }
+    }
+
+    assert!(errors.is_empty(), "golden audio differs:\n{}", errors.join("\n"));
+}
+
+#[test]
+fn check_golden_is_deterministic() {
+    let a = render_case(&KICKMESS_CASES[1]);
+    let b = render_case(&KICKMESS_CASES[1]);
+    assert!(compare("deterministic", &a, &b).is_ok());
+}
@@ -0,0 +1,70 @@
+// Copyright (c) 2020-2021 Weird Constructor <weirdconstructor@gmail.com>
+// This is a part of Kickmess. See README.md and COPYING for details.
+
+//! Checks that logging is real-time safe: `klog!` must not allocate,
+//! because it is used on the audio thread.
+
+use kickmessvst::klog;
+use kickmessvst::log::{self, Category, Level};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+
+thread_local! {
+    static ALLOCATIONS : Cell<usize> = const { Cell::new(0) };
+}
+
+/// Counts the allocations of the current thread.
+struct CountingAlloc;
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let _ = ALLOCATIONS.try_with(|a| a.set(a.get() + 1));
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL : CountingAlloc = CountingAlloc;
+
+fn allocations() -> usize { ALLOCATIONS.with(|a| a.get()) }
+
+#[test]
+fn check_logging_does_not_allocate() {
+    log::set_all_levels(Level::Trace);
+
+    let text = "x".repeat(1000);
+    let before = allocations();
+
+    for i in 0..100 {
+        klog!(Midi, Debug, "note on {} vel={} dt={}", 36 + (i % 12), 127, i);
+        klog!(Dsp,  Trace, "value {:8.3} {:?}", i as f32 * 0.1, [1.0, 2.0]);
+        klog!(Gui,  Info,  "{}", text);
+    }
+
+    // More messages than the queue holds are dropped, not blocked on:
+    for _ in 0..2000 {
+        klog!(Host, Warn, "overflow");
+    }
+
+    assert_eq!(allocations() - before, 0);
+
+    log::set_all_levels(Level::Off);
+
+    let mut count = 0;
+    let mut midi  = 0;
+    log::drain(|rec| {
+        count += 1;
+        if rec.category == Category::Midi { midi += 1; }
+        if rec.category == Category::Gui {
+            assert_eq!(rec.message().len(), log::MSG_LEN);
+        }
+    });
+
+    assert_eq!(midi, 100);
+    assert!(count < 2300);
+    assert_eq!(log::take_dropped(), 2300 - count);
+}
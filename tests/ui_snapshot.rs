@@ -0,0 +1,762 @@
+// Copyright (c) 2020-2021 Weird Constructor <weirdconstructor@gmail.com>
+// This is a part of Kickmess. See README.md and COPYING for details.
+
+//! UI tests without a window or GPU.
+//!
+//! The GUI is drawn with the `RecordingPainter` and the display list is
+//! compared with the snapshots in `res/ui_snapshots/`. After an intended
+//! change of the GUI the snapshots are regenerated with:
+//!
+//!     KICKMESS_BLESS=1 cargo test --test ui_snapshot
+//!     KICKMESS_BLESS=1 cargo test --all-features --test ui_snapshot
+
+use kickmessvst::ui::protocol::*;
+use kickmessvst::ui::recording::RecordingPainter;
+use kickmessvst::ui::{WValuePlugUI, UIEvent, MouseButton};
+use kickmessvst::param_model::pid;
+use kickmessvst::engine::{EngineParams, HostAutomation};
+use kickmessvst::editor::KickmessEditorController;
+use std::sync::{Arc, Mutex};
+use std::path::PathBuf;
+
+struct TestController {
+    changes: Mutex<Vec<(usize, f32)>>,
+}
+
+impl UIController for TestController {
+    fn init(&self, ui: &mut dyn UI) {
+        use kickmessvst::MonoProcessor;
+        let mut ps        = kickmessvst::ParamSet::new();
+        let mut public_ps = kickmessvst::ParamSet::new();
+        kickmessvst::OpKickmess::init_params(&mut ps, &mut public_ps);
+        kickmessvst::editor::define_gui(&ps, ui);
+        ui.set_version("test");
+        ui.set_default_values();
+    }
+
+    fn value_change(&self, _ui: &mut dyn UI, id: usize, value: f32, _single_change: bool) {
+        self.changes.lock().unwrap().push((id, value));
+    }
+}
+
+struct TestUI<C: UIController + 'static = TestController> {
+    ctrl:    Arc<C>,
+    ui:      WValuePlugUI,
+    painter: RecordingPainter,
+}
+
+impl TestUI {
+    fn new() -> Self {
+        Self::with_controller(
+            Arc::new(TestController { changes: Mutex::new(vec![]) }))
+    }
+
+    fn changes(&self) -> Vec<(usize, f32)> {
+        self.ctrl.changes.lock().unwrap().clone()
+    }
+}
+
+impl TestUI<KickmessEditorController> {
+    /// The GUI of the plugin, with the `params` and the `host`.
+    fn with_params(params: Arc<EngineParams>, host: Arc<dyn HostAutomation>) -> Self {
+        Self::with_controller(Arc::new(KickmessEditorController::new(params, host)))
+    }
+}
+
+impl<C: UIController + 'static> TestUI<C> {
+    fn with_controller(ctrl: Arc<C>) -> Self {
+        // The graphs use the sine table, that is usually set up by the
+        // first engine. Without it the drawing depends on the order of
+        // the tests:
+        kickmessvst::helpers::init_cos_tab();
+
+        let mut ui = WValuePlugUI::new(ctrl.clone());
+        ui.set_window_size(
+            kickmessvst::editor::WINDOW_WIDTH  as f64,
+            kickmessvst::editor::WINDOW_HEIGHT as f64);
+
+        let mut this = Self { ctrl, ui, painter: RecordingPainter::new() };
+        this.draw();
+        this
+    }
+
+    fn draw(&mut self) {
+        self.painter.clear();
+        self.ui.draw(&mut self.painter);
+    }
+
+    /// Moves the mouse to the center of the last label with `text`.
+    fn hover_label(&mut self, text: &str) {
+        let (x, y, w, h) =
+            self.painter.find_label(text)
+                .unwrap_or_else(|| panic!("label '{}' not drawn", text));
+        self.ui.handle_ui_event(
+            UIEvent::MousePosition(x + w * 0.5, y + h * 0.5));
+    }
+
+    fn click(&mut self, btn: MouseButton) {
+        self.ui.handle_ui_event(UIEvent::MouseButtonPressed(btn));
+        self.ui.handle_ui_event(UIEvent::MouseButtonReleased(btn));
+    }
+}
+
+fn snapshot_path(name: &str) -> PathBuf {
+    let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    path.push("res");
+    path.push("ui_snapshots");
+    path.push(if cfg!(feature="mega") { "megamess" } else { "kickmess" });
+    path.push(format!("{}.txt", name));
+    path
+}
+
+fn check_snapshot(name: &str, painter: &RecordingPainter) {
+    let path = snapshot_path(name);
+    let list = painter.display_list();
+
+    if std::env::var("KICKMESS_BLESS").is_ok() {
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, list).unwrap();
+        return;
+    }
+
+    let reference =
+        std::fs::read_to_string(&path)
+            .unwrap_or_else(|e|
+                panic!("Can't read {:?}: {} (run with KICKMESS_BLESS=1?)", path, e));
+
+    for (i, (r, l)) in reference.lines().zip(list.lines()).enumerate() {
+        assert_eq!(r, l, "{}: display list differs in line {}", name, i + 1);
+    }
+    assert_eq!(reference.lines().count(), list.lines().count(),
+               "{}: display list length differs", name);
+}
+
+#[test]
+fn check_ui_snapshot_default() {
+    let tui = TestUI::new();
+    assert!(tui.painter.labels().contains(&"Main"));
+    assert!(tui.painter.labels().contains(&"Distortion"));
+    check_snapshot("default", &tui.painter);
+}
+
+#[test]
+fn check_ui_draw_is_deterministic() {
+    let mut tui = TestUI::new();
+    let first = tui.painter.display_list();
+    tui.draw();
+    assert_eq!(first, tui.painter.display_list());
+}
+
+#[test]
+fn check_ui_help_tab() {
+    let mut tui = TestUI::new();
+    assert!(!tui.painter.labels().contains(&"Usage"));
+
+    tui.hover_label("Help");
+    tui.click(MouseButton::Left);
+    tui.draw();
+
+    assert!(tui.painter.labels().contains(&"Usage"));
+    assert!(!tui.painter.labels().contains(&"Distortion"));
+    check_snapshot("help_tab", &tui.painter);
+}
+
+#[test]
+fn check_ui_toggle_button() {
+    let mut tui = TestUI::new();
+
+    // The filter section is drawn after the distortion section,
+    // so the last "Off" label belongs to the filter toggle:
+    tui.hover_label("Off");
+    tui.click(MouseButton::Left);
+    tui.draw();
+
+    let changes = tui.changes();
+    assert_eq!(changes.len(), 1);
+    assert_eq!(changes[0].0, pid::f1_on);
+    assert!(changes[0].1 > 0.5);
+    assert!(tui.painter.labels().contains(&"On"));
+}
+
+#[test]
+fn check_ui_knob_reset_and_wheel() {
+    let mut tui = TestUI::new();
+
+    let before = tui.painter.display_list();
+
+    // The knob is drawn above its name label:
+    let (x, y, w, h) = tui.painter.find_label("Main Gain").unwrap();
+    tui.ui.handle_ui_event(UIEvent::MousePosition(x + w * 0.5, y - h));
+    tui.ui.handle_ui_event(UIEvent::MouseWheel(1.0));
+    tui.draw();
+
+    let changes = tui.changes();
+    assert_eq!(changes.len(), 1, "changes: {:?}", changes);
+    assert_eq!(changes[0].0, pid::main_gain);
+    assert_ne!(before, tui.painter.display_list());
+
+    tui.click(MouseButton::Middle);
+    let changes = tui.changes();
+    assert_eq!(changes.len(), 2);
+    assert_eq!(changes[1].0, pid::main_gain);
+    assert!(changes[1].1 < changes[0].1);
+}
+
+#[derive(Default)]
+struct TestHost {
+    calls: Mutex<Vec<String>>,
+}
+
+impl HostAutomation for TestHost {
+    fn begin_edit(&self, idx: usize) {
+        self.calls.lock().unwrap().push(format!("begin {}", idx));
+    }
+
+    fn automate(&self, idx: usize, value: f32) {
+        self.calls.lock().unwrap().push(format!("automate {} {}", idx, value));
+    }
+
+    fn end_edit(&self, idx: usize) {
+        self.calls.lock().unwrap().push(format!("end {}", idx));
+    }
+
+    fn resize_editor(&self, width: i32, height: i32) -> bool {
+        self.calls.lock().unwrap().push(format!("resize {}x{}", width, height));
+        true
+    }
+}
+
+#[test]
+fn check_ui_editor_controller() {
+    use kickmessvst::proc::ParamProvider;
+
+    let params  = Arc::new(EngineParams::new());
+    let host    = Arc::new(TestHost::default());
+    let mut tui = TestUI::with_params(params.clone(), host.clone());
+    let before  = tui.painter.display_list();
+
+    // A change from the host shows up in the GUI:
+    params.set(pid::main_gain, 0.5);
+    tui.ui.pre_frame();
+
+    tui.draw();
+    assert_ne!(before, tui.painter.display_list());
+    assert!(host.calls.lock().unwrap().is_empty());
+
+    // The filter toggle is the last "Off" label, see check_ui_toggle_button:
+    tui.hover_label("Off");
+    tui.click(MouseButton::Left);
+
+    // The filter toggle is a private parameter, the host doesn't know it:
+    assert!(params.param(pid::f1_on) > 0.5);
+    assert!(host.calls.lock().unwrap().is_empty());
+
+    // The knob is drawn above its name label:
+    let (x, y, w, h) = tui.painter.find_label("Main Gain").unwrap();
+    tui.ui.handle_ui_event(UIEvent::MousePosition(x + w * 0.5, y - h));
+    tui.ui.handle_ui_event(UIEvent::MouseWheel(1.0));
+
+    let value = params.param(pid::main_gain);
+    assert!(value > 0.5);
+    assert_eq!(*host.calls.lock().unwrap(), vec![
+        format!("begin {}",       pid::main_gain),
+        format!("automate {} {}", pid::main_gain, value),
+        format!("end {}",         pid::main_gain),
+    ]);
+}
+
+#[test]
+fn check_ui_scope_shows_last_hit() {
+    use kickmessvst::engine::{Engine, NoHost};
+    use kickmessvst::scope::SCOPE_POINTS;
+    use kickmessvst::ui::recording::DrawCmd;
+
+    let params  = Arc::new(EngineParams::new());
+    let mut tui = TestUI::with_params(params.clone(), Arc::new(NoHost));
+
+    let waveforms = |tui: &mut TestUI<_>| {
+        tui.draw();
+        assert!(tui.painter.labels().contains(&"Last Hit"));
+
+        tui.painter.commands().iter().filter(|cmd| {
+            if let DrawCmd::PathFill { points, .. } = cmd {
+                points.len() == 2 * SCOPE_POINTS
+            } else {
+                false
+            }
+        }).count()
+    };
+
+    tui.ui.pre_frame();
+    assert_eq!(waveforms(&mut tui), 0);
+
+    let mut engine = Engine::new(params.clone());
+    engine.set_sample_rate(44100.0);
+    engine.handle_midi(&[0x90, 36, 127], 0);
+
+    let mut out = vec![0.0; 512];
+    while params.scope().generation() == 0 {
+        engine.process(&mut out[..]);
+    }
+
+    tui.ui.pre_frame();
+    assert_eq!(waveforms(&mut tui), 1);
+}
+
+#[test]
+fn check_ui_spectrum_shows_fundamental() {
+    use kickmessvst::engine::{Engine, NoHost};
+    use kickmessvst::spectrum::SPECTRUM_BANDS;
+    use kickmessvst::ui::recording::DrawCmd;
+
+    let params  = Arc::new(EngineParams::new());
+    let mut tui = TestUI::with_params(params.clone(), Arc::new(NoHost));
+
+    tui.hover_label("Spectrum");
+    tui.click(MouseButton::Left);
+
+    let mut engine = Engine::new(params.clone());
+    engine.set_sample_rate(44100.0);
+    engine.handle_midi(&[0x90, 36, 127], 0);
+
+    let mut out = vec![0.0; 512];
+    for _ in 0..8 {
+        engine.process(&mut out[..]);
+    }
+
+    tui.ui.pre_frame();
+
+    tui.draw();
+    assert!(!tui.painter.labels().contains(&"Last Hit"));
+
+    let bands = tui.painter.commands().iter().filter(|cmd| {
+        if let DrawCmd::PathFill { points, .. } = cmd {
+            points.len() == SPECTRUM_BANDS + 2
+        } else {
+            false
+        }
+    }).count();
+    assert_eq!(bands, 1);
+
+    // The fundamental is labeled with its note name, like "C2 -12c":
+    let notes = "CDEFGAB";
+    assert!(tui.painter.labels().iter().any(|l| {
+        l.ends_with('c') && l.chars().next().map(|c| notes.contains(c)).unwrap_or(false)
+    }), "no note label in {:?}", tui.painter.labels());
+}
+
+#[test]
+fn check_ui_meter_clip_hold() {
+    use kickmessvst::engine::{Engine, NoHost};
+    use kickmessvst::ui::constants::UI_METER_CLIP_CLR;
+    use kickmessvst::ui::recording::DrawCmd;
+
+    let params = Arc::new(EngineParams::new());
+    let ps     = params.ps();
+    params.set(pid::main_gain, ps.definition(pid::main_gain).unwrap().unmap(2.0));
+    params.set(pid::gain,      ps.definition(pid::gain).unwrap().unmap(2.0));
+
+    let mut tui = TestUI::with_params(params.clone(), Arc::new(NoHost));
+
+    let clip_lit = |tui: &mut TestUI<_>| {
+        tui.draw();
+        tui.painter.commands().iter().any(|cmd| {
+            matches!(cmd, DrawCmd::RectFill { color, .. } if *color == UI_METER_CLIP_CLR.rgb)
+        })
+    };
+
+    tui.ui.pre_frame();
+    assert!(!clip_lit(&mut tui));
+
+    let mut engine = Engine::new(params.clone());
+    engine.set_sample_rate(44100.0);
+    engine.handle_midi(&[0x90, 36, 127], 0);
+
+    let mut out = vec![0.0; 512];
+    for _ in 0..40 {
+        engine.process(&mut out[..]);
+    }
+
+    // The clip indicator stays on after the hit is over:
+    tui.ui.pre_frame();
+    tui.ui.pre_frame();
+    assert!(clip_lit(&mut tui));
+
+    // Clicking the meter turns it off:
+    tui.hover_label("Out");
+    tui.click(MouseButton::Left);
+
+    tui.ui.pre_frame();
+    assert!(!clip_lit(&mut tui));
+}
+
+#[test]
+fn check_ui_graph_handle_drag() {
+    use kickmessvst::proc::ParamProvider;
+    use kickmessvst::ui::constants::UI_GRPH_HANDLE_SIZE;
+    use kickmessvst::ui::recording::DrawCmd;
+
+    let params  = Arc::new(EngineParams::new());
+    let host    = Arc::new(TestHost::default());
+    let mut tui = TestUI::with_params(params.clone(), host.clone());
+
+    // The handles above the label of the frequency envelope include the
+    // ones of the amplitude envelope above it. The end of the frequency
+    // envelope is the lowest of the handles furthest to the right:
+    let (lx, ly, lw, _lh) = tui.painter.find_label("Freq. Env").unwrap();
+    let (hx, hy) =
+        tui.painter.commands().iter().filter_map(|cmd| {
+            match cmd {
+                DrawCmd::RectStroke { x, y, w, h, .. }
+                    if *w == UI_GRPH_HANDLE_SIZE - 1.0
+                       && *x >= lx && *x <= lx + lw && *y < ly
+                    => Some((x + w * 0.5, y + h * 0.5)),
+                _ => None,
+            }
+        }).fold(None, |m: Option<(f64, f64)>, p| {
+            match m {
+                Some(m) if m >= p => Some(m),
+                _                 => Some(p),
+            }
+        }).expect("frequency envelope handles drawn");
+
+    let release  = params.param(pid::f_env_release);
+    let freq_end = params.param(pid::freq_end);
+
+    // Dragging to the right and up makes the envelope
+    // longer and raises the end frequency:
+    tui.ui.handle_ui_event(UIEvent::MousePosition(hx, hy));
+    tui.ui.handle_ui_event(UIEvent::MouseButtonPressed(MouseButton::Left));
+    tui.ui.handle_ui_event(UIEvent::MousePosition(hx + 10.0, hy - 10.0));
+    tui.ui.handle_ui_event(UIEvent::MousePosition(hx + 20.0, hy - 20.0));
+    tui.ui.handle_ui_event(UIEvent::MouseButtonReleased(MouseButton::Left));
+
+    let new_release  = params.param(pid::f_env_release);
+    let new_freq_end = params.param(pid::freq_end);
+    assert!((new_release  - release  - 0.1).abs() < 1e-4, "{} {}", release, new_release);
+    assert!((new_freq_end - freq_end - 0.1).abs() < 1e-4, "{} {}", freq_end, new_freq_end);
+
+    // Both parameters are automated as one gesture of the host:
+    let calls = host.calls.lock().unwrap().clone();
+    let edits : Vec<&String> =
+        calls.iter().filter(|c| !c.starts_with("automate")).collect();
+    assert_eq!(edits, vec![
+        &format!("begin {}", pid::f_env_release),
+        &format!("begin {}", pid::freq_end),
+        &format!("end {}",   pid::f_env_release),
+        &format!("end {}",   pid::freq_end),
+    ]);
+    assert!(calls.contains(
+        &format!("automate {} {}", pid::f_env_release, new_release)));
+    assert!(calls.contains(
+        &format!("automate {} {}", pid::freq_end, new_freq_end)));
+}
+
+#[test]
+fn check_ui_zoom_resizes_editor() {
+    use kickmessvst::editor::{editor_size, zoom_factor, WINDOW_WIDTH, WINDOW_HEIGHT};
+
+    assert_eq!(zoom_factor(0.0), 0.75);
+    assert_eq!(zoom_factor(1.0), 2.0);
+    assert_eq!(editor_size(2.0), (WINDOW_WIDTH * 2, WINDOW_HEIGHT * 2));
+
+    let params  = Arc::new(EngineParams::new());
+    let host    = Arc::new(TestHost::default());
+    let mut tui = TestUI::with_params(params, host.clone());
+    assert_eq!(tui.ui.window_zoom(), 1.0);
+
+    tui.ui.pre_frame();
+    tui.draw();
+    assert!(host.calls.lock().unwrap().is_empty());
+    let (dist_x, _, _, _) = tui.painter.find_label("Distortion").unwrap();
+
+    // Selecting the next zoom level asks the host for a bigger window:
+    tui.hover_label("100%");
+    tui.click(MouseButton::Left);
+    tui.ui.pre_frame();
+    tui.ui.pre_frame();
+
+    assert_eq!(tui.ui.window_zoom(), 1.25);
+    let (zw, zh) = editor_size(1.25);
+    assert_eq!(*host.calls.lock().unwrap(), vec![format!("resize {}x{}", zw, zh)]);
+
+    // A window, that the host made wider, gets a wider layout:
+    tui.ui.set_window_size(WINDOW_WIDTH as f64 * 1.5, WINDOW_HEIGHT as f64);
+    tui.draw();
+    let (wide_dist_x, _, _, _) = tui.painter.find_label("Distortion").unwrap();
+    assert!(wide_dist_x > dist_x * 1.4, "{} vs {}", wide_dist_x, dist_x);
+}
+
+#[test]
+fn check_ui_high_contrast_theme() {
+    use kickmessvst::ui::theme::{Theme, PALETTE};
+    use kickmessvst::ui::recording::DrawCmd;
+
+    let mut tui = TestUI::new();
+    let default_labels : Vec<String> =
+        tui.painter.labels().iter().map(|l| l.to_string()).collect();
+
+    tui.ui.set_theme(Theme::high_contrast());
+    tui.draw();
+
+    // The theme changes only the colours:
+    assert_eq!(tui.painter.labels(), default_labels);
+
+    for cmd in tui.painter.commands().iter() {
+        let color =
+            match cmd {
+                DrawCmd::PathFill   { color, .. } => color,
+                DrawCmd::PathStroke { color, .. } => color,
+                DrawCmd::ArcStroke  { color, .. } => color,
+                DrawCmd::RectFill   { color, .. } => color,
+                DrawCmd::RectStroke { color, .. } => color,
+                DrawCmd::Label      { color, .. } => color,
+            };
+        assert!(!PALETTE.iter().any(|(_, def)| def.rgb == *color),
+                "default colour drawn: {:?}", cmd);
+    }
+}
+
+fn key_event(key: keyboard_types::Key, shift: bool) -> keyboard_types::KeyboardEvent {
+    use keyboard_types::*;
+    KeyboardEvent {
+        state:        KeyState::Down,
+        key,
+        code:         Code::Unidentified,
+        location:     Location::Standard,
+        modifiers:    if shift { Modifiers::SHIFT } else { Modifiers::empty() },
+        repeat:       false,
+        is_composing: false,
+    }
+}
+
+impl TestUI {
+    fn press_key(&mut self, key: keyboard_types::Key, shift: bool) {
+        self.ui.handle_ui_event(UIEvent::KeyPressed(key_event(key.clone(), shift)));
+        let mut kev = key_event(key, shift);
+        kev.state = keyboard_types::KeyState::Up;
+        self.ui.handle_ui_event(UIEvent::KeyReleased(kev));
+        self.draw();
+    }
+
+    /// The rectangle of the drawn keyboard focus.
+    fn focus_rect(&self) -> Option<(f64, f64, f64, f64)> {
+        use kickmessvst::ui::constants::{UI_FOCUS_CLR, UI_FOCUS_WIDTH};
+        use kickmessvst::ui::recording::DrawCmd;
+
+        self.painter.commands().iter().find_map(|cmd| {
+            match cmd {
+                DrawCmd::RectStroke { width, color, x, y, w, h }
+                    if *width == UI_FOCUS_WIDTH && *color == UI_FOCUS_CLR.rgb
+                    => Some((*x, *y, *w, *h)),
+                _ => None,
+            }
+        })
+    }
+}
+
+#[test]
+fn check_ui_keyboard_navigation() {
+    use keyboard_types::Key;
+
+    let mut tui = TestUI::new();
+    assert_eq!(tui.focus_rect(), None);
+
+    // Tab through the elements until the fine adjustment
+    // area (the name label) of the main gain knob is focused:
+    let (lx, ly, lw, lh) = tui.painter.find_label("Main Gain").unwrap();
+    let (cx, cy) = (lx + lw * 0.5, ly + lh * 0.5);
+    let mut tabs = 0;
+    loop {
+        tui.press_key(Key::Tab, false);
+        tabs += 1;
+        assert!(tabs < 500, "main gain knob not reachable with Tab");
+
+        let (x, y, w, h) = tui.focus_rect().expect("focus drawn");
+        if cx > x && cx < x + w && cy > y && cy < y + h {
+            break;
+        }
+    }
+    let gain_focus = tui.focus_rect();
+
+    // Shift+Tab goes back:
+    tui.press_key(Key::Tab, true);
+    assert_ne!(tui.focus_rect(), gain_focus);
+    tui.press_key(Key::Tab, false);
+    assert_eq!(tui.focus_rect(), gain_focus);
+
+    tui.press_key(Key::ArrowUp, false);
+    tui.press_key(Key::ArrowUp, true);
+    tui.press_key(Key::Delete, false);
+    let changes = tui.changes();
+    assert_eq!(changes.len(), 3);
+    assert!(changes.iter().all(|(id, _)| *id == pid::main_gain));
+    assert!(changes[0].1 > changes[2].1);
+    // The fine step with Shift is smaller than the coarse step:
+    assert!(changes[1].1 > changes[0].1);
+    assert!(changes[1].1 - changes[0].1 < changes[0].1 - changes[2].1);
+
+    // Enter opens the value input box:
+    assert!(!tui.painter.labels().contains(&"Old:"));
+    tui.press_key(Key::Enter, false);
+    assert!(tui.painter.labels().contains(&"Old:"));
+    tui.press_key(Key::Escape, false);
+    assert!(!tui.painter.labels().contains(&"Old:"));
+    assert_eq!(tui.focus_rect(), gain_focus);
+
+    // Escape without a mode to exit removes the focus:
+    tui.press_key(Key::Escape, false);
+    assert_eq!(tui.focus_rect(), None);
+}
+
+/// Opens the context menu of the knob above the label `knob`
+/// and clicks its entry `entry`.
+fn click_menu_entry(ui: &mut WValuePlugUI, painter: &mut RecordingPainter,
+                    knob: &str, entry: &str) {
+    let (x, y, w, h) = painter.find_label(knob).unwrap();
+    ui.handle_ui_event(UIEvent::MousePosition(x + w * 0.5, y - h));
+    ui.handle_ui_event(UIEvent::MouseButtonPressed(MouseButton::Right));
+    ui.handle_ui_event(UIEvent::MouseButtonReleased(MouseButton::Right));
+    painter.clear();
+    ui.draw(painter);
+
+    let (x, y, w, h) =
+        painter.find_label(entry)
+            .unwrap_or_else(|| panic!("menu entry '{}' not drawn", entry));
+    ui.handle_ui_event(UIEvent::MousePosition(x + w * 0.5, y + h * 0.5));
+    ui.handle_ui_event(UIEvent::MouseButtonPressed(MouseButton::Left));
+    ui.handle_ui_event(UIEvent::MouseButtonReleased(MouseButton::Left));
+    painter.clear();
+    ui.draw(painter);
+}
+
+#[test]
+fn check_ui_context_menu() {
+    let mut tui = TestUI::new();
+
+    let (x, y, w, h) = tui.painter.find_label("Main Gain").unwrap();
+    tui.ui.handle_ui_event(UIEvent::MousePosition(x + w * 0.5, y - h));
+    tui.ui.handle_ui_event(UIEvent::MouseWheel(1.0));
+    tui.click(MouseButton::Right);
+    tui.draw();
+    for entry in UIMenuAction::ALL.iter() {
+        assert!(tui.painter.labels().contains(&entry.label()), "{:?}", entry);
+    }
+    assert!(!tui.painter.labels().contains(&"Old:"));
+    check_snapshot("context_menu", &tui.painter);
+
+    // Without support by the controller, MIDI learn is disabled
+    // and clicking it keeps the menu open:
+    tui.hover_label("MIDI Learn");
+    tui.click(MouseButton::Left);
+    tui.draw();
+    assert!(tui.painter.labels().contains(&"Reset to Default"));
+
+    tui.hover_label("Reset to Default");
+    tui.click(MouseButton::Left);
+    tui.draw();
+    assert!(!tui.painter.labels().contains(&"Reset to Default"));
+    let changes = tui.changes();
+    assert_eq!(changes.len(), 2, "changes: {:?}", changes);
+    assert_eq!(changes[1], (pid::main_gain, changes[1].1));
+    assert!(changes[1].1 < changes[0].1);
+
+    // The main gain of 1.0 is pasted as 1.0 into the oscillator gain,
+    // which has a different range:
+    click_menu_entry(&mut tui.ui, &mut tui.painter, "Main Gain", "Copy Value (Internal)");
+    click_menu_entry(&mut tui.ui, &mut tui.painter, "MOsc Gain", "Paste Value (Internal)");
+    let changes = tui.changes();
+    assert_eq!(changes.len(), 3, "changes: {:?}", changes);
+    assert_eq!(changes[2].0, pid::gain);
+    assert!((changes[2].1 - 0.5).abs() < 1e-4, "{:?}", changes[2]);
+
+    click_menu_entry(&mut tui.ui, &mut tui.painter, "MOsc Gain", "Type Value...");
+    assert!(tui.painter.labels().contains(&"Old:"));
+}
+
+#[test]
+fn check_ui_context_menu_controller_entries() {
+    use kickmessvst::proc::ParamProvider;
+
+    let params  = Arc::new(EngineParams::new());
+    let host    = Arc::new(TestHost::default());
+    let mut tui = TestUI::with_params(params.clone(), host.clone());
+    tui.draw();
+
+    click_menu_entry(&mut tui.ui, &mut tui.painter, "Start Hz", "Show Automation Lane");
+    let freq = params.param(pid::freq_start);
+    assert_eq!(*host.calls.lock().unwrap(), vec![
+        format!("begin {}", pid::freq_start),
+        format!("automate {} {}", pid::freq_start, freq),
+        format!("end {}", pid::freq_start),
+    ]);
+
+    click_menu_entry(&mut tui.ui, &mut tui.painter, "Start Hz", "MIDI Learn");
+    assert_eq!(params.midi_learn().learning(), Some(pid::freq_start));
+
+    click_menu_entry(&mut tui.ui, &mut tui.painter, "Start Hz", "Lock from Randomize");
+    assert!(params.is_locked(pid::freq_start));
+
+    host.calls.lock().unwrap().clear();
+    let freq_end = params.param(pid::freq_end);
+    click_menu_entry(&mut tui.ui, &mut tui.painter, "End Hz", "Randomize Unlocked");
+    assert_eq!(params.param(pid::freq_start), freq);
+    assert_ne!(params.param(pid::freq_end), freq_end);
+    assert!(host.calls.lock().unwrap().contains(
+        &format!("automate {} {}", pid::freq_end, params.param(pid::freq_end))));
+    assert!(!host.calls.lock().unwrap().contains(
+        &format!("begin {}", pid::freq_start)));
+}
+
+#[test]
+fn check_ui_value_tooltip() {
+    let mut tui = TestUI::new();
+    assert!(!tui.painter.labels().contains(&"Start Frequency"));
+
+    let (x, y, w, h) = tui.painter.find_label("Start Hz").unwrap();
+    tui.ui.handle_ui_event(UIEvent::MousePosition(x + w * 0.5, y - h));
+    tui.draw();
+
+    let labels = tui.painter.labels();
+    assert!(labels.contains(&"Start Frequency"), "{:?}", labels);
+    assert!(labels.contains(&"150.00 Hz"), "{:?}", labels);
+    assert!(labels.contains(&"Default: 150.00 Hz"), "{:?}", labels);
+    assert!(!labels.iter().any(|l| l.starts_with("Delta:")));
+    check_snapshot("tooltip", &tui.painter);
+
+    // While dragging the change since the start of the drag is shown:
+    tui.ui.handle_ui_event(UIEvent::MouseButtonPressed(MouseButton::Left));
+    tui.ui.handle_ui_event(UIEvent::MousePosition(x + w * 0.5, y - h - 30.0));
+    tui.draw();
+
+    let labels = tui.painter.labels();
+    assert!(labels.contains(&"Start Frequency"), "{:?}", labels);
+    assert!(!labels.contains(&"150.00 Hz"), "{:?}", labels);
+    assert!(labels.contains(&"Default: 150.00 Hz"), "{:?}", labels);
+    assert!(
+        labels.iter().any(|l| l.starts_with("Delta: +") && l.ends_with(" Hz")),
+        "{:?}", labels);
+
+    tui.ui.handle_ui_event(UIEvent::MouseButtonReleased(MouseButton::Left));
+    tui.draw();
+    assert!(!tui.painter.labels().iter().any(|l| l.starts_with("Delta:")));
+
+    // The tooltip of a knob at the right edge is moved inside the window,
+    // to the left of the mouse:
+    let right_knob = if cfg!(feature="mega") { "Release ms" } else { "Main Gain" };
+    let (x, y, w, h) = tui.painter.find_label(right_knob).unwrap();
+    let mouse_x = x + w * 0.5;
+    tui.ui.handle_ui_event(UIEvent::MousePosition(mouse_x, y - h));
+    tui.draw();
+
+    // Only the tooltip draws the default value:
+    let default =
+        tui.painter.labels().into_iter()
+            .find(|l| l.starts_with("Default: "))
+            .expect("no tooltip drawn")
+            .to_string();
+    let (tx, ty, tw, th) = tui.painter.find_label(&default).unwrap();
+    assert!(tx < mouse_x, "tooltip not moved: {:?}", (tx, ty));
+    assert!(tx + tw <= kickmessvst::editor::WINDOW_WIDTH as f64);
+    assert!(ty + th <= kickmessvst::editor::WINDOW_HEIGHT as f64);
+}